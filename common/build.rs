@@ -0,0 +1,49 @@
+//! Extracts the resolved versions of the zkEVM crates from the workspace
+//! lockfile so that they can be recorded alongside test results.
+
+use std::{env, fs, path::Path};
+
+const TRACKED_CRATES: [(&str, &str); 2] = [
+    ("evm_arithmetization", "EVM_ARITHMETIZATION_VERSION"),
+    ("plonky2", "PLONKY2_VERSION"),
+];
+
+fn main() {
+    let lock_path = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("../Cargo.lock");
+    println!("cargo:rerun-if-changed={}", lock_path.display());
+
+    let lock = fs::read_to_string(&lock_path).unwrap_or_default();
+    for (crate_name, env_var) in TRACKED_CRATES {
+        let version = locked_version(&lock, crate_name).unwrap_or_else(|| "unknown".into());
+        println!("cargo:rustc-env={}={}", env_var, version);
+    }
+}
+
+/// Returns the locked version of the crate, along with the short commit hash if
+/// it is a git dependency.
+fn locked_version(lock: &str, crate_name: &str) -> Option<String> {
+    let name_line = format!("name = \"{}\"", crate_name);
+    let mut lines = lock.lines();
+
+    lines.find(|l| *l == name_line)?;
+    let version = lines
+        .next()?
+        .strip_prefix("version = \"")?
+        .strip_suffix('"')?;
+
+    let git_rev = lines
+        .next()
+        .and_then(|l| l.strip_prefix("source = \"git+"))
+        .and_then(|l| l.rsplit_once('#'))
+        .map(|(_, rev)| {
+            rev.trim_end_matches('"')
+                .chars()
+                .take(7)
+                .collect::<String>()
+        });
+
+    Some(match git_rev {
+        Some(rev) => format!("{}-{}", version, rev),
+        None => version.to_string(),
+    })
+}
@@ -3,5 +3,13 @@ pub const GENERATION_INPUTS_DEFAULT_OUTPUT_DIR: &str = "generation_inputs";
 /// We use the `BlockchainTests` subdirectory of the `Cancun` folder
 /// as it contains all hardfork variants up to this one.
 pub const MAIN_TEST_DIR: &str = "Cancun/BlockchainTests";
+/// The file written by the parser at the root of its output directory, holding
+/// the commit of `ethereum/tests` the tests were parsed from.
+pub const ETH_TESTS_COMMIT_FILE_NAME: &str = "eth_tests_commit";
 pub const MATIC_CHAIN_ID: u64 = 137;
 pub const ETHEREUM_CHAIN_ID: u64 = 1;
+
+/// The version of `evm_arithmetization` resolved in the workspace lockfile.
+pub const EVM_ARITHMETIZATION_VERSION: &str = env!("EVM_ARITHMETIZATION_VERSION");
+/// The version of `plonky2` resolved in the workspace lockfile.
+pub const PLONKY2_VERSION: &str = env!("PLONKY2_VERSION");
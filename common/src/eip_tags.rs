@@ -0,0 +1,53 @@
+//! Tagging of the tests with the EIPs and features they cover, as defined in
+//! `eip_tags.toml`.
+
+use std::{collections::BTreeMap, sync::LazyLock};
+
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct EipTags {
+    /// The patterns of the tests covered by each tag.
+    tags: BTreeMap<String, Vec<String>>,
+}
+
+static EIP_TAGS: LazyLock<EipTags> = LazyLock::new(|| {
+    toml::from_str(include_str!("../eip_tags.toml")).expect("Parsing eip_tags.toml")
+});
+
+/// Returns the tags of a test, given its sub-group and the name of its JSON
+/// file (without extension).
+pub fn tags_for_test(sub_group: &str, test: &str) -> Vec<String> {
+    let path = format!("{}/{}", sub_group, test).to_lowercase();
+
+    EIP_TAGS
+        .tags
+        .iter()
+        .filter(|(_, patterns)| {
+            patterns
+                .iter()
+                .any(|pattern| glob_matches(pattern.to_lowercase().as_bytes(), path.as_bytes()))
+        })
+        .map(|(tag, _)| tag.clone())
+        .collect()
+}
+
+/// Returns whether the tag is defined.
+pub fn is_known_tag(tag: &str) -> bool {
+    EIP_TAGS.tags.contains_key(tag)
+}
+
+/// Returns the defined tags, in alphabetical order.
+pub fn known_tags() -> impl Iterator<Item = &'static str> {
+    EIP_TAGS.tags.keys().map(|tag| tag.as_str())
+}
+
+/// Matches `s` against a pattern in which `*` matches any (possibly empty)
+/// sequence of characters.
+fn glob_matches(pattern: &[u8], s: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => s.is_empty(),
+        Some((b'*', rest)) => (0..=s.len()).any(|i| glob_matches(rest, &s[i..])),
+        Some((c, rest)) => s.first() == Some(c) && glob_matches(rest, &s[1..]),
+    }
+}
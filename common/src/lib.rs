@@ -1,3 +1,8 @@
 pub mod config;
+pub mod eip_tags;
+pub mod opcodes;
+pub mod profiles;
+pub mod t8n;
 pub mod types;
 pub mod utils;
+mod variant_sections;
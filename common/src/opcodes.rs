@@ -0,0 +1,134 @@
+//! Static inspection of EVM bytecode.
+
+use rlp::Rlp;
+
+/// Returns the mnemonic of an opcode (as of Cancun), or `None` if the opcode is
+/// undefined.
+pub fn opcode_name(opcode: u8) -> Option<String> {
+    let name = match opcode {
+        0x00 => "STOP",
+        0x01 => "ADD",
+        0x02 => "MUL",
+        0x03 => "SUB",
+        0x04 => "DIV",
+        0x05 => "SDIV",
+        0x06 => "MOD",
+        0x07 => "SMOD",
+        0x08 => "ADDMOD",
+        0x09 => "MULMOD",
+        0x0a => "EXP",
+        0x0b => "SIGNEXTEND",
+        0x10 => "LT",
+        0x11 => "GT",
+        0x12 => "SLT",
+        0x13 => "SGT",
+        0x14 => "EQ",
+        0x15 => "ISZERO",
+        0x16 => "AND",
+        0x17 => "OR",
+        0x18 => "XOR",
+        0x19 => "NOT",
+        0x1a => "BYTE",
+        0x1b => "SHL",
+        0x1c => "SHR",
+        0x1d => "SAR",
+        0x20 => "KECCAK256",
+        0x30 => "ADDRESS",
+        0x31 => "BALANCE",
+        0x32 => "ORIGIN",
+        0x33 => "CALLER",
+        0x34 => "CALLVALUE",
+        0x35 => "CALLDATALOAD",
+        0x36 => "CALLDATASIZE",
+        0x37 => "CALLDATACOPY",
+        0x38 => "CODESIZE",
+        0x39 => "CODECOPY",
+        0x3a => "GASPRICE",
+        0x3b => "EXTCODESIZE",
+        0x3c => "EXTCODECOPY",
+        0x3d => "RETURNDATASIZE",
+        0x3e => "RETURNDATACOPY",
+        0x3f => "EXTCODEHASH",
+        0x40 => "BLOCKHASH",
+        0x41 => "COINBASE",
+        0x42 => "TIMESTAMP",
+        0x43 => "NUMBER",
+        0x44 => "PREVRANDAO",
+        0x45 => "GASLIMIT",
+        0x46 => "CHAINID",
+        0x47 => "SELFBALANCE",
+        0x48 => "BASEFEE",
+        0x49 => "BLOBHASH",
+        0x4a => "BLOBBASEFEE",
+        0x50 => "POP",
+        0x51 => "MLOAD",
+        0x52 => "MSTORE",
+        0x53 => "MSTORE8",
+        0x54 => "SLOAD",
+        0x55 => "SSTORE",
+        0x56 => "JUMP",
+        0x57 => "JUMPI",
+        0x58 => "PC",
+        0x59 => "MSIZE",
+        0x5a => "GAS",
+        0x5b => "JUMPDEST",
+        0x5c => "TLOAD",
+        0x5d => "TSTORE",
+        0x5e => "MCOPY",
+        0x5f => "PUSH0",
+        0x60..=0x7f => return Some(format!("PUSH{}", opcode - 0x5f)),
+        0x80..=0x8f => return Some(format!("DUP{}", opcode - 0x7f)),
+        0x90..=0x9f => return Some(format!("SWAP{}", opcode - 0x8f)),
+        0xa0..=0xa4 => return Some(format!("LOG{}", opcode - 0xa0)),
+        0xf0 => "CREATE",
+        0xf1 => "CALL",
+        0xf2 => "CALLCODE",
+        0xf3 => "RETURN",
+        0xf4 => "DELEGATECALL",
+        0xf5 => "CREATE2",
+        0xfa => "STATICCALL",
+        0xfd => "REVERT",
+        0xfe => "INVALID",
+        0xff => "SELFDESTRUCT",
+        _ => return None,
+    };
+
+    Some(name.to_string())
+}
+
+/// Returns the opcodes of some bytecode, skipping the immediate data of the
+/// `PUSH` instructions. As this is a static scan, data appended to the code is
+/// also interpreted as opcodes.
+pub fn code_opcodes(code: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    let mut pc = 0;
+    std::iter::from_fn(move || {
+        let opcode = *code.get(pc)?;
+        pc += 1;
+        if (0x60..=0x7f).contains(&opcode) {
+            pc += (opcode - 0x5f) as usize;
+        }
+
+        Some(opcode)
+    })
+}
+
+/// Returns the init code of a signed transaction if it is a contract creation.
+pub fn create_txn_init_code(signed_txn: &[u8]) -> Option<Vec<u8>> {
+    // The index of the `to` field in the RLP list of each transaction type. The
+    // value and data fields follow it.
+    let (payload, to_idx) = match *signed_txn.first()? {
+        0x01 => (&signed_txn[1..], 4),
+        0x02 => (&signed_txn[1..], 5),
+        b if b >= 0xc0 => (signed_txn, 3),
+        // Other transaction types can't create contracts.
+        _ => return None,
+    };
+
+    let rlp = Rlp::new(payload);
+    let to: Vec<u8> = rlp.val_at(to_idx).ok()?;
+
+    match to.is_empty() {
+        true => rlp.val_at(to_idx + 2).ok(),
+        false => None,
+    }
+}
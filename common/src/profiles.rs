@@ -0,0 +1,95 @@
+//! Named resource profiles (`--profile`), bundling the defaults of the parser
+//! and the runner for common machine classes, so that flags tuned for a large
+//! server don't get copied onto a laptop.
+//!
+//! Arguments passed explicitly (on the command line or in the config file of
+//! the runner) take precedence over the ones of the profile.
+
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Profile {
+    /// A developer machine, with a few cores and 16 GiB of RAM or so.
+    Laptop,
+    /// A CI runner, with tens of GiB of RAM.
+    Ci,
+    /// A large proving server, with hundreds of GiB of RAM.
+    Server,
+}
+
+/// The defaults of a profile. `None` keeps the default of the argument.
+#[derive(Clone, Copy, Debug)]
+pub struct ProfileDefaults {
+    /// The timeout of each test.
+    pub test_timeout: Option<Duration>,
+    /// The max CPU log length of each segment.
+    pub max_cpu_log_len: Option<usize>,
+    /// The number of tests run in parallel by the runner.
+    pub jobs: usize,
+    /// The number of test files parsed in parallel by the parser.
+    pub parse_jobs: usize,
+    /// The memory budget (in GiB) of the tests running in parallel.
+    pub memory_limit_gb: Option<f64>,
+}
+
+impl Profile {
+    const ALL: [Self; 3] = [Self::Laptop, Self::Ci, Self::Server];
+
+    pub fn defaults(self) -> ProfileDefaults {
+        match self {
+            Self::Laptop => ProfileDefaults {
+                test_timeout: Some(Duration::from_secs(10 * 60)),
+                max_cpu_log_len: Some(20),
+                jobs: 1,
+                parse_jobs: 4,
+                memory_limit_gb: Some(12.0),
+            },
+            Self::Ci => ProfileDefaults {
+                test_timeout: Some(Duration::from_secs(30 * 60)),
+                max_cpu_log_len: Some(22),
+                jobs: 2,
+                parse_jobs: 16,
+                memory_limit_gb: Some(48.0),
+            },
+            // The whole machine is available.
+            Self::Server => ProfileDefaults {
+                test_timeout: Some(Duration::from_secs(3 * 60 * 60)),
+                max_cpu_log_len: None,
+                jobs: 8,
+                parse_jobs: 64,
+                memory_limit_gb: None,
+            },
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Laptop => "laptop",
+            Self::Ci => "ci",
+            Self::Server => "server",
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|p| p.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown profile \"{}\", expected one of: {}",
+                    s,
+                    Self::ALL.map(Self::name).join(", ")
+                )
+            })
+    }
+}
@@ -0,0 +1,153 @@
+//! Types for the input/output files of the `t8n` (state transition) tool
+//! shipped by execution clients (eg. geth's `evm t8n`).
+//!
+//! See <https://github.com/ethereum/go-ethereum/tree/master/cmd/evm> for the
+//! file formats.
+
+use std::collections::{BTreeMap, HashMap};
+
+use ethereum_types::{Address, H256, U256};
+use evm_arithmetization::proof::{BlockHashes, BlockMetadata};
+use rlp::RlpStream;
+use serde::{Deserialize, Serialize};
+
+use crate::types::AccountState;
+
+/// An account entry of `alloc.json`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct T8nAccount {
+    pub balance: U256,
+    pub nonce: U256,
+    pub code: String,
+    pub storage: BTreeMap<U256, U256>,
+}
+
+/// The content of `alloc.json`.
+pub type T8nAlloc = BTreeMap<Address, T8nAccount>;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct T8nWithdrawal {
+    pub index: U256,
+    pub validator_index: U256,
+    pub address: Address,
+    pub amount: U256,
+}
+
+/// The content of `env.json`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct T8nEnv {
+    pub current_coinbase: Address,
+    #[serde(skip_serializing_if = "U256::is_zero", default)]
+    pub current_difficulty: U256,
+    pub current_random: H256,
+    pub current_gas_limit: U256,
+    pub current_number: U256,
+    pub current_timestamp: U256,
+    pub current_base_fee: U256,
+    pub current_excess_blob_gas: U256,
+    pub parent_beacon_block_root: H256,
+    pub withdrawals: Vec<T8nWithdrawal>,
+    /// The hashes of the blocks before the current one, by number, which the
+    /// `BLOCKHASH` opcode returns.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub block_hashes: BTreeMap<U256, H256>,
+}
+
+/// The subset of `result.json` that we care about.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct T8nResult {
+    pub state_root: H256,
+    pub tx_root: H256,
+    pub receipts_root: H256,
+    pub gas_used: U256,
+}
+
+pub fn t8n_alloc(pre_state: &HashMap<Address, AccountState>) -> T8nAlloc {
+    pre_state
+        .iter()
+        .map(|(addr, acc)| {
+            (
+                *addr,
+                T8nAccount {
+                    balance: acc.balance,
+                    nonce: acc.nonce.into(),
+                    code: prefixed_hex(&acc.code),
+                    storage: acc.storage.iter().map(|(k, v)| (*k, *v)).collect(),
+                },
+            )
+        })
+        .collect()
+}
+
+pub fn t8n_env(
+    block_metadata: &BlockMetadata,
+    withdrawals: &[(Address, U256)],
+    block_hashes: &BlockHashes,
+) -> T8nEnv {
+    // The previous hashes end with the parent of the block, and are zero
+    // before the first block of the chain.
+    let num_prev_hashes = block_hashes.prev_hashes.len();
+    T8nEnv {
+        current_coinbase: block_metadata.block_beneficiary,
+        current_difficulty: block_metadata.block_difficulty,
+        current_random: block_metadata.block_random,
+        current_gas_limit: block_metadata.block_gaslimit,
+        current_number: block_metadata.block_number,
+        current_timestamp: block_metadata.block_timestamp,
+        current_base_fee: block_metadata.block_base_fee,
+        current_excess_blob_gas: block_metadata.block_excess_blob_gas,
+        parent_beacon_block_root: block_metadata.parent_beacon_block_root,
+        withdrawals: withdrawals
+            .iter()
+            .enumerate()
+            .map(|(i, (address, amount))| T8nWithdrawal {
+                index: i.into(),
+                validator_index: U256::zero(),
+                address: *address,
+                amount: *amount,
+            })
+            .collect(),
+        block_hashes: block_hashes
+            .prev_hashes
+            .iter()
+            .enumerate()
+            .filter(|(_, hash)| !hash.is_zero())
+            .filter_map(|(i, hash)| {
+                let num_blocks_back = num_prev_hashes - i;
+                Some((
+                    block_metadata
+                        .block_number
+                        .checked_sub(num_blocks_back.into())?,
+                    *hash,
+                ))
+            })
+            .collect(),
+    }
+}
+
+/// Encodes the transactions into the `txs.rlp` format, which is a JSON string
+/// of the hex encoded RLP list of transactions.
+pub fn t8n_txs_rlp(signed_txns: &[Vec<u8>]) -> String {
+    let mut stream = RlpStream::new_list(signed_txns.len());
+    for txn in signed_txns {
+        match txn.first() {
+            // Legacy transactions are RLP lists themselves, while typed ones are
+            // wrapped in a byte string.
+            Some(b) if *b >= 0xc0 => {
+                stream.append_raw(txn, 1);
+            }
+            _ => {
+                stream.append(txn);
+            }
+        }
+    }
+
+    format!("\"{}\"", prefixed_hex(&stream.out()))
+}
+
+fn prefixed_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
@@ -1,33 +1,95 @@
 use std::{
     collections::HashMap,
+    iter, mem,
     ops::RangeInclusive,
     str::{FromStr, Split},
+    sync::Arc,
 };
 
 use anyhow::{anyhow, Context};
 use ethereum_types::{Address, H256, U256};
 use evm_arithmetization::{generation::TrieInputs, proof::BlockMetadata};
 use evm_arithmetization::{
-    proof::{BlockHashes, TrieRoots},
-    GenerationInputs,
+    proof::{consolidate_hashes, BlockHashes, TrieRoots},
+    GenerationInputs, EMPTY_CONSOLIDATED_BLOCKHASH,
 };
+use mpt_trie::partial_trie::PartialTrie;
 use plonky2::{
     field::{goldilocks_field::GoldilocksField, types::Field},
-    hash::hash_types::NUM_HASH_OUT_ELTS,
+    hash::{hash_types::NUM_HASH_OUT_ELTS, poseidon::PoseidonHash},
 };
 use serde::{Deserialize, Serialize};
 
+/// The version of the format of [`ParsedTestManifest`], to bump whenever it
+/// changes in a way that older runners can't read:
+/// - 0: manifests written before the version was recorded. Their variants may
+///   be stored inline rather than in sections (see
+///   [`crate::variant_sections`]), and the fields added since then are
+///   defaulted.
+/// - 1: the version is recorded.
+/// - 2: variants with identical generation inputs are stored once, along with
+///   the names of the others (see [`Plonky2ParsedTest::aliases`]).
+/// - 3: the hashes of the blocks before the first one are recorded (see
+///   [`Plonky2ParsedTest::block_hashes`]).
+/// - 4: the accounts deleted by each block are recorded (see
+///   [`TestMetadata::deleted_accounts`]).
+/// - 5: the state after each block is recorded (see
+///   [`TestMetadata::post_state`]).
+/// - 6: the outcome of the transaction of each block is recorded (see
+///   [`TestMetadata::txn_outcome`]).
+/// - 7: the logs bloom of each block computed with `revm` is recorded (see
+///   [`TestMetadata::logs_bloom`]).
+pub const MANIFEST_SCHEMA_VERSION: u32 = 7;
+
+/// The oldest manifest version that can still be read.
+const MIN_MANIFEST_SCHEMA_VERSION: u32 = 0;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ParsedTestManifest {
+    /// Serialized first, so that readers can check it before the variants
+    /// (see [`check_manifest_schema_version`]).
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Serialized as one section per variant so that variants can be skipped
+    /// when reading (see [`ParsedTestManifest::read_filtered_variants`]).
+    #[serde(with = "crate::variant_sections")]
     pub plonky2_variants: Vec<Plonky2ParsedTest>,
+    /// The EIPs and features covered by the test (see
+    /// [`crate::eip_tags`]).
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Fails if a manifest with the given version can't be read, in which case the
+/// tests need to be parsed again.
+pub fn check_manifest_schema_version(version: u32) -> anyhow::Result<()> {
+    match version {
+        MIN_MANIFEST_SCHEMA_VERSION..=MANIFEST_SCHEMA_VERSION => Ok(()),
+        _ => Err(anyhow!(
+            "The manifest has schema version {}, but only versions {} to {} are supported",
+            version,
+            MIN_MANIFEST_SCHEMA_VERSION,
+            MANIFEST_SCHEMA_VERSION
+        )),
+    }
 }
 
 pub struct FilteredVariantsOutput {
     pub variants: Vec<TestVariantRunInfo>,
     pub tot_variants_without_filter: usize,
+    pub tags: Vec<String>,
 }
 
 impl ParsedTestManifest {
+    /// The number of variants of the test, including the aliases of those
+    /// stored.
+    pub fn num_variants(&self) -> usize {
+        self.plonky2_variants
+            .iter()
+            .map(|variant| 1 + variant.aliases.len())
+            .sum()
+    }
+
     pub fn into_filtered_variants(
         self,
         v_filter: Option<VariantFilterType>,
@@ -38,74 +100,279 @@ impl ParsedTestManifest {
             .plonky2_variants
             .into_iter()
             .enumerate()
-            .filter(|(variant_idx, _)| match &v_filter {
-                Some(VariantFilterType::Single(v)) => variant_idx == v,
-                Some(VariantFilterType::Range(r)) => r.contains(variant_idx),
-                None => true,
-            })
-            .map(|(variant_idx, t_var)| {
-                let trie_roots_after = TrieRoots {
-                    state_root: t_var.final_roots.state_root_hash,
-                    transactions_root: t_var.final_roots.txn_trie_root_hash,
-                    receipts_root: t_var.final_roots.receipts_trie_root_hash,
-                };
-                let gen_inputs = GenerationInputs {
-                    signed_txns: vec![t_var.txn_bytes],
-                    tries: t_var.plonky2_metadata.tries.clone(),
-                    trie_roots_after,
-                    checkpoint_state_trie_root: t_var.plonky2_metadata.genesis_state_root,
-                    contract_code: t_var.plonky2_metadata.contract_code.clone(),
-                    block_metadata: t_var.plonky2_metadata.block_metadata.clone(),
-                    txn_number_before: U256::zero(),
-                    gas_used_before: U256::zero(),
-                    gas_used_after: t_var.plonky2_metadata.block_metadata.block_gas_used,
-                    withdrawals: t_var.plonky2_metadata.withdrawals,
-                    block_hashes: BlockHashes::default(),
-                    ger_data: None,
-                    burn_addr: None,
-                    checkpoint_consolidated_hash: [GoldilocksField::ZERO; NUM_HASH_OUT_ELTS],
-                };
-
-                TestVariantRunInfo {
-                    variant_name: t_var.test_name,
-                    gen_inputs,
-                    final_roots: t_var.final_roots,
-                    variant_idx,
-                }
-            })
+            .filter(|(variant_idx, _)| v_filter.as_ref().is_none_or(|f| f.contains(*variant_idx)))
+            .map(|(variant_idx, t_var)| t_var.into_run_info(variant_idx))
             .collect();
 
         FilteredVariantsOutput {
             variants,
             tot_variants_without_filter,
+            tags: self.tags,
         }
     }
+
+    /// Reads the variants matching the filter from a CBOR manifest, without
+    /// deserializing the other ones.
+    pub fn read_filtered_variants(
+        bytes: &[u8],
+        v_filter: Option<&VariantFilterType>,
+    ) -> anyhow::Result<FilteredVariantsOutput> {
+        let (variants, tot_variants_without_filter, tags) =
+            crate::variant_sections::deserialize_manifest_filtered(bytes, v_filter)?;
+
+        Ok(FilteredVariantsOutput {
+            variants: variants
+                .into_iter()
+                .map(|(variant_idx, t_var)| t_var.into_run_info(variant_idx))
+                .collect(),
+            tot_variants_without_filter,
+            tags,
+        })
+    }
 }
 
 /// A parsed Ethereum test that is ready to be fed into `Plonky2`.
 ///
 /// Note that for our runner we break any txn "variants" (see `indexes` under https://ethereum-tests.readthedocs.io/en/latest/test_types/gstate_tests.html#post-section) into separate sub-tests when running. This is because we don't want a single sub-test variant to cause the entire test to fail (we just want the variant to fail).
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Plonky2ParsedTest {
     pub test_name: String,
 
+    /// Empty for blocks without a transaction (eg. that only process
+    /// withdrawals).
     pub txn_bytes: Vec<u8>,
     pub final_roots: ExpectedFinalRoots,
 
     /// All the metadata needed to prove the transaction in the `test_variant`.
     pub plonky2_metadata: TestMetadata,
+
+    /// The hashes of the blocks before the first one (its parent, and the
+    /// blocks before it if the test gives them), and its own hash.
+    #[serde(default)]
+    pub block_hashes: BlockHashes,
+
+    /// The blocks following the first one, for tests made of several blocks.
+    #[serde(default)]
+    pub chained_blocks: Vec<ParsedChainedBlock>,
+
+    /// The EOF containers of the test, only recorded when parsing its EOF
+    /// variants (see `--eof`).
+    #[serde(default)]
+    pub eof_containers: Vec<EofContainerInfo>,
+
+    /// The other variants of the test whose generation inputs are identical to
+    /// this one, which aren't stored. The result of this variant is theirs too.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+/// The validation metadata of the code of an account holding an EOF container
+/// (see EIP-3540), before or after the test.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EofContainerInfo {
+    pub address: Address,
+    /// Why the container is invalid (see EIP-3670), if it is.
+    pub validation_error: Option<String>,
+    pub num_code_sections: usize,
+    pub num_subcontainers: usize,
+    pub data_size: usize,
+}
+
+/// A block of a multi-block test following the first one. Its pre-state is the
+/// post-state of the block before it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParsedChainedBlock {
+    /// Empty for blocks without a transaction.
+    pub txn_bytes: Vec<u8>,
+    pub final_roots: ExpectedFinalRoots,
+    pub plonky2_metadata: TestMetadata,
+    /// The hashes of the blocks of the test before this one, and its own hash.
+    pub block_hashes: BlockHashes,
+}
+
+impl Plonky2ParsedTest {
+    /// The signed transactions of the first block.
+    pub fn signed_txns(&self) -> Vec<Vec<u8>> {
+        signed_txns(self.txn_bytes.clone())
+    }
+
+    pub fn into_run_info(mut self, variant_idx: usize) -> TestVariantRunInfo {
+        let pre_state = Arc::new(mem::take(&mut self.plonky2_metadata.pre_state));
+        let post_state = Arc::new(mem::take(&mut self.plonky2_metadata.post_state));
+        let txn_outcome = self.plonky2_metadata.txn_outcome;
+        let logs_bloom = self.plonky2_metadata.logs_bloom;
+        let first_block_hashes = self.block_hashes;
+        let checkpoint = Checkpoint::before(&self.plonky2_metadata, &first_block_hashes);
+        let gen_inputs = block_gen_inputs(
+            self.txn_bytes,
+            &self.final_roots,
+            self.plonky2_metadata,
+            first_block_hashes,
+            &checkpoint,
+        );
+
+        TestVariantRunInfo {
+            variant_name: self.test_name,
+            aliases: self.aliases,
+            gen_inputs: Arc::new(gen_inputs),
+            final_roots: self.final_roots,
+            variant_idx,
+            pre_state,
+            post_state,
+            txn_outcome,
+            logs_bloom,
+            chained_blocks: self
+                .chained_blocks
+                .into_iter()
+                .map(|mut block| ChainedBlockRunInfo {
+                    post_state: Arc::new(mem::take(&mut block.plonky2_metadata.post_state)),
+                    txn_outcome: block.plonky2_metadata.txn_outcome,
+                    logs_bloom: block.plonky2_metadata.logs_bloom,
+                    gen_inputs: Arc::new(block_gen_inputs(
+                        block.txn_bytes,
+                        &block.final_roots,
+                        block.plonky2_metadata,
+                        block.block_hashes,
+                        &checkpoint,
+                    )),
+                    final_roots: block.final_roots,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The checkpoint the blocks of a test are proven against, shared by all of
+/// them: the state before their first block, along with the hashes of the
+/// blocks before it.
+struct Checkpoint {
+    state_root: H256,
+    consolidated_hash: [GoldilocksField; NUM_HASH_OUT_ELTS],
+}
+
+impl Checkpoint {
+    fn before(first_block: &TestMetadata, block_hashes: &BlockHashes) -> Self {
+        let consolidated_hash = match block_hashes.prev_hashes.is_empty() {
+            true => EMPTY_CONSOLIDATED_BLOCKHASH.map(GoldilocksField::from_canonical_u64),
+            false => consolidate_hashes::<PoseidonHash, _>(&block_hashes.prev_hashes),
+        };
+
+        Self {
+            // Not the state root of the genesis block of the test, which lacks
+            // the accounts added by the parser.
+            state_root: first_block.tries.state_trie.hash(),
+            consolidated_hash,
+        }
+    }
 }
 
-#[derive(Debug)]
+fn block_gen_inputs(
+    txn_bytes: Vec<u8>,
+    final_roots: &ExpectedFinalRoots,
+    metadata: TestMetadata,
+    block_hashes: BlockHashes,
+    checkpoint: &Checkpoint,
+) -> GenerationInputs {
+    let TestMetadata {
+        tries,
+        contract_code,
+        block_metadata,
+        withdrawals,
+        ..
+    } = metadata;
+
+    let trie_roots_after = TrieRoots {
+        state_root: final_roots.state_root_hash,
+        transactions_root: final_roots.txn_trie_root_hash,
+        receipts_root: final_roots.receipts_trie_root_hash,
+    };
+    GenerationInputs {
+        signed_txns: signed_txns(txn_bytes),
+        tries,
+        trie_roots_after,
+        checkpoint_state_trie_root: checkpoint.state_root,
+        contract_code,
+        txn_number_before: U256::zero(),
+        gas_used_before: U256::zero(),
+        gas_used_after: block_metadata.block_gas_used,
+        block_metadata,
+        withdrawals,
+        block_hashes,
+        ger_data: None,
+        burn_addr: None,
+        checkpoint_consolidated_hash: checkpoint.consolidated_hash,
+    }
+}
+
+fn signed_txns(txn_bytes: Vec<u8>) -> Vec<Vec<u8>> {
+    match txn_bytes.is_empty() {
+        true => Vec::new(),
+        false => vec![txn_bytes],
+    }
+}
+
+/// A test variant ready to be run.
+///
+/// The generation inputs and pre-state are behind `Arc`s, so that cloning a
+/// variant (eg. to keep it for a reproduction bundle) doesn't copy its tries.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct TestVariantRunInfo {
     pub variant_name: String,
+    /// The variants with identical inputs, to which the result of this one is
+    /// attributed.
+    #[serde(default)]
+    pub aliases: Vec<String>,
 
-    pub gen_inputs: GenerationInputs,
+    /// The inputs of the first block of the test.
+    pub gen_inputs: Arc<GenerationInputs>,
     pub final_roots: ExpectedFinalRoots,
     pub variant_idx: usize,
+    pub pre_state: Arc<HashMap<Address, AccountState>>,
+    /// The state after the first block, empty for manifests that don't record
+    /// it.
+    #[serde(default)]
+    pub post_state: Arc<HashMap<Address, AccountState>>,
+    /// The outcome of the transaction of the first block.
+    #[serde(default)]
+    pub txn_outcome: Option<TxnOutcome>,
+    /// The logs bloom of the first block as computed with `revm`.
+    #[serde(default)]
+    pub logs_bloom: Option<[U256; 8]>,
+    /// The blocks to prove after the first one, in order.
+    #[serde(default)]
+    pub chained_blocks: Vec<ChainedBlockRunInfo>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl TestVariantRunInfo {
+    /// The gas used by all the blocks of the test. Blocks whose gas counters
+    /// are inconsistent (which the prover rejects anyway) count as using none.
+    pub fn gas_used(&self) -> u64 {
+        iter::once(&self.gen_inputs)
+            .chain(self.chained_blocks.iter().map(|b| &b.gen_inputs))
+            .map(|inputs| {
+                inputs
+                    .gas_used_after
+                    .saturating_sub(inputs.gas_used_before)
+                    .low_u64()
+            })
+            .fold(0, u64::saturating_add)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChainedBlockRunInfo {
+    pub gen_inputs: Arc<GenerationInputs>,
+    pub final_roots: ExpectedFinalRoots,
+    /// The state after the block, empty for manifests that don't record it.
+    #[serde(default)]
+    pub post_state: Arc<HashMap<Address, AccountState>>,
+    #[serde(default)]
+    pub txn_outcome: Option<TxnOutcome>,
+    #[serde(default)]
+    pub logs_bloom: Option<[U256; 8]>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ExpectedFinalRoots {
     /// The root hash of the expected final state trie.
     pub state_root_hash: H256,
@@ -115,13 +382,57 @@ pub struct ExpectedFinalRoots {
     pub receipts_trie_root_hash: H256,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct TestMetadata {
     pub tries: TrieInputs,
     pub genesis_state_root: H256,
     pub contract_code: HashMap<H256, Vec<u8>>,
     pub block_metadata: BlockMetadata,
     pub withdrawals: Vec<(Address, U256)>,
+    /// The pre-state accounts keyed by address, for backends that cannot
+    /// consume hashed tries directly (eg. `evm t8n`).
+    #[serde(default)]
+    pub pre_state: HashMap<Address, AccountState>,
+    /// The sender of the transaction of the block, recovered from its
+    /// signature.
+    #[serde(default)]
+    pub txn_sender: Option<Address>,
+    /// The accounts of the pre-state that no longer exist after the block,
+    /// sorted: those that self-destructed, and the empty ones that the block
+    /// touched (see EIP-161).
+    #[serde(default)]
+    pub deleted_accounts: Vec<Address>,
+    /// The accounts after the block keyed by address, against which the
+    /// accounts the zkEVM ends up with can be checked (see
+    /// `--check-post-state`).
+    #[serde(default)]
+    pub post_state: HashMap<Address, AccountState>,
+    /// The outcome of the transaction of the block, as executed with `revm`.
+    /// `None` for blocks without a transaction, or whose execution failed.
+    #[serde(default)]
+    pub txn_outcome: Option<TxnOutcome>,
+    /// The logs bloom of the block as computed with `revm`, split in words like
+    /// the one of [`BlockMetadata`], which is taken from the header. `None` if
+    /// the block couldn't be executed.
+    #[serde(default)]
+    pub logs_bloom: Option<[U256; 8]>,
+}
+
+/// The outcome of a transaction, which its receipt records.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TxnOutcome {
+    /// Whether the transaction succeeded, rather than reverted or halted (the
+    /// status of its receipt).
+    pub success: bool,
+    pub gas_used: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AccountState {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code: Vec<u8>,
+    pub storage: HashMap<U256, U256>,
 }
 
 #[derive(Clone, Debug)]
@@ -146,6 +457,13 @@ impl FromStr for VariantFilterType {
 }
 
 impl VariantFilterType {
+    pub fn contains(&self, variant_idx: usize) -> bool {
+        match self {
+            Self::Single(v) => *v == variant_idx,
+            Self::Range(r) => r.contains(&variant_idx),
+        }
+    }
+
     fn from_str_intern(s: &str) -> anyhow::Result<Self> {
         // Did we get passed a single value?
         if let Ok(v) = s.parse::<usize>() {
@@ -178,3 +496,64 @@ impl VariantFilterType {
         Ok(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ParsedTestManifest, Plonky2ParsedTest, VariantFilterType, MANIFEST_SCHEMA_VERSION,
+    };
+
+    const NUM_VARIANTS: usize = 4;
+
+    fn variant(idx: usize) -> Plonky2ParsedTest {
+        Plonky2ParsedTest {
+            test_name: format!("test_d{}g0v0_Cancun", idx),
+            ..Default::default()
+        }
+    }
+
+    fn read_variants(bytes: &[u8], v_filter: Option<VariantFilterType>) -> Vec<(usize, String)> {
+        let output = ParsedTestManifest::read_filtered_variants(bytes, v_filter.as_ref()).unwrap();
+
+        assert_eq!(output.tot_variants_without_filter, NUM_VARIANTS);
+        assert_eq!(output.tags, ["eip1559"]);
+        output
+            .variants
+            .into_iter()
+            .map(|variant| (variant.variant_idx, variant.variant_name))
+            .collect()
+    }
+
+    #[test]
+    fn read_filtered_variants_works() {
+        let manifest = ParsedTestManifest {
+            schema_version: MANIFEST_SCHEMA_VERSION,
+            plonky2_variants: (0..NUM_VARIANTS).map(variant).collect(),
+            tags: vec!["eip1559".to_string()],
+        };
+        let bytes = serde_cbor::to_vec(&manifest).unwrap();
+
+        let all_variants = read_variants(&bytes, None);
+        assert_eq!(
+            all_variants,
+            (0..NUM_VARIANTS)
+                .map(|idx| (idx, format!("test_d{}g0v0_Cancun", idx)))
+                .collect::<Vec<_>>()
+        );
+
+        let single = read_variants(&bytes, Some(VariantFilterType::Single(2)));
+        assert_eq!(single, [(2, "test_d2g0v0_Cancun".to_string())]);
+
+        let range = read_variants(&bytes, Some(VariantFilterType::Range(1..=2)));
+        assert_eq!(
+            range,
+            [
+                (1, "test_d1g0v0_Cancun".to_string()),
+                (2, "test_d2g0v0_Cancun".to_string())
+            ]
+        );
+
+        let out_of_range = read_variants(&bytes, Some(VariantFilterType::Single(NUM_VARIANTS)));
+        assert!(out_of_range.is_empty());
+    }
+}
@@ -1,4 +1,9 @@
-use flexi_logger::Logger;
+use std::{ffi::OsString, iter, path::Path};
+
+use anyhow::Context;
+use flexi_logger::{FileSpec, Logger, LoggerHandle};
+
+const DEFAULT_LOG_SPEC: &str = "plonky2::util::timing=info";
 
 #[macro_export]
 macro_rules! unwrap_or_continue {
@@ -21,7 +26,52 @@ macro_rules! unwrap_or_return {
 }
 
 pub fn init_env_logger() {
-    let _ = Logger::try_with_env_or_str("plonky2::util::timing=info")
+    let _ = Logger::try_with_env_or_str(DEFAULT_LOG_SPEC)
         .unwrap()
         .start();
 }
+
+/// Initializes the logger with the given verbosity level (`0` only logs the
+/// plonky2 timing trees, `1` is `info`, `2` is `debug` and `3+` is `trace`).
+/// `RUST_LOG` still takes precedence if set.
+///
+/// If a log file is provided, logs are written to a timestamped file (one per
+/// run) derived from it instead of stderr, with a verbosity of at least `info`.
+/// The returned handle must be kept alive for as long as logs are emitted.
+pub fn init_logger(verbosity: u8, log_file: Option<&Path>) -> anyhow::Result<LoggerHandle> {
+    let verbosity = match log_file {
+        Some(_) => verbosity.max(1),
+        None => verbosity,
+    };
+    let spec = match verbosity {
+        0 => DEFAULT_LOG_SPEC,
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+
+    let mut logger = Logger::try_with_env_or_str(spec)?;
+    if let Some(path) = log_file {
+        let file_spec = FileSpec::try_from(path)
+            .with_context(|| format!("Invalid log file path {:?}", path))?
+            .use_timestamp(true);
+        logger = logger.log_to_file(file_spec);
+    }
+
+    logger.start().with_context(|| "Starting the logger")
+}
+
+/// Returns the command line arguments of a binary run as the `subcommand` of
+/// another one (eg. `evm-tests run`), given the `args` following the
+/// subcommand. The binary is named after both, so that its usage and errors
+/// refer to the whole command.
+pub fn subcommand_args(subcommand: &str, args: Vec<OsString>) -> Vec<OsString> {
+    let mut bin_name = std::env::args_os()
+        .next()
+        .and_then(|arg0| Path::new(&arg0).file_name().map(OsString::from))
+        .unwrap_or_default();
+    bin_name.push(" ");
+    bin_name.push(subcommand);
+
+    iter::once(bin_name).chain(args).collect()
+}
@@ -0,0 +1,166 @@
+//! (De)serialization of the variants of a parsed test manifest as separate
+//! sections.
+//!
+//! Each variant is stored as its own CBOR byte string, which is length
+//! prefixed. This lets a reader that only wants some of the variants (eg. with
+//! `--variant-filter`) skip the others without deserializing their tries.
+//!
+//! Manifests written before this change stored the variants inline, and are
+//! still accepted (skipping their variants requires scanning them though).
+
+use std::fmt;
+
+use serde::{
+    de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    ser::{self, SerializeSeq},
+    Deserialize, Deserializer, Serializer,
+};
+
+use crate::types::{check_manifest_schema_version, Plonky2ParsedTest, VariantFilterType};
+
+/// The variants read from a sequence of variants along with their index, and
+/// the total number of variants in the sequence.
+type SeqVariants = (Vec<(usize, Plonky2ParsedTest)>, usize);
+
+/// The variants read from a manifest along with their index, the total number
+/// of variants in the manifest and the tags of the manifest.
+pub(crate) type FilteredVariants = (Vec<(usize, Plonky2ParsedTest)>, usize, Vec<String>);
+
+pub(crate) fn serialize<S: Serializer>(
+    variants: &[Plonky2ParsedTest],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut seq = serializer.serialize_seq(Some(variants.len()))?;
+    for variant in variants {
+        let section = serde_cbor::to_vec(variant).map_err(ser::Error::custom)?;
+        seq.serialize_element(&Section(&section))?;
+    }
+    seq.end()
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Plonky2ParsedTest>, D::Error> {
+    let (variants, _) = VariantsSeed(None).deserialize(deserializer)?;
+    Ok(variants.into_iter().map(|(_, variant)| variant).collect())
+}
+
+/// Deserializes a manifest, only keeping the variants matching the filter.
+pub(crate) fn deserialize_manifest_filtered(
+    bytes: &[u8],
+    v_filter: Option<&VariantFilterType>,
+) -> serde_cbor::Result<FilteredVariants> {
+    let mut deserializer = serde_cbor::Deserializer::from_slice(bytes);
+    let res = deserializer.deserialize_map(ManifestSeed(v_filter))?;
+    deserializer.end()?;
+
+    Ok(res)
+}
+
+struct Section<'a>(&'a [u8]);
+
+impl ser::Serialize for Section<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// A variant, either in its own section or inline.
+struct VariantSection(Plonky2ParsedTest);
+
+impl<'de> Deserialize<'de> for VariantSection {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(VariantSectionVisitor)
+    }
+}
+
+struct VariantSectionVisitor;
+
+impl<'de> Visitor<'de> for VariantSectionVisitor {
+    type Value = VariantSection;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a test variant or a section containing one")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        serde_cbor::from_slice(v)
+            .map(VariantSection)
+            .map_err(E::custom)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        Plonky2ParsedTest::deserialize(de::value::MapAccessDeserializer::new(map))
+            .map(VariantSection)
+    }
+}
+
+struct ManifestSeed<'a>(Option<&'a VariantFilterType>);
+
+impl<'de> Visitor<'de> for ManifestSeed<'_> {
+    type Value = FilteredVariants;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a parsed test manifest")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut variants = None;
+        let mut tags = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "schema_version" => check_manifest_schema_version(map.next_value()?)
+                    .map_err(|err| de::Error::custom(format!("{:#}", err)))?,
+                "plonky2_variants" => variants = Some(map.next_value_seed(VariantsSeed(self.0))?),
+                "tags" => tags = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let (variants, tot_variants) =
+            variants.ok_or_else(|| de::Error::missing_field("plonky2_variants"))?;
+        Ok((variants, tot_variants, tags))
+    }
+}
+
+struct VariantsSeed<'a>(Option<&'a VariantFilterType>);
+
+impl<'de> DeserializeSeed<'de> for VariantsSeed<'_> {
+    type Value = SeqVariants;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for VariantsSeed<'_> {
+    type Value = SeqVariants;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of test variants")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut variants = Vec::new();
+        let mut variant_idx = 0;
+
+        loop {
+            let wanted = self.0.is_none_or(|f| f.contains(variant_idx));
+            let read = match wanted {
+                true => seq
+                    .next_element::<VariantSection>()?
+                    .map(|v| variants.push((variant_idx, v.0))),
+                false => seq.next_element::<IgnoredAny>()?.map(|_| ()),
+            };
+
+            if read.is_none() {
+                break;
+            }
+            variant_idx += 1;
+        }
+
+        Ok((variants, variant_idx))
+    }
+}
@@ -1,6 +1,9 @@
 use std::path::PathBuf;
 
 use clap::Parser;
+use common::{config::ETHEREUM_CHAIN_ID, profiles::Profile};
+
+use crate::{config::Fork, provability::ProvabilityLimits};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about)]
@@ -10,4 +13,121 @@ pub(crate) struct ProgArgs {
     #[arg(short, long, default_value_t = false)]
     /// Allow deserializing without fetching git remote
     pub no_fetch: bool,
+
+    #[arg(long, default_value_t = false)]
+    /// Parse the tests at the commit pinned in `eth_tests.lock` (checking it
+    /// out instead of pulling the latest one), and refuse to parse any other
+    /// commit
+    pub frozen: bool,
+
+    #[arg(long)]
+    /// Additionally export each parsed variant as `alloc.json`, `env.json`
+    /// and `txs.rlp` t8n input files under the given directory
+    pub t8n_out: Option<PathBuf>,
+
+    #[arg(long)]
+    /// Additionally export each parsed variant as the JSON block prover inputs
+    /// of zero-bin (one per block) under the given directory
+    pub prover_input_out: Option<PathBuf>,
+
+    #[arg(long, conflicts_with_all = ["t8n_out", "prover_input_out", "frozen"])]
+    /// Parse the hive fixtures (directories holding a `genesis.json` and a
+    /// `chain.rlp`) in the given directory instead of the Ethereum tests. They
+    /// are written to the `Hive` group, in a sub-group named after the
+    /// directory
+    pub hive: Option<PathBuf>,
+
+    #[arg(long, value_delimiter = ',', conflicts_with = "hive")]
+    /// Only parse the tests of these sub-groups (eg. `stCreate2,stSStore`),
+    /// leaving the parsed tests of the others as they are
+    pub subgroups: Vec<String>,
+
+    #[arg(short = 'f', long)]
+    /// An optional filter to only parse the test files whose path contains the
+    /// given string, as the `--test-filter` of the runner
+    pub test_filter: Option<String>,
+
+    #[arg(long, conflicts_with = "hive")]
+    /// Only parse the test files added or modified upstream since the given
+    /// commit of the tests, leaving the parsed tests of the others as they are
+    pub since: Option<String>,
+
+    #[arg(long, default_value_t = false, conflicts_with_all = ["hive", "prover_input_out"])]
+    /// Also parse the `GeneralStateTests` in the plain state test format that
+    /// have no blockchain test counterpart, rebuilding their block from their
+    /// environment. Their parsed tests are written as if they were blockchain
+    /// tests
+    pub state_tests: bool,
+
+    #[arg(long, value_enum, default_value_t = Fork::Cancun)]
+    /// The fork whose test variants are parsed. Forks prior to the merge
+    /// (Berlin and London) have proof-of-work blocks, possibly with ommers
+    pub fork: Fork,
+
+    #[arg(long, default_value_t = false, conflicts_with = "fork")]
+    /// Parse the variants of the fork introducing EOF instead, recording the
+    /// validation metadata of their EOF containers. The prover doesn't
+    /// support EOF yet, so these are meant for witness-only experiments
+    pub eof: bool,
+
+    #[arg(long, default_value_t = false)]
+    /// Execute the blocks of each variant with revm and skip the variants
+    /// whose headers disagree with it (gas used, logs bloom, base fee, and
+    /// transactions, receipts and withdrawals roots)
+    pub validate_headers: bool,
+
+    #[arg(long, default_value_t = ETHEREUM_CHAIN_ID)]
+    /// The chain id of the parsed tests (eg. 137 for Polygon). With another
+    /// chain id than Ethereum's, each transaction is re-signed for it with a
+    /// test key derived from its sender, whose account is moved to the address
+    /// of the key, and the headers and post-state are recomputed with revm
+    pub chain_id: u64,
+
+    #[arg(long, default_value_t = ProvabilityLimits::default().max_gas_used)]
+    /// Leave out the variants in which a block uses more gas. The prover
+    /// requires it to fit in 32 bits. The variants left out for exceeding a
+    /// limit of the prover are listed in `excluded.json`, along with the
+    /// limit they exceeded
+    pub max_gas_used: u64,
+
+    #[arg(long)]
+    /// Leave out the variants in which an account of the pre-state has more
+    /// code, in bytes
+    pub max_code_size: Option<u64>,
+
+    #[arg(long)]
+    /// Leave out the variants whose pre-state has more storage slots, over all
+    /// its accounts
+    pub max_storage_slots: Option<u64>,
+
+    #[arg(long)]
+    /// A file listing the variants known to be unprovable (stress tests going
+    /// beyond the provable bounds of the zkEVM) to leave out instead of the
+    /// builtin list. Each line leaves out the variants whose name contains it
+    pub unprovable_variants: Option<PathBuf>,
+
+    #[arg(long, default_value_t = false, conflicts_with = "unprovable_variants")]
+    /// Parse the variants listed as unprovable as well
+    pub include_unprovable: bool,
+
+    #[arg(short, long, default_value_t = false)]
+    /// Use a simple progress indicator that relies on `println!`s instead of an
+    /// actual progress bar. In some situations, the more elegant progress bar
+    /// may interfere with stdout/stderr
+    pub simple_progress_indicator: bool,
+
+    #[arg(long)]
+    /// An optional resource profile (`laptop`, `ci` or `server`) providing the
+    /// default number of jobs, sized for the given class of machine
+    pub profile: Option<Profile>,
+
+    #[arg(short, long)]
+    /// The number of test files parsed in parallel. Defaults to the one of the
+    /// `--profile`, or to tokio's limit of blocking threads (512)
+    pub jobs: Option<usize>,
+
+    #[arg(long, default_value_t = 10)]
+    /// The number of largest parsed tests (by CBOR size, number of accounts
+    /// and code size) to list once they are all written. `0` lists none
+    pub num_largest: usize,
 }
@@ -0,0 +1,486 @@
+//! Execution of blocks with `revm`.
+//!
+//! The tests only provide the state before their first block and the one after
+//! their last block, so the state each following block of a multi-block test
+//! starts from is obtained by executing the blocks before it.
+//!
+//! The helpers deriving a state, a receipt and a bloom from the outcome of
+//! `revm` are also used by the fuzzer of the runner, which executes its cases
+//! with `revm` too.
+
+use std::{collections::HashMap, iter};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use common::types::AccountState;
+use ethereum_types::{H160, H256, U256};
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use keccak_hash::keccak;
+use mpt_trie::{
+    nibbles::Nibbles,
+    partial_trie::{HashedPartialTrie, PartialTrie},
+};
+use revm::{
+    db::{AccountState as DbAccountState, CacheDB, EmptyDB},
+    primitives::{
+        AccessListItem, AccountInfo, Address, Bytecode, Bytes, ExecutionResult, Log, SpecId, TxEnv,
+        TxKind, B256, GAS_PER_BLOB, U256 as RU256,
+    },
+    Evm,
+};
+use rlp::{Rlp, RlpStream};
+
+use crate::{
+    config::Fork,
+    deserialize::{Block, BlockHeader},
+    eof::is_eof,
+    txn_signing::{split_txn, verifying_key_address},
+};
+
+/// The address of the beacon roots contract (see EIP-4788).
+const BEACON_ROOTS_ADDRESS: H160 = H160([
+    0x00, 0x0f, 0x3d, 0xf6, 0xd7, 0x32, 0x80, 0x7e, 0xf1, 0x31, 0x9f, 0xb7, 0xb8, 0xbb, 0x85, 0x22,
+    0xd0, 0xbe, 0xac, 0x02,
+]);
+const HISTORY_BUFFER_LENGTH: u64 = 8191;
+const GWEI_TO_WEI: u64 = 1_000_000_000;
+
+/// The outcome of the execution of a block.
+#[derive(Debug)]
+pub(crate) struct ExecutedBlock {
+    pub(crate) state: HashMap<H160, AccountState>,
+    /// The state right after the transaction of the block, before its
+    /// withdrawals are processed.
+    pub(crate) txn_state: HashMap<H160, AccountState>,
+    /// Whether the transaction of the block succeeded.
+    pub(crate) success: bool,
+    pub(crate) gas_used: u64,
+    pub(crate) blob_gas_used: u64,
+    pub(crate) logs: Vec<Log>,
+}
+
+/// The accounts of `pre_state` that are absent from `post_state`, sorted.
+pub fn deleted_accounts(
+    pre_state: &HashMap<H160, AccountState>,
+    post_state: &HashMap<H160, AccountState>,
+) -> Vec<H160> {
+    let mut deleted: Vec<_> = pre_state
+        .keys()
+        .filter(|address| !post_state.contains_key(address))
+        .copied()
+        .collect();
+    deleted.sort();
+    deleted
+}
+
+impl ExecutedBlock {
+    pub(crate) fn logs_bloom(&self) -> [u8; 256] {
+        logs_bloom(&self.logs)
+    }
+
+    /// The receipt of the transaction `txn_bytes` of the block, as stored in
+    /// the receipts trie.
+    pub(crate) fn receipt(&self, txn_bytes: &[u8]) -> Vec<u8> {
+        receipt(txn_bytes, self.success, self.gas_used, &self.logs)
+    }
+}
+
+/// The bloom filter of the addresses and topics of `logs`.
+pub fn logs_bloom(logs: &[Log]) -> [u8; 256] {
+    let mut bloom = [0; 256];
+    let items = logs.iter().flat_map(|log| {
+        iter::once(log.address.as_slice()).chain(log.topics().iter().map(|t| t.as_slice()))
+    });
+    for item in items {
+        let hash = keccak(item);
+        for i in [0, 2, 4] {
+            let bit = (((hash[i] as usize) << 8) | hash[i + 1] as usize) & 2047;
+            bloom[255 - bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    bloom
+}
+
+/// The receipt of the transaction `txn_bytes`, as stored in the receipts trie.
+pub fn receipt(txn_bytes: &[u8], success: bool, gas_used: u64, logs: &[Log]) -> Vec<u8> {
+    let mut stream = RlpStream::new_list(4);
+    stream.append(&(success as u8));
+    stream.append(&gas_used);
+    stream.append(&logs_bloom(logs).as_slice());
+    stream.begin_list(logs.len());
+    for log in logs {
+        stream.begin_list(3);
+        stream.append(&log.address.as_slice());
+        stream.begin_list(log.topics().len());
+        for topic in log.topics() {
+            stream.append(&topic.as_slice());
+        }
+        stream.append(&log.data.data.as_ref());
+    }
+
+    // Typed receipts are prefixed with the type of their transaction.
+    let mut receipt = match txn_bytes.first() {
+        Some(&txn_type) if txn_type < 0xc0 => vec![txn_type],
+        _ => Vec::new(),
+    };
+    receipt.extend(stream.out());
+    receipt
+}
+
+/// The sender of a transaction, recovered from its signature, and what it
+/// needs to own to send it.
+#[derive(Debug)]
+pub(crate) struct TxnSender {
+    pub(crate) address: H160,
+    pub(crate) nonce: u64,
+    /// The value of the transaction, plus its fees at their maximum price.
+    pub(crate) max_cost: U256,
+}
+
+/// Recovers the sender of a signed transaction (of any type).
+pub(crate) fn txn_sender(signed_txn: &[u8]) -> Result<TxnSender> {
+    let tx = txn_env(signed_txn)?;
+    let max_cost = RU256::from(tx.gas_limit)
+        .saturating_mul(tx.gas_price)
+        .saturating_add(
+            RU256::from(tx.blob_hashes.len() as u64 * GAS_PER_BLOB)
+                .saturating_mul(tx.max_fee_per_blob_gas.unwrap_or_default()),
+        )
+        .saturating_add(tx.value);
+
+    Ok(TxnSender {
+        address: H160::from_slice(tx.caller.as_slice()),
+        nonce: tx.nonce.unwrap_or_default(),
+        max_cost: from_revm_u256(max_cost),
+    })
+}
+
+/// The root of a trie of the given values, keyed by their RLP encoded index, as
+/// the transactions, receipts and withdrawals tries.
+pub(crate) fn list_trie_root(values: impl Iterator<Item = Vec<u8>>) -> H256 {
+    let mut trie = HashedPartialTrie::default();
+    for (idx, value) in values.enumerate() {
+        trie.insert(Nibbles::from_bytes_be(&rlp::encode(&idx)).unwrap(), value)
+            .expect("Inserting a new key");
+    }
+
+    trie.hash()
+}
+
+/// Executes `block` on top of `pre_state`, on the chain of `chain_id`.
+pub(crate) fn execute_block(
+    pre_state: &HashMap<H160, AccountState>,
+    block: &Block,
+    fork: Fork,
+    chain_id: u64,
+) -> Result<ExecutedBlock> {
+    let header = &block.block_header;
+    let mut db = state_db(pre_state, fork)?;
+
+    // `revm` doesn't store the beacon root at the start of the block. Like the
+    // system call doing it, this is a no-op without the beacon roots contract.
+    let has_beacon_roots_contract = pre_state
+        .get(&BEACON_ROOTS_ADDRESS)
+        .is_some_and(|acc| !acc.code.is_empty());
+    if fork >= Fork::Cancun && has_beacon_roots_contract {
+        let timestamp = header.timestamp.low_u64();
+        let timestamp_idx = timestamp % HISTORY_BUFFER_LENGTH;
+        let beacon_roots_address = to_revm_address(&BEACON_ROOTS_ADDRESS);
+        db.insert_account_storage(
+            beacon_roots_address,
+            RU256::from(timestamp_idx),
+            RU256::from(timestamp),
+        )?;
+        db.insert_account_storage(
+            beacon_roots_address,
+            RU256::from(timestamp_idx + HISTORY_BUFFER_LENGTH),
+            RU256::from_be_bytes(header.parent_beacon_block_root.0),
+        )?;
+    }
+
+    // Blocks without a transaction only process their withdrawals.
+    let (mut db, result, blob_gas_used) = match block.txn() {
+        Some(txn) => transact(db, txn, header, fork, chain_id)?,
+        None => (db, None, 0),
+    };
+    let txn_state = db_state(&db);
+
+    for withdrawal in block.withdrawals.iter() {
+        let account = db.load_account(to_revm_address(&withdrawal.address))?;
+        account.info.balance += to_revm_u256(withdrawal.amount) * RU256::from(GWEI_TO_WEI);
+        if matches!(account.account_state, DbAccountState::NotExisting) {
+            account.account_state = DbAccountState::Touched;
+        }
+    }
+
+    let state = db_state(&db);
+    let (success, gas_used, logs) = match result {
+        Some(ExecutionResult::Success { gas_used, logs, .. }) => (true, gas_used, logs),
+        Some(ExecutionResult::Revert { gas_used, .. } | ExecutionResult::Halt { gas_used, .. }) => {
+            (false, gas_used, Vec::new())
+        }
+        None => (true, 0, Vec::new()),
+    };
+
+    Ok(ExecutedBlock {
+        state,
+        txn_state,
+        success,
+        gas_used,
+        blob_gas_used,
+        logs,
+    })
+}
+
+/// A `revm` database holding the accounts of `state`, whose code is decoded as
+/// per `fork`.
+pub fn state_db(state: &HashMap<H160, AccountState>, fork: Fork) -> Result<CacheDB<EmptyDB>> {
+    let mut db = CacheDB::new(EmptyDB::default());
+    for (address, account) in state.iter() {
+        let address = to_revm_address(address);
+        let code = Bytes::from(account.code.clone());
+        // Before EOF, code starting with its magic is just invalid legacy code.
+        let bytecode = match fork >= Fork::Osaka && is_eof(&account.code) {
+            true => Bytecode::new_raw_checked(code)
+                .map_err(|err| anyhow!("Decoding the code of {:?}: {}", address, err))?,
+            false => Bytecode::new_legacy(code),
+        };
+        db.insert_account_info(
+            address,
+            AccountInfo::new(
+                to_revm_u256(account.balance),
+                account.nonce,
+                B256::from(keccak(&account.code).0),
+                bytecode,
+            ),
+        );
+        for (key, value) in account.storage.iter() {
+            db.insert_account_storage(address, to_revm_u256(*key), to_revm_u256(*value))?;
+        }
+    }
+
+    Ok(db)
+}
+
+/// The accounts of `db` that exist after the block. Self-destructed accounts
+/// are deleted, and so are the empty accounts touched by the block (see
+/// EIP-161), but not the empty accounts of the pre-state that it left alone.
+pub fn db_state(db: &CacheDB<EmptyDB>) -> HashMap<H160, AccountState> {
+    db.accounts
+        .iter()
+        .filter(|(_, acc)| match acc.account_state {
+            DbAccountState::NotExisting => false,
+            DbAccountState::None => true,
+            DbAccountState::Touched | DbAccountState::StorageCleared => !acc.info.is_empty(),
+        })
+        .map(|(address, acc)| {
+            let code = acc
+                .info
+                .code
+                .as_ref()
+                .map(|code| code.original_bytes().to_vec())
+                .unwrap_or_default();
+            let storage = acc
+                .storage
+                .iter()
+                .filter(|(_, v)| !v.is_zero())
+                .map(|(k, v)| (from_revm_u256(*k), from_revm_u256(*v)))
+                .collect();
+
+            (
+                H160::from_slice(address.as_slice()),
+                AccountState {
+                    balance: from_revm_u256(acc.info.balance),
+                    nonce: acc.info.nonce,
+                    code,
+                    storage,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Executes the transaction `txn` of the block of `header`, returning the
+/// updated database, the outcome of the transaction and its blob gas.
+fn transact(
+    db: CacheDB<EmptyDB>,
+    txn: &[u8],
+    header: &BlockHeader,
+    fork: Fork,
+    chain_id: u64,
+) -> Result<(CacheDB<EmptyDB>, Option<ExecutionResult>, u64)> {
+    let txn_env = txn_env(txn).context("Decoding the transaction")?;
+    let blob_gas_used = txn_env.blob_hashes.len() as u64 * GAS_PER_BLOB;
+    let mut evm = Evm::builder()
+        .with_db(db)
+        .with_spec_id(spec_id(fork))
+        .modify_cfg_env(|cfg| cfg.chain_id = chain_id)
+        .modify_block_env(|block_env| {
+            block_env.number = to_revm_u256(header.number);
+            block_env.coinbase = to_revm_address(&header.coinbase);
+            block_env.timestamp = to_revm_u256(header.timestamp);
+            block_env.gas_limit = to_revm_u256(header.gas_limit);
+            block_env.basefee = to_revm_u256(header.base_fee_per_gas);
+            block_env.difficulty = to_revm_u256(header.difficulty);
+            block_env.prevrandao = (fork >= Fork::Paris).then(|| B256::from(header.mix_hash.0));
+            if fork >= Fork::Cancun {
+                block_env.set_blob_excess_gas_and_price(header.excess_blob_gas.low_u64());
+            }
+        })
+        .modify_tx_env(|tx_env| *tx_env = txn_env)
+        .build();
+
+    let result = evm
+        .transact_commit()
+        .map_err(|err| anyhow!("Executing the transaction with revm: {}", err))?;
+
+    Ok((evm.into_context().evm.inner.db, Some(result), blob_gas_used))
+}
+
+fn spec_id(fork: Fork) -> SpecId {
+    match fork {
+        Fork::Berlin => SpecId::BERLIN,
+        Fork::London => SpecId::LONDON,
+        Fork::Paris => SpecId::MERGE,
+        Fork::Shanghai => SpecId::SHANGHAI,
+        Fork::Cancun => SpecId::CANCUN,
+        Fork::Osaka => SpecId::OSAKA,
+    }
+}
+
+/// Decodes a signed transaction (of any type) into the environment `revm` runs
+/// it in, recovering its sender from its signature.
+fn txn_env(signed_txn: &[u8]) -> Result<TxEnv> {
+    let (txn_type, payload, _) =
+        split_txn(signed_txn).context("Empty transaction or unsupported transaction type")?;
+    let rlp = Rlp::new(payload);
+    let num_fields = rlp.item_count()?;
+    ensure!(
+        num_fields >= 9,
+        "Expected at least 9 fields, got {}",
+        num_fields
+    );
+
+    // All fields are at the same position in every type of transaction, once
+    // shifted by the chain id of typed ones and the fee fields of EIP-1559 ones.
+    let base = txn_type.is_some() as usize;
+    let num_fee_fields = 1 + matches!(txn_type, Some(2) | Some(3)) as usize;
+    let field = |idx: usize| rlp.at(base + idx);
+    let after_fees = |idx: usize| rlp.at(base + num_fee_fields + idx);
+
+    let mut tx = TxEnv {
+        nonce: Some(field(0)?.as_val()?),
+        gas_limit: after_fees(1)?.as_val()?,
+        value: to_revm_u256(after_fees(3)?.as_val()?),
+        data: Bytes::from(after_fees(4)?.as_val::<Vec<u8>>()?),
+        ..Default::default()
+    };
+    let to = after_fees(2)?;
+    tx.transact_to = match to.is_empty() {
+        true => TxKind::Create,
+        false => TxKind::Call(to_revm_address(&to.as_val()?)),
+    };
+    match num_fee_fields {
+        1 => tx.gas_price = to_revm_u256(field(1)?.as_val()?),
+        _ => {
+            tx.gas_priority_fee = Some(to_revm_u256(field(1)?.as_val()?));
+            tx.gas_price = to_revm_u256(field(2)?.as_val()?);
+        }
+    }
+    if txn_type.is_some() {
+        tx.chain_id = Some(rlp.val_at(0)?);
+        tx.access_list = access_list(&after_fees(5)?)?;
+    }
+    if txn_type == Some(3) {
+        tx.max_fee_per_blob_gas = Some(to_revm_u256(after_fees(6)?.as_val()?));
+        tx.blob_hashes = after_fees(7)?
+            .as_list::<H256>()?
+            .into_iter()
+            .map(|hash| B256::from(hash.0))
+            .collect();
+    }
+
+    let v: u64 = rlp.val_at(num_fields - 3)?;
+    let r: U256 = rlp.val_at(num_fields - 2)?;
+    let s: U256 = rlp.val_at(num_fields - 1)?;
+    let unsigned_list = |extra_fields: &[u64]| {
+        let mut stream = RlpStream::new_list(num_fields - 3 + extra_fields.len());
+        for field in rlp.iter().take(num_fields - 3) {
+            stream.append_raw(field.as_raw(), 1);
+        }
+        for field in extra_fields {
+            stream.append(field);
+        }
+        stream.out().to_vec()
+    };
+    let (signing_payload, recovery_id) = match txn_type {
+        // EIP-155.
+        None if v >= 35 => {
+            let chain_id = (v - 35) / 2;
+            tx.chain_id = Some(chain_id);
+            (unsigned_list(&[chain_id, 0, 0]), (v - 35) % 2)
+        }
+        None => (unsigned_list(&[]), v.wrapping_sub(27)),
+        Some(txn_type) => {
+            let mut payload = vec![txn_type];
+            payload.extend(unsigned_list(&[]));
+            (payload, v)
+        }
+    };
+    tx.caller = to_revm_address(&recover_sender(&signing_payload, recovery_id, r, s)?);
+
+    Ok(tx)
+}
+
+fn access_list(rlp: &Rlp) -> Result<Vec<AccessListItem>> {
+    rlp.iter()
+        .map(|item| {
+            // Some tests have badly encoded storage keys, which are shorter than
+            // 32 bytes.
+            let storage_keys = item
+                .at(1)?
+                .iter()
+                .map(|key| {
+                    let key: Vec<u8> = key.as_val()?;
+                    ensure!(key.len() <= 32, "Storage key of {} bytes", key.len());
+                    Ok(B256::left_padding_from(&key))
+                })
+                .collect::<Result<_>>()?;
+
+            Ok(AccessListItem {
+                address: to_revm_address(&item.val_at(0)?),
+                storage_keys,
+            })
+        })
+        .collect()
+}
+
+fn recover_sender(signing_payload: &[u8], recovery_id: u64, r: U256, s: U256) -> Result<H160> {
+    let mut signature = [0; 64];
+    r.to_big_endian(&mut signature[..32]);
+    s.to_big_endian(&mut signature[32..]);
+    let signature = Signature::from_slice(&signature)?;
+    let recovery_id = u8::try_from(recovery_id)
+        .ok()
+        .and_then(RecoveryId::from_byte)
+        .with_context(|| format!("Invalid recovery id {}", recovery_id))?;
+
+    let key = VerifyingKey::recover_from_prehash(
+        keccak(signing_payload).as_bytes(),
+        &signature,
+        recovery_id,
+    )?;
+    Ok(verifying_key_address(&key))
+}
+
+pub fn to_revm_address(address: &H160) -> Address {
+    Address::from_slice(address.as_bytes())
+}
+
+pub fn to_revm_u256(value: U256) -> RU256 {
+    RU256::from_limbs(value.0)
+}
+
+pub fn from_revm_u256(value: RU256) -> U256 {
+    U256(value.into_limbs())
+}
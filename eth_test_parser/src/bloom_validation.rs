@@ -0,0 +1,45 @@
+//! Checks of the logs blooms of the blocks of the tests.
+//!
+//! The logs bloom of a block is taken from its header, and the state and
+//! receipts roots say nothing about whether it's right at the level of the
+//! transaction. A header bloom that differs from the one computed from the
+//! logs of the block with `revm` points at a broken test, or at logs decoded
+//! incorrectly.
+
+use std::{fmt::Write, iter};
+
+use common::types::Plonky2ParsedTest;
+use ethereum_types::U256;
+
+/// Returns a description of each block of `test` whose header bloom differs
+/// from the one computed with `revm`.
+pub(crate) fn logs_bloom_mismatches(test: &Plonky2ParsedTest) -> Vec<String> {
+    let blocks = iter::once(&test.plonky2_metadata).chain(
+        test.chained_blocks
+            .iter()
+            .map(|block| &block.plonky2_metadata),
+    );
+
+    blocks
+        .enumerate()
+        .filter_map(|(block_idx, metadata)| {
+            let header_bloom = metadata.block_metadata.block_bloom;
+            match metadata.logs_bloom {
+                Some(bloom) if bloom != header_bloom => Some(format!(
+                    "Block {}: the logs bloom of the header is {}, but revm computes {}",
+                    block_idx + 1,
+                    bloom_hex(&header_bloom),
+                    bloom_hex(&bloom)
+                )),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn bloom_hex(bloom: &[U256; 8]) -> String {
+    bloom.iter().fold(String::new(), |mut hex, word| {
+        let _ = write!(hex, "{:064x}", word);
+        hex
+    })
+}
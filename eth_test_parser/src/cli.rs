@@ -0,0 +1,434 @@
+//! The command line interface of the parser, wiring its arguments to the rest
+//! of the crate.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use common::{
+    config::{ETH_TESTS_COMMIT_FILE_NAME, MAIN_TEST_DIR},
+    types::ParsedTestManifest,
+    utils::{init_env_logger, subcommand_args},
+};
+use futures::{stream::FuturesOrdered, StreamExt};
+use log::debug;
+
+use crate::arg_parsing::ProgArgs;
+use crate::diagnostics::{Diagnostic, DiagnosticReason, ParseReport, PARSE_REPORT_FILE_NAME};
+use crate::fs_scaffolding::{
+    get_default_out_dir, get_state_test_files, get_test_files, prepare_output_dir,
+};
+use crate::hive::{get_hive_fixtures, HIVE_GROUP};
+use crate::largest::{LargestTests, TestSize};
+use crate::progress::{FileOutcome, ParseProgress};
+use crate::provability::{
+    ExclusionManifest, ProvabilityLimits, UnprovableVariants, EXCLUSION_MANIFEST_FILE_NAME,
+};
+use crate::prover_input_export::write_prover_input_files;
+use crate::t8n_export::write_t8n_files;
+use crate::{
+    config::{Fork, ETH_TESTS_REPO_LOCAL_PATH},
+    eth_tests_fetching::{
+        check_eth_tests_commit, checkout_state_tests, clone_or_update_remote_tests,
+        get_eth_tests_commit, get_test_files_changed_since, read_eth_tests_lock, test_file_key,
+        write_eth_tests_lock,
+    },
+};
+use crate::{
+    parse_hive_fixture, parse_state_test_file, parse_test_file, ParseOptions, ParsedTestFile,
+};
+
+/// Runs the parser as invoked from the command line (see `--help`), which is
+/// all the `eth_test_parser` binary does.
+pub fn run_cli() -> Result<()> {
+    run_with_args(std::env::args_os().collect())
+}
+
+/// Runs the parser as the `subcommand` of another binary (eg. `evm-tests
+/// parse`), given the command line arguments following it.
+pub fn run_cli_as_subcommand(subcommand: &str, args: Vec<OsString>) -> Result<()> {
+    run_with_args(subcommand_args(subcommand, args))
+}
+
+fn run_with_args(args: Vec<OsString>) -> Result<()> {
+    init_env_logger();
+    let p_args = ProgArgs::parse_from(args);
+
+    let mut rt = tokio::runtime::Builder::new_multi_thread();
+    if let Some(jobs) = p_args
+        .jobs
+        .or(p_args.profile.map(|p| p.defaults().parse_jobs))
+    {
+        // Test files are parsed on blocking threads.
+        rt.max_blocking_threads(jobs.max(1));
+    }
+
+    rt.enable_all().build()?.block_on(run(p_args))
+}
+
+async fn run(
+    ProgArgs {
+        no_fetch,
+        frozen,
+        out_path,
+        t8n_out,
+        prover_input_out,
+        hive,
+        subgroups,
+        test_filter,
+        since,
+        state_tests,
+        fork,
+        validate_headers,
+        chain_id,
+        max_gas_used,
+        max_code_size,
+        max_storage_slots,
+        unprovable_variants,
+        include_unprovable,
+        eof,
+        simple_progress_indicator,
+        num_largest,
+        profile: _,
+        jobs: _,
+    }: ProgArgs,
+) -> anyhow::Result<()> {
+    let fork = if eof { Fork::Osaka } else { fork };
+    let options = ParseOptions {
+        validate_headers,
+        chain_id,
+        limits: ProvabilityLimits {
+            max_gas_used,
+            max_code_size,
+            max_storage_slots,
+        },
+        unprovable_variants: match (include_unprovable, unprovable_variants) {
+            (true, _) => UnprovableVariants::default(),
+            (false, Some(path)) => UnprovableVariants::load(&path)?,
+            (false, None) => UnprovableVariants::builtin(),
+        },
+    };
+    let out_path = out_path.map(Ok).unwrap_or_else(get_default_out_dir)?;
+
+    if let Some(hive_dir) = hive {
+        let (report, exclusions) = parse_hive_fixtures(
+            &hive_dir,
+            fork,
+            options.clone(),
+            test_filter.as_deref(),
+            simple_progress_indicator,
+            num_largest,
+            &out_path,
+        )
+        .await?;
+        return write_report(&report, &exclusions, &out_path);
+    }
+
+    let locked_commit = frozen.then(read_eth_tests_lock).transpose()?;
+
+    if !no_fetch {
+        // Fetch the most recent (or locked) test json.
+        clone_or_update_remote_tests(locked_commit.as_deref());
+        if state_tests {
+            checkout_state_tests();
+        }
+
+        // Create output directories mirroring the structure of source tests.
+        prepare_output_dir(&out_path)?;
+    }
+
+    if let Some(locked_commit) = &locked_commit {
+        check_eth_tests_commit(locked_commit)?;
+    }
+
+    println!("Converting test json to plonky2 generation inputs");
+
+    let mut test_files: Vec<_> = get_test_files(&subgroups)?
+        .map(|entry| TestFileSource {
+            path: entry.path(),
+            mirrored_path: entry.path(),
+            parse: parse_test_file,
+        })
+        .collect();
+    if state_tests {
+        // Only the tests missing from the blockchain tests are parsed.
+        test_files.extend(get_state_test_files(&subgroups)?.into_iter().map(
+            |(path, counterpart)| TestFileSource {
+                path,
+                mirrored_path: counterpart,
+                parse: parse_state_test_file,
+            },
+        ));
+    }
+    test_files.retain(|file| in_test_filter(test_filter.as_deref(), &file.path));
+    if let Some(commit) = since {
+        let changed_files =
+            get_test_files_changed_since(Path::new(ETH_TESTS_REPO_LOCAL_PATH), &commit)?;
+        test_files.retain(|file| {
+            test_file_key(&file.path).is_some_and(|key| changed_files.contains(&key))
+        });
+        println!(
+            "{} test files changed upstream since {}",
+            test_files.len(),
+            commit
+        );
+    }
+    let mut progress = ParseProgress::new(test_files.len() as u64, simple_progress_indicator);
+    let generation_input_handles = test_files.into_iter().map(|test_file| {
+        let t8n_out = t8n_out.clone();
+        let prover_input_out = prover_input_out.clone();
+        let options = options.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let test_path = test_file
+                .mirrored_path
+                .strip_prefix(ETH_TESTS_REPO_LOCAL_PATH)
+                .unwrap()
+                .to_path_buf();
+            let (test_manifest, mut diagnostics, exclusions) =
+                match (test_file.parse)(&test_file.path, fork, options) {
+                    Ok(parsed) => (parsed.manifest, parsed.diagnostics, parsed.exclusions),
+                    Err(err) => {
+                        // Skip any errors in parsing a test. As the upstream repo changes, we may
+                        // get tests that start to fail (eg. some tests do not have a `merge`
+                        // field).
+                        let diagnostic = Diagnostic::new(
+                            DiagnosticReason::ParseError,
+                            None,
+                            format!("{:#}", err),
+                        );
+                        return (test_path, None, vec![diagnostic], Vec::new());
+                    }
+                };
+
+            if let Some(t8n_out) = t8n_out {
+                let test_dir = t8n_out.join(test_path.with_extension(""));
+
+                // Identical variants are exported under all their names.
+                for variant in test_manifest.plonky2_variants.iter() {
+                    for name in iter::once(&variant.test_name).chain(variant.aliases.iter()) {
+                        if let Err(err) = write_t8n_files(&test_dir.join(name), variant) {
+                            diagnostics.push(Diagnostic::new(
+                                DiagnosticReason::T8nExportError,
+                                Some(name),
+                                format!("{:#}", err),
+                            ));
+                        }
+                    }
+                }
+            }
+
+            if let Some(prover_input_out) = prover_input_out {
+                let test_dir = prover_input_out.join(test_path.with_extension(""));
+                diagnostics.extend(write_prover_input_files(
+                    &test_file.path,
+                    fork,
+                    &test_manifest,
+                    &test_dir,
+                ));
+            }
+
+            // Files whose variants were all skipped are only in the report.
+            let generation_inputs = (!test_manifest.plonky2_variants.is_empty()).then(|| {
+                let inputs = serde_cbor::to_vec(&test_manifest).unwrap();
+                let size = TestSize::new(&test_manifest, inputs.len());
+                (inputs, test_manifest.num_variants(), size)
+            });
+            (test_path, generation_inputs, diagnostics, exclusions)
+        })
+    });
+    let mut generation_input_handles: FuturesOrdered<_> = generation_input_handles.collect();
+
+    println!(
+        "Writing plonky2 generation input cbor to disk, {:?}",
+        out_path.as_os_str()
+    );
+
+    let mut report = ParseReport::default();
+    let mut excluded = ExclusionManifest::default();
+    let mut largest_tests = LargestTests::new(num_largest);
+    while let Some(thread) = generation_input_handles.next().await {
+        let (test_path, generation_inputs, diagnostics, exclusions) = thread.unwrap();
+        for diagnostic in diagnostics.iter() {
+            debug!("{:?}: {:?}", test_path, diagnostic);
+        }
+        let num_variants = generation_inputs.as_ref().map_or(0, |(_, n, _)| *n);
+        let outcome = FileOutcome::new(num_variants, &diagnostics);
+        report.record_file(&test_path.to_string_lossy(), num_variants, diagnostics);
+        excluded.record(&test_path.to_string_lossy(), exclusions);
+
+        let bytes_written = match generation_inputs {
+            Some((generation_inputs, _, size)) => {
+                let mut path = out_path.join(&test_path);
+                path.set_extension("cbor");
+                // State tests may be in sub-groups without blockchain tests.
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                let mut file = File::create(path).unwrap();
+                file.write_all(&generation_inputs).unwrap();
+                largest_tests.record(&test_path.to_string_lossy(), size);
+                generation_inputs.len()
+            }
+            None => 0,
+        };
+        progress.record_file(
+            &file_name(test_path.parent()),
+            &file_name(Some(&test_path)),
+            outcome,
+            bytes_written,
+        );
+    }
+    progress.finish();
+    largest_tests.print();
+
+    // Record the tests commit so that the runner reports can refer to it, and
+    // so that later runs can be pinned to it.
+    match get_eth_tests_commit() {
+        Ok(commit) => {
+            std::fs::write(out_path.join(ETH_TESTS_COMMIT_FILE_NAME), &commit)?;
+            write_eth_tests_lock(&commit)?;
+        }
+        Err(err) => report.record(
+            "",
+            vec![Diagnostic::new(
+                DiagnosticReason::UnknownTestsCommit,
+                None,
+                format!("{:#}", err),
+            )],
+        ),
+    }
+
+    write_report(&report, &excluded, &out_path)
+}
+
+/// A test file to parse.
+struct TestFileSource {
+    path: PathBuf,
+    /// The path of the blockchain test whose layout the parsed test mirrors,
+    /// which is the test file itself unless it is a state test.
+    mirrored_path: PathBuf,
+    parse: fn(&Path, Fork, ParseOptions) -> Result<ParsedTestFile>,
+}
+
+/// Parses the hive fixtures in `hive_dir` into the `Hive` group of `out_path`,
+/// in a sub-group named after `hive_dir`, returning the parse report and the
+/// exclusion manifest.
+async fn parse_hive_fixtures(
+    hive_dir: &Path,
+    fork: Fork,
+    options: ParseOptions,
+    test_filter: Option<&str>,
+    simple_progress_indicator: bool,
+    num_largest: usize,
+    out_path: &Path,
+) -> anyhow::Result<(ParseReport, ExclusionManifest)> {
+    let sub_group = hive_dir
+        .file_name()
+        .ok_or_else(|| anyhow!("{:?} has no directory name", hive_dir))?;
+    let test_dir = Path::new(MAIN_TEST_DIR).join(HIVE_GROUP).join(sub_group);
+    fs::create_dir_all(out_path.join(&test_dir))?;
+
+    println!("Converting hive fixtures to plonky2 generation inputs");
+
+    let fixtures: Vec<_> = get_hive_fixtures(hive_dir)?
+        .into_iter()
+        .filter(|fixture| in_test_filter(test_filter, fixture))
+        .collect();
+    let mut progress = ParseProgress::new(fixtures.len() as u64, simple_progress_indicator);
+    let mut generation_input_handles: FuturesOrdered<_> = fixtures
+        .into_iter()
+        .map(|fixture| {
+            let options = options.clone();
+            tokio::task::spawn_blocking(move || {
+                let parsed = parse_hive_fixture(&fixture, fork, options);
+                (fixture, parsed)
+            })
+        })
+        .collect();
+
+    let mut report = ParseReport::default();
+    let mut excluded = ExclusionManifest::default();
+    let mut largest_tests = LargestTests::new(num_largest);
+    while let Some(thread) = generation_input_handles.next().await {
+        let (fixture, parsed) = thread.unwrap();
+        let name = fixture.file_name().unwrap().to_string_lossy().into_owned();
+        let (manifest, diagnostics, exclusions) = match parsed {
+            Ok(parsed) => (Some(parsed.manifest), parsed.diagnostics, parsed.exclusions),
+            Err(err) => {
+                let diagnostic =
+                    Diagnostic::new(DiagnosticReason::ParseError, None, format!("{:#}", err));
+                (None, vec![diagnostic], Vec::new())
+            }
+        };
+        for diagnostic in diagnostics.iter() {
+            debug!("{}: {:?}", name, diagnostic);
+        }
+        let num_variants = manifest
+            .as_ref()
+            .map_or(0, ParsedTestManifest::num_variants);
+        let outcome = FileOutcome::new(num_variants, &diagnostics);
+        let test_path = test_dir.join(&name).to_string_lossy().into_owned();
+        report.record_file(&test_path, num_variants, diagnostics);
+        excluded.record(&test_path, exclusions);
+
+        let mut bytes_written = 0;
+        if let Some(manifest) = manifest.filter(|_| num_variants > 0) {
+            let path = out_path.join(&test_dir).join(format!("{}.cbor", name));
+            let generation_inputs = serde_cbor::to_vec(&manifest)?;
+            fs::write(path, &generation_inputs)?;
+            bytes_written = generation_inputs.len();
+            largest_tests.record(&test_path, TestSize::new(&manifest, bytes_written));
+        }
+        progress.record_file(&sub_group.to_string_lossy(), &name, outcome, bytes_written);
+    }
+    progress.finish();
+    largest_tests.print();
+
+    Ok((report, excluded))
+}
+
+/// Whether the test file at `path` matches the `--test-filter`, if any.
+fn in_test_filter(test_filter: Option<&str>, path: &Path) -> bool {
+    test_filter.is_none_or(|f_str| path.to_string_lossy().contains(f_str))
+}
+
+fn file_name(path: Option<&Path>) -> String {
+    path.and_then(|p| p.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn write_report(
+    report: &ParseReport,
+    excluded: &ExclusionManifest,
+    out_path: &Path,
+) -> anyhow::Result<()> {
+    report.write(out_path)?;
+    excluded.write(out_path)?;
+    println!(
+        "Parsed {} variants from {}/{} files ({})",
+        report.num_parsed_variants,
+        report.num_parsed_files,
+        report.num_files,
+        report
+            .counts_by_severity
+            .iter()
+            .map(|(severity, count)| format!("{:?}: {}", severity, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    println!(
+        "The skipped tests and warnings are listed in {:?}",
+        out_path.join(PARSE_REPORT_FILE_NAME)
+    );
+    println!(
+        "{} variants exceeding the limits of the prover are listed in {:?}",
+        excluded.num_excluded_variants,
+        out_path.join(EXCLUSION_MANIFEST_FILE_NAME)
+    );
+
+    Ok(())
+}
@@ -1,34 +1,50 @@
+use clap::ValueEnum;
 use common::config::MAIN_TEST_DIR;
 
 // The PR <https://github.com/ethereum/tests/pull/1380> moved all test versions prior Cancun HF
 // to the `LegacyTests` folder.
 pub(crate) const ETH_TESTS_REPO_URL: &str = "https://github.com/ethereum/legacytests.git";
-pub(crate) const ETH_TESTS_REPO_LOCAL_PATH: &str = "eth_tests";
+pub const ETH_TESTS_REPO_LOCAL_PATH: &str = "eth_tests";
+/// The file recording the commit of the tests that were last parsed.
+pub const ETH_TESTS_LOCK_PATH: &str = "eth_tests.lock";
 pub(crate) const GENERAL_GROUP: &str = MAIN_TEST_DIR;
 pub(crate) const TEST_GROUPS: [&str; 1] = ["GeneralStateTests"];
+/// The `GeneralStateTests` in the plain state test format, which are only
+/// parsed with `--state-tests`.
+pub(crate) const STATE_TESTS_GROUP: &str = "Cancun/GeneralStateTests";
 // The following subgroups contain subfolders unlike the other test folders.
 pub(crate) const SPECIAL_TEST_SUBGROUPS: [&str; 3] = ["Cancun", "Shanghai", "VMTests"];
+/// The subgroup of the tests generated from the execution spec tests, nested by
+/// fork and then by EIP (eg. `Pyspecs/cancun/eip4844_blobs`). Each EIP folder
+/// is flattened into its own subgroup (eg. `Pyspecs_cancun_eip4844_blobs`).
+pub(crate) const PYSPECS_SUBGROUP: &str = "Pyspecs";
 
-/// These test variants are used for stress testing. As such, they have
-/// unrealistic scenarios that go beyond the provable bounds of the zkEVM.
-/// Witness generation for these variants is still possible, but takes too
-/// much time to be useful and usable in testing occuring regularly.
-pub(crate) const UNPROVABLE_VARIANTS: [&str; 17] = [
-    "CALLBlake2f_d9g0v0_Cancun",
-    "CALLCODEBlake2f_d9g0v0_Cancun",
-    "Call50000_d0g1v0_Cancun",
-    "Callcode50000_d0g1v0_Cancun",
-    "static_Call50000_d1g0v0_Cancun",
-    "static_Call50000_ecrec_d0g0v0_Cancun",
-    "static_Call50000_ecrec_d1g0v0_Cancun",
-    "static_Call50000_identity2_d0g0v0_Cancun",
-    "static_Call50000_identity2_d1g0v0_Cancun",
-    "static_Call50000_identity_d0g0v0_Cancun",
-    "static_Call50000_identity_d1g0v0_Cancun",
-    "static_Call50000_rip160_d0g0v0_Cancun",
-    "static_Call50000_sha256_d0g0v0_Cancun",
-    "static_Call50000_sha256_d1g0v0_Cancun",
-    "static_Return50000_2_d0g0v0_Cancun",
-    "Return50000_d0g1v0_Cancun",
-    "Return50000_2_d0g1v0_Cancun",
-];
+/// The fork whose test variants get parsed. Each test file contains a variant
+/// per fork, whose key ends with the fork name (eg. `add_d0g0v0_Cancun`).
+/// Forks are ordered chronologically.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd, ValueEnum)]
+pub enum Fork {
+    Berlin,
+    London,
+    Paris,
+    Shanghai,
+    #[default]
+    Cancun,
+    /// Introduces EOF. Its variants are only parsed with `--eof`, as the
+    /// prover doesn't support them yet.
+    #[value(skip)]
+    Osaka,
+}
+
+impl Fork {
+    pub fn name(self) -> &'static str {
+        match self {
+            Fork::Berlin => "Berlin",
+            Fork::London => "London",
+            Fork::Paris => "Paris",
+            Fork::Shanghai => "Shanghai",
+            Fork::Cancun => "Cancun",
+            Fork::Osaka => "Osaka",
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Deduplication of the variants of a test whose generation inputs are
+//! identical (same tries, same transaction, ...), which would otherwise be
+//! proven several times for the same result.
+
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    iter,
+};
+
+use common::types::Plonky2ParsedTest;
+use ethereum_types::H256;
+use keccak_hash::keccak;
+use mpt_trie::partial_trie::PartialTrie;
+
+/// Keeps a single copy of the variants with identical generation inputs, the
+/// others being recorded as its aliases. The variant kept is the first one by
+/// name, and the variants are returned sorted by name.
+pub(crate) fn dedup_variants(mut variants: Vec<Plonky2ParsedTest>) -> Vec<Plonky2ParsedTest> {
+    variants.sort_unstable_by(|v1, v2| v1.test_name.cmp(&v2.test_name));
+
+    let mut deduped: Vec<Plonky2ParsedTest> = Vec::with_capacity(variants.len());
+    let mut by_content_hash: HashMap<H256, usize> = HashMap::new();
+    for variant in variants {
+        match by_content_hash.entry(content_hash(&variant)) {
+            Entry::Occupied(entry) => deduped[*entry.get()].aliases.push(variant.test_name),
+            Entry::Vacant(entry) => {
+                entry.insert(deduped.len());
+                deduped.push(variant);
+            }
+        }
+    }
+
+    deduped
+}
+
+/// A hash of everything the generation inputs of a variant are built from.
+/// The hash maps of the variant are iterated in an arbitrary order, so they
+/// are hashed through the roots of their tries or their sorted keys instead.
+fn content_hash(variant: &Plonky2ParsedTest) -> H256 {
+    let blocks = iter::once((
+        &variant.txn_bytes,
+        &variant.final_roots,
+        &variant.plonky2_metadata,
+        &variant.block_hashes,
+    ))
+    .chain(variant.chained_blocks.iter().map(|block| {
+        (
+            &block.txn_bytes,
+            &block.final_roots,
+            &block.plonky2_metadata,
+            &block.block_hashes,
+        )
+    }));
+
+    let mut bytes = Vec::new();
+    for (txn_bytes, final_roots, metadata, block_hashes) in blocks {
+        let tries = &metadata.tries;
+        let block = (
+            txn_bytes,
+            final_roots,
+            tries.state_trie.hash(),
+            tries.transactions_trie.hash(),
+            tries.receipts_trie.hash(),
+            metadata.contract_code.keys().collect::<BTreeSet<_>>(),
+            metadata.genesis_state_root,
+            &metadata.block_metadata,
+            &metadata.withdrawals,
+            block_hashes,
+        );
+        bytes.extend(serde_cbor::to_vec(&block).unwrap());
+    }
+    bytes.extend(serde_cbor::to_vec(&variant.eof_containers).unwrap());
+
+    H256::from(keccak(bytes).0)
+}
+
+#[cfg(test)]
+mod tests {
+    use common::types::Plonky2ParsedTest;
+
+    use super::dedup_variants;
+
+    fn variant(name: &str, txn_bytes: &[u8]) -> Plonky2ParsedTest {
+        Plonky2ParsedTest {
+            test_name: name.to_string(),
+            txn_bytes: txn_bytes.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dedup_variants_collapses_identical_variants() {
+        let variants = vec![
+            variant("test_d2g0v0_Cancun", &[1]),
+            variant("test_d1g0v0_Cancun", &[2]),
+            variant("test_d0g0v0_Cancun", &[1]),
+            variant("test_d3g0v0_Cancun", &[1]),
+        ];
+
+        let deduped = dedup_variants(variants);
+
+        let names_and_aliases: Vec<_> = deduped
+            .iter()
+            .map(|v| (v.test_name.as_str(), v.aliases.clone()))
+            .collect();
+        assert_eq!(
+            names_and_aliases,
+            [
+                (
+                    "test_d0g0v0_Cancun",
+                    vec![
+                        "test_d2g0v0_Cancun".to_string(),
+                        "test_d3g0v0_Cancun".to_string()
+                    ]
+                ),
+                ("test_d1g0v0_Cancun", Vec::new()),
+            ]
+        );
+    }
+}
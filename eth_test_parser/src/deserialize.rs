@@ -1,23 +1,28 @@
-use std::{collections::HashMap, marker::PhantomData};
+use std::collections::HashMap;
 
 use anyhow::Result;
 use bytes::Bytes;
+use common::config::ETHEREUM_CHAIN_ID;
 use ethereum_types::{Address, H160, H256, U256};
 use evm_arithmetization::generation::mpt::transaction_testing::{
     AddressOption, LegacyTransactionRlp,
 };
 use hex::FromHex;
 use hex_literal::hex;
-use rlp::{Decodable, DecoderError, Rlp};
-use rlp_derive::RlpDecodable;
+use keccak_hash::keccak;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use rlp_derive::{RlpDecodable, RlpEncodable};
 use serde::de::MapAccess;
 use serde::{
-    de::{Error, Visitor},
+    de::{DeserializeSeed, Error, Visitor},
     Deserialize, Deserializer,
 };
 use serde_with::serde_as;
 
-use crate::config::UNPROVABLE_VARIANTS;
+use crate::{
+    config::Fork,
+    diagnostics::{Diagnostic, DiagnosticReason},
+};
 
 #[derive(Deserialize, Debug, Clone)]
 // "self" just points to this module.
@@ -83,10 +88,13 @@ impl<T: Decodable> Decodable for FieldOption<T> {
 }
 
 /// An Ethereum block header that can be RLP decoded.
-#[derive(Clone, Debug, Default, RlpDecodable)]
+///
+/// Headers of older forks lack the trailing fields introduced since (eg. the
+/// base fee before London), which are then left to their default value.
+#[derive(Clone, Debug, Default)]
 pub(crate) struct BlockHeader {
-    pub(crate) _parent_hash: H256,
-    pub(crate) _uncle_hash: H256,
+    pub(crate) parent_hash: H256,
+    pub(crate) uncle_hash: H256,
     pub(crate) coinbase: H160,
     pub(crate) state_root: H256,
     pub(crate) transactions_trie: H256,
@@ -104,31 +112,87 @@ pub(crate) struct BlockHeader {
     // define it as `Vec<u8>` to be fine all the time.
     pub(crate) _nonce: Vec<u8>,
     pub(crate) base_fee_per_gas: U256,
-    pub(crate) _withdrawals_root: FieldOption<H256>,
+    pub(crate) withdrawals_root: FieldOption<H256>,
     pub(crate) blob_gas_used: U256,
     pub(crate) excess_blob_gas: U256,
     pub(crate) parent_beacon_block_root: H256,
+    /// The hash of the header, which isn't part of its encoding.
+    pub(crate) hash: H256,
+}
+
+impl Decodable for BlockHeader {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        Ok(Self {
+            parent_hash: rlp.val_at(0)?,
+            uncle_hash: rlp.val_at(1)?,
+            coinbase: rlp.val_at(2)?,
+            state_root: rlp.val_at(3)?,
+            transactions_trie: rlp.val_at(4)?,
+            receipt_trie: rlp.val_at(5)?,
+            bloom: rlp.val_at(6)?,
+            difficulty: rlp.val_at(7)?,
+            number: rlp.val_at(8)?,
+            gas_limit: rlp.val_at(9)?,
+            gas_used: rlp.val_at(10)?,
+            timestamp: rlp.val_at(11)?,
+            _extra_data: rlp.val_at(12)?,
+            mix_hash: rlp.val_at(13)?,
+            _nonce: rlp.val_at(14)?,
+            base_fee_per_gas: optional_val_at(rlp, 15)?.unwrap_or_default(),
+            withdrawals_root: optional_val_at(rlp, 16)?.unwrap_or_default(),
+            blob_gas_used: optional_val_at(rlp, 17)?.unwrap_or_default(),
+            excess_blob_gas: optional_val_at(rlp, 18)?.unwrap_or_default(),
+            parent_beacon_block_root: optional_val_at(rlp, 19)?.unwrap_or_default(),
+            hash: H256::from(keccak(rlp.as_raw()).0),
+        })
+    }
+}
+
+/// Decodes the item at `index` of an RLP list, if the list is long enough.
+fn optional_val_at<T: Decodable>(rlp: &Rlp, index: usize) -> Result<Option<T>, DecoderError> {
+    if index < rlp.item_count()? {
+        rlp.val_at(index).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Decodes the list at `index` of an RLP list, which is empty if the list
+/// isn't long enough.
+fn optional_list_at<T: Decodable>(rlp: &Rlp, index: usize) -> Result<Vec<T>, DecoderError> {
+    if index < rlp.item_count()? {
+        rlp.list_at(index)
+    } else {
+        Ok(Vec::new())
+    }
 }
 
 // Some tests store the access list in a way that doesn't respect the specs,
 // and hence they require a specific handling.
-#[derive(Clone, Debug, RlpDecodable)]
+#[derive(Clone, Debug, RlpDecodable, RlpEncodable)]
 pub struct AccessItemRlp {
     _address: Address,
     _storage_keys: Vec<StorageKey>,
 }
 
 #[derive(Clone, Debug)]
-pub struct StorageKey;
+pub struct StorageKey(Vec<u8>);
 
 impl Decodable for StorageKey {
     fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        // We just need to decode the key as a `Vec<u8>`
-        // to deal with badly encoded scalars, but we do
-        // not care about the result.
-        let _key = rlp.as_val::<Vec<u8>>()?;
+        // We need to decode the key as a `Vec<u8>`
+        // to deal with badly encoded scalars.
+        Ok(Self(rlp.as_val()?))
+    }
+}
 
-        Ok(Self)
+impl Encodable for StorageKey {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        // Storage keys are 32 bytes long, so the shorter ones are re-encoded
+        // with their leading zeros.
+        let mut key = vec![0; 32usize.saturating_sub(self.0.len())];
+        key.extend_from_slice(&self.0);
+        s.append(&key);
     }
 }
 
@@ -138,23 +202,27 @@ impl Decodable for StorageKey {
 // - a regular list of items (i.e. transactions)
 // - a single item (i.e. transaction) but not a list
 // - a list of strings (i.e. encodings of transactions)
+// Blocks may also have no transaction at all (eg. to only process withdrawals).
 #[derive(Debug)]
-pub(crate) struct Transactions(pub(crate) Transaction);
+pub(crate) struct Transactions(pub(crate) Option<Transaction>);
 
 impl Decodable for Transactions {
     fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
         if rlp.is_list() {
+            if rlp.is_empty() {
+                return Ok(Transactions(None));
+            }
             let txn = rlp.at(0)?.as_val::<Transaction>()?;
-            Ok(Transactions(txn))
+            Ok(Transactions(Some(txn)))
         } else {
             let txn = rlp.as_val::<Transaction>()?;
-            Ok(Transactions(txn))
+            Ok(Transactions(Some(txn)))
         }
     }
 }
 
 // A custom type-1 txn to handle some edge-cases with the access_list field.
-#[derive(RlpDecodable, Debug, Clone)]
+#[derive(RlpDecodable, RlpEncodable, Debug, Clone)]
 pub struct CustomAccessListTransactionRlp {
     _chain_id: u64,
     _nonce: U256,
@@ -170,7 +238,7 @@ pub struct CustomAccessListTransactionRlp {
 }
 
 // A custom type-2 txn to handle some edge-cases with the access_list field.
-#[derive(RlpDecodable, Debug, Clone)]
+#[derive(RlpDecodable, RlpEncodable, Debug, Clone)]
 pub struct CustomFeeMarketTransactionRlp {
     _chain_id: u64,
     _nonce: U256,
@@ -187,7 +255,7 @@ pub struct CustomFeeMarketTransactionRlp {
 }
 
 // A custom type-2 txn to handle some edge-cases with the access_list field.
-#[derive(RlpDecodable, Debug, Clone)]
+#[derive(RlpDecodable, RlpEncodable, Debug, Clone)]
 pub struct CustomBlobTransactionRlp {
     _chain_id: u64,
     _nonce: U256,
@@ -209,16 +277,32 @@ pub struct CustomBlobTransactionRlp {
 pub struct Transaction(pub Vec<u8>);
 
 impl Transaction {
-    fn decode_actual_rlp(bytes: &[u8]) -> Result<Self, DecoderError> {
+    pub(crate) fn decode_actual_rlp(bytes: &[u8]) -> Result<Self, DecoderError> {
+        Self::reencode(bytes).map(|_| Self(bytes.to_vec()))
+    }
+
+    /// Decodes a signed transaction and encodes it again, which gives back
+    /// `bytes` unless the decoders accept a malformed encoding (see
+    /// `encoding_validation`).
+    pub(crate) fn reencode(bytes: &[u8]) -> Result<Vec<u8>, DecoderError> {
+        fn typed<T: Decodable + Encodable>(
+            txn_type: u8,
+            payload: &[u8],
+        ) -> Result<Vec<u8>, DecoderError> {
+            let txn = T::decode(&Rlp::new(payload))?;
+            let mut bytes = vec![txn_type];
+            bytes.extend_from_slice(&rlp::encode(&txn));
+            Ok(bytes)
+        }
+
         let first_byte = bytes.first().ok_or(DecoderError::RlpInvalidLength)?;
         match *first_byte {
-            1 => CustomAccessListTransactionRlp::decode(&Rlp::new(&bytes[1..]))
-                .map(|_| Self(bytes.to_vec())),
-            2 => CustomFeeMarketTransactionRlp::decode(&Rlp::new(&bytes[1..]))
-                .map(|_| Self(bytes.to_vec())),
-            3 => CustomBlobTransactionRlp::decode(&Rlp::new(&bytes[1..]))
-                .map(|_| Self(bytes.to_vec())),
-            _ => LegacyTransactionRlp::decode(&Rlp::new(bytes)).map(|_| Self(bytes.to_vec())),
+            1 => typed::<CustomAccessListTransactionRlp>(1, &bytes[1..]),
+            2 => typed::<CustomFeeMarketTransactionRlp>(2, &bytes[1..]),
+            3 => typed::<CustomBlobTransactionRlp>(3, &bytes[1..]),
+            _ => {
+                LegacyTransactionRlp::decode(&Rlp::new(bytes)).map(|txn| rlp::encode(&txn).to_vec())
+            }
         }
     }
 }
@@ -241,13 +325,13 @@ impl Decodable for Transaction {
 // Only needed for proper RLP decoding
 #[derive(Debug, RlpDecodable)]
 pub(crate) struct Withdrawal {
-    pub(crate) _index: U256,
-    pub(crate) _validator_index: U256,
+    pub(crate) index: U256,
+    pub(crate) validator_index: U256,
     pub(crate) address: H160,
     pub(crate) amount: U256,
 }
 
-#[derive(Debug, RlpDecodable)]
+#[derive(Debug)]
 pub(crate) struct Block {
     pub(crate) block_header: BlockHeader,
     pub(crate) transactions: Transactions,
@@ -255,7 +339,35 @@ pub(crate) struct Block {
     pub(crate) withdrawals: Vec<Withdrawal>,
 }
 
-#[derive(Debug, RlpDecodable)]
+impl Decodable for Block {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        Self::decode_with_transactions(rlp, rlp.val_at(1)?)
+    }
+}
+
+impl Block {
+    /// The signed transaction of the block, if it has one.
+    pub(crate) fn txn(&self) -> Option<&[u8]> {
+        self.transactions.0.as_ref().map(|txn| txn.0.as_slice())
+    }
+
+    /// Decodes a block, replacing its transactions with `transactions`.
+    fn decode_with_transactions(
+        rlp: &Rlp,
+        transactions: Transactions,
+    ) -> Result<Self, DecoderError> {
+        let block_header: BlockHeader = rlp.val_at(0)?;
+        Ok(Self {
+            transactions,
+            _uncle_headers: decode_uncle_headers(rlp, &block_header)?,
+            // Withdrawals were only introduced in Shanghai.
+            withdrawals: optional_list_at(rlp, 3)?,
+            block_header,
+        })
+    }
+}
+
+#[derive(Debug)]
 pub(crate) struct GenesisBlock {
     pub(crate) block_header: BlockHeader,
     pub(crate) _transactions: Vec<Transaction>,
@@ -263,8 +375,36 @@ pub(crate) struct GenesisBlock {
     pub(crate) _withdrawals: Vec<Withdrawal>,
 }
 
+impl Decodable for GenesisBlock {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let block_header: BlockHeader = rlp.val_at(0)?;
+        Ok(Self {
+            _transactions: rlp.list_at(1)?,
+            _uncle_headers: decode_uncle_headers(rlp, &block_header)?,
+            _withdrawals: optional_list_at(rlp, 3)?,
+            block_header,
+        })
+    }
+}
+
+/// Decodes the uncle headers (ommers) of a block, checking them against the
+/// ommers hash of its header. They are always empty after the merge.
+fn decode_uncle_headers(
+    block_rlp: &Rlp,
+    header: &BlockHeader,
+) -> Result<Vec<BlockHeader>, DecoderError> {
+    let uncles = block_rlp.at(2)?;
+    if H256::from(keccak(uncles.as_raw()).0) != header.uncle_hash {
+        return Err(DecoderError::Custom(
+            "The uncle headers don't match the ommers hash of the block",
+        ));
+    }
+
+    uncles.as_list()
+}
+
 /// Contains the RLP encoding of the block, as well as the `transactionSequence`
-/// field (if any) to indicate if this test contains a malformed transaction
+/// field (if any) to indicate if this block contains malformed transactions
 /// that *should* be ignored for testing (as all input txns to plonky2 zkEVM are
 /// expected to be valid).
 #[derive(Deserialize, Debug)]
@@ -272,11 +412,15 @@ pub(crate) struct GenesisBlock {
 pub(crate) struct BlockRlpWithExceptions {
     pub(crate) rlp: ByteString,
     pub(crate) transaction_sequence: Option<Vec<TransactionSequence>>,
+    /// Set for the invalid blocks of multi-block tests, which are left out of
+    /// the chain.
+    pub(crate) expect_exception: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct TransactionSequence {
+    pub(crate) raw_bytes: Option<ByteString>,
     pub(crate) valid: String,
 }
 
@@ -289,10 +433,36 @@ pub(crate) struct PreAccount {
     pub(crate) storage: HashMap<U256, U256>,
 }
 
+/// The address of the account added by the parser to the pre-state of all tests
+/// (see [`exit_root_pre_account`]).
+pub(crate) const EXIT_ROOT_ADDRESS: H160 = H160(hex!("a40D5f56745a118D0906a34E69aeC8C0Db1cB8fA"));
+
+/// The account added by the parser to the pre-state of all tests, at
+/// [`EXIT_ROOT_ADDRESS`].
+pub(crate) fn exit_root_pre_account() -> PreAccount {
+    // TODO: export from plonky2 kernel constants directly
+    PreAccount {
+        balance: U256::zero(),
+        nonce: 0,
+        code: ByteString(hex!("60806040526004361061004e5760003560e01c80633659cfe6146100655780634f1ef286146100855780635c60da1b146100985780638f283970146100c9578063f851a440146100e95761005d565b3661005d5761005b6100fe565b005b61005b6100fe565b34801561007157600080fd5b5061005b6100803660046106ca565b610118565b61005b6100933660046106e5565b61015f565b3480156100a457600080fd5b506100ad6101d0565b6040516001600160a01b03909116815260200160405180910390f35b3480156100d557600080fd5b5061005b6100e43660046106ca565b61020b565b3480156100f557600080fd5b506100ad610235565b610106610292565b610116610111610331565b61033b565b565b61012061035f565b6001600160a01b0316336001600160a01b031614156101575761015481604051806020016040528060008152506000610392565b50565b6101546100fe565b61016761035f565b6001600160a01b0316336001600160a01b031614156101c8576101c38383838080601f01602080910402602001604051908101604052809392919081815260200183838082843760009201919091525060019250610392915050565b505050565b6101c36100fe565b60006101da61035f565b6001600160a01b0316336001600160a01b03161415610200576101fb610331565b905090565b6102086100fe565b90565b61021361035f565b6001600160a01b0316336001600160a01b0316141561015757610154816103f1565b600061023f61035f565b6001600160a01b0316336001600160a01b03161415610200576101fb61035f565b606061028583836040518060600160405280602781526020016107e460279139610445565b9392505050565b3b151590565b61029a61035f565b6001600160a01b0316336001600160a01b031614156101165760405162461bcd60e51b815260206004820152604260248201527f5472616e73706172656e745570677261646561626c6550726f78793a2061646d60448201527f696e2063616e6e6f742066616c6c6261636b20746f2070726f78792074617267606482015261195d60f21b608482015260a4015b60405180910390fd5b60006101fb610519565b3660008037600080366000845af43d6000803e80801561035a573d6000f35b3d6000fd5b60007fb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d61035b546001600160a01b0316919050565b61039b83610541565b6040516001600160a01b038416907fbc7cd75a20ee27fd9adebab32041f755214dbc6bffa90cc0225b39da2e5c2d3b90600090a26000825111806103dc5750805b156101c3576103eb8383610260565b50505050565b7f7e644d79422f17c01e4894b5f4f588d331ebfa28653d42ae832dc59e38c9798f61041a61035f565b604080516001600160a01b03928316815291841660208301520160405180910390a1610154816105e9565b6060833b6104a45760405162461bcd60e51b815260206004820152602660248201527f416464726573733a2064656c65676174652063616c6c20746f206e6f6e2d636f6044820152651b9d1c9858dd60d21b6064820152608401610328565b600080856001600160a01b0316856040516104bf9190610794565b600060405180830381855af49150503d80600081146104fa576040519150601f19603f3d011682016040523d82523d6000602084013e6104ff565b606091505b509150915061050f828286610675565b9695505050505050565b60007f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc610383565b803b6105a55760405162461bcd60e51b815260206004820152602d60248201527f455243313936373a206e657720696d706c656d656e746174696f6e206973206e60448201526c1bdd08184818dbdb9d1c9858dd609a1b6064820152608401610328565b807f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc5b80546001600160a01b0319166001600160a01b039290921691909117905550565b6001600160a01b03811661064e5760405162461bcd60e51b815260206004820152602660248201527f455243313936373a206e65772061646d696e20697320746865207a65726f206160448201526564647265737360d01b6064820152608401610328565b807fb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d61036105c8565b60608315610684575081610285565b8251156106945782518084602001fd5b8160405162461bcd60e51b815260040161032891906107b0565b80356001600160a01b03811681146106c557600080fd5b919050565b6000602082840312156106dc57600080fd5b610285826106ae565b6000806000604084860312156106fa57600080fd5b610703846106ae565b9250602084013567ffffffffffffffff8082111561072057600080fd5b818601915086601f83011261073457600080fd5b81358181111561074357600080fd5b87602082850101111561075557600080fd5b6020830194508093505050509250925092565b60005b8381101561078357818101518382015260200161076b565b838111156103eb5750506000910152565b600082516107a6818460208701610768565b9190910192915050565b60208152600082518060208401526107cf816040850160208701610768565b601f01601f1916919091016040019291505056fe416464726573733a206c6f772d6c6576656c2064656c65676174652063616c6c206661696c6564a26469706673582212204675187caf3a43285d9a2c1844a981e977bd52a85ff073e7fc649f73847d70a464736f6c63430008090033").to_vec()),
+        storage: HashMap::new(),
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct TestBody {
     pub(crate) name: String,
+    pub(crate) fork: Fork,
     pub(crate) block: Block,
+    /// The valid blocks following the first one, for tests made of several
+    /// blocks.
+    pub(crate) chained_blocks: Vec<Block>,
+    /// Whether invalid transactions were dropped from the last block, whose
+    /// header and post-state no longer match the test and are recomputed.
+    pub(crate) dropped_invalid_txns: bool,
+    /// The chain id the transactions are signed for, which is the one of
+    /// Ethereum unless they were re-signed (see `resigning`).
+    pub(crate) chain_id: u64,
     // The genesis block has an empty transactions list, which needs a
     // different handling than the logic present in `Block` decoding.
     pub(crate) genesis_block: GenesisBlock,
@@ -301,41 +471,108 @@ pub(crate) struct TestBody {
 }
 
 impl TestBody {
-    fn from_parsed_json(value: &ValueJson, variant_name: String) -> Self {
-        let block: Block = rlp::decode(&value.blocks[0].rlp.0).unwrap();
+    /// Fails with the reason to skip the variant if the prover can't be given
+    /// any of its blocks.
+    fn from_parsed_json(
+        value: &ValueJson,
+        variant_name: String,
+        fork: Fork,
+    ) -> Result<Self, Diagnostic> {
+        let skip = |reason, message: &str| Diagnostic::new(reason, Some(&variant_name), message);
+        let decoding_error = |err| {
+            Diagnostic::new(
+                DiagnosticReason::ParseError,
+                Some(&variant_name),
+                format!("Decoding: {}", err),
+            )
+        };
+
+        let mut blocks = Vec::with_capacity(value.blocks.len());
+        let mut dropped_invalid_txns = false;
+        for (block_idx, block_json) in value.blocks.iter().enumerate() {
+            let Some(txn_sequence) = &block_json.transaction_sequence else {
+                // Invalid blocks are left out of the chain, except for the first
+                // one.
+                if block_idx == 0 || block_json.expect_exception.is_none() {
+                    blocks.push(rlp::decode(&block_json.rlp.0).map_err(decoding_error)?);
+                }
+                continue;
+            };
+
+            // Some tests deal with malformed transactions that wouldn't be passed
+            // to plonky2 zkEVM in the first place, so we only keep the valid ones.
+            let valid_txns: Vec<_> = txn_sequence
+                .iter()
+                .filter(|txn| txn.valid == "true")
+                .collect();
+            match valid_txns[..] {
+                // The block was rejected as a whole.
+                [] if !txn_sequence.is_empty() => continue,
+                [] | [_] if valid_txns.len() == txn_sequence.len() => {
+                    blocks.push(rlp::decode(&block_json.rlp.0).map_err(decoding_error)?);
+                }
+                [txn] => {
+                    let Some(raw_txn) = &txn.raw_bytes else {
+                        return Err(skip(
+                            DiagnosticReason::MissingRawTransaction,
+                            "A valid transaction has no raw bytes",
+                        ));
+                    };
+                    let txn = Transaction::decode_actual_rlp(&raw_txn.0).map_err(decoding_error)?;
+                    blocks.push(
+                        Block::decode_with_transactions(
+                            &Rlp::new(&block_json.rlp.0),
+                            Transactions(Some(txn)),
+                        )
+                        .map_err(decoding_error)?,
+                    );
+                    // The following blocks were built on top of another version of
+                    // this one.
+                    dropped_invalid_txns = true;
+                    break;
+                }
+                // The blocks given to the prover have a single transaction.
+                _ => {
+                    return Err(skip(
+                        DiagnosticReason::MultipleTransactions,
+                        &format!("Block {} has several valid transactions", block_idx + 1),
+                    ))
+                }
+            }
+        }
+        if blocks.is_empty() {
+            return Err(skip(
+                DiagnosticReason::InvalidTransactions,
+                "No block is valid",
+            ));
+        }
+        let block = blocks.remove(0);
+        let chained_blocks = blocks;
         let genesis_block: GenesisBlock =
-            rlp::decode(&value.genesis_rlp.as_ref().unwrap().0).unwrap();
+            rlp::decode(&value.genesis_rlp.as_ref().unwrap().0).map_err(decoding_error)?;
 
         let mut pre = value.pre.clone();
-        let mut post = value.post_state.clone();
-
-        // TODO: export from plonky2 kernel constants directly
-        let exit_root_pre_account = PreAccount {
-            balance: U256::zero(),
-            nonce: 0,
-            code: ByteString(hex!("60806040526004361061004e5760003560e01c80633659cfe6146100655780634f1ef286146100855780635c60da1b146100985780638f283970146100c9578063f851a440146100e95761005d565b3661005d5761005b6100fe565b005b61005b6100fe565b34801561007157600080fd5b5061005b6100803660046106ca565b610118565b61005b6100933660046106e5565b61015f565b3480156100a457600080fd5b506100ad6101d0565b6040516001600160a01b03909116815260200160405180910390f35b3480156100d557600080fd5b5061005b6100e43660046106ca565b61020b565b3480156100f557600080fd5b506100ad610235565b610106610292565b610116610111610331565b61033b565b565b61012061035f565b6001600160a01b0316336001600160a01b031614156101575761015481604051806020016040528060008152506000610392565b50565b6101546100fe565b61016761035f565b6001600160a01b0316336001600160a01b031614156101c8576101c38383838080601f01602080910402602001604051908101604052809392919081815260200183838082843760009201919091525060019250610392915050565b505050565b6101c36100fe565b60006101da61035f565b6001600160a01b0316336001600160a01b03161415610200576101fb610331565b905090565b6102086100fe565b90565b61021361035f565b6001600160a01b0316336001600160a01b0316141561015757610154816103f1565b600061023f61035f565b6001600160a01b0316336001600160a01b03161415610200576101fb61035f565b606061028583836040518060600160405280602781526020016107e460279139610445565b9392505050565b3b151590565b61029a61035f565b6001600160a01b0316336001600160a01b031614156101165760405162461bcd60e51b815260206004820152604260248201527f5472616e73706172656e745570677261646561626c6550726f78793a2061646d60448201527f696e2063616e6e6f742066616c6c6261636b20746f2070726f78792074617267606482015261195d60f21b608482015260a4015b60405180910390fd5b60006101fb610519565b3660008037600080366000845af43d6000803e80801561035a573d6000f35b3d6000fd5b60007fb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d61035b546001600160a01b0316919050565b61039b83610541565b6040516001600160a01b038416907fbc7cd75a20ee27fd9adebab32041f755214dbc6bffa90cc0225b39da2e5c2d3b90600090a26000825111806103dc5750805b156101c3576103eb8383610260565b50505050565b7f7e644d79422f17c01e4894b5f4f588d331ebfa28653d42ae832dc59e38c9798f61041a61035f565b604080516001600160a01b03928316815291841660208301520160405180910390a1610154816105e9565b6060833b6104a45760405162461bcd60e51b815260206004820152602660248201527f416464726573733a2064656c65676174652063616c6c20746f206e6f6e2d636f6044820152651b9d1c9858dd60d21b6064820152608401610328565b600080856001600160a01b0316856040516104bf9190610794565b600060405180830381855af49150503d80600081146104fa576040519150601f19603f3d011682016040523d82523d6000602084013e6104ff565b606091505b509150915061050f828286610675565b9695505050505050565b60007f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc610383565b803b6105a55760405162461bcd60e51b815260206004820152602d60248201527f455243313936373a206e657720696d706c656d656e746174696f6e206973206e60448201526c1bdd08184818dbdb9d1c9858dd609a1b6064820152608401610328565b807f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc5b80546001600160a01b0319166001600160a01b039290921691909117905550565b6001600160a01b03811661064e5760405162461bcd60e51b815260206004820152602660248201527f455243313936373a206e65772061646d696e20697320746865207a65726f206160448201526564647265737360d01b6064820152608401610328565b807fb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d61036105c8565b60608315610684575081610285565b8251156106945782518084602001fd5b8160405162461bcd60e51b815260040161032891906107b0565b80356001600160a01b03811681146106c557600080fd5b919050565b6000602082840312156106dc57600080fd5b610285826106ae565b6000806000604084860312156106fa57600080fd5b610703846106ae565b9250602084013567ffffffffffffffff8082111561072057600080fd5b818601915086601f83011261073457600080fd5b81358181111561074357600080fd5b87602082850101111561075557600080fd5b6020830194508093505050509250925092565b60005b8381101561078357818101518382015260200161076b565b838111156103eb5750506000910152565b600082516107a6818460208701610768565b9190910192915050565b60208152600082518060208401526107cf816040850160208701610768565b601f01601f1916919091016040019291505056fe416464726573733a206c6f772d6c6576656c2064656c65676174652063616c6c206661696c6564a26469706673582212204675187caf3a43285d9a2c1844a981e977bd52a85ff073e7fc649f73847d70a464736f6c63430008090033").to_vec()),
-            storage: HashMap::new(),
+        let Some(mut post) = value.post_state.clone() else {
+            return Err(skip(
+                DiagnosticReason::MissingPostState,
+                "Only the hash of the post-state is given",
+            ));
         };
-        pre.insert(
-            H160(hex!("a40D5f56745a118D0906a34E69aeC8C0Db1cB8fA")),
-            exit_root_pre_account.clone(),
-        );
-        post.insert(
-            H160(hex!("a40D5f56745a118D0906a34E69aeC8C0Db1cB8fA")),
-            exit_root_pre_account,
-        );
-
-        Self {
+
+        pre.insert(EXIT_ROOT_ADDRESS, exit_root_pre_account());
+        post.insert(EXIT_ROOT_ADDRESS, exit_root_pre_account());
+
+        Ok(Self {
             name: variant_name,
+            fork,
             block,
+            chained_blocks,
+            dropped_invalid_txns,
+            chain_id: ETHEREUM_CHAIN_ID,
             genesis_block,
             pre,
             post,
-        }
-    }
-
-    pub(crate) fn get_tx(&self) -> Transaction {
-        self.block.transactions.0.clone()
+        })
     }
 }
 
@@ -347,30 +584,32 @@ struct ValueJson {
     #[serde(rename = "genesisRLP")]
     pub(crate) genesis_rlp: Option<ByteString>,
     pub(crate) pre: HashMap<H160, PreAccount>,
+    /// Some generated tests only give the hash of their post-state.
     #[serde(rename = "postState")]
-    pub(crate) post_state: HashMap<H160, PreAccount>,
+    pub(crate) post_state: Option<HashMap<H160, PreAccount>>,
 }
 
-// Wrapper around a regular `HashMap` used to conveniently skip
-// non-Cancun related tests when deserializing.
+// Wrapper around a regular `HashMap` used to conveniently skip the variants
+// of other forks when deserializing.
 #[derive(Default, Debug)]
-pub(crate) struct TestFile(pub(crate) HashMap<String, TestBody>);
+pub(crate) struct TestFile {
+    pub(crate) variants: HashMap<String, TestBody>,
+    /// Why the other variants of the fork were skipped.
+    pub(crate) diagnostics: Vec<Diagnostic>,
+}
 
-impl<'de> Deserialize<'de> for TestFile {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+/// Deserializes a [`TestFile`] containing the variants of the given fork only.
+pub(crate) struct TestFileSeed(pub(crate) Fork);
+
+impl<'de> DeserializeSeed<'de> for TestFileSeed {
+    type Value = TestFile;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
         D: Deserializer<'de>,
     {
         struct TestFileVisitor {
-            marker: PhantomData<fn() -> TestFile>,
-        }
-
-        impl TestFileVisitor {
-            fn new() -> Self {
-                TestFileVisitor {
-                    marker: PhantomData,
-                }
-            }
+            fork: Fork,
         }
 
         impl<'de> Visitor<'de> for TestFileVisitor {
@@ -384,38 +623,48 @@ impl<'de> Deserialize<'de> for TestFile {
             where
                 M: MapAccess<'de>,
             {
-                let mut map = TestFile(HashMap::with_capacity(access.size_hint().unwrap_or(0)));
-
-                // While we are parsing many values, we only care about the ones containing
-                // `Cancun` in their key name.
+                let mut map = TestFile {
+                    variants: HashMap::with_capacity(access.size_hint().unwrap_or(0)),
+                    diagnostics: Vec::new(),
+                };
+                let fork_suffix = format!("_{}", self.fork.name());
+                let pyspecs_fork_param = format!("[fork_{}-", self.fork.name());
+
+                // While we are parsing many values, we only care about the ones of the
+                // selected fork, whose key name ends with it (or has it as parameter for
+                // the Pyspecs tests).
                 while let Some((key, value)) = access.next_entry::<String, ValueJson>()? {
-                    if key.contains("_Cancun")
-                        && !UNPROVABLE_VARIANTS.iter().any(|v| key.contains(v))
-                    {
-                        if value.blocks[0].transaction_sequence.is_none() {
-                            let test_body = TestBody::from_parsed_json(&value, key.clone());
-
-                            // Ensure that the gas used fits in 32 bits, otherwise the prover will
-                            // abort.
-                            if TryInto::<u32>::try_into(test_body.block.block_header.gas_used)
-                                .is_ok()
-                            {
-                                map.0.insert(key, test_body);
-                            }
-                        } else {
-                            // Some tests deal with malformed transactions that wouldn't be passed
-                            // to plonky2 zkEVM in the first place, so we just ignore them.
-                            let exception = value.blocks[0].transaction_sequence.as_ref().unwrap();
-                            assert_eq!(exception[0].valid, "false".to_string());
-                        }
+                    if !key.ends_with(&fork_suffix) && !key.contains(&pyspecs_fork_param) {
+                        continue;
                     }
+                    let name = variant_name(&key);
+                    let test_body = match TestBody::from_parsed_json(&value, name, self.fork) {
+                        Ok(test_body) => test_body,
+                        Err(diagnostic) => {
+                            map.diagnostics.push(diagnostic);
+                            continue;
+                        }
+                    };
+
+                    map.variants.insert(key, test_body);
                 }
 
                 Ok(map)
             }
         }
 
-        deserializer.deserialize_map(TestFileVisitor::new())
+        deserializer.deserialize_map(TestFileVisitor { fork: self.0 })
+    }
+}
+
+/// The name of a test variant given its key. The keys of the Pyspecs tests are
+/// the path of their Python test followed by its name and parameters (eg.
+/// `tests/cancun/.../test_tstorage.py::test_tload[fork_Cancun-...]`), of which
+/// only the latter are kept.
+fn variant_name(key: &str) -> String {
+    match key.rsplit_once("::") {
+        Some((_, name)) => name.replace('/', "_"),
+        None => key.to_string(),
     }
 }
 
@@ -0,0 +1,167 @@
+//! Structured records of everything the parser skipped or warned about, so
+//! that the completeness of a parsed corpus can be checked from its
+//! `parse_report.json`.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::Context;
+use serde::Serialize;
+
+/// The file written at the root of the output directory of the parser.
+pub const PARSE_REPORT_FILE_NAME: &str = "parse_report.json";
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Something went wrong, but the output is complete.
+    Warning,
+    /// A variant was deliberately left out.
+    Skip,
+    /// A file or variant couldn't be parsed.
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticReason {
+    /// The test file couldn't be deserialized.
+    ParseError,
+    /// The generation inputs of the variant couldn't be built.
+    BuildError,
+    /// The variant is listed as unprovable (see `--unprovable-variants`).
+    UnprovableVariant,
+    /// The variant exceeds one of the limits of the prover (see
+    /// `excluded.json`).
+    ExceedsProverLimits,
+    /// No block of the variant has a valid transaction.
+    InvalidTransactions,
+    /// A block has several valid transactions.
+    MultipleTransactions,
+    /// The variant only gives the hash of its post-state.
+    MissingPostState,
+    /// A valid transaction of the `transactionSequence` has no raw bytes.
+    MissingRawTransaction,
+    /// The block headers of the variant are inconsistent (see
+    /// `--validate-headers`).
+    InconsistentHeaders,
+    /// The sender of a transaction couldn't be recovered, or can't send it from
+    /// the state before its block.
+    InvalidTxnSender,
+    /// The state after the last block, as computed by `revm`, differs from
+    /// the post-state of the test, or couldn't be computed.
+    PostStateMismatch,
+    /// The logs bloom of a block header differs from the one computed with
+    /// `revm`.
+    LogsBloomMismatch,
+    /// A transaction doesn't encode back to the bytes it was decoded from.
+    NonCanonicalTxnEncoding,
+    /// The block headers of the variant couldn't be validated.
+    HeaderValidationError,
+    /// The variant couldn't be exported as t8n inputs.
+    T8nExportError,
+    /// The variant couldn't be exported as block prover inputs.
+    ProverInputExportError,
+    /// The commit of the parsed tests is unknown.
+    UnknownTestsCommit,
+}
+
+impl DiagnosticReason {
+    pub fn severity(self) -> Severity {
+        match self {
+            Self::ParseError | Self::BuildError => Severity::Error,
+            Self::UnprovableVariant
+            | Self::ExceedsProverLimits
+            | Self::InvalidTransactions
+            | Self::MultipleTransactions
+            | Self::MissingPostState
+            | Self::MissingRawTransaction
+            | Self::InconsistentHeaders => Severity::Skip,
+            Self::InvalidTxnSender
+            | Self::PostStateMismatch
+            | Self::LogsBloomMismatch
+            | Self::NonCanonicalTxnEncoding
+            | Self::HeaderValidationError
+            | Self::T8nExportError
+            | Self::ProverInputExportError
+            | Self::UnknownTestsCommit => Severity::Warning,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub reason: DiagnosticReason,
+    /// The variant the diagnostic is about, if not the whole file.
+    pub variant: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(
+        reason: DiagnosticReason,
+        variant: Option<&str>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            severity: reason.severity(),
+            reason,
+            variant: variant.map(str::to_string),
+            message: message.into(),
+        }
+    }
+}
+
+/// The content of `parse_report.json`.
+#[derive(Debug, Default, Serialize)]
+pub struct ParseReport {
+    pub num_files: usize,
+    pub num_parsed_files: usize,
+    pub num_parsed_variants: usize,
+    pub counts_by_reason: BTreeMap<DiagnosticReason, usize>,
+    pub counts_by_severity: BTreeMap<Severity, usize>,
+    /// The diagnostics of each test file (relative to the tests checkout).
+    /// Those that aren't about a file are under an empty key.
+    pub files: BTreeMap<String, Vec<Diagnostic>>,
+}
+
+impl ParseReport {
+    /// Records the outcome of parsing a test file.
+    pub fn record_file(
+        &mut self,
+        file: &str,
+        num_parsed_variants: usize,
+        diagnostics: Vec<Diagnostic>,
+    ) {
+        self.num_files += 1;
+        if num_parsed_variants > 0 {
+            self.num_parsed_files += 1;
+        }
+        self.num_parsed_variants += num_parsed_variants;
+        self.record(file, diagnostics);
+    }
+
+    pub fn record(&mut self, file: &str, diagnostics: Vec<Diagnostic>) {
+        if diagnostics.is_empty() {
+            return;
+        }
+
+        for diagnostic in diagnostics.iter() {
+            *self.counts_by_reason.entry(diagnostic.reason).or_default() += 1;
+            *self
+                .counts_by_severity
+                .entry(diagnostic.severity)
+                .or_default() += 1;
+        }
+        self.files
+            .entry(file.to_string())
+            .or_default()
+            .extend(diagnostics);
+    }
+
+    pub fn write(&self, out_path: &Path) -> anyhow::Result<()> {
+        let path = out_path.join(PARSE_REPORT_FILE_NAME);
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Writing {:?}", path))
+    }
+}
@@ -0,0 +1,39 @@
+//! Round-trip checks of the encodings of the transactions of the tests.
+//!
+//! The transactions are decoded with bespoke decoders, which accept some
+//! encodings that don't respect the specs (eg. badly encoded storage keys in
+//! access lists). A transaction that doesn't encode back to the same bytes is
+//! one the decoders accepted despite being malformed, which the zkEVM may
+//! decode differently.
+
+use std::iter;
+
+use crate::deserialize::{TestBody, Transaction};
+
+/// Returns a description of each transaction of `test` that doesn't encode
+/// back to the bytes it was decoded from.
+pub(crate) fn txn_encoding_issues(test: &TestBody) -> Vec<String> {
+    let blocks = iter::once(&test.block).chain(test.chained_blocks.iter());
+
+    blocks
+        .enumerate()
+        .filter_map(|(block_idx, block)| {
+            let txn = block.txn()?;
+            let issue = match Transaction::reencode(txn) {
+                Ok(reencoded) if reencoded == txn => return None,
+                Ok(reencoded) => format!(
+                    "the transaction is encoded in {} bytes, but re-encodes in {} bytes, differing from byte {}",
+                    txn.len(),
+                    reencoded.len(),
+                    txn.iter()
+                        .zip(reencoded.iter())
+                        .take_while(|(byte, reencoded_byte)| byte == reencoded_byte)
+                        .count()
+                ),
+                Err(err) => format!("the transaction can't be decoded again: {}", err),
+            };
+
+            Some(format!("Block {}: {}", block_idx + 1, issue))
+        })
+        .collect()
+}
@@ -0,0 +1,33 @@
+//! Handling of the EOF containers (see EIP-3540) of the tests of the forks
+//! introducing EOF, whose variants are parsed with `--eof`.
+
+use common::types::EofContainerInfo;
+use ethereum_types::H160;
+use revm::{interpreter::analysis::validate_raw_eof, primitives::Bytes};
+
+/// The prefix of EOF containers.
+const EOF_MAGIC: [u8; 2] = [0xef, 0x00];
+
+pub(crate) fn is_eof(code: &[u8]) -> bool {
+    code.starts_with(&EOF_MAGIC)
+}
+
+/// Validates the EOF container held by `address` (see EIP-3670).
+pub(crate) fn eof_container_info(address: H160, code: &[u8]) -> EofContainerInfo {
+    match validate_raw_eof(Bytes::copy_from_slice(code)) {
+        Ok(eof) => EofContainerInfo {
+            address,
+            validation_error: None,
+            num_code_sections: eof.body.code_section.len(),
+            num_subcontainers: eof.body.container_section.len(),
+            data_size: eof.header.data_size as usize,
+        },
+        Err(err) => EofContainerInfo {
+            address,
+            validation_error: Some(err.to_string()),
+            num_code_sections: 0,
+            num_subcontainers: 0,
+            data_size: 0,
+        },
+    }
+}
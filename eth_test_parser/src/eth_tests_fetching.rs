@@ -1,31 +1,219 @@
 //! Utils to clone and pull the eth test repo.
 
-use std::{fs, path::Path, process::Command};
+use std::{
+    collections::HashSet,
+    fs, iter,
+    path::{Component, Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Context};
 
 use crate::{
     config::{
-        ETH_TESTS_REPO_LOCAL_PATH, ETH_TESTS_REPO_URL, GENERAL_GROUP, SPECIAL_TEST_SUBGROUPS,
+        ETH_TESTS_LOCK_PATH, ETH_TESTS_REPO_LOCAL_PATH, ETH_TESTS_REPO_URL, GENERAL_GROUP,
+        PYSPECS_SUBGROUP, SPECIAL_TEST_SUBGROUPS, STATE_TESTS_GROUP, TEST_GROUPS,
     },
     fs_scaffolding::get_test_group_dirs,
     utils::run_cmd,
 };
 
-pub(crate) fn clone_or_update_remote_tests() {
-    if Path::new(&ETH_TESTS_REPO_LOCAL_PATH).exists() {
-        update_remote_tests();
-    } else {
+/// Returns the commit of the local `ethereum/tests` checkout.
+pub fn get_eth_tests_commit() -> anyhow::Result<String> {
+    let commit =
+        run_cmd(Command::new("git").args(["-C", ETH_TESTS_REPO_LOCAL_PATH, "rev-parse", "HEAD"]))?;
+
+    Ok(commit.trim().to_string())
+}
+
+/// Reads the commit pinned in `eth_tests.lock`.
+pub fn read_eth_tests_lock() -> anyhow::Result<String> {
+    let commit = fs::read_to_string(ETH_TESTS_LOCK_PATH)
+        .with_context(|| format!("Reading {}", ETH_TESTS_LOCK_PATH))?;
+
+    Ok(commit.trim().to_string())
+}
+
+pub fn write_eth_tests_lock(commit: &str) -> anyhow::Result<()> {
+    fs::write(ETH_TESTS_LOCK_PATH, format!("{}\n", commit))
+        .with_context(|| format!("Writing {}", ETH_TESTS_LOCK_PATH))
+}
+
+/// Fails if the local checkout is not at the commit pinned in the lockfile.
+pub fn check_eth_tests_commit(locked_commit: &str) -> anyhow::Result<()> {
+    let commit = get_eth_tests_commit()?;
+    if commit != locked_commit {
+        return Err(anyhow!(
+            "The local tests are at commit {}, but {} pins {}",
+            commit,
+            ETH_TESTS_LOCK_PATH,
+            locked_commit
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the keys (see [`test_file_key`]) of the test files of the checkout
+/// at `repo` that were added or modified since the given commit (see
+/// `--since`), as laid out once the special folders and the Pyspecs are
+/// flattened.
+pub fn get_test_files_changed_since(repo: &Path, commit: &str) -> anyhow::Result<HashSet<PathBuf>> {
+    // The clone is shallow, so the commit may need to be fetched first.
+    run_cmd(
+        Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(["fetch", "--depth=1", "origin", commit]),
+    )
+    .with_context(|| format!("Fetching the commit {} of the tests", commit))?;
+    let changed_files = run_cmd(Command::new("git").arg("-C").arg(repo).args([
+        "diff",
+        "--name-only",
+        "--diff-filter=d",
+        commit,
+        "HEAD",
+    ]))?;
+
+    Ok(changed_files
+        .lines()
+        .filter_map(|path| test_file_key(&flattened_path(Path::new(path))))
+        .collect())
+}
+
+/// The key a test file is matched with the changed files on: its path from
+/// its test group, without extension (eg.
+/// `GeneralStateTests/stCreate2/CREATE2_Bounds`). It is the same for a test
+/// file, its state test counterpart and its parsed test.
+pub fn test_file_key(path: &Path) -> Option<PathBuf> {
+    let components: Vec<_> = path.components().collect();
+    let group_idx = components
+        .iter()
+        .rposition(|c| TEST_GROUPS.iter().any(|group| c.as_os_str() == *group))?;
+
+    let mut key: PathBuf = components[group_idx..].iter().collect();
+    key.set_extension("");
+    Some(key)
+}
+
+/// The path of a test file of the upstream repo once its folder is flattened
+/// (see [`flatten_special_folders`] and [`flatten_pyspecs`]).
+fn flattened_path(path: &Path) -> PathBuf {
+    let components: Vec<Component> = path.components().collect();
+    let Some(group_idx) = components
+        .iter()
+        .rposition(|c| TEST_GROUPS.iter().any(|group| c.as_os_str() == *group))
+    else {
+        return path.to_path_buf();
+    };
+    let (prefix, rest) = components.split_at(group_idx + 1);
+    let [sub_group, dirs @ .., file] = rest else {
+        return path.to_path_buf();
+    };
+    let sub_group = sub_group.as_os_str().to_string_lossy();
+    let flattened_sub_group = match dirs {
+        [] => return path.to_path_buf(),
+        _ if sub_group == PYSPECS_SUBGROUP => iter::once(sub_group.as_ref())
+            .chain(
+                dirs.iter()
+                    .map(|dir| dir.as_os_str().to_str().unwrap_or_default()),
+            )
+            .collect::<Vec<_>>()
+            .join("_"),
+        [_] if SPECIAL_TEST_SUBGROUPS.contains(&sub_group.as_ref()) => sub_group.into_owned(),
+        _ => return path.to_path_buf(),
+    };
+
+    prefix
+        .iter()
+        .collect::<PathBuf>()
+        .join(flattened_sub_group)
+        .join(file)
+}
+
+/// Clones the tests if needed, and moves them either to the given commit or
+/// to the latest one.
+pub fn clone_or_update_remote_tests(locked_commit: Option<&str>) {
+    if !Path::new(&ETH_TESTS_REPO_LOCAL_PATH).exists() {
         download_remote_tests();
     }
 
-    // Flatten special folders before parsing test files
-    flatten_special_folders();
+    match locked_commit {
+        Some(commit) => checkout_locked_tests(commit),
+        None => update_remote_tests(),
+    }
+
+    // Flatten special folders before parsing test files, from scratch so that
+    // the copies of tests since removed upstream don't linger.
+    clean_checkout(GENERAL_GROUP);
+    flatten_special_folders(get_test_group_dirs().unwrap().map(|entry| entry.path()));
+    flatten_pyspecs();
+}
+
+/// Adds the tests in the plain state test format to the local checkout (see
+/// `--state-tests`), which only has the blockchain tests by default.
+pub fn checkout_state_tests() {
+    println!(
+        "Adding the state tests to the sparse checkout... ({})",
+        STATE_TESTS_GROUP
+    );
+    run_cmd(Command::new("git").args([
+        "-C",
+        ETH_TESTS_REPO_LOCAL_PATH,
+        "sparse-checkout",
+        "add",
+        STATE_TESTS_GROUP,
+    ]))
+    .unwrap();
+
+    clean_checkout(STATE_TESTS_GROUP);
+    flatten_special_folders(iter::once(
+        Path::new(ETH_TESTS_REPO_LOCAL_PATH).join(STATE_TESTS_GROUP),
+    ));
+}
+
+/// Copies the tests of each (possibly nested) folder of the Pyspecs subgroup to
+/// their own subgroup, named after their path (see [`PYSPECS_SUBGROUP`]).
+fn flatten_pyspecs() {
+    for group in get_test_group_dirs().unwrap() {
+        let pyspecs_dir = group.path().join(PYSPECS_SUBGROUP);
+        if !pyspecs_dir.is_dir() {
+            continue;
+        }
+
+        for entry in fs::read_dir(&pyspecs_dir).unwrap().flatten() {
+            if entry.path().is_dir() {
+                copy_pyspecs_tests(&entry.path(), &group.path(), PYSPECS_SUBGROUP);
+            }
+        }
+    }
+}
+
+/// Copies the JSON tests of `dir` to the subgroup of `group_dir` named after
+/// `parent_sub_group` and `dir`, and recurses into the folders of `dir`.
+fn copy_pyspecs_tests(dir: &Path, group_dir: &Path, parent_sub_group: &str) {
+    let sub_group = format!(
+        "{}_{}",
+        parent_sub_group,
+        dir.file_name().unwrap().to_string_lossy()
+    );
+
+    for entry in fs::read_dir(dir).unwrap().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            copy_pyspecs_tests(&path, group_dir, &sub_group);
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            let sub_group_dir = group_dir.join(&sub_group);
+            fs::create_dir_all(&sub_group_dir).unwrap();
+            fs::copy(&path, sub_group_dir.join(entry.file_name())).unwrap();
+        }
+    }
 }
 
 #[allow(clippy::permissions_set_readonly_false)]
-fn flatten_special_folders() {
-    let dirs = get_test_group_dirs()
-        .unwrap()
-        .flat_map(|entry| fs::read_dir(entry.path()).unwrap())
+fn flatten_special_folders(group_dirs: impl Iterator<Item = PathBuf>) {
+    let dirs = group_dirs
+        .flat_map(|dir| fs::read_dir(dir).unwrap())
         .flatten()
         .filter(|entry| match entry.file_name().to_str() {
             Some(file_name) => SPECIAL_TEST_SUBGROUPS.contains(&file_name),
@@ -64,14 +252,59 @@ fn flatten_special_folders() {
     });
 }
 
+/// Removes the untracked files (such as the flattened copies of the tests) of
+/// the given folder of the checkout.
+fn clean_checkout(dir: &str) {
+    run_cmd(Command::new("git").args([
+        "-C",
+        ETH_TESTS_REPO_LOCAL_PATH,
+        "clean",
+        "-fdx",
+        "--",
+        dir,
+    ]))
+    .unwrap();
+}
+
 fn update_remote_tests() {
-    println!("Pulling for the most recent changes for the Ethereum tests repo...");
-    run_cmd(
-        Command::new("git")
-            .arg("pull")
-            .current_dir(ETH_TESTS_REPO_LOCAL_PATH),
-    )
+    println!("Fetching the most recent changes for the Ethereum tests repo...");
+    // A locked commit may have been checked out before, leaving a detached HEAD
+    // that can't be pulled, so the default branch is checked out explicitly.
+    run_cmd(Command::new("git").args([
+        "-C",
+        ETH_TESTS_REPO_LOCAL_PATH,
+        "fetch",
+        "--depth=1",
+        "origin",
+    ]))
+    .unwrap();
+    run_cmd(Command::new("git").args([
+        "-C",
+        ETH_TESTS_REPO_LOCAL_PATH,
+        "reset",
+        "--hard",
+        "origin/HEAD",
+    ]))
+    .unwrap();
+}
+
+fn checkout_locked_tests(commit: &str) {
+    println!(
+        "Checking out the locked commit of the Ethereum tests repo... ({})",
+        commit
+    );
+    // The clone is shallow, so the commit may need to be fetched first.
+    run_cmd(Command::new("git").args([
+        "-C",
+        ETH_TESTS_REPO_LOCAL_PATH,
+        "fetch",
+        "--depth=1",
+        "origin",
+        commit,
+    ]))
     .unwrap();
+    run_cmd(Command::new("git").args(["-C", ETH_TESTS_REPO_LOCAL_PATH, "checkout", commit]))
+        .unwrap();
 }
 
 fn download_remote_tests() {
@@ -98,7 +331,8 @@ fn download_remote_tests() {
         "Setting sparse checkout for test groups... ({})",
         GENERAL_GROUP
     );
-    // sparse-checkout out the relevant test group folders.
+    // sparse-checkout out the relevant test group folders, which include the
+    // Pyspecs tests.
     run_cmd(Command::new("git").args([
         "-C",
         ETH_TESTS_REPO_LOCAL_PATH,
@@ -2,16 +2,17 @@
 //! input and output directories.
 use std::{
     fs::{self, DirEntry, File},
-    io::BufReader,
+    io::{BufReader, Read},
     path::{Path, PathBuf},
 };
 
 use anyhow::{anyhow, Result};
 use common::config::GENERATION_INPUTS_DEFAULT_OUTPUT_DIR;
+use serde::de::DeserializeSeed;
 
 use crate::{
-    config::{ETH_TESTS_REPO_LOCAL_PATH, GENERAL_GROUP, TEST_GROUPS},
-    deserialize::{TestBody, TestFile},
+    config::{Fork, ETH_TESTS_REPO_LOCAL_PATH, GENERAL_GROUP, STATE_TESTS_GROUP, TEST_GROUPS},
+    deserialize::{TestFile, TestFileSeed},
 };
 
 /// Get the default parsed test output directory.
@@ -19,7 +20,7 @@ use crate::{
 /// in the current working directory. If so, we assume we're in a development
 /// context, and default to the project root. Otherwise, we cannot make any
 /// assumptions, fall back to the `GENERATION_INPUTS_DEFAULT_OUTPUT_DIR` value.
-pub(crate) fn get_default_out_dir() -> anyhow::Result<PathBuf> {
+pub fn get_default_out_dir() -> anyhow::Result<PathBuf> {
     let cwd = std::env::current_dir()?;
     let mut dev_check_path = cwd.clone();
     dev_check_path.push("ETH_TEST_PARSER_DEV");
@@ -78,7 +79,8 @@ pub(crate) fn get_test_group_sub_dirs() -> Result<impl Iterator<Item = DirEntry>
     Ok(dirs)
 }
 
-/// Generate an iterator over the entire set of inner test case files.
+/// Generate an iterator over the entire set of inner test case files, or over
+/// those of the given inner test group folders (sub-groups) only.
 ///
 /// Expected directory structure
 /// ```ignore
@@ -87,8 +89,20 @@ pub(crate) fn get_test_group_sub_dirs() -> Result<impl Iterator<Item = DirEntry>
 /// // │   ├── {test_case_1}.json  <--- HERE
 /// // │   └── {test_case_n}.json
 /// ```
-pub(crate) fn get_test_files() -> Result<impl Iterator<Item = DirEntry>> {
-    let dirs = get_test_group_sub_dirs()?
+pub fn get_test_files(sub_groups: &[String]) -> Result<impl Iterator<Item = DirEntry> + '_> {
+    let sub_group_dirs: Vec<_> = get_test_group_sub_dirs()?.collect();
+    let sub_group_name = |entry: &DirEntry| entry.file_name().to_string_lossy().into_owned();
+    if let Some(unknown) = sub_groups.iter().find(|sub_group| {
+        !sub_group_dirs
+            .iter()
+            .any(|e| sub_group_name(e) == **sub_group)
+    }) {
+        return Err(anyhow!("Unknown test sub-group {}", unknown));
+    }
+
+    let dirs = sub_group_dirs
+        .into_iter()
+        .filter(move |entry| sub_groups.is_empty() || sub_groups.contains(&sub_group_name(entry)))
         .flat_map(|entry| fs::read_dir(entry.path()))
         .flatten()
         .flatten()
@@ -100,9 +114,52 @@ pub(crate) fn get_test_files() -> Result<impl Iterator<Item = DirEntry>> {
     Ok(dirs)
 }
 
+/// Generate the list of the test files in the plain state test format that
+/// have no blockchain test counterpart, of all the sub-groups or of the given
+/// ones only. Each comes with the path its counterpart would have, which its
+/// parsed test mirrors.
+///
+/// Expected directory structure
+/// ```ignore
+/// // GeneralStateTests
+/// // ├── {TestNameN}
+/// // │   ├── {test_case_1}.json  <--- HERE
+/// // │   └── {test_case_n}.json
+/// ```
+pub fn get_state_test_files(sub_groups: &[String]) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let state_tests_dir = Path::new(ETH_TESTS_REPO_LOCAL_PATH).join(STATE_TESTS_GROUP);
+    let blockchain_tests_dir = Path::new(ETH_TESTS_REPO_LOCAL_PATH).join(GENERAL_GROUP);
+
+    let mut files = Vec::new();
+    for sub_group_dir in fs::read_dir(&state_tests_dir)?.flatten() {
+        let sub_group = sub_group_dir.file_name().to_string_lossy().into_owned();
+        if !sub_groups.is_empty() && !sub_groups.contains(&sub_group) {
+            continue;
+        }
+
+        for entry in fs::read_dir(sub_group_dir.path())
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let counterpart = blockchain_tests_dir.join(path.strip_prefix(&state_tests_dir)?);
+            if !counterpart.exists() {
+                files.push((path, counterpart));
+            }
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
 /// Create output directories mirroring the structure of source test
 /// directories.
-pub(crate) fn prepare_output_dir(out_path: &Path) -> Result<()> {
+pub fn prepare_output_dir(out_path: &Path) -> Result<()> {
     for dir in get_test_group_sub_dirs()? {
         fs::create_dir_all(out_path.join(dir.path().strip_prefix(ETH_TESTS_REPO_LOCAL_PATH)?))?
     }
@@ -110,31 +167,26 @@ pub(crate) fn prepare_output_dir(out_path: &Path) -> Result<()> {
     Ok(())
 }
 
-/// Generate an iterator containing the deserialized test bodies (`TestBody`)
-/// and their `DirEntry`s.
-#[allow(clippy::type_complexity)]
-pub(crate) fn get_deserialized_test_bodies(
-) -> Result<impl Iterator<Item = Result<(DirEntry, Vec<TestBody>), (String, String)>>> {
-    Ok(get_test_files()?.map(|entry| {
-        let test_body = get_deserialized_test_body(&entry)
-            .map_err(|err| (err.to_string(), entry.path().to_string_lossy().to_string()))?;
-        Ok((entry, test_body))
-    }))
-}
-
-fn get_deserialized_test_body(entry: &DirEntry) -> Result<Vec<TestBody>> {
-    if entry.path().to_str().unwrap().contains("ValueOverflow") {
+/// Deserializes all the test bodies (`TestBody`) of the given fork of a test
+/// file, along with the reasons why the others were skipped.
+pub(crate) fn get_deserialized_test_body(path: &Path, fork: Fork) -> Result<TestFile> {
+    if path.to_str().unwrap().contains("ValueOverflow") {
         return Err(anyhow!(
             "Test has invalid RLP encoding and hence cannot be processed"
         ));
     }
-    let buf = BufReader::new(File::open(entry.path())?);
-    let test_file: TestFile = serde_json::from_reader(buf)?;
+    deserialize_test_file(BufReader::new(File::open(path)?), fork)
+}
+
+/// Deserializes all the test bodies of the given fork of the JSON test file
+/// read from `reader`, along with the reasons why the others were skipped.
+pub(crate) fn deserialize_test_file(reader: impl Read, fork: Fork) -> Result<TestFile> {
+    let test_file =
+        TestFileSeed(fork).deserialize(&mut serde_json::Deserializer::from_reader(reader))?;
 
-    let tests: Vec<TestBody> = test_file.0.into_values().collect();
-    if tests.is_empty() {
+    if test_file.variants.is_empty() && test_file.diagnostics.is_empty() {
         Err(anyhow!("No valid tests found"))
     } else {
-        anyhow::Ok(tests)
+        anyhow::Ok(test_file)
     }
 }
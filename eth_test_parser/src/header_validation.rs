@@ -0,0 +1,139 @@
+//! Consistency checks of the block headers of the tests.
+//!
+//! Some header fields can be derived from the rest of the test: the gas used,
+//! the logs bloom and the receipts root from the execution of the block with
+//! `revm`, the base fee from the parent header, and the transactions and
+//! withdrawals roots from the block body. A test whose headers disagree with
+//! them is broken, and failures to prove it would say nothing about the
+//! prover.
+
+use std::{collections::HashMap, iter};
+
+use anyhow::{Context, Result};
+use common::types::AccountState;
+use ethereum_types::{H160, H256, U256};
+use rlp::RlpStream;
+
+use crate::{
+    block_execution::{execute_block, list_trie_root},
+    config::Fork,
+    deserialize::{Block, BlockHeader, TestBody},
+};
+
+/// The maximum change of the base fee between two blocks, as a fraction of it
+/// (see EIP-1559).
+const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+const ELASTICITY_MULTIPLIER: u64 = 2;
+
+/// Returns a description of each header field of `test` that disagrees with
+/// the value derived from the rest of the test.
+pub(crate) fn header_inconsistencies(test: &TestBody) -> Result<Vec<String>> {
+    let mut inconsistencies = Vec::new();
+    let mut state: HashMap<H160, AccountState> = test
+        .pre
+        .iter()
+        .map(|(addr, pre_acc)| (*addr, pre_acc.into()))
+        .collect();
+    let mut parent = &test.genesis_block.block_header;
+
+    // The header of a block whose invalid transactions were dropped is
+    // recomputed by the parser, so there is nothing to check.
+    let num_checked_blocks = test.chained_blocks.len() + 1 - test.dropped_invalid_txns as usize;
+    let blocks = iter::once(&test.block)
+        .chain(test.chained_blocks.iter())
+        .take(num_checked_blocks);
+    for (block_idx, block) in blocks.enumerate() {
+        let executed = execute_block(&state, block, test.fork, test.chain_id)
+            .with_context(|| format!("Executing block {}", block_idx + 1))?;
+        let header = &block.block_header;
+
+        let mut check = |field: &str, expected: String, derived: String| {
+            if expected != derived {
+                inconsistencies.push(format!(
+                    "Block {}: {} is {}, but should be {}",
+                    block_idx + 1,
+                    field,
+                    expected,
+                    derived
+                ));
+            }
+        };
+        check(
+            "the gas used",
+            header.gas_used.to_string(),
+            executed.gas_used.to_string(),
+        );
+        check(
+            "the logs bloom",
+            hex::encode(&header.bloom),
+            hex::encode(executed.logs_bloom()),
+        );
+        check(
+            "the transactions root",
+            format!("{:?}", header.transactions_trie),
+            format!(
+                "{:?}",
+                list_trie_root(block.txn().map(<[u8]>::to_vec).into_iter())
+            ),
+        );
+        check(
+            "the receipts root",
+            format!("{:?}", header.receipt_trie),
+            format!(
+                "{:?}",
+                list_trie_root(block.txn().map(|txn| executed.receipt(txn)).into_iter())
+            ),
+        );
+        if test.fork >= Fork::London {
+            check(
+                "the base fee",
+                header.base_fee_per_gas.to_string(),
+                expected_base_fee(parent).to_string(),
+            );
+        }
+        if test.fork >= Fork::Shanghai {
+            check(
+                "the withdrawals root",
+                format!("{:?}", header.withdrawals_root.0.unwrap_or_default()),
+                format!("{:?}", withdrawals_root(block)),
+            );
+        }
+
+        state = executed.state;
+        parent = header;
+    }
+
+    Ok(inconsistencies)
+}
+
+/// The base fee of the child of `parent` (see EIP-1559).
+fn expected_base_fee(parent: &BlockHeader) -> U256 {
+    let parent_base_fee = parent.base_fee_per_gas;
+    let gas_target = parent.gas_limit / ELASTICITY_MULTIPLIER;
+    if gas_target.is_zero() || parent.gas_used == gas_target {
+        return parent_base_fee;
+    }
+
+    if parent.gas_used > gas_target {
+        let delta = parent_base_fee * (parent.gas_used - gas_target)
+            / gas_target
+            / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+        parent_base_fee + delta.max(U256::one())
+    } else {
+        let delta = parent_base_fee * (gas_target - parent.gas_used)
+            / gas_target
+            / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+        parent_base_fee.saturating_sub(delta)
+    }
+}
+
+fn withdrawals_root(block: &Block) -> H256 {
+    list_trie_root(block.withdrawals.iter().map(|w| {
+        let mut stream = RlpStream::new_list(4);
+        stream.append(&w.index);
+        stream.append(&w.validator_index);
+        stream.append(&w.address);
+        stream.append(&w.amount);
+        stream.out().to_vec()
+    }))
+}
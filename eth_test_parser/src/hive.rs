@@ -0,0 +1,307 @@
+//! Ingestion of the fixtures of hive's consensus simulators, so that the chains
+//! they produce can go through the same parse→prove pipeline as the Ethereum
+//! tests.
+//!
+//! A fixture is a directory holding the `genesis.json` of the chain (in the
+//! format of geth) and its blocks, RLP encoded one after the other in
+//! `chain.rlp`. Unlike the tests, fixtures don't give their post-state, which
+//! is obtained by executing their blocks and checked against the state root of
+//! the last one.
+//!
+//! Expected directory structure
+//! ```ignore
+//! // {HiveDir}
+//! // ├── {FixtureN}
+//! // │   ├── genesis.json
+//! // │   └── chain.rlp
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use common::{config::ETHEREUM_CHAIN_ID, types::AccountState};
+use ethereum_types::{H160, U256};
+use mpt_trie::partial_trie::PartialTrie;
+use rlp::Rlp;
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    block_execution::execute_block,
+    config::Fork,
+    deserialize::{
+        exit_root_pre_account, Block, BlockHeader, ByteString, GenesisBlock, PreAccount, TestBody,
+        TestFile, EXIT_ROOT_ADDRESS,
+    },
+    diagnostics::{Diagnostic, DiagnosticReason},
+    trie_builder::{build_state_tries, check_state_root},
+};
+
+/// The group under which the parsed fixtures are written, next to the
+/// `GeneralStateTests` of the Ethereum tests.
+pub const HIVE_GROUP: &str = "Hive";
+const GENESIS_FILE_NAME: &str = "genesis.json";
+const CHAIN_FILE_NAME: &str = "chain.rlp";
+
+/// The fields of a geth genesis file that the parser needs. The other fields
+/// of the genesis header don't affect the execution of the chain.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct HiveGenesis {
+    #[serde(default)]
+    config: HiveChainConfig,
+    alloc: HashMap<String, HiveAccount>,
+    #[serde(deserialize_with = "u256_from_str")]
+    gas_limit: U256,
+    #[serde(default, deserialize_with = "u256_from_str")]
+    difficulty: U256,
+    #[serde(default, deserialize_with = "u256_from_str")]
+    timestamp: U256,
+    #[serde(default, deserialize_with = "u256_from_str")]
+    base_fee_per_gas: U256,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct HiveChainConfig {
+    chain_id: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct HiveAccount {
+    #[serde(deserialize_with = "u256_from_str")]
+    balance: U256,
+    #[serde(default)]
+    code: Option<ByteString>,
+    #[serde(default, deserialize_with = "u256_from_str")]
+    nonce: U256,
+    #[serde(default)]
+    storage: HashMap<String, String>,
+}
+
+/// Deserializes a number given either as a `0x`-prefixed hex string or as a
+/// decimal one, as geth accepts both.
+fn u256_from_str<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+    let s: String = Deserialize::deserialize(deserializer)?;
+    parse_u256(&s).map_err(serde::de::Error::custom)
+}
+
+fn parse_u256(s: &str) -> Result<U256> {
+    match s.strip_prefix("0x") {
+        Some("") => Ok(U256::zero()),
+        Some(hex) => Ok(U256::from_str_radix(hex, 16)?),
+        None => Ok(U256::from_dec_str(s).map_err(|err| anyhow::anyhow!("{:?}", err))?),
+    }
+}
+
+/// Finds the fixtures in `dir`, ie. its sub-directories holding both a
+/// `genesis.json` and a `chain.rlp`.
+pub fn get_hive_fixtures(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut fixtures: Vec<_> = fs::read_dir(dir)
+        .with_context(|| format!("Reading the hive fixtures in {:?}", dir))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.join(GENESIS_FILE_NAME).is_file() && path.join(CHAIN_FILE_NAME).is_file()
+        })
+        .collect();
+    fixtures.sort();
+
+    Ok(fixtures)
+}
+
+/// Deserializes the fixture at `dir` into a test file holding a single
+/// variant named after the fixture, or the reason why it was skipped.
+pub(crate) fn read_hive_fixture(dir: &Path, fork: Fork) -> Result<TestFile> {
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let genesis_path = dir.join(GENESIS_FILE_NAME);
+    let genesis: HiveGenesis = serde_json::from_reader(BufReader::new(
+        File::open(&genesis_path).with_context(|| format!("Opening {:?}", genesis_path))?,
+    ))
+    .with_context(|| format!("Deserializing {:?}", genesis_path))?;
+    let chain_path = dir.join(CHAIN_FILE_NAME);
+    let chain = fs::read(&chain_path).with_context(|| format!("Reading {:?}", chain_path))?;
+
+    let mut test_file = TestFile::default();
+    match TestBody::from_hive_fixture(&genesis, &chain, name.clone(), fork) {
+        Ok(test_body) => {
+            test_file.variants.insert(name, test_body);
+        }
+        Err(diagnostic) => test_file.diagnostics.push(diagnostic),
+    }
+
+    Ok(test_file)
+}
+
+impl TestBody {
+    /// Fails with the reason to skip the fixture if the prover can't be given
+    /// its chain.
+    fn from_hive_fixture(
+        genesis: &HiveGenesis,
+        chain: &[u8],
+        name: String,
+        fork: Fork,
+    ) -> Result<Self, Diagnostic> {
+        let error = |reason, message: String| Diagnostic::new(reason, Some(&name), message);
+        let parse_error =
+            |err: anyhow::Error| error(DiagnosticReason::ParseError, format!("{:#}", err));
+
+        match genesis.config.chain_id {
+            Some(chain_id) if chain_id != ETHEREUM_CHAIN_ID => {
+                return Err(error(
+                    DiagnosticReason::BuildError,
+                    format!(
+                        "The chain ID is {}, but the prover expects {}",
+                        chain_id, ETHEREUM_CHAIN_ID
+                    ),
+                ))
+            }
+            _ => (),
+        }
+
+        let mut blocks = decode_chain(chain).map_err(|err| match err {
+            ChainError::MultipleTransactions(block_idx) => error(
+                DiagnosticReason::MultipleTransactions,
+                format!("Block {} has several transactions", block_idx + 1),
+            ),
+            ChainError::Decoding(err) => parse_error(err),
+        })?;
+        if blocks.is_empty() {
+            return Err(parse_error(anyhow::anyhow!("The chain has no block")));
+        }
+
+        let alloc = genesis_alloc(genesis).map_err(parse_error)?;
+        let alloc_state: HashMap<_, AccountState> = alloc
+            .iter()
+            .map(|(addr, acc)| (*addr, acc.into()))
+            .collect();
+        let genesis_block = GenesisBlock {
+            block_header: BlockHeader {
+                state_root: build_state_tries(&alloc_state).0.hash(),
+                difficulty: genesis.difficulty,
+                gas_limit: genesis.gas_limit,
+                timestamp: genesis.timestamp,
+                base_fee_per_gas: genesis.base_fee_per_gas,
+                // Hashing the genesis header would require all the fields that
+                // its fork adds, so the hash is taken from its child instead.
+                hash: blocks[0].block_header.parent_hash,
+                ..Default::default()
+            },
+            _transactions: Vec::new(),
+            _uncle_headers: Vec::new(),
+            _withdrawals: Vec::new(),
+        };
+
+        let mut pre = alloc;
+        pre.insert(EXIT_ROOT_ADDRESS, exit_root_pre_account());
+        let post = chain_post_state(&pre, &blocks, fork)
+            .map_err(|err| error(DiagnosticReason::BuildError, format!("{:#}", err)))?;
+
+        let block = blocks.remove(0);
+        Ok(Self {
+            name,
+            fork,
+            block,
+            chained_blocks: blocks,
+            dropped_invalid_txns: false,
+            chain_id: ETHEREUM_CHAIN_ID,
+            genesis_block,
+            pre,
+            post,
+        })
+    }
+}
+
+enum ChainError {
+    /// The index of the block with several transactions, which the prover
+    /// can't be given.
+    MultipleTransactions(usize),
+    Decoding(anyhow::Error),
+}
+
+/// Decodes the blocks of `chain.rlp`, which are concatenated.
+fn decode_chain(mut chain: &[u8]) -> Result<Vec<Block>, ChainError> {
+    let decoding_error = |err| ChainError::Decoding(anyhow::anyhow!("Decoding: {}", err));
+
+    let mut blocks = Vec::new();
+    while !chain.is_empty() {
+        let len = Rlp::new(chain)
+            .payload_info()
+            .map_err(decoding_error)?
+            .total();
+        let block_bytes = chain
+            .get(..len)
+            .ok_or_else(|| decoding_error(rlp::DecoderError::RlpIsTooShort))?;
+        let block_rlp = Rlp::new(block_bytes);
+        if block_rlp
+            .at(1)
+            .and_then(|txns| txns.item_count())
+            .map_err(decoding_error)?
+            > 1
+        {
+            return Err(ChainError::MultipleTransactions(blocks.len()));
+        }
+        blocks.push(block_rlp.as_val().map_err(decoding_error)?);
+        chain = &chain[len..];
+    }
+
+    Ok(blocks)
+}
+
+fn genesis_alloc(genesis: &HiveGenesis) -> Result<HashMap<H160, PreAccount>> {
+    genesis
+        .alloc
+        .iter()
+        .map(|(address, account)| {
+            let bytes = hex::decode(address.strip_prefix("0x").unwrap_or(address))
+                .ok()
+                .filter(|bytes| bytes.len() == H160::len_bytes())
+                .ok_or_else(|| anyhow::anyhow!("Invalid address {}", address))?;
+            let address = H160::from_slice(&bytes);
+            let storage = account
+                .storage
+                .iter()
+                .map(|(key, value)| Ok((parse_u256(key)?, parse_u256(value)?)))
+                .collect::<Result<_>>()
+                .with_context(|| format!("Decoding the storage of {:?}", address))?;
+            let pre_account = PreAccount {
+                balance: account.balance,
+                code: account.code.clone().unwrap_or(ByteString(Vec::new())),
+                nonce: account.nonce.low_u64(),
+                storage,
+            };
+
+            Ok((address, pre_account))
+        })
+        .collect()
+}
+
+/// The state after all the blocks of the chain, checked against the state root
+/// of the last one.
+fn chain_post_state(
+    pre: &HashMap<H160, PreAccount>,
+    blocks: &[Block],
+    fork: Fork,
+) -> Result<HashMap<H160, PreAccount>> {
+    let mut state: HashMap<_, AccountState> =
+        pre.iter().map(|(addr, acc)| (*addr, acc.into())).collect();
+    for (block_idx, block) in blocks.iter().enumerate() {
+        state = execute_block(&state, block, fork, ETHEREUM_CHAIN_ID)
+            .with_context(|| format!("Executing block {}", block_idx + 1))?
+            .state;
+    }
+    check_state_root(&state, &blocks.last().unwrap().block_header)
+        .context("Executing the last block")?;
+
+    Ok(state
+        .iter()
+        .map(|(addr, acc)| (*addr, acc.into()))
+        .collect())
+}
@@ -0,0 +1,108 @@
+//! Report of the largest parsed tests, which are usually the ones that make
+//! the runner slow or run out of memory.
+
+use common::types::ParsedTestManifest;
+
+/// The size of the generation inputs of a test file.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TestSize {
+    cbor_bytes: usize,
+    /// The number of accounts of the largest pre-state of the variants.
+    num_accounts: usize,
+    /// The size of the contract code of the variant with the most.
+    code_bytes: usize,
+}
+
+impl TestSize {
+    pub(crate) fn new(manifest: &ParsedTestManifest, cbor_bytes: usize) -> Self {
+        let variants = manifest.plonky2_variants.iter();
+        let num_accounts = variants
+            .clone()
+            .map(|v| v.plonky2_metadata.pre_state.len())
+            .max()
+            .unwrap_or_default();
+        let code_bytes = variants
+            .map(|v| {
+                v.plonky2_metadata
+                    .contract_code
+                    .values()
+                    .map(Vec::len)
+                    .sum()
+            })
+            .max()
+            .unwrap_or_default();
+
+        Self {
+            cbor_bytes,
+            num_accounts,
+            code_bytes,
+        }
+    }
+}
+
+/// The sizes of all the parsed test files, to print the largest ones once they
+/// are all written.
+pub(crate) struct LargestTests {
+    num_largest: usize,
+    sizes: Vec<(String, TestSize)>,
+}
+
+impl LargestTests {
+    pub(crate) fn new(num_largest: usize) -> Self {
+        Self {
+            num_largest,
+            sizes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, test_path: &str, size: TestSize) {
+        self.sizes.push((test_path.to_string(), size));
+    }
+
+    /// Prints the `num_largest` tests by CBOR size, number of accounts and
+    /// code size.
+    pub(crate) fn print(mut self) {
+        if self.num_largest == 0 || self.sizes.is_empty() {
+            return;
+        }
+
+        self.print_table(
+            "CBOR size",
+            |s| s.cbor_bytes,
+            |s| format_bytes(s.cbor_bytes),
+        );
+        self.print_table(
+            "accounts",
+            |s| s.num_accounts,
+            |s| s.num_accounts.to_string(),
+        );
+        self.print_table(
+            "code size",
+            |s| s.code_bytes,
+            |s| format_bytes(s.code_bytes),
+        );
+    }
+
+    fn print_table(
+        &mut self,
+        metric: &str,
+        key: impl Fn(&TestSize) -> usize,
+        format: impl Fn(&TestSize) -> String,
+    ) {
+        self.sizes
+            .sort_by(|(path_a, a), (path_b, b)| key(b).cmp(&key(a)).then(path_a.cmp(path_b)));
+
+        println!("Largest parsed tests by {}:", metric);
+        for (path, size) in self.sizes.iter().take(self.num_largest) {
+            println!("{:>12}  {}", format(size), path);
+        }
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+    }
+}
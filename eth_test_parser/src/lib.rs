@@ -0,0 +1,302 @@
+//! Parsing of the Ethereum JSON tests into generation inputs for Polygon Zero's
+//! EVM.
+//!
+//! This is used by the parser binary (a thin wrapper around [`run_cli`]) to
+//! convert the whole upstream test suite, but also by the runner to parse
+//! individual JSON tests in-process.
+
+use std::path::Path;
+
+use anyhow::Result;
+use common::{
+    config::ETHEREUM_CHAIN_ID,
+    eip_tags::tags_for_test,
+    types::{ParsedTestManifest, Plonky2ParsedTest, MANIFEST_SCHEMA_VERSION},
+};
+
+pub use crate::cli::{run_cli, run_cli_as_subcommand};
+pub use crate::trie_builder::{bloom_words, build_state_tries};
+use crate::{
+    bloom_validation::logs_bloom_mismatches,
+    config::Fork,
+    dedup::dedup_variants,
+    deserialize::{TestBody, TestFile},
+    diagnostics::{Diagnostic, DiagnosticReason},
+    encoding_validation::txn_encoding_issues,
+    fs_scaffolding::{deserialize_test_file, get_deserialized_test_body},
+    header_validation::header_inconsistencies,
+    hive::{read_hive_fixture, HIVE_GROUP},
+    post_state_validation::post_state_mismatches,
+    provability::{Exclusion, ProvabilityLimits, UnprovableVariants},
+    sender_validation::txn_sender_issues,
+    state_tests::read_state_test_file,
+};
+
+mod arg_parsing;
+pub mod block_execution;
+mod bloom_validation;
+mod cli;
+pub mod config;
+mod dedup;
+mod deserialize;
+pub mod diagnostics;
+mod encoding_validation;
+mod eof;
+pub mod eth_tests_fetching;
+pub mod fs_scaffolding;
+mod header_validation;
+pub mod hive;
+mod largest;
+mod post_state_validation;
+mod progress;
+pub mod provability;
+pub mod prover_input_export;
+mod resigning;
+mod sender_validation;
+mod state_tests;
+pub mod t8n_export;
+mod trie_builder;
+pub mod txn_signing;
+mod utils;
+
+/// A parsed test file, along with everything that was skipped or warned about
+/// while parsing it.
+#[derive(Debug)]
+pub struct ParsedTestFile {
+    pub manifest: ParsedTestManifest,
+    pub diagnostics: Vec<Diagnostic>,
+    /// The variants left out for exceeding the limits of the prover.
+    pub exclusions: Vec<Exclusion>,
+}
+
+/// How the variants of the test files are parsed.
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    /// Leave out the variants whose block headers are inconsistent with the
+    /// rest of the test.
+    pub validate_headers: bool,
+    /// The chain id the transactions are re-signed for, unless it is the one
+    /// of Ethereum (see `resigning`).
+    pub chain_id: u64,
+    /// Leave out the variants exceeding these limits.
+    pub limits: ProvabilityLimits,
+    /// Leave out these variants.
+    pub unprovable_variants: UnprovableVariants,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            validate_headers: false,
+            chain_id: ETHEREUM_CHAIN_ID,
+            limits: ProvabilityLimits::default(),
+            unprovable_variants: UnprovableVariants::default(),
+        }
+    }
+}
+
+/// Parses a single JSON test file into a manifest containing all its variants
+/// for the given fork.
+pub fn parse_test_file(path: &Path, fork: Fork, options: ParseOptions) -> Result<ParsedTestFile> {
+    let test_file = get_deserialized_test_body(path, fork)?;
+
+    Ok(parsed_test_file(test_file, options, test_file_tags(path)))
+}
+
+/// Parses a single JSON blockchain test file held in memory, like
+/// [`parse_test_file`] does for one on disk, without touching the filesystem.
+/// This builds the inputs of the prover from fixtures, eg. in tests. The
+/// manifest isn't tagged, as tags are derived from the path of the test file.
+pub fn parse_test_json(json: &str, fork: Fork, options: ParseOptions) -> Result<ParsedTestFile> {
+    let test_file = deserialize_test_file(json.as_bytes(), fork)?;
+
+    Ok(parsed_test_file(test_file, options, Vec::new()))
+}
+
+/// Parses a single test file in the plain `GeneralStateTests` format (see
+/// [`state_tests`]), like [`parse_test_file`] does for blockchain tests.
+pub fn parse_state_test_file(
+    path: &Path,
+    fork: Fork,
+    options: ParseOptions,
+) -> Result<ParsedTestFile> {
+    let test_file = read_state_test_file(path, fork)?;
+
+    Ok(parsed_test_file(test_file, options, test_file_tags(path)))
+}
+
+/// The tags of the test file at `path`, after its sub-group and name.
+fn test_file_tags(path: &Path) -> Vec<String> {
+    let file_stem = |p: Option<&Path>| {
+        p.and_then(|p| p.file_stem())
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+
+    tags_for_test(&file_stem(path.parent()), &file_stem(Some(path)))
+}
+
+/// Builds the manifest of a deserialized test file, with the given tags.
+fn parsed_test_file(
+    TestFile {
+        variants,
+        mut diagnostics,
+    }: TestFile,
+    options: ParseOptions,
+    tags: Vec<String>,
+) -> ParsedTestFile {
+    let mut exclusions = Vec::new();
+    let plonky2_variants = parse_variants(
+        variants.into_values(),
+        options,
+        &mut diagnostics,
+        &mut exclusions,
+    );
+
+    ParsedTestFile {
+        manifest: ParsedTestManifest {
+            schema_version: MANIFEST_SCHEMA_VERSION,
+            plonky2_variants,
+            tags,
+        },
+        diagnostics,
+        exclusions,
+    }
+}
+
+/// Parses the hive fixture at `dir` (see [`hive`]) into a manifest containing
+/// its chain as a single variant, named after the fixture.
+pub fn parse_hive_fixture(dir: &Path, fork: Fork, options: ParseOptions) -> Result<ParsedTestFile> {
+    let test_file = read_hive_fixture(dir, fork)?;
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Ok(parsed_test_file(
+        test_file,
+        options,
+        tags_for_test(HIVE_GROUP, &name),
+    ))
+}
+
+/// Builds the generation inputs of the given variants, recording why those
+/// left out were, and which limits those exceeding the limits of the prover
+/// exceeded. Identical variants are only kept once (see [`dedup`]).
+fn parse_variants(
+    variants: impl Iterator<Item = TestBody>,
+    options: ParseOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+    exclusions: &mut Vec<Exclusion>,
+) -> Vec<Plonky2ParsedTest> {
+    let mut plonky2_variants = Vec::new();
+    for mut test in variants {
+        if options.unprovable_variants.contains(&test.name) {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticReason::UnprovableVariant,
+                Some(&test.name),
+                "Listed as unprovable",
+            ));
+            continue;
+        }
+        let test_exclusions = options.limits.exclusions(&test);
+        if !test_exclusions.is_empty() {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticReason::ExceedsProverLimits,
+                Some(&test.name),
+                test_exclusions
+                    .iter()
+                    .map(Exclusion::description)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ));
+            exclusions.extend(test_exclusions);
+            continue;
+        }
+        if options.validate_headers && !has_consistent_headers(&test, diagnostics) {
+            continue;
+        }
+        // Re-signed transactions are re-encoded, so they are checked before.
+        let issues = txn_encoding_issues(&test);
+        if !issues.is_empty() {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticReason::NonCanonicalTxnEncoding,
+                Some(&test.name),
+                issues.join("\n"),
+            ));
+        }
+        if options.chain_id != ETHEREUM_CHAIN_ID {
+            if let Err(err) = test.resign_txns(options.chain_id) {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticReason::BuildError,
+                    Some(&test.name),
+                    format!("{:#}", err),
+                ));
+                continue;
+            }
+        }
+
+        match test.as_plonky2_test_inputs() {
+            Ok(inputs) => {
+                let issues = txn_sender_issues(&inputs);
+                if !issues.is_empty() {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticReason::InvalidTxnSender,
+                        Some(&test.name),
+                        issues.join("\n"),
+                    ));
+                }
+                let mismatches = logs_bloom_mismatches(&inputs);
+                if !mismatches.is_empty() {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticReason::LogsBloomMismatch,
+                        Some(&test.name),
+                        mismatches.join("\n"),
+                    ));
+                }
+                let mismatches = post_state_mismatches(&test, &inputs)
+                    .unwrap_or_else(|err| vec![format!("{:#}", err)]);
+                if !mismatches.is_empty() {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticReason::PostStateMismatch,
+                        Some(&test.name),
+                        mismatches.join("\n"),
+                    ));
+                }
+                plonky2_variants.push(inputs);
+            }
+            Err(err) => diagnostics.push(Diagnostic::new(
+                DiagnosticReason::BuildError,
+                Some(&test.name),
+                format!("{:#}", err),
+            )),
+        }
+    }
+
+    dedup_variants(plonky2_variants)
+}
+
+/// Whether the block headers of `test` are consistent with the rest of it,
+/// recording their inconsistencies otherwise.
+fn has_consistent_headers(test: &TestBody, diagnostics: &mut Vec<Diagnostic>) -> bool {
+    match header_inconsistencies(test) {
+        Ok(inconsistencies) if inconsistencies.is_empty() => true,
+        Ok(inconsistencies) => {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticReason::InconsistentHeaders,
+                Some(&test.name),
+                inconsistencies.join("\n"),
+            ));
+            false
+        }
+        // The test may still be fine, so leave it to the prover.
+        Err(err) => {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticReason::HeaderValidationError,
+                Some(&test.name),
+                format!("{:#}", err),
+            ));
+            true
+        }
+    }
+}
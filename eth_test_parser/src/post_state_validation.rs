@@ -0,0 +1,132 @@
+//! Account-by-account checks of the post-state of the tests.
+//!
+//! The state root alone doesn't say which accounts are wrong, and in
+//! particular whether the self-destructed accounts and the empty accounts
+//! touched by the last block (see EIP-161) are deleted. The post-state of the
+//! test is compared with the one obtained by executing its last block with
+//! `revm`, which also checks the deletions recorded in the manifest (see
+//! `TestMetadata::deleted_accounts`).
+
+use std::collections::BTreeSet;
+
+use anyhow::{Context, Result};
+use common::types::{AccountState, Plonky2ParsedTest};
+use ethereum_types::H160;
+use keccak_hash::keccak;
+
+use crate::{
+    block_execution::{deleted_accounts, execute_block},
+    deserialize::TestBody,
+};
+
+/// Returns a description of each account whose state after the last block of
+/// `test` differs between the test and `revm`.
+pub(crate) fn post_state_mismatches(
+    test: &TestBody,
+    inputs: &Plonky2ParsedTest,
+) -> Result<Vec<String>> {
+    // The post-state of a block whose invalid transactions were dropped, or of
+    // a re-signed test, is computed with `revm` in the first place.
+    if test.dropped_invalid_txns || test.is_resigned() {
+        return Ok(Vec::new());
+    }
+
+    let block = test.chained_blocks.last().unwrap_or(&test.block);
+    let metadata = inputs
+        .chained_blocks
+        .last()
+        .map_or(&inputs.plonky2_metadata, |block| &block.plonky2_metadata);
+    let pre_state = &metadata.pre_state;
+    let revm_state = execute_block(pre_state, block, test.fork, test.chain_id)
+        .context("Executing the last block")?
+        .state;
+    let test_state = &metadata.post_state;
+
+    let mut mismatches = Vec::new();
+    let revm_deleted = deleted_accounts(pre_state, &revm_state);
+    for address in metadata.deleted_accounts.iter() {
+        if !revm_deleted.contains(address) {
+            mismatches.push(format!(
+                "{:?} is deleted by the test, but not by revm",
+                address
+            ));
+        }
+    }
+    for address in revm_deleted.iter() {
+        if !metadata.deleted_accounts.contains(address) {
+            mismatches.push(format!(
+                "{:?} is deleted by revm, but not by the test",
+                address
+            ));
+        }
+    }
+
+    let addresses: BTreeSet<_> = test_state.keys().chain(revm_state.keys()).collect();
+    for address in addresses {
+        match (test_state.get(address), revm_state.get(address)) {
+            (Some(expected), Some(actual)) => {
+                mismatches.extend(account_mismatches(address, expected, actual))
+            }
+            // Deletions are reported above.
+            _ if pre_state.contains_key(address) => {}
+            (Some(_), None) => mismatches.push(format!(
+                "{:?} is created by the test, but not by revm",
+                address
+            )),
+            (None, _) => mismatches.push(format!(
+                "{:?} is created by revm, but not by the test",
+                address
+            )),
+        }
+    }
+
+    Ok(mismatches)
+}
+
+fn account_mismatches(
+    address: &H160,
+    expected: &AccountState,
+    actual: &AccountState,
+) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    let mut check = |field: &str, expected: String, actual: String| {
+        if expected != actual {
+            mismatches.push(format!(
+                "{:?}: the {} is {}, but revm gives {}",
+                address, field, expected, actual
+            ));
+        }
+    };
+
+    check(
+        "balance",
+        expected.balance.to_string(),
+        actual.balance.to_string(),
+    );
+    check(
+        "nonce",
+        expected.nonce.to_string(),
+        actual.nonce.to_string(),
+    );
+    check(
+        "code hash",
+        format!("{:?}", keccak(&expected.code)),
+        format!("{:?}", keccak(&actual.code)),
+    );
+
+    let slots: BTreeSet<_> = expected
+        .storage
+        .keys()
+        .chain(actual.storage.keys())
+        .collect();
+    for slot in slots {
+        let value = |acc: &AccountState| acc.storage.get(slot).copied().unwrap_or_default();
+        check(
+            &format!("storage slot {:#x}", slot),
+            value(expected).to_string(),
+            value(actual).to_string(),
+        );
+    }
+
+    mismatches
+}
@@ -0,0 +1,161 @@
+//! Progress reporting of the parser, which would otherwise go silent for many
+//! minutes while parsing the whole corpus.
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::diagnostics::{Diagnostic, Severity};
+
+/// What came out of parsing a test file.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum FileOutcome {
+    /// At least one variant was parsed.
+    Parsed,
+    /// All the variants were left out.
+    Skipped,
+    /// The file, or one of its variants, couldn't be parsed.
+    Errored,
+}
+
+impl FileOutcome {
+    pub(crate) fn new(num_parsed_variants: usize, diagnostics: &[Diagnostic]) -> Self {
+        if num_parsed_variants > 0 {
+            Self::Parsed
+        } else if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            Self::Errored
+        } else {
+            Self::Skipped
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SubGroupStats {
+    parsed: usize,
+    skipped: usize,
+    errored: usize,
+}
+
+impl Display for SubGroupStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} parsed, {} skipped, {} errored",
+            self.parsed, self.skipped, self.errored
+        )
+    }
+}
+
+trait ParseProgressIndicator {
+    fn set_status(&mut self, num_done: u64, status: String);
+    fn finish(&self);
+}
+
+/// Simple progress indicator that prints a line per file.
+#[derive(Debug)]
+struct SimpleProgressIndicator {
+    num_files: u64,
+}
+
+impl ParseProgressIndicator for SimpleProgressIndicator {
+    fn set_status(&mut self, num_done: u64, status: String) {
+        println!("({}/{}) {}", num_done, self.num_files, status);
+    }
+
+    fn finish(&self) {}
+}
+
+/// More elegant progress indicator that uses a progress bar library.
+#[derive(Debug)]
+struct FancyProgressIndicator {
+    prog_bar: ProgressBar,
+}
+
+impl ParseProgressIndicator for FancyProgressIndicator {
+    fn set_status(&mut self, num_done: u64, status: String) {
+        self.prog_bar.set_position(num_done);
+        self.prog_bar.set_message(status);
+    }
+
+    fn finish(&self) {
+        self.prog_bar.finish_and_clear();
+    }
+}
+
+/// The progress of the parser, with the outcome of the files parsed so far by
+/// sub-group.
+pub(crate) struct ParseProgress {
+    indicator: Box<dyn ParseProgressIndicator>,
+    num_done: u64,
+    bytes_written: u64,
+    stats_by_sub_group: BTreeMap<String, SubGroupStats>,
+}
+
+impl ParseProgress {
+    pub(crate) fn new(num_files: u64, simple_progress_indicator: bool) -> Self {
+        let indicator: Box<dyn ParseProgressIndicator> = match simple_progress_indicator {
+            false => Box::new(FancyProgressIndicator {
+                prog_bar: ProgressBar::new(num_files).with_style(
+                    ProgressStyle::with_template("{bar:60.magenta} {pos}/{len} | {msg}").unwrap(),
+                ),
+            }),
+            true => Box::new(SimpleProgressIndicator { num_files }),
+        };
+
+        Self {
+            indicator,
+            num_done: 0,
+            bytes_written: 0,
+            stats_by_sub_group: BTreeMap::new(),
+        }
+    }
+
+    /// Records the outcome of a file of `sub_group`, for which `bytes_written`
+    /// bytes of generation inputs were written.
+    pub(crate) fn record_file(
+        &mut self,
+        sub_group: &str,
+        file: &str,
+        outcome: FileOutcome,
+        bytes_written: usize,
+    ) {
+        self.num_done += 1;
+        self.bytes_written += bytes_written as u64;
+        let stats = self
+            .stats_by_sub_group
+            .entry(sub_group.to_string())
+            .or_default();
+        match outcome {
+            FileOutcome::Parsed => stats.parsed += 1,
+            FileOutcome::Skipped => stats.skipped += 1,
+            FileOutcome::Errored => stats.errored += 1,
+        }
+
+        let status = format!(
+            "{}: {} | {} written | {}",
+            sub_group,
+            stats,
+            format_size(self.bytes_written),
+            file
+        );
+        self.indicator.set_status(self.num_done, status);
+    }
+
+    /// Clears the progress bar and prints the outcome of the files by
+    /// sub-group.
+    pub(crate) fn finish(self) {
+        self.indicator.finish();
+        for (sub_group, stats) in self.stats_by_sub_group.iter() {
+            println!("{}: {}", sub_group, stats);
+        }
+        println!(
+            "Wrote {} of generation inputs",
+            format_size(self.bytes_written)
+        );
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
@@ -0,0 +1,193 @@
+//! The limits beyond which the prover can't be given a test, the
+//! `excluded.json` manifest of the variants left out for exceeding them, and
+//! the variants known to be unprovable regardless.
+//!
+//! Variants exceeding a limit are still listed in `parse_report.json`, but the
+//! manifest records which limit each one exceeded and by how much, so that the
+//! coverage lost to the limits of the prover can be measured.
+
+use std::{collections::BTreeMap, fs, iter, path::Path, sync::Arc};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::deserialize::TestBody;
+
+/// The file written at the root of the output directory of the parser.
+pub const EXCLUSION_MANIFEST_FILE_NAME: &str = "excluded.json";
+
+/// The limits of the prover that the variants of the tests are checked
+/// against before being parsed.
+#[derive(Clone, Copy, Debug)]
+pub struct ProvabilityLimits {
+    /// The gas used by each block, which the prover requires to fit in 32
+    /// bits.
+    pub max_gas_used: u64,
+    /// The size of the code of each account of the pre-state, in bytes.
+    pub max_code_size: Option<u64>,
+    /// The number of storage slots over all the accounts of the pre-state.
+    pub max_storage_slots: Option<u64>,
+}
+
+impl Default for ProvabilityLimits {
+    fn default() -> Self {
+        Self {
+            max_gas_used: u32::MAX as u64,
+            max_code_size: None,
+            max_storage_slots: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvabilityLimit {
+    GasUsed,
+    CodeSize,
+    StorageSlots,
+}
+
+/// A variant left out for exceeding a limit.
+#[derive(Clone, Debug, Serialize)]
+pub struct Exclusion {
+    pub variant: String,
+    pub limit: ProvabilityLimit,
+    /// The value of the variant exceeding the limit.
+    pub value: u64,
+    pub max: u64,
+}
+
+impl Exclusion {
+    pub fn description(&self) -> String {
+        format!(
+            "The {} of {} exceeds the limit of {}",
+            match self.limit {
+                ProvabilityLimit::GasUsed => "gas used by a block",
+                ProvabilityLimit::CodeSize => "code size of an account",
+                ProvabilityLimit::StorageSlots => "number of storage slots",
+            },
+            self.value,
+            self.max
+        )
+    }
+}
+
+impl ProvabilityLimits {
+    /// Returns an exclusion for each limit `test` exceeds.
+    pub(crate) fn exclusions(&self, test: &TestBody) -> Vec<Exclusion> {
+        let gas_used = iter::once(&test.block)
+            .chain(test.chained_blocks.iter())
+            .map(|block| block.block_header.gas_used)
+            .max()
+            .map_or(0, |gas_used| gas_used.try_into().unwrap_or(u64::MAX));
+        let code_size = test
+            .pre
+            .values()
+            .map(|account| account.code.0.len() as u64)
+            .max()
+            .unwrap_or_default();
+        let storage_slots = test
+            .pre
+            .values()
+            .map(|account| account.storage.len() as u64)
+            .sum();
+
+        [
+            (ProvabilityLimit::GasUsed, gas_used, Some(self.max_gas_used)),
+            (ProvabilityLimit::CodeSize, code_size, self.max_code_size),
+            (
+                ProvabilityLimit::StorageSlots,
+                storage_slots,
+                self.max_storage_slots,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(limit, value, max)| {
+            let max = max.filter(|max| value > *max)?;
+            Some(Exclusion {
+                variant: test.name.clone(),
+                limit,
+                value,
+                max,
+            })
+        })
+        .collect()
+    }
+}
+
+/// The list of the variants known to be unprovable, embedded in the binaries
+/// so that they don't depend on the location of the checkout.
+const BUILTIN_UNPROVABLE_VARIANTS: &str = include_str!("../unprovable_variants.txt");
+
+/// The variants known to be unprovable, which are left out. These are used for
+/// stress testing, with unrealistic scenarios going beyond the provable bounds
+/// of the zkEVM. None are by default.
+#[derive(Clone, Debug, Default)]
+pub struct UnprovableVariants(Arc<[String]>);
+
+impl UnprovableVariants {
+    /// The variants listed in `eth_test_parser/unprovable_variants.txt`.
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_UNPROVABLE_VARIANTS)
+    }
+
+    /// Reads the file at `path`, in the format of the builtin list.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let list = fs::read_to_string(path)
+            .with_context(|| format!("Reading the unprovable variants {:?}", path))?;
+
+        Ok(Self::parse(&list))
+    }
+
+    /// Each line of `list` leaves out the variants whose name contains it.
+    /// Empty lines and lines starting with `#` are ignored.
+    fn parse(list: &str) -> Self {
+        Self(
+            list.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+
+    pub(crate) fn contains(&self, variant: &str) -> bool {
+        self.0
+            .iter()
+            .any(|pattern| variant.contains(pattern.as_str()))
+    }
+}
+
+/// The content of `excluded.json`.
+#[derive(Debug, Default, Serialize)]
+pub struct ExclusionManifest {
+    pub num_excluded_variants: usize,
+    pub counts_by_limit: BTreeMap<ProvabilityLimit, usize>,
+    /// The exclusions of each test file (relative to the tests checkout).
+    pub files: BTreeMap<String, Vec<Exclusion>>,
+}
+
+impl ExclusionManifest {
+    pub fn record(&mut self, file: &str, exclusions: Vec<Exclusion>) {
+        if exclusions.is_empty() {
+            return;
+        }
+
+        let mut variants: Vec<_> = exclusions.iter().map(|e| &e.variant).collect();
+        variants.dedup();
+        self.num_excluded_variants += variants.len();
+        for exclusion in exclusions.iter() {
+            *self.counts_by_limit.entry(exclusion.limit).or_default() += 1;
+        }
+        self.files
+            .entry(file.to_string())
+            .or_default()
+            .extend(exclusions);
+    }
+
+    pub fn write(&self, out_path: &Path) -> anyhow::Result<()> {
+        let path = out_path.join(EXCLUSION_MANIFEST_FILE_NAME);
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Writing {:?}", path))
+    }
+}
@@ -0,0 +1,317 @@
+//! Exports parsed test variants in the block prover input format of zero-bin
+//! and the proving service (`trace_decoder`'s `BlockProverInput`), so that
+//! the parsed corpus can be replayed through the production proving pipeline
+//! and not only through the runner.
+//!
+//! Each block is given as its pre-state tries along with the trace of its
+//! transaction, obtained by executing it with `revm`. Which accounts and slots
+//! the transaction accesses isn't recorded, so all the ones of the pre-state
+//! are traced as read, which keeps the tries whole in the decoder.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs, iter,
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use common::types::{AccountState, ParsedTestManifest, Plonky2ParsedTest, TestMetadata};
+use ethereum_types::{Address, H256, U256};
+use evm_arithmetization::proof::{BlockHashes, BlockMetadata};
+use keccak_hash::keccak;
+use mpt_trie::partial_trie::{HashedPartialTrie, PartialTrie};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    block_execution::execute_block,
+    config::Fork,
+    deserialize::{Block, TestBody},
+    diagnostics::{Diagnostic, DiagnosticReason},
+    fs_scaffolding::get_deserialized_test_body,
+};
+
+#[derive(Debug, Serialize)]
+pub struct BlockProverInput {
+    pub block_trace: BlockTrace,
+    pub other_data: OtherBlockData,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockTrace {
+    pub trie_pre_images: BlockTraceTriePreImages,
+    pub code_db: BTreeSet<HexBytes>,
+    pub txn_info: Vec<TxnInfo>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockTraceTriePreImages {
+    Separate(SeparateTriePreImages),
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeparateTriePreImages {
+    pub state: SeparateTriePreImage,
+    pub storage: SeparateStorageTriesPreImage,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeparateTriePreImage {
+    Direct(HashedPartialTrie),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeparateStorageTriesPreImage {
+    /// The storage tries keyed by the hash of the address of their account.
+    MultipleTries(HashMap<H256, SeparateTriePreImage>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct TxnInfo {
+    pub traces: BTreeMap<Address, TxnTrace>,
+    pub meta: TxnMeta,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TxnMeta {
+    pub byte_code: HexBytes,
+    pub new_txn_trie_node_byte: HexBytes,
+    pub new_receipt_trie_node_byte: HexBytes,
+    pub gas_used: u64,
+}
+
+/// The accesses of a transaction to an account, with the values after it.
+#[derive(Debug, Default, Serialize)]
+pub struct TxnTrace {
+    pub balance: Option<U256>,
+    pub nonce: Option<U256>,
+    pub storage_read: BTreeSet<H256>,
+    pub storage_written: BTreeMap<H256, U256>,
+    pub code_usage: Option<ContractCodeUsage>,
+    pub self_destructed: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractCodeUsage {
+    Read(H256),
+    Write(HexBytes),
+}
+
+#[derive(Debug, Serialize)]
+pub struct OtherBlockData {
+    pub b_data: BlockLevelData,
+    pub checkpoint_state_trie_root: H256,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockLevelData {
+    pub b_meta: BlockMetadata,
+    pub b_hashes: BlockHashes,
+    pub withdrawals: Vec<(Address, U256)>,
+}
+
+/// Bytes serialized as a `0x`-prefixed hex string.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct HexBytes(pub Vec<u8>);
+
+impl Serialize for HexBytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(&self.0)))
+    }
+}
+
+/// Writes the prover inputs of the variants of `manifest`, parsed from the
+/// test file at `test_path`, as `<variant>.json` in `out_dir`. Returns the
+/// variants that couldn't be exported.
+pub fn write_prover_input_files(
+    test_path: &Path,
+    fork: Fork,
+    manifest: &ParsedTestManifest,
+    out_dir: &Path,
+) -> Vec<Diagnostic> {
+    let export_error = |variant: Option<&str>, err: anyhow::Error| {
+        Diagnostic::new(
+            DiagnosticReason::ProverInputExportError,
+            variant,
+            format!("{:#}", err),
+        )
+    };
+
+    // The blocks of the variants are only kept in the test file.
+    let test_file = match get_deserialized_test_body(test_path, fork) {
+        Ok(test_file) => test_file,
+        Err(err) => return vec![export_error(None, err)],
+    };
+    if let Err(err) = fs::create_dir_all(out_dir)
+        .with_context(|| format!("Creating prover input directory {:?}", out_dir))
+    {
+        return vec![export_error(None, err)];
+    }
+
+    let mut diagnostics = Vec::new();
+    for variant in manifest.plonky2_variants.iter() {
+        let res = test_file
+            .variants
+            .get(&variant.test_name)
+            .ok_or_else(|| anyhow!("The variant is missing from the test file"))
+            .and_then(|test| prover_inputs(test, variant))
+            .and_then(|inputs| {
+                let inputs = serde_json::to_vec(&inputs)?;
+                // Identical variants are exported under all their names.
+                for name in iter::once(&variant.test_name).chain(variant.aliases.iter()) {
+                    let path = out_dir.join(name).with_extension("json");
+                    fs::write(&path, &inputs).with_context(|| format!("Writing {:?}", path))?;
+                }
+
+                Ok(())
+            });
+        if let Err(err) = res {
+            diagnostics.push(export_error(Some(&variant.test_name), err));
+        }
+    }
+
+    diagnostics
+}
+
+/// The prover inputs of the blocks of `variant`, whose blocks are the ones of
+/// `test`.
+fn prover_inputs(test: &TestBody, variant: &Plonky2ParsedTest) -> Result<Vec<BlockProverInput>> {
+    let parsed_blocks = iter::once((&variant.plonky2_metadata, &variant.block_hashes)).chain(
+        variant
+            .chained_blocks
+            .iter()
+            .map(|b| (&b.plonky2_metadata, &b.block_hashes)),
+    );
+    let checkpoint_state_trie_root = variant.plonky2_metadata.tries.state_trie.hash();
+
+    iter::once(&test.block)
+        .chain(test.chained_blocks.iter())
+        .zip(parsed_blocks)
+        .enumerate()
+        .map(|(block_idx, (block, (metadata, block_hashes)))| {
+            let block_trace = block_trace(block, metadata, test.fork)
+                .with_context(|| format!("Tracing block {}", block_idx + 1))?;
+
+            Ok(BlockProverInput {
+                block_trace,
+                other_data: OtherBlockData {
+                    b_data: BlockLevelData {
+                        b_meta: metadata.block_metadata.clone(),
+                        b_hashes: block_hashes.clone(),
+                        withdrawals: metadata.withdrawals.clone(),
+                    },
+                    checkpoint_state_trie_root,
+                },
+            })
+        })
+        .collect()
+}
+
+fn block_trace(block: &Block, metadata: &TestMetadata, fork: Fork) -> Result<BlockTrace> {
+    let tries = &metadata.tries;
+    let storage = tries
+        .storage_tries
+        .iter()
+        .map(|(hashed_address, trie)| (*hashed_address, SeparateTriePreImage::Direct(trie.clone())))
+        .collect();
+    let code_db = metadata
+        .contract_code
+        .values()
+        .filter(|code| !code.is_empty())
+        .map(|code| HexBytes(code.clone()))
+        .collect();
+
+    let txn_info = match block.txn() {
+        Some(txn) => {
+            let chain_id = metadata.block_metadata.block_chain_id.low_u64();
+            let executed = execute_block(&metadata.pre_state, block, fork, chain_id)?;
+            let receipt = executed.receipt(txn);
+            vec![TxnInfo {
+                traces: txn_traces(&metadata.pre_state, &executed.txn_state),
+                meta: TxnMeta {
+                    byte_code: HexBytes(txn.to_vec()),
+                    new_txn_trie_node_byte: HexBytes(txn.to_vec()),
+                    new_receipt_trie_node_byte: HexBytes(receipt),
+                    gas_used: executed.gas_used,
+                },
+            }]
+        }
+        None => Vec::new(),
+    };
+
+    Ok(BlockTrace {
+        trie_pre_images: BlockTraceTriePreImages::Separate(SeparateTriePreImages {
+            state: SeparateTriePreImage::Direct(tries.state_trie.clone()),
+            storage: SeparateStorageTriesPreImage::MultipleTries(storage),
+        }),
+        code_db,
+        txn_info,
+    })
+}
+
+/// The traces of a transaction going from `pre_state` to `post_state`, for
+/// every account of either.
+fn txn_traces(
+    pre_state: &HashMap<Address, AccountState>,
+    post_state: &HashMap<Address, AccountState>,
+) -> BTreeMap<Address, TxnTrace> {
+    let addresses: BTreeSet<_> = pre_state.keys().chain(post_state.keys()).collect();
+
+    addresses
+        .into_iter()
+        .map(|address| {
+            let pre = pre_state.get(address);
+            let trace = match post_state.get(address) {
+                Some(post) => account_trace(pre, post),
+                // Accounts left empty are deleted like self-destructed ones.
+                None => TxnTrace {
+                    self_destructed: true,
+                    ..Default::default()
+                },
+            };
+
+            (*address, trace)
+        })
+        .collect()
+}
+
+fn account_trace(pre: Option<&AccountState>, post: &AccountState) -> TxnTrace {
+    let empty = AccountState::default();
+    let pre = pre.unwrap_or(&empty);
+
+    let storage_written = pre
+        .storage
+        .keys()
+        .chain(post.storage.keys())
+        .filter(|key| pre.storage.get(key) != post.storage.get(key))
+        .map(|key| {
+            (
+                slot_key(*key),
+                post.storage.get(key).copied().unwrap_or_default(),
+            )
+        })
+        .collect();
+    let code_usage = match (post.code.is_empty(), post.code == pre.code) {
+        (true, _) => None,
+        (false, true) => Some(ContractCodeUsage::Read(keccak(&post.code))),
+        (false, false) => Some(ContractCodeUsage::Write(HexBytes(post.code.clone()))),
+    };
+
+    TxnTrace {
+        balance: (post.balance != pre.balance).then_some(post.balance),
+        nonce: (post.nonce != pre.nonce).then(|| post.nonce.into()),
+        storage_read: pre.storage.keys().map(|key| slot_key(*key)).collect(),
+        storage_written,
+        code_usage,
+        self_destructed: false,
+    }
+}
+
+fn slot_key(key: U256) -> H256 {
+    let mut bytes = [0; 32];
+    key.to_big_endian(&mut bytes);
+    H256(bytes)
+}
@@ -0,0 +1,108 @@
+//! Re-signing of the transactions of the tests for another chain than Ethereum
+//! (see `--chain-id`).
+//!
+//! The transactions of the tests are signed for the chain id of Ethereum, which
+//! the zkEVM rejects once configured with another one. Their private keys
+//! aren't part of the tests, so each sender is replaced with the address of a
+//! deterministic test key derived from it, which takes over its account. The
+//! headers and post-state of a re-signed test no longer match its blocks, and
+//! are recomputed with `revm` (see `TestBody::is_resigned`).
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    iter,
+};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use common::config::ETHEREUM_CHAIN_ID;
+use ethereum_types::H160;
+use k256::ecdsa::SigningKey;
+use keccak_hash::keccak;
+
+use crate::{
+    block_execution::txn_sender,
+    deserialize::{Block, TestBody, Transaction},
+    txn_signing::{key_address, rlp_fields, sign_fields, split_txn},
+};
+
+impl TestBody {
+    /// Whether the transactions of the test were re-signed for another chain
+    /// than Ethereum.
+    pub(crate) fn is_resigned(&self) -> bool {
+        self.chain_id != ETHEREUM_CHAIN_ID
+    }
+
+    /// Re-signs the transactions of the test for `chain_id`, moving the
+    /// accounts of their senders to the addresses of the keys signing them.
+    pub(crate) fn resign_txns(&mut self, chain_id: u64) -> Result<()> {
+        let mut keys = HashMap::new();
+        let blocks = iter::once(&mut self.block).chain(self.chained_blocks.iter_mut());
+        for (block_idx, block) in blocks.enumerate() {
+            resign_block_txn(block, chain_id, &mut keys).with_context(|| {
+                format!("Re-signing the transaction of block {}", block_idx + 1)
+            })?;
+        }
+
+        for (sender, key) in keys.iter() {
+            let address = key_address(key);
+            ensure!(
+                !self.pre.contains_key(&address),
+                "The test key of {:?} signs for {:?}, which already has an account",
+                sender,
+                address
+            );
+            for state in [&mut self.pre, &mut self.post] {
+                if let Some(account) = state.remove(sender) {
+                    state.insert(address, account);
+                }
+            }
+        }
+        self.chain_id = chain_id;
+
+        Ok(())
+    }
+}
+
+fn resign_block_txn(
+    block: &mut Block,
+    chain_id: u64,
+    keys: &mut HashMap<H160, SigningKey>,
+) -> Result<()> {
+    let Some(txn) = block.txn() else {
+        return Ok(());
+    };
+    let sender = txn_sender(txn)?.address;
+    let key = match keys.entry(sender) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(test_key(&sender)?),
+    };
+    let resigned_txn = resign_txn(txn, chain_id, key)?;
+    block.transactions.0 = Some(Transaction(resigned_txn));
+
+    Ok(())
+}
+
+/// The test key replacing `sender`, whose secret is the hash of its address.
+fn test_key(sender: &H160) -> Result<SigningKey> {
+    SigningKey::from_slice(keccak(sender).as_bytes())
+        .map_err(|err| anyhow!("Deriving the test key of {:?}: {}", sender, err))
+}
+
+/// Signs a signed transaction (of any type) again with `key`, for `chain_id`.
+/// Legacy transactions signed without a chain id are replaced with EIP-155
+/// ones.
+fn resign_txn(signed_txn: &[u8], chain_id: u64, key: &SigningKey) -> Result<Vec<u8>> {
+    let (txn_type, payload, _) = split_txn(signed_txn).context("Unsupported transaction type")?;
+    let mut fields = rlp_fields(payload);
+    ensure!(
+        fields.len() >= 9,
+        "Expected at least 9 fields, got {}",
+        fields.len()
+    );
+    fields.truncate(fields.len() - 3);
+    if txn_type.is_some() {
+        fields[0] = rlp::encode(&chain_id).to_vec();
+    }
+
+    sign_fields(txn_type, &fields, Some(chain_id), key)
+}
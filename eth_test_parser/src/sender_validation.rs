@@ -0,0 +1,140 @@
+//! Checks of the senders of the transactions of the tests.
+//!
+//! The sender of a transaction is recovered from its signature, so a sender
+//! that doesn't exist in the state before its block, or that can't pay for the
+//! transaction or has another nonce, most likely means that the transaction
+//! (or its chain id) was decoded incorrectly.
+
+use std::{collections::HashMap, iter};
+
+use common::types::{AccountState, Plonky2ParsedTest};
+use ethereum_types::H160;
+
+use crate::block_execution::txn_sender;
+
+/// Returns a description of each transaction of `test` whose sender couldn't
+/// be recovered, or can't send it.
+pub(crate) fn txn_sender_issues(test: &Plonky2ParsedTest) -> Vec<String> {
+    let blocks = iter::once((&test.txn_bytes, &test.plonky2_metadata)).chain(
+        test.chained_blocks
+            .iter()
+            .map(|block| (&block.txn_bytes, &block.plonky2_metadata)),
+    );
+
+    blocks
+        .enumerate()
+        .filter(|(_, (txn_bytes, _))| !txn_bytes.is_empty())
+        .flat_map(|(block_idx, (txn_bytes, metadata))| {
+            block_txn_sender_issues(block_idx, txn_bytes, &metadata.pre_state)
+        })
+        .collect()
+}
+
+/// The issues of the sender of the transaction `txn_bytes` of the block at
+/// `block_idx`, whose state before the block is `pre_state`.
+fn block_txn_sender_issues(
+    block_idx: usize,
+    txn_bytes: &[u8],
+    pre_state: &HashMap<H160, AccountState>,
+) -> Vec<String> {
+    let sender = match txn_sender(txn_bytes) {
+        Ok(sender) => sender,
+        Err(err) => {
+            return vec![format!(
+                "Block {}: the sender couldn't be recovered: {:#}",
+                block_idx + 1,
+                err
+            )]
+        }
+    };
+
+    let mut issues = Vec::new();
+    let default_account = AccountState::default();
+    let account = pre_state.get(&sender.address).unwrap_or(&default_account);
+    if account.nonce != sender.nonce {
+        issues.push(format!(
+            "Block {}: the nonce of the sender {:?} is {}, but the transaction has {}",
+            block_idx + 1,
+            sender.address,
+            account.nonce,
+            sender.nonce
+        ));
+    }
+    if account.balance < sender.max_cost {
+        issues.push(format!(
+            "Block {}: the balance of the sender {:?} is {}, but the transaction may cost {}",
+            block_idx + 1,
+            sender.address,
+            account.balance,
+            sender.max_cost
+        ));
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use common::types::AccountState;
+    use ethereum_types::{H160, U256};
+
+    use super::block_txn_sender_issues;
+    use crate::block_execution::txn_sender;
+
+    /// A legacy transaction of the Ethereum tests, signed without a chain id
+    /// by their usual sender.
+    const LEGACY_TXN: &str = "f863800a83061a8094095e7baea6a6c7c4c2dfeb977efac326af552d87830186a0801ba0ffb600e63115a7362e7811894a91d8ba4330e526f22121c994c4692035dfdfd5a06198379fcac8de3dbfac48b165df4bf88e2088f294b61efb9a65fe2281c76e16";
+    const LEGACY_SENDER: &str = "a94f5374fce5edbc8e2a8697c15331677e6ebf0b";
+
+    /// The example transaction of EIP-155.
+    const EIP_155_TXN: &str = "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+    const EIP_155_SENDER: &str = "9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f";
+
+    /// An EIP-1559 transaction of mainnet.
+    const EIP_1559_TXN: &str = "02f86f0102843b9aca0085029e7822d68298f094d9e1459a7a482635700cbc20bbaf52d495ab9c9680841b55ba3ac080a0c199674fcb29f353693dd779c017823b954b3c69dffa3cd6b2a6ff7888798039a028ca912de909e7e6cdef9cdcaf24c54dd8c1032946dfa1d85c206b32a9064fe8";
+    const EIP_1559_SENDER: &str = "001e2b7de757ba469a57bf6b23d982458a07efce";
+
+    fn address(hex_str: &str) -> H160 {
+        H160::from_slice(&hex::decode(hex_str).unwrap())
+    }
+
+    #[test]
+    fn txn_sender_recovers_address() {
+        for (txn, sender, nonce) in [
+            (LEGACY_TXN, LEGACY_SENDER, 0),
+            (EIP_155_TXN, EIP_155_SENDER, 9),
+            (EIP_1559_TXN, EIP_1559_SENDER, 2),
+        ] {
+            let recovered = txn_sender(&hex::decode(txn).unwrap()).unwrap();
+
+            assert_eq!(recovered.address, address(sender));
+            assert_eq!(recovered.nonce, nonce);
+        }
+    }
+
+    #[test]
+    fn txn_sender_issues_flag_unaffordable_txns() {
+        let txn = hex::decode(LEGACY_TXN).unwrap();
+        // 400000 gas at a price of 10, plus a value of 100000.
+        let max_cost = U256::from(4_100_000);
+        assert_eq!(txn_sender(&txn).unwrap().max_cost, max_cost);
+
+        let pre_state = |balance| {
+            HashMap::from([(
+                address(LEGACY_SENDER),
+                AccountState {
+                    balance,
+                    ..Default::default()
+                },
+            )])
+        };
+
+        assert!(block_txn_sender_issues(0, &txn, &pre_state(max_cost)).is_empty());
+
+        let issues = block_txn_sender_issues(0, &txn, &pre_state(max_cost - 1));
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].contains("balance"));
+    }
+}
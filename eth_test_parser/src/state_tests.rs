@@ -0,0 +1,254 @@
+//! Parsing of the plain `GeneralStateTests` format, for the tests that aren't
+//! also filled as blockchain tests (see `--state-tests`).
+//!
+//! Unlike blockchain tests, state tests don't have blocks: they give the
+//! environment of the block, a transaction template (whose data, gas limit and
+//! value are picked by the indexes of each variant), and the state root and
+//! signed transaction of each variant under `post`. The block of each variant
+//! is rebuilt from its environment, and the header fields that depend on the
+//! execution (gas used, logs bloom, transactions and receipts roots) are
+//! obtained by executing its transaction.
+//!
+//! Expected directory structure
+//! ```ignore
+//! // GeneralStateTests
+//! // ├── {TestNameN}
+//! // │   ├── {test_case_1}.json
+//! // │   └── {test_case_n}.json
+//! ```
+
+use std::{collections::HashMap, fs::File, io::BufReader, iter, path::Path};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use common::{config::ETHEREUM_CHAIN_ID, types::AccountState};
+use ethereum_types::{H160, H256, U256};
+use keccak_hash::keccak;
+use mpt_trie::partial_trie::PartialTrie;
+use serde::Deserialize;
+
+use crate::{
+    block_execution::{execute_block, list_trie_root},
+    config::Fork,
+    deserialize::{
+        exit_root_pre_account, Block, BlockHeader, ByteString, FieldOption, GenesisBlock,
+        PreAccount, TestBody, TestFile, Transaction, Transactions, EXIT_ROOT_ADDRESS,
+    },
+    diagnostics::{Diagnostic, DiagnosticReason},
+    trie_builder::{build_state_tries, check_state_root},
+};
+
+#[derive(Deserialize, Debug)]
+struct StateTestJson {
+    env: StateTestEnv,
+    pre: HashMap<H160, PreAccount>,
+    transaction: StateTestTransaction,
+    /// The variants of each fork.
+    post: HashMap<String, Vec<PostEntry>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct StateTestEnv {
+    current_coinbase: H160,
+    current_difficulty: U256,
+    current_gas_limit: U256,
+    current_number: U256,
+    current_timestamp: U256,
+    #[serde(default)]
+    current_base_fee: U256,
+    /// The `PREVRANDAO` value, only given after the merge.
+    #[serde(default)]
+    current_random: Option<H256>,
+    #[serde(default)]
+    current_excess_blob_gas: U256,
+    #[serde(default)]
+    current_beacon_root: H256,
+    #[serde(default)]
+    previous_hash: H256,
+}
+
+/// The transaction template of the test. Only the number of values it gives
+/// for each field picked by the indexes of the variants matters, as the signed
+/// transaction of each variant is given.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct StateTestTransaction {
+    data: Vec<ByteString>,
+    gas_limit: Vec<U256>,
+    value: Vec<U256>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PostEntry {
+    /// The state root after the transaction.
+    hash: H256,
+    indexes: Indexes,
+    /// The signed transaction, which older fillers don't give.
+    txbytes: Option<ByteString>,
+    expect_exception: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Indexes {
+    data: usize,
+    gas: usize,
+    value: usize,
+}
+
+/// Deserializes the variants of the given fork of the state test file at
+/// `path`, named like their blockchain test counterparts (eg.
+/// `add_d0g0v0_Cancun`), along with the reasons why the others were skipped.
+pub(crate) fn read_state_test_file(path: &Path, fork: Fork) -> Result<TestFile> {
+    let tests: HashMap<String, StateTestJson> =
+        serde_json::from_reader(BufReader::new(File::open(path)?))
+            .with_context(|| format!("Deserializing {:?}", path))?;
+
+    let mut test_file = TestFile::default();
+    for (test_name, test) in tests.iter() {
+        let Some(post_entries) = test.post.get(fork.name()) else {
+            continue;
+        };
+
+        for post in post_entries {
+            let Indexes { data, gas, value } = post.indexes;
+            let name = format!("{}_d{}g{}v{}_{}", test_name, data, gas, value, fork.name());
+            match TestBody::from_state_test(test, post, name.clone(), fork) {
+                Ok(test_body) => {
+                    test_file.variants.insert(name, test_body);
+                }
+                Err(diagnostic) => test_file.diagnostics.push(diagnostic),
+            }
+        }
+    }
+
+    Ok(test_file)
+}
+
+impl TestBody {
+    /// Fails with the reason to skip the variant if its block can't be
+    /// rebuilt.
+    fn from_state_test(
+        test: &StateTestJson,
+        post: &PostEntry,
+        name: String,
+        fork: Fork,
+    ) -> Result<Self, Diagnostic> {
+        let skip = |reason, message: String| Diagnostic::new(reason, Some(&name), message);
+
+        let txn_template = &test.transaction;
+        let Indexes { data, gas, value } = post.indexes;
+        if data >= txn_template.data.len()
+            || gas >= txn_template.gas_limit.len()
+            || value >= txn_template.value.len()
+        {
+            return Err(skip(
+                DiagnosticReason::ParseError,
+                "The indexes are out of the bounds of the transaction".to_string(),
+            ));
+        }
+        if let Some(exception) = &post.expect_exception {
+            return Err(skip(
+                DiagnosticReason::InvalidTransactions,
+                format!("The transaction is invalid ({})", exception),
+            ));
+        }
+        let Some(txn_bytes) = &post.txbytes else {
+            return Err(skip(
+                DiagnosticReason::MissingRawTransaction,
+                "The signed transaction isn't given".to_string(),
+            ));
+        };
+        let txn = Transaction::decode_actual_rlp(&txn_bytes.0).map_err(|err| {
+            skip(
+                DiagnosticReason::ParseError,
+                format!("Decoding the transaction: {}", err),
+            )
+        })?;
+
+        let env = &test.env;
+        let mut block = Block {
+            block_header: BlockHeader {
+                parent_hash: env.previous_hash,
+                uncle_hash: H256(keccak(rlp::EMPTY_LIST_RLP).0),
+                coinbase: env.current_coinbase,
+                state_root: post.hash,
+                // After the merge, the difficulty is given but zeroed in the
+                // blocks.
+                difficulty: match env.current_random {
+                    Some(_) if fork >= Fork::Paris => U256::zero(),
+                    _ => env.current_difficulty,
+                },
+                number: env.current_number,
+                gas_limit: env.current_gas_limit,
+                timestamp: env.current_timestamp,
+                mix_hash: env.current_random.unwrap_or_default(),
+                base_fee_per_gas: env.current_base_fee,
+                withdrawals_root: FieldOption(
+                    (fork >= Fork::Shanghai).then(|| list_trie_root(iter::empty())),
+                ),
+                excess_blob_gas: env.current_excess_blob_gas,
+                parent_beacon_block_root: env.current_beacon_root,
+                // No test can observe the hash of its own block.
+                ..Default::default()
+            },
+            transactions: Transactions(Some(txn)),
+            _uncle_headers: Vec::new(),
+            withdrawals: Vec::new(),
+        };
+
+        let mut pre = test.pre.clone();
+        pre.insert(EXIT_ROOT_ADDRESS, exit_root_pre_account());
+        let pre_state: HashMap<_, AccountState> =
+            pre.iter().map(|(addr, acc)| (*addr, acc.into())).collect();
+        let executed = execute_block(&pre_state, &block, fork, ETHEREUM_CHAIN_ID)
+            .and_then(|executed| {
+                check_state_root(&executed.state, &block.block_header)?;
+                Ok(executed)
+            })
+            .map_err(|err| skip(DiagnosticReason::BuildError, format!("{:#}", err)))?;
+
+        let header = &mut block.block_header;
+        header.transactions_trie = list_trie_root(iter::once(txn_bytes.0.clone()));
+        header.receipt_trie = list_trie_root(iter::once(executed.receipt(&txn_bytes.0)));
+        header.bloom = Bytes::copy_from_slice(&executed.logs_bloom());
+        header.gas_used = executed.gas_used.into();
+        header.blob_gas_used = executed.blob_gas_used.into();
+
+        let test_state: HashMap<_, AccountState> = test
+            .pre
+            .iter()
+            .map(|(addr, acc)| (*addr, acc.into()))
+            .collect();
+        let genesis_block = GenesisBlock {
+            block_header: BlockHeader {
+                state_root: build_state_tries(&test_state).0.hash(),
+                // Without a gas limit, the base fee of the child of the header
+                // is its own (see `--validate-headers`).
+                base_fee_per_gas: env.current_base_fee,
+                hash: env.previous_hash,
+                ..Default::default()
+            },
+            _transactions: Vec::new(),
+            _uncle_headers: Vec::new(),
+            _withdrawals: Vec::new(),
+        };
+
+        Ok(Self {
+            name,
+            fork,
+            block,
+            chained_blocks: Vec::new(),
+            dropped_invalid_txns: false,
+            chain_id: ETHEREUM_CHAIN_ID,
+            genesis_block,
+            pre,
+            post: executed
+                .state
+                .iter()
+                .map(|(addr, acc)| (*addr, acc.into()))
+                .collect(),
+        })
+    }
+}
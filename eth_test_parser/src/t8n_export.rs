@@ -0,0 +1,35 @@
+//! Exports parsed test variants in the input format of the `t8n` (state
+//! transition) tool, so that the parsed corpus can be consumed by other
+//! execution layer tooling (eg. geth's `evm t8n`).
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use common::{
+    t8n::{t8n_alloc, t8n_env, t8n_txs_rlp},
+    types::Plonky2ParsedTest,
+};
+
+/// Writes `alloc.json`, `env.json` and `txs.rlp` for the given variant in
+/// `out_dir`.
+pub fn write_t8n_files(out_dir: &Path, test: &Plonky2ParsedTest) -> Result<()> {
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("Creating t8n output directory {:?}", out_dir))?;
+
+    let metadata = &test.plonky2_metadata;
+    fs::write(
+        out_dir.join("alloc.json"),
+        serde_json::to_vec_pretty(&t8n_alloc(&metadata.pre_state))?,
+    )?;
+    fs::write(
+        out_dir.join("env.json"),
+        serde_json::to_vec_pretty(&t8n_env(
+            &metadata.block_metadata,
+            &metadata.withdrawals,
+            &test.block_hashes,
+        ))?,
+    )?;
+    fs::write(out_dir.join("txs.rlp"), t8n_txs_rlp(&test.signed_txns()))?;
+
+    Ok(())
+}
@@ -5,15 +5,18 @@
 //! ```ignore
 //! crate::deserialize::TestBody -> evm_arithmetization::generation::GenerationInputs
 //! ```
-use std::collections::HashMap;
+use std::{collections::HashMap, iter};
 
-use anyhow::Result;
-use common::{
-    config::ETHEREUM_CHAIN_ID,
-    types::{ExpectedFinalRoots, Plonky2ParsedTest, TestMetadata},
+use anyhow::{anyhow, Context, Result};
+use common::types::{
+    AccountState, EofContainerInfo, ExpectedFinalRoots, ParsedChainedBlock, Plonky2ParsedTest,
+    TestMetadata, TxnOutcome,
 };
 use ethereum_types::{H160, H256, U256};
-use evm_arithmetization::{generation::TrieInputs, proof::BlockMetadata};
+use evm_arithmetization::{
+    generation::TrieInputs,
+    proof::{BlockHashes, BlockMetadata},
+};
 use keccak_hash::keccak;
 use mpt_trie::{
     nibbles::Nibbles,
@@ -23,7 +26,12 @@ use mpt_trie::{
 use rlp::Encodable;
 use rlp_derive::{RlpDecodable, RlpEncodable};
 
-use crate::deserialize::{Block, PreAccount, TestBody};
+use crate::{
+    block_execution::{deleted_accounts, execute_block, list_trie_root, txn_sender, ExecutedBlock},
+    config::Fork,
+    deserialize::{Block, BlockHeader, ByteString, PreAccount, TestBody, EXIT_ROOT_ADDRESS},
+    eof::{eof_container_info, is_eof},
+};
 
 #[derive(RlpDecodable, RlpEncodable)]
 pub(crate) struct AccountRlp {
@@ -34,7 +42,7 @@ pub(crate) struct AccountRlp {
 }
 
 impl Block {
-    fn block_metadata(&self) -> BlockMetadata {
+    fn block_metadata(&self, chain_id: u64) -> BlockMetadata {
         let header = &self.block_header;
         BlockMetadata {
             block_beneficiary: header.coinbase,
@@ -42,33 +50,212 @@ impl Block {
             block_number: header.number,
             block_difficulty: header.difficulty,
             block_gaslimit: header.gas_limit,
-            block_chain_id: ETHEREUM_CHAIN_ID.into(),
+            block_chain_id: chain_id.into(),
             block_base_fee: header.base_fee_per_gas,
-            block_random: header.mix_hash,
+            block_random: header.block_random(),
             block_gas_used: header.gas_used,
             block_blob_gas_used: header.blob_gas_used,
             block_excess_blob_gas: header.excess_blob_gas,
             parent_beacon_block_root: header.parent_beacon_block_root,
-            block_bloom: header
-                .bloom
-                .chunks_exact(32)
-                .map(U256::from_big_endian)
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
+            block_bloom: bloom_words(&header.bloom),
+        }
+    }
+}
+
+/// Splits a logs bloom into the words stored in the block metadata.
+pub fn bloom_words(bloom: &[u8]) -> [U256; 8] {
+    bloom
+        .chunks_exact(32)
+        .map(U256::from_big_endian)
+        .collect::<Vec<_>>()
+        .try_into()
+        .unwrap()
+}
+
+impl BlockHeader {
+    /// The value returned by the `PREVRANDAO` opcode, which replaced
+    /// `DIFFICULTY` with the merge. Proof-of-work blocks have a non-zero
+    /// difficulty, which the opcode returns instead of the mix hash.
+    fn block_random(&self) -> H256 {
+        if self.difficulty.is_zero() {
+            self.mix_hash
+        } else {
+            H256(u256_to_be_bytes(self.difficulty))
         }
     }
 }
 
 impl TestBody {
-    pub fn as_plonky2_test_inputs(&self) -> Plonky2ParsedTest {
-        let block = &self.block;
+    pub fn as_plonky2_test_inputs(&self) -> Result<Plonky2ParsedTest> {
+        let pre_state: HashMap<_, AccountState> = self
+            .pre
+            .iter()
+            .map(|(addr, pre_acc)| (*addr, pre_acc.into()))
+            .collect();
+
+        let blocks: Vec<_> = iter::once(&self.block)
+            .chain(self.chained_blocks.iter())
+            .collect();
+
+        // The tests only give the state before the first block, so the ones
+        // before the following blocks are obtained by executing the blocks.
+        // Once re-signed, none of the headers match the test anymore, and they
+        // are recomputed.
+        let mut pre_states = vec![pre_state];
+        let mut executions = Vec::with_capacity(blocks.len());
+        let mut recomputed_headers = Vec::with_capacity(blocks.len());
+        for (block_idx, block) in blocks[..blocks.len() - 1].iter().enumerate() {
+            let executed =
+                execute_block(pre_states.last().unwrap(), block, self.fork, self.chain_id)
+                    .with_context(|| format!("Executing block {}", block_idx + 1))?;
+            if !self.is_resigned() {
+                check_state_root(&executed.state, &block.block_header)
+                    .with_context(|| format!("Executing block {}", block_idx + 1))?;
+            }
+            executions.push(BlockExecution::new(block, &executed));
+            recomputed_headers.push(
+                self.is_resigned()
+                    .then(|| RecomputedHeader::new(block, &executed)),
+            );
+            pre_states.push(executed.state);
+        }
+
+        // The last block is executed for the outcome of its transaction and its
+        // logs bloom. Once its invalid transactions are dropped or it is
+        // re-signed, its header and post-state no longer match the test, and
+        // are recomputed as well. Otherwise, failing to execute it is reported
+        // by `post_state_validation`.
+        let last_block = blocks.last().unwrap();
+        let executed_last_block = execute_block(
+            pre_states.last().unwrap(),
+            last_block,
+            self.fork,
+            self.chain_id,
+        )
+        .context("Executing the last block");
+        executions.push(executed_last_block.as_ref().map_or_else(
+            |_| BlockExecution::default(),
+            |executed| BlockExecution::new(last_block, executed),
+        ));
+        let recomputed_last_block = (self.dropped_invalid_txns || self.is_resigned())
+            .then_some(executed_last_block)
+            .transpose()?;
+        recomputed_headers.push(
+            recomputed_last_block
+                .as_ref()
+                .map(|executed| RecomputedHeader::new(last_block, executed)),
+        );
+        let last_state: HashMap<_, AccountState> = match &recomputed_last_block {
+            Some(executed) => executed.state.clone(),
+            None => self
+                .post
+                .iter()
+                .map(|(addr, post_acc)| (*addr, post_acc.into()))
+                .collect(),
+        };
+        let post_states: Vec<_> = pre_states[1..]
+            .iter()
+            .chain(iter::once(&last_state))
+            .collect();
+        let final_state_roots = post_states
+            .iter()
+            .map(|state| build_state_tries(state).0.hash())
+            .collect::<Vec<_>>();
+
+        let mut hashes = vec![self.genesis_block.block_header.hash];
+        let mut parsed_blocks = Vec::with_capacity(blocks.len());
+        for (
+            ((((block, pre_state), post_state), final_state_root), execution),
+            recomputed_header,
+        ) in blocks
+            .iter()
+            .zip(pre_states.iter())
+            .zip(post_states)
+            .zip(final_state_roots)
+            .zip(executions)
+            .zip(recomputed_headers)
+        {
+            let header = &block.block_header;
+            let parent_hash = *hashes.last().unwrap();
+            if header.parent_hash != parent_hash {
+                return Err(anyhow!(
+                    "The parent hash of block {} is {:?}, expected {:?}",
+                    hashes.len(),
+                    header.parent_hash,
+                    parent_hash
+                ));
+            }
+
+            let txn = block.txn();
+            let mut final_roots = ExpectedFinalRoots {
+                state_root_hash: final_state_root,
+                txn_trie_root_hash: header.transactions_trie,
+                receipts_trie_root_hash: header.receipt_trie,
+            };
+            let mut plonky2_metadata =
+                self.block_test_metadata(block, pre_state, post_state, execution);
+            if let Some(recomputed) = recomputed_header {
+                final_roots.txn_trie_root_hash = recomputed.txn_trie_root;
+                final_roots.receipts_trie_root_hash = recomputed.receipts_trie_root;
+                let block_metadata = &mut plonky2_metadata.block_metadata;
+                block_metadata.block_gas_used = recomputed.gas_used.into();
+                block_metadata.block_blob_gas_used = recomputed.blob_gas_used.into();
+                block_metadata.block_bloom = recomputed.bloom;
+            }
+
+            parsed_blocks.push(ParsedChainedBlock {
+                txn_bytes: txn.map(<[u8]>::to_vec).unwrap_or_default(),
+                final_roots,
+                plonky2_metadata,
+                block_hashes: block_hashes(&hashes, header.hash),
+            });
+            hashes.push(header.hash);
+        }
 
-        let storage_tries = self.get_storage_tries(&self.pre);
-        let state_trie = self.get_state_trie(&self.pre, &storage_tries);
+        let mut parsed_blocks = parsed_blocks.into_iter();
+        let first_block = parsed_blocks.next().unwrap();
+        Ok(Plonky2ParsedTest {
+            test_name: self.name.clone(),
+            txn_bytes: first_block.txn_bytes,
+            final_roots: first_block.final_roots,
+            plonky2_metadata: first_block.plonky2_metadata,
+            block_hashes: first_block.block_hashes,
+            chained_blocks: parsed_blocks.collect(),
+            eof_containers: self.eof_containers(),
+            aliases: Vec::new(),
+        })
+    }
 
-        let final_storage_tries = self.get_storage_tries(&self.post);
-        let final_state_trie = self.get_state_trie(&self.post, &final_storage_tries);
+    /// The EOF containers held by the accounts of the test, before or after it.
+    fn eof_containers(&self) -> Vec<EofContainerInfo> {
+        if self.fork < Fork::Osaka {
+            return Vec::new();
+        }
+
+        let mut containers: Vec<_> = self
+            .pre
+            .iter()
+            .chain(self.post.iter())
+            .filter(|(_, acc)| is_eof(&acc.code.0))
+            .map(|(address, acc)| (*address, &acc.code.0))
+            .collect();
+        containers.sort();
+        containers.dedup();
+
+        containers
+            .into_iter()
+            .map(|(address, code)| eof_container_info(address, code))
+            .collect()
+    }
+
+    fn block_test_metadata(
+        &self,
+        block: &Block,
+        pre_state: &HashMap<H160, AccountState>,
+        post_state: &HashMap<H160, AccountState>,
+        execution: BlockExecution,
+    ) -> TestMetadata {
+        let (state_trie, storage_tries) = build_state_tries(pre_state);
 
         let tries = TrieInputs {
             state_trie,
@@ -77,98 +264,189 @@ impl TestBody {
             storage_tries,
         };
 
-        let contract_code: HashMap<_, _> = self
-            .pre
+        let contract_code: HashMap<_, _> = pre_state
             .values()
-            .map(|pre| (hash(&pre.code.0), pre.code.0.clone()))
+            .map(|acc| (hash(&acc.code), acc.code.clone()))
             .collect();
 
-        let header = &block.block_header;
-
-        let plonky2_metadata = TestMetadata {
+        TestMetadata {
             tries,
             contract_code,
             genesis_state_root: self.genesis_block.block_header.state_root,
-            block_metadata: self.block.block_metadata(),
+            block_metadata: block.block_metadata(self.chain_id),
             withdrawals: block
                 .withdrawals
                 .iter()
                 .map(|w| (w.address, w.amount))
                 .collect(),
-        };
+            pre_state: pre_state.clone(),
+            // A sender that can't be recovered is reported by
+            // `sender_validation`.
+            txn_sender: block
+                .txn()
+                .and_then(|txn| txn_sender(txn).ok())
+                .map(|sender| sender.address),
+            deleted_accounts: deleted_accounts(pre_state, post_state),
+            post_state: post_state.clone(),
+            txn_outcome: execution.txn_outcome,
+            logs_bloom: execution.logs_bloom,
+        }
+    }
+}
 
-        Plonky2ParsedTest {
-            test_name: self.name.clone(),
-            txn_bytes: self.get_txn_bytes(),
-            final_roots: ExpectedFinalRoots {
-                state_root_hash: final_state_trie.hash(),
-                txn_trie_root_hash: header.transactions_trie,
-                receipts_trie_root_hash: header.receipt_trie,
-            },
-            plonky2_metadata,
+/// What the manifest records of the execution of a block with `revm`, which is
+/// nothing if it failed.
+#[derive(Default)]
+struct BlockExecution {
+    txn_outcome: Option<TxnOutcome>,
+    logs_bloom: Option<[U256; 8]>,
+}
+
+impl BlockExecution {
+    fn new(block: &Block, executed: &ExecutedBlock) -> Self {
+        Self {
+            txn_outcome: block.txn().map(|_| TxnOutcome {
+                success: executed.success,
+                gas_used: executed.gas_used,
+            }),
+            logs_bloom: Some(bloom_words(&executed.logs_bloom())),
         }
     }
+}
 
-    fn get_storage_tries(
-        &self,
-        accounts: &HashMap<H160, PreAccount>,
-    ) -> Vec<(H256, HashedPartialTrie)> {
-        accounts
-            .iter()
-            .map(|(acc_key, pre_acc)| {
-                let storage_trie = HashedPartialTrie::try_from_iter(
-                    pre_acc
-                        .storage
-                        .iter()
-                        .filter(|(_, v)| !v.is_zero())
-                        .map(|(k, v)| {
-                            (
-                                Nibbles::from_h256_be(hash(&u256_to_be_bytes(*k))),
-                                v.rlp_bytes().to_vec(),
-                            )
-                        }),
-                )
-                .unwrap();
-
-                (hash(acc_key.as_bytes()), storage_trie)
-            })
-            .collect()
+/// The fields of the header of a block that follow from its execution, for the
+/// blocks whose header no longer matches the test.
+struct RecomputedHeader {
+    txn_trie_root: H256,
+    receipts_trie_root: H256,
+    gas_used: u64,
+    blob_gas_used: u64,
+    bloom: [U256; 8],
+}
+
+impl RecomputedHeader {
+    fn new(block: &Block, executed: &ExecutedBlock) -> Self {
+        let txn = block.txn();
+        Self {
+            txn_trie_root: list_trie_root(txn.map(<[u8]>::to_vec).into_iter()),
+            receipts_trie_root: list_trie_root(txn.map(|txn| executed.receipt(txn)).into_iter()),
+            gas_used: executed.gas_used,
+            blob_gas_used: executed.blob_gas_used,
+            bloom: bloom_words(&executed.logs_bloom()),
+        }
     }
+}
 
-    fn get_state_trie(
-        &self,
-        accounts: &HashMap<H160, PreAccount>,
-        storage_tries: &[(H256, HashedPartialTrie)],
-    ) -> HashedPartialTrie {
-        HashedPartialTrie::try_from_iter(accounts.iter().map(|(acc_key, pre_acc)| {
-            let addr_hash = hash(acc_key.as_bytes());
-            let code_hash = hash(&pre_acc.code.0);
-            let storage_hash = get_storage_hash(&addr_hash, storage_tries);
-
-            let rlp = AccountRlp {
-                nonce: pre_acc.nonce,
-                balance: pre_acc.balance,
-                storage_hash,
-                code_hash,
-            }
-            .rlp_bytes();
+/// Checks the state after a block against its header. The account added by
+/// the parser to all tests isn't part of the state of the test itself.
+pub(crate) fn check_state_root(
+    state: &HashMap<H160, AccountState>,
+    header: &BlockHeader,
+) -> Result<()> {
+    let mut state = state.clone();
+    state.remove(&EXIT_ROOT_ADDRESS);
+    let state_root = build_state_tries(&state).0.hash();
+    if state_root != header.state_root {
+        return Err(anyhow!(
+            "The state root is {:?}, but the header expects {:?}",
+            state_root,
+            header.state_root
+        ));
+    }
 
-            (Nibbles::from_h256_be(addr_hash), rlp.to_vec())
-        }))
-        .unwrap()
+    Ok(())
+}
+
+/// The block hashes of a block given the hashes of the blocks before it, from
+/// the oldest to its parent.
+fn block_hashes(prev_hashes: &[H256], cur_hash: H256) -> BlockHashes {
+    let num_hashes = prev_hashes.len().min(256);
+    let mut hashes = vec![H256::zero(); 256];
+    hashes[256 - num_hashes..].copy_from_slice(&prev_hashes[prev_hashes.len() - num_hashes..]);
+
+    BlockHashes {
+        prev_hashes: hashes,
+        cur_hash,
     }
+}
 
-    pub(crate) fn get_txn_bytes(&self) -> Vec<u8> {
-        self.get_tx().0
+impl From<&PreAccount> for AccountState {
+    fn from(pre_acc: &PreAccount) -> Self {
+        Self {
+            balance: pre_acc.balance,
+            nonce: pre_acc.nonce,
+            code: pre_acc.code.0.clone(),
+            storage: pre_acc.storage.clone(),
+        }
     }
 }
 
-impl From<TestBody> for Plonky2ParsedTest {
-    fn from(test_body: TestBody) -> Self {
-        test_body.as_plonky2_test_inputs()
+impl From<&AccountState> for PreAccount {
+    fn from(acc: &AccountState) -> Self {
+        Self {
+            balance: acc.balance,
+            nonce: acc.nonce,
+            code: ByteString(acc.code.clone()),
+            storage: acc.storage.clone(),
+        }
     }
 }
 
+/// Builds the state trie of the given accounts, along with their storage tries
+/// (keyed by hashed address).
+pub fn build_state_tries(
+    accounts: &HashMap<H160, AccountState>,
+) -> (HashedPartialTrie, Vec<(H256, HashedPartialTrie)>) {
+    let storage_tries = get_storage_tries(accounts);
+    let state_trie = get_state_trie(accounts, &storage_tries);
+
+    (state_trie, storage_tries)
+}
+
+fn get_storage_tries(accounts: &HashMap<H160, AccountState>) -> Vec<(H256, HashedPartialTrie)> {
+    accounts
+        .iter()
+        .map(|(acc_key, acc)| {
+            let storage_trie = HashedPartialTrie::try_from_iter(
+                acc.storage
+                    .iter()
+                    .filter(|(_, v)| !v.is_zero())
+                    .map(|(k, v)| {
+                        (
+                            Nibbles::from_h256_be(hash(&u256_to_be_bytes(*k))),
+                            v.rlp_bytes().to_vec(),
+                        )
+                    }),
+            )
+            .unwrap();
+
+            (hash(acc_key.as_bytes()), storage_trie)
+        })
+        .collect()
+}
+
+fn get_state_trie(
+    accounts: &HashMap<H160, AccountState>,
+    storage_tries: &[(H256, HashedPartialTrie)],
+) -> HashedPartialTrie {
+    HashedPartialTrie::try_from_iter(accounts.iter().map(|(acc_key, acc)| {
+        let addr_hash = hash(acc_key.as_bytes());
+        let code_hash = hash(&acc.code);
+        let storage_hash = get_storage_hash(&addr_hash, storage_tries);
+
+        let rlp = AccountRlp {
+            nonce: acc.nonce,
+            balance: acc.balance,
+            storage_hash,
+            code_hash,
+        }
+        .rlp_bytes();
+
+        (Nibbles::from_h256_be(addr_hash), rlp.to_vec())
+    }))
+    .unwrap()
+}
+
 fn get_storage_hash(
     hashed_account_address: &H256,
     storage_tries: &[(H256, HashedPartialTrie)],
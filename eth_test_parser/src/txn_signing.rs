@@ -0,0 +1,153 @@
+//! Signing of the transactions of the tests, either with the private key used
+//! throughout the Ethereum tests, so that their payload can be altered while
+//! keeping the same sender, or with other test keys (see `--chain-id`).
+
+use anyhow::{anyhow, ensure, Context, Result};
+use ethereum_types::{H160, U256};
+use k256::ecdsa::{SigningKey, VerifyingKey};
+use keccak_hash::keccak;
+use rlp::{Rlp, RlpStream};
+
+/// The private key of the sender of (almost) all transactions of the Ethereum
+/// tests, whose address is `0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b`.
+const TEST_SENDER_KEY: [u8; 32] = [
+    0x45, 0xa9, 0x15, 0xe4, 0xd0, 0x60, 0x14, 0x9e, 0xb4, 0x36, 0x59, 0x60, 0xe6, 0xa7, 0xa4, 0x5f,
+    0x33, 0x43, 0x93, 0x09, 0x30, 0x61, 0x11, 0x6b, 0x19, 0x7e, 0x32, 0x40, 0x06, 0x5f, 0xf2, 0xd8,
+];
+
+pub fn test_sender_key() -> SigningKey {
+    SigningKey::from_slice(&TEST_SENDER_KEY).expect("Valid sender key")
+}
+
+/// The address of the account `key` signs for.
+pub fn key_address(key: &SigningKey) -> H160 {
+    verifying_key_address(key.verifying_key())
+}
+
+/// The address of the account whose signatures `key` verifies.
+pub fn verifying_key_address(key: &VerifyingKey) -> H160 {
+    let public_key = key.to_encoded_point(false);
+    H160::from_slice(&keccak(&public_key.as_bytes()[1..]).as_bytes()[12..])
+}
+
+/// Signs the keccak hash of `payload`, returning the recovery id along with
+/// the `r` and `s` values of the signature.
+pub fn sign_payload(payload: &[u8], key: &SigningKey) -> Result<(u64, U256, U256)> {
+    let (signature, recovery_id) = key
+        .sign_prehash_recoverable(keccak(payload).as_bytes())
+        .map_err(|err| anyhow!("Signing the transaction: {}", err))?;
+
+    Ok((
+        recovery_id.to_byte() as u64,
+        U256::from_big_endian(&signature.r().to_bytes()),
+        U256::from_big_endian(&signature.s().to_bytes()),
+    ))
+}
+
+/// Splits a signed transaction into its type (`None` for legacy ones) and RLP
+/// payload, along with the index of its data field in the payload.
+pub fn split_txn(signed_txn: &[u8]) -> Option<(Option<u8>, &[u8], usize)> {
+    // Legacy transactions are RLP lists, while typed ones are prefixed with
+    // their type.
+    let (txn_type, payload) = match *signed_txn.first()? {
+        b if b >= 0xc0 => (None, signed_txn),
+        b => (Some(b), &signed_txn[1..]),
+    };
+    let data_idx = match txn_type {
+        None => 5,
+        Some(1) => 6,
+        Some(2) | Some(3) => 7,
+        Some(_) => return None,
+    };
+
+    Some((txn_type, payload, data_idx))
+}
+
+/// Returns the data (calldata or init code) of a signed transaction.
+pub fn txn_data(signed_txn: &[u8]) -> Option<Vec<u8>> {
+    let (_, payload, data_idx) = split_txn(signed_txn)?;
+    Rlp::new(payload).val_at(data_idx).ok()
+}
+
+/// Replaces the data of a signed transaction (of any type) and signs it again
+/// with `key`.
+pub fn with_txn_data(signed_txn: &[u8], data: &[u8], key: &SigningKey) -> Result<Vec<u8>> {
+    let (txn_type, payload, data_idx) =
+        split_txn(signed_txn).context("Unsupported transaction type")?;
+    let mut fields = rlp_fields(payload);
+    ensure!(
+        fields.len() >= data_idx + 4,
+        "Expected at least {} fields, got {}",
+        data_idx + 4,
+        fields.len()
+    );
+    fields[data_idx] = rlp::encode(&data.to_vec()).to_vec();
+
+    let chain_id = match txn_type {
+        None => {
+            let v: u64 = Rlp::new(&fields[fields.len() - 3]).as_val()?;
+            // EIP-155.
+            (v >= 35).then(|| (v - 35) / 2)
+        }
+        Some(_) => None,
+    };
+    fields.truncate(fields.len() - 3);
+
+    sign_fields(txn_type, &fields, chain_id, key)
+}
+
+/// The raw RLP of each field of a transaction payload.
+pub(crate) fn rlp_fields(payload: &[u8]) -> Vec<Vec<u8>> {
+    Rlp::new(payload)
+        .iter()
+        .map(|field| field.as_raw().to_vec())
+        .collect()
+}
+
+/// Signs a transaction of the given type made of `unsigned_fields` (the raw
+/// RLP of all its fields but the signature). Legacy transactions are signed as
+/// per EIP-155 if given a chain id.
+pub(crate) fn sign_fields(
+    txn_type: Option<u8>,
+    unsigned_fields: &[Vec<u8>],
+    chain_id: Option<u64>,
+    key: &SigningKey,
+) -> Result<Vec<u8>> {
+    let unsigned_list = |extra_fields: &[u64]| {
+        let mut stream = RlpStream::new_list(unsigned_fields.len() + extra_fields.len());
+        for field in unsigned_fields {
+            stream.append_raw(field, 1);
+        }
+        for field in extra_fields {
+            stream.append(field);
+        }
+        stream.out().to_vec()
+    };
+    let (v, r, s) = match (txn_type, chain_id) {
+        (None, Some(chain_id)) => {
+            let (recovery_id, r, s) = sign_payload(&unsigned_list(&[chain_id, 0, 0]), key)?;
+            (recovery_id + 35 + 2 * chain_id, r, s)
+        }
+        (None, None) => {
+            let (recovery_id, r, s) = sign_payload(&unsigned_list(&[]), key)?;
+            (recovery_id + 27, r, s)
+        }
+        (Some(txn_type), _) => {
+            let mut payload = vec![txn_type];
+            payload.extend(unsigned_list(&[]));
+            sign_payload(&payload, key)?
+        }
+    };
+
+    let mut stream = RlpStream::new_list(unsigned_fields.len() + 3);
+    for field in unsigned_fields {
+        stream.append_raw(field, 1);
+    }
+    stream.append(&v);
+    stream.append(&r);
+    stream.append(&s);
+
+    let mut signed_txn: Vec<u8> = txn_type.into_iter().collect();
+    signed_txn.extend_from_slice(&stream.out());
+    Ok(signed_txn)
+}
@@ -0,0 +1,336 @@
+//! A small REST API to trigger and monitor runs, served by the `serve`
+//! subcommand alongside the dashboard, so that nightly schedulers or bots can
+//! drive the runner without wrapping the CLI:
+//!
+//! - `POST /api/runs` starts a run of the tests matching the filters of its
+//!   JSON body (see [`RunRequest`]) and returns the id of the run.
+//! - `GET /api/runs` lists all runs, from the most recent to the oldest.
+//! - `GET /api/runs/:id` returns the counts of a run, along with its state and
+//!   progress if it was started through the API.
+//! - `GET /api/runs/:id/results` returns the results of the tests of a run that
+//!   completed so far.
+//! - `DELETE /api/runs/:id` stops a run after its current test.
+//!
+//! Runs are recorded in the history database, from which all results are
+//! read. Only one run can be in progress at a time.
+
+use std::{
+    collections::HashSet,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use common::{
+    eip_tags::{is_known_tag, known_tags},
+    types::VariantFilterType,
+};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{runtime::Handle, sync::mpsc};
+
+use crate::{
+    persistent_run_state::load_existing_pass_state_from_disk_if_exists_or_create,
+    plonky2_runner::{
+        run_plonky2_tests, GasLimitPolicy, RunProgress, TestOrder, TestRunConfig, TestTimeouts,
+    },
+    run_history::{RunHistoryDb, RunHistoryReader, RunSummary, TestRunRecord},
+    test_dir_reading::{
+        get_default_parsed_tests_path, read_in_all_parsed_tests, ParsedTestFilters,
+    },
+};
+
+/// The settings of the runs started through the API that can't be set per run,
+/// taken from the command line of `serve`.
+#[derive(Debug)]
+pub(crate) struct ApiRunDefaults {
+    pub(crate) witness_only: bool,
+    pub(crate) max_cpu_log_len: Option<usize>,
+    pub(crate) test_timeouts: TestTimeouts,
+    pub(crate) blacklist: Option<Arc<HashSet<String>>>,
+    pub(crate) repro_dir: Option<PathBuf>,
+}
+
+/// The body of `POST /api/runs`. All fields are optional, and behave as the
+/// command line arguments of the same name.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RunRequest {
+    test_filter: Option<String>,
+    variant_filter: Option<String>,
+    /// The exact test variants to run.
+    test_list: Option<Vec<String>>,
+    tags: Vec<String>,
+    /// Defaults to whether `serve` was given `--witness-only`.
+    witness_only: Option<bool>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RunState {
+    Running,
+    Completed,
+    Stopped,
+    Failed(String),
+}
+
+/// The run started through the API.
+#[derive(Debug)]
+struct ApiRun {
+    id: i64,
+    state: RunState,
+    progress: Arc<Mutex<RunProgress>>,
+    abort: mpsc::Sender<()>,
+}
+
+#[derive(Debug, Serialize)]
+struct RunStatus {
+    #[serde(flatten)]
+    run: RunSummary,
+    /// Only known for the latest run started through the API.
+    state: Option<RunState>,
+    progress: Option<RunProgress>,
+}
+
+#[derive(Debug)]
+struct ApiState {
+    history_db: PathBuf,
+    defaults: ApiRunDefaults,
+    /// The latest run started through the API, if any.
+    current: Mutex<Option<ApiRun>>,
+}
+
+#[derive(Debug)]
+struct ApiError(StatusCode, String);
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        error!("API error: {:#}", err);
+        Self(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", err))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+pub(crate) fn api_router(history_db: PathBuf, defaults: ApiRunDefaults) -> Router {
+    let state = Arc::new(ApiState {
+        history_db,
+        defaults,
+        current: Mutex::new(None),
+    });
+
+    Router::new()
+        .route("/api/runs", get(list_runs).post(start_run))
+        .route("/api/runs/:id", get(get_run).delete(stop_run))
+        .route("/api/runs/:id/results", get(get_run_results))
+        .with_state(state)
+}
+
+impl ApiState {
+    fn filters(&self, request: RunRequest) -> ApiResult<ParsedTestFilters> {
+        let bad_request = |msg: String| ApiError(StatusCode::BAD_REQUEST, msg);
+
+        if let Some(unknown) = request.tags.iter().find(|t| !is_known_tag(t)) {
+            return Err(bad_request(format!(
+                "Unknown tag {}, expected one of: {}",
+                unknown,
+                known_tags().collect::<Vec<_>>().join(", ")
+            )));
+        }
+        let variant_filter = request
+            .variant_filter
+            .as_deref()
+            .map(str::parse::<VariantFilterType>)
+            .transpose()
+            .map_err(bad_request)?;
+
+        Ok(ParsedTestFilters {
+            filter_str: request.test_filter,
+            variant_filter,
+            blacklist: self.defaults.blacklist.clone(),
+            test_list: request
+                .test_list
+                .map(|names| Arc::new(names.into_iter().collect())),
+            tags: (!request.tags.is_empty()).then(|| Arc::new(request.tags.into_iter().collect())),
+            changed_files: None,
+        })
+    }
+
+    fn finish_run(&self, id: i64, state: RunState) {
+        info!("API run {} finished: {:?}", id, state);
+        if let Some(run) = self.current.lock().unwrap().as_mut()
+            && run.id == id
+        {
+            run.state = state;
+        }
+    }
+
+    /// Reads from the history database on a blocking thread.
+    async fn read_history<T: Send + 'static>(
+        &self,
+        read: impl FnOnce(&RunHistoryReader) -> anyhow::Result<T> + Send + 'static,
+    ) -> ApiResult<T> {
+        let history_db = self.history_db.clone();
+        let res = tokio::task::spawn_blocking(move || read(&RunHistoryReader::open(&history_db)?))
+            .await
+            .map_err(|err| anyhow!(err))??;
+
+        Ok(res)
+    }
+}
+
+async fn start_run(
+    State(api): State<Arc<ApiState>>,
+    Json(request): Json<RunRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let witness_only = request.witness_only.unwrap_or(api.defaults.witness_only);
+    let filters = api.filters(request)?;
+
+    let mut current = api.current.lock().unwrap();
+    if let Some(run) = current.as_ref()
+        && matches!(run.state, RunState::Running)
+    {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            format!("Run {} is still in progress", run.id),
+        ));
+    }
+
+    let run_history = RunHistoryDb::open(&api.history_db, witness_only)?;
+    let id = run_history.run_id();
+    let progress = Arc::new(Mutex::new(RunProgress::default()));
+    let (abort, abort_recv) = mpsc::channel(1);
+    *current = Some(ApiRun {
+        id,
+        state: RunState::Running,
+        progress: progress.clone(),
+        abort,
+    });
+    drop(current);
+
+    let config = TestRunConfig {
+        simple_progress_indicator: false,
+        witness_only,
+        check_post_state: false,
+        gas_limit_policy: GasLimitPolicy::default(),
+        order: TestOrder::default(),
+        max_cpu_log_len: api.defaults.max_cpu_log_len,
+        test_timeouts: api.defaults.test_timeouts.clone(),
+        t8n_bin: None,
+        repro_dir: api.defaults.repro_dir.clone(),
+        run_history: Some(run_history),
+        auto_blacklist: None,
+        aggregate_segments: false,
+        circuits_cache: None,
+        fail_fast: None,
+        witness_cache: None,
+        proof_archive: None,
+        jobs: 1,
+        memory_budget: None,
+        progress: Some(progress),
+        coordinator: None,
+        isolation: None,
+    };
+
+    info!("Starting API run {}", id);
+    let api = api.clone();
+    tokio::task::spawn_blocking(move || {
+        // Tests are run one at a time, so a panic of the prover isn't caught
+        // by the runner, and would otherwise leave the run in progress forever.
+        let res = panic::catch_unwind(AssertUnwindSafe(|| run_tests(filters, config, abort_recv)));
+        let state = match res {
+            Ok(Ok(false)) => RunState::Completed,
+            Ok(Ok(true)) => RunState::Stopped,
+            Ok(Err(err)) => RunState::Failed(format!("{:#}", err)),
+            Err(_) => RunState::Failed("The runner panicked".to_string()),
+        };
+        api.finish_run(id, state);
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(json!({ "id": id }))))
+}
+
+/// Reads in and runs the tests, returning whether the run was stopped early.
+fn run_tests(
+    filters: ParsedTestFilters,
+    config: TestRunConfig,
+    abort_recv: mpsc::Receiver<()>,
+) -> anyhow::Result<bool> {
+    let parsed_tests = Handle::current().block_on(read_in_all_parsed_tests(
+        &get_default_parsed_tests_path()?,
+        filters,
+    ))?;
+
+    let mut persistent_test_state = load_existing_pass_state_from_disk_if_exists_or_create();
+    let output = run_plonky2_tests(parsed_tests, &mut persistent_test_state, abort_recv, config);
+    persistent_test_state.write_to_disk();
+
+    Ok(output.stopped_early)
+}
+
+async fn list_runs(State(api): State<Arc<ApiState>>) -> ApiResult<Json<Vec<RunSummary>>> {
+    Ok(Json(api.read_history(|reader| reader.runs()).await?))
+}
+
+async fn get_run(
+    State(api): State<Arc<ApiState>>,
+    Path(id): Path<i64>,
+) -> ApiResult<Json<RunStatus>> {
+    let run = api
+        .read_history(move |reader| reader.run(id))
+        .await?
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("No run {}", id)))?;
+
+    let (state, progress) = match api.current.lock().unwrap().as_ref() {
+        Some(current) if current.id == id => (
+            Some(current.state.clone()),
+            Some(current.progress.lock().unwrap().clone()),
+        ),
+        _ => (None, None),
+    };
+
+    Ok(Json(RunStatus {
+        run,
+        state,
+        progress,
+    }))
+}
+
+async fn get_run_results(
+    State(api): State<Arc<ApiState>>,
+    Path(id): Path<i64>,
+) -> ApiResult<Json<Vec<TestRunRecord>>> {
+    Ok(Json(
+        api.read_history(move |reader| reader.run_tests(id)).await?,
+    ))
+}
+
+async fn stop_run(State(api): State<Arc<ApiState>>, Path(id): Path<i64>) -> ApiResult<StatusCode> {
+    let current = api.current.lock().unwrap();
+    match current.as_ref() {
+        Some(run) if run.id == id && matches!(run.state, RunState::Running) => {
+            // A full channel means that the run is already being stopped.
+            let _ = run.abort.try_send(());
+            Ok(StatusCode::ACCEPTED)
+        }
+        _ => Err(ApiError(
+            StatusCode::CONFLICT,
+            format!("Run {} is not in progress", id),
+        )),
+    }
+}
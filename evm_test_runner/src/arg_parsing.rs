@@ -1,7 +1,12 @@
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf};
 
-use clap::{Parser, ValueEnum};
-use common::types::VariantFilterType;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use common::{profiles::Profile, types::VariantFilterType};
+
+use crate::{
+    persistent_run_state::PassState,
+    plonky2_runner::{GasLimitPolicy, ProvingConfig, TestOrder},
+};
 
 #[derive(Clone, Debug, ValueEnum)]
 pub(crate) enum ReportType {
@@ -13,11 +18,45 @@ pub(crate) enum ReportType {
     /// The summary does not contain information on individual tests and instead
     /// aggregates all of the tests in a sub-group into row entries.
     Summary,
+
+    /// Run all tests and write a markdown report sized for a GitHub PR comment,
+    /// with collapsible sections for regressions and other failures.
+    PrComment,
+}
+
+/// Which failed tests make the runner exit with a non-zero code.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub(crate) enum FailOn {
+    /// The tests that passed in their previous run but failed in this one.
+    Regressions,
+    /// Any test that failed, timed out or crashed.
+    AnyFailure,
+    /// None, the failures only being reported.
+    #[default]
+    Never,
 }
 
 #[derive(Debug, Parser)]
-#[clap(author, version, about)]
+#[clap(author, version, about, args_override_self = true)]
 pub(crate) struct ProgArgs {
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+
+    /// An optional path to a TOML config file providing default values for
+    /// any of the other arguments. Defaults to `runner.toml` if it exists in
+    /// the working directory. Arguments passed on the command line take
+    /// precedence over the config file, and `--no-<flag>` turns off a boolean
+    /// flag set in it.
+    #[arg(long)]
+    pub(crate) config: Option<PathBuf>,
+
+    /// An optional resource profile (`laptop`, `ci` or `server`) providing
+    /// defaults for the test timeout, the max CPU log length, the number of
+    /// jobs and the memory limit, sized for the given class of machine. The
+    /// arguments passed explicitly (or in the config file) take precedence.
+    #[arg(long)]
+    pub(crate) profile: Option<Profile>,
+
     /// An optional path to a blacklist file containing test variants to prevent
     /// from running. This can be used to skip particularly heavy or badly
     /// configured tests.
@@ -28,6 +67,44 @@ pub(crate) struct ProgArgs {
     #[arg(short='r', long, value_enum, default_value_t=ReportType::Test)]
     pub(crate) report_type: ReportType,
 
+    /// An optional directory containing report templates overriding the
+    /// built-in ones (`filtered_test_results.md`, `test_results_summary.md`
+    /// and `opcode_coverage.md`). They are rendered with `minijinja` and get
+    /// the same data as the built-in templates.
+    #[arg(long)]
+    pub(crate) template_dir: Option<PathBuf>,
+
+    /// An optional baseline to compare the summary report against: either the
+    /// `summary.json` or the `summary.md` of a previous summary report.
+    #[arg(long)]
+    pub(crate) baseline: Option<PathBuf>,
+
+    /// An optional path to which the duration of every test that passed is
+    /// written, to be used as a `--perf-baseline` by later runs.
+    #[arg(long)]
+    pub(crate) save_timings: Option<PathBuf>,
+
+    /// An optional timing file (written by `--save-timings`) to compare the
+    /// duration of the tests against. Tests that got slower by more than
+    /// `--perf-threshold` are reported as performance regressions.
+    #[arg(long)]
+    pub(crate) perf_baseline: Option<PathBuf>,
+
+    /// The slowdown (in percent) above which a test is reported as a
+    /// performance regression.
+    #[arg(long, default_value_t = 20.0, requires = "perf_baseline")]
+    pub(crate) perf_threshold: f64,
+
+    /// Exit with a non-zero code if any performance regression is found.
+    #[arg(long, requires = "perf_baseline")]
+    pub(crate) fail_on_perf_regression: bool,
+
+    /// Which failed tests make the runner exit with code 2 (rather than 1, as
+    /// when the run can't complete), so that automation can tell new breakage
+    /// from known failures.
+    #[arg(long, value_enum, default_value_t = FailOn::Never)]
+    pub(crate) fail_on: FailOn,
+
     /// Only run test variants that match this index (either a single value or a
     /// range).
     ///
@@ -40,24 +117,214 @@ pub(crate) struct ProgArgs {
     #[arg(short = 'f', long)]
     pub(crate) test_filter: Option<String>,
 
+    /// An optional path to a file listing the exact test variants to run, one
+    /// per line (same format as the blacklist).
+    #[arg(long)]
+    pub(crate) test_list: Option<PathBuf>,
+
+    /// Only run the tests whose JSON test file was added or modified upstream
+    /// since the given commit of the tests, according to the checkout of the
+    /// parser.
+    #[arg(long, conflicts_with_all = ["file", "gen_inputs"])]
+    pub(crate) since: Option<String>,
+
+    /// The checkout of the tests used by `--since`. Defaults to the one of the
+    /// parser, looked for in the ancestry of the cwd.
+    #[arg(long, requires = "since")]
+    pub(crate) eth_tests_dir: Option<PathBuf>,
+
+    /// Only run the tests covering any of these EIPs or features (eg.
+    /// `eip-1153` or `precompiles`), as defined in `common/eip_tags.toml`.
+    /// Can be repeated.
+    #[arg(long)]
+    pub(crate) tag: Vec<String>,
+
+    /// An optional path to a single parsed test (`.cbor`) to run, bypassing
+    /// the scan of the parsed tests directory.
+    #[arg(long, conflicts_with = "update_persistent_state_from_upstream")]
+    pub(crate) file: Option<PathBuf>,
+
+    /// An optional path to raw Ethereum JSON tests (a single test file or a
+    /// directory of them) to parse in-process and run, instead of the output
+    /// of the parser binary.
+    #[arg(long, conflicts_with = "file")]
+    pub(crate) json: Option<PathBuf>,
+
+    /// An optional path to raw `GenerationInputs` produced by other tools (eg.
+    /// the trace decoder), as JSON or CBOR: a single file or a directory of
+    /// them. Each file is run as a test of its own, named after the file.
+    #[arg(long, conflicts_with_all = ["file", "json"])]
+    pub(crate) gen_inputs: Option<PathBuf>,
+
+    /// Read the parsed tests on demand while running them, instead of loading
+    /// the whole corpus before the first test starts. This reduces both the
+    /// startup time and the peak memory usage, but is incompatible with the
+    /// options that need to know about all tests up front.
+    #[arg(
+        long,
+        conflicts_with_all = ["file", "json", "gen_inputs", "sample", "jobs", "skip_memory_heavy", "update_persistent_state_from_upstream"]
+    )]
+    pub(crate) stream: bool,
+
+    /// Only run a random sample of this many test variants (after all other
+    /// filters are applied).
+    #[arg(long)]
+    pub(crate) sample: Option<usize>,
+
+    /// The seed used to pick the `--sample`d test variants. A random one is
+    /// picked (and printed) if not provided.
+    #[arg(long, requires = "sample")]
+    pub(crate) seed: Option<u64>,
+
+    /// The order in which the tests are run: in the order of the corpus, or
+    /// starting with the tests whose last run is the oldest (those never run
+    /// first), so that runs stopped before the end of the corpus still get
+    /// every test re-validated over time. Results are reported in the order
+    /// of the corpus either way.
+    #[arg(long, value_enum, default_value_t = TestOrder::Corpus, conflicts_with = "stream")]
+    pub(crate) order: TestOrder,
+
+    /// Instead of running the selected tests, write a report of the opcodes
+    /// appearing in their contract code (and in the init code of contract
+    /// creations) to `reports/opcode_coverage.md`.
+    #[arg(long, conflicts_with = "stream")]
+    pub(crate) opcode_coverage: bool,
+
     /// An optional max CPU log length for each segment to be generated.
+    /// Defaults to the one of the `--profile`, if any.
     #[arg(short = 'c', long)]
     pub(crate) max_cpu_log_len: Option<usize>,
 
+    /// Prove tests as continuations: the execution is split into segments of
+    /// at most 2^N CPU cycles, each segment is proven recursively and the
+    /// segment proofs are aggregated into a single one. This exercises the
+    /// same machinery as production proving, but building the recursive
+    /// circuits takes several minutes at the start of the run.
+    #[arg(long, conflicts_with_all = ["max_cpu_log_len", "witness_only", "witness_cache"])]
+    pub(crate) max_segment_log_len: Option<usize>,
+
+    /// An optional directory in which the recursive circuits built for
+    /// `--max-segment-log-len` are cached, so that later runs (and workers)
+    /// load them instead of building them again.
+    #[arg(long)]
+    pub(crate) circuits_cache: Option<PathBuf>,
+
     /// Do not run tests that have already passed in the past or that are
-    /// ignored.
+    /// ignored. Only results obtained with the current zkEVM version are
+    /// considered.
     #[arg(short = 'p', long)]
     pub(crate) skip_passed: bool,
 
+    /// Only run the tests that failed in their last run, whatever the zkEVM
+    /// version they were run against (eg. to check a fix of the zkEVM). Can be
+    /// combined with the other filters.
+    #[arg(long, conflicts_with = "skip_passed")]
+    pub(crate) only_failed: bool,
+
+    /// Also run the tests that timed out in their last run with
+    /// `--only-failed`.
+    #[arg(long, requires = "only_failed")]
+    pub(crate) include_timed_out: bool,
+
+    /// Consider all results in the persistent test pass state as obtained with
+    /// the current zkEVM version, even if they were run against another one.
+    #[arg(long)]
+    pub(crate) carry_state_forward: bool,
+
     /// Only generate the witness and not the entire proof (significantly
     /// faster, but may give false negatives).
     #[arg(short = 'w', long)]
     pub(crate) witness_only: bool,
 
+    /// Check the accounts each block ends with against the post-state recorded
+    /// in the manifest, rather than only its state root. Failing blocks report
+    /// the accounts the zkEVM got wrong, field by field.
+    #[arg(long)]
+    pub(crate) check_post_state: bool,
+
+    /// What to do with the blocks whose gas limit doesn't fit in 32 bits,
+    /// which the zkEVM verifier doesn't support: prove them with their gas
+    /// limit clamped to `u32::MAX` (ignoring them if that fails, and recording
+    /// them as `PassedClampedProof` otherwise), skip them, or fail them. Only
+    /// applies when generating proofs.
+    #[arg(long, value_enum, default_value_t = GasLimitPolicy::Clamp)]
+    pub(crate) gas_limit_policy: GasLimitPolicy,
+
+    /// The memory (in GiB) above which a test is reported as likely to run out
+    /// of memory before the run starts. This is also the memory budget shared
+    /// by tests running in parallel. Defaults to the one of the `--profile`, or
+    /// to the total RAM of the machine.
+    #[arg(long)]
+    pub(crate) memory_limit_gb: Option<f64>,
+
+    /// Skip the tests whose estimated memory usage exceeds the memory limit
+    /// instead of only warning about them.
+    #[arg(long)]
+    pub(crate) skip_memory_heavy: bool,
+
+    /// The number of tests to run in parallel. Tests are scheduled so that
+    /// their estimated memory usage stays within the memory limit. Defaults to
+    /// the one of the `--profile`, or to 1.
+    #[arg(short = 'j', long)]
+    pub(crate) jobs: Option<usize>,
+
+    /// Run each test in its own child process, so that a crash of the prover
+    /// (segfault, abort or OOM kill) is recorded as a `Crashed` test instead of
+    /// ending the whole run. Timed out tests are killed.
+    #[arg(long, conflicts_with_all = ["max_segment_log_len", "coordinator"])]
+    pub(crate) isolate: bool,
+
+    /// Run the tests on workers (see the `worker` subcommand) connecting on
+    /// this address instead of locally. Results are recorded by this process
+    /// as if the tests had been run locally.
+    #[arg(long, conflicts_with_all = ["stream", "jobs"])]
+    pub(crate) coordinator: Option<SocketAddr>,
+
+    /// The number of threads used by the prover for each test. Defaults to
+    /// the number of CPUs (or `RAYON_NUM_THREADS` if set). Requires the
+    /// `parallel` feature.
+    #[arg(long)]
+    pub(crate) prover_threads: Option<usize>,
+
+    /// An optional directory in which the witnesses generated in witness-only
+    /// mode are cached, so that later proving runs can reuse them instead of
+    /// re-running the execution of each segment.
+    #[arg(long)]
+    pub(crate) witness_cache: Option<PathBuf>,
+
+    /// An optional directory in which the proofs of the blocks of the tests
+    /// are archived, compressed and with their public values deduplicated.
+    /// Segment proofs aggregated with `--max-segment-log-len` and the proofs
+    /// of workers aren't archived.
+    #[arg(long, conflicts_with_all = ["witness_only", "max_segment_log_len", "coordinator"])]
+    pub(crate) proof_archive: Option<PathBuf>,
+
     /// Mark a test as timed out if it takes longer than this amount of time.
+    /// Sub-groups can have their own in the `[timeouts]` section of the config
+    /// file. Defaults to the one of the `--profile`, if any.
     #[arg(short = 't', long)]
     pub(crate) test_timeout: Option<humantime::Duration>,
 
+    /// Scale the timeout of each test with the gas it uses, giving it this
+    /// amount of time per million gas on top of the `--test-timeout`, which is
+    /// then required (from the command line, the config file or the
+    /// `--profile`). The sub-groups with their own timeout in the config file
+    /// keep it.
+    #[arg(long)]
+    pub(crate) timeout_per_mgas: Option<humantime::Duration>,
+
+    /// Increase the logging verbosity. Can be repeated (once for `info`, twice
+    /// for `debug` and three times for `trace`). Note that `-v` is already used
+    /// by `--variant-filter`.
+    #[arg(long, action = ArgAction::Count)]
+    pub(crate) verbose: u8,
+
+    /// An optional path to a file to which logs (including the plonky2 timing
+    /// trees) are written instead of stderr. A timestamp is appended to the
+    /// file name so that each run gets its own log file.
+    #[arg(long)]
+    pub(crate) log_file: Option<PathBuf>,
+
     /// Use a simple progress indicator that relies on `println!`s instead of an
     /// actual progress bar to display the current test status. In some
     /// situations, the more elegant progress bar may interfere with
@@ -70,4 +337,197 @@ pub(crate) struct ProgArgs {
     /// state. If it's removed, we purge it from our persistent state.
     #[arg(short = 'u', long, default_value_t = false)]
     pub(crate) update_persistent_state_from_upstream: bool,
+
+    /// An optional path to a geth `evm` binary. If provided, every test is
+    /// additionally run through `evm t8n` and its resulting roots are compared
+    /// against the expected ones and the plonky2 result.
+    #[arg(long)]
+    pub(crate) t8n_bin: Option<PathBuf>,
+
+    /// An optional directory in which a self-contained reproduction bundle
+    /// (generation inputs, raw txn RLP, pre-state and expected roots) is
+    /// written for each failing test variant.
+    #[arg(long)]
+    pub(crate) repro_dir: Option<PathBuf>,
+
+    /// An optional path to an SQLite database in which every test run is
+    /// recorded (status, duration, error and zkEVM version), complementing
+    /// the persistent pass state which only keeps the latest run.
+    #[arg(long)]
+    pub(crate) history_db: Option<PathBuf>,
+
+    /// Automatically append a test variant to the blacklist file once it has
+    /// timed out this many times in a row.
+    #[arg(long, requires = "blacklist_path")]
+    pub(crate) auto_blacklist_after: Option<u32>,
+
+    /// Stop the run at the first failure, or after the given number of
+    /// failures, and only report the tests that completed.
+    #[arg(long, num_args = 0..=1, default_missing_value = "1")]
+    pub(crate) fail_fast: Option<usize>,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Manage a blacklist file instead of running tests.
+    Blacklist {
+        /// The path to the blacklist file to manage.
+        #[arg(short = 'b', long)]
+        blacklist_path: PathBuf,
+
+        #[command(subcommand)]
+        action: BlacklistAction,
+    },
+
+    /// Run a fixed corpus of representative tests several times each and
+    /// write their mean/stddev witness generation time, proving time and peak
+    /// memory as JSON (`reports/bench.json` by default).
+    Bench {
+        /// The file listing the test variants of the corpus (same format as
+        /// the blacklist), eg. `bench_corpus.txt` at the root of the repo.
+        #[arg(long)]
+        corpus: PathBuf,
+
+        /// The number of times each test is run.
+        #[arg(short = 'n', long, default_value_t = 3)]
+        iterations: usize,
+
+        /// An optional path to write the results to.
+        #[arg(short = 'o', long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Prove the tests under several proving configurations, each being a
+    /// hasher (`keccak` or `poseidon`) optionally followed by a FRI preset
+    /// (`fast`, `balanced` or `small-proofs`), eg. `keccak poseidon:fast`.
+    /// The status and timing of each configuration are printed side by side
+    /// and written as JSON (`reports/config_comparison.json` by default). The
+    /// tests are read like for regular runs (see `--file`, `--json`,
+    /// `--test-filter` and `--variant-filter`), and `--check-post-state`,
+    /// `--gas-limit-policy` and `--max-cpu-log-len` apply as well.
+    CompareConfigs {
+        /// The configurations to compare.
+        #[arg(required = true)]
+        configs: Vec<ProvingConfig>,
+
+        /// An optional path to write the results to.
+        #[arg(short = 'o', long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Run randomly generated pre-states and transactions through the zkEVM,
+    /// checking its resulting roots against the ones obtained by executing
+    /// them with `revm`. Uses `--witness-only`, `--max-cpu-log-len` and
+    /// `--repro-dir` like regular runs.
+    Fuzz {
+        /// Mutate the variants of the parsed tests (flipping storage values,
+        /// perturbing calldata, changing gas limits) instead of generating
+        /// random cases. The variants are read like for regular runs (see
+        /// `--file`, `--json`, `--test-filter` and `--variant-filter`).
+        #[arg(long)]
+        mutate: bool,
+
+        /// The number of cases to run.
+        #[arg(short = 'n', long, default_value_t = 100)]
+        cases: usize,
+
+        /// The seed of the first case (each following case uses the next
+        /// seed). A random one is picked (and printed) if not provided.
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+
+    /// Verify again, in parallel, the proofs of a proof archive (see
+    /// `--proof-archive`) and write whether each one passed as JSON
+    /// (`reports/reverify.json` by default), eg. to cross-check a change to
+    /// the verifier.
+    Reverify {
+        /// The directory of the proof archive.
+        archive: PathBuf,
+
+        /// The number of blocks verified in parallel. Defaults to the number
+        /// of cores.
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// An optional path to write the results to.
+        #[arg(short = 'o', long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Shrink a failing test variant (dropping accounts, zeroing storage
+    /// slots, truncating code and calldata) as long as witness generation
+    /// still fails with the same error, and write the result as a repro
+    /// bundle. The variant is read like for regular runs (see `--file` and
+    /// `--json`).
+    Minimize {
+        /// The name of the failing test variant.
+        test: String,
+
+        /// The directory in which the reproducer is written.
+        #[arg(short = 'o', long, default_value = "minimized")]
+        out: PathBuf,
+    },
+
+    /// Run the tests handed out by a coordinator (a run started with
+    /// `--coordinator`) until it has none left. The settings of the run, such
+    /// as `--witness-only`, are taken from the coordinator.
+    Worker {
+        /// The address of the coordinator, eg. `10.0.0.1:7878`.
+        coordinator: String,
+    },
+
+    /// Run a single test read from stdin, as a child process of a run with
+    /// `--isolate`.
+    #[command(hide = true)]
+    RunIsolated,
+
+    /// Serve a local web dashboard for browsing the runs recorded in the
+    /// `--history-db` database, including the one in progress.
+    Serve {
+        /// The address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+    },
+
+    /// Import or export the persistent test pass state.
+    State {
+        #[command(subcommand)]
+        action: StateAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum StateAction {
+    /// Export the persistent test pass state as JSON.
+    Export { path: PathBuf },
+
+    /// Replace the persistent test pass state with a previously exported JSON
+    /// one.
+    Import { path: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum BlacklistAction {
+    /// Add test variants to the blacklist.
+    Add { t_names: Vec<String> },
+
+    /// Remove test variants from the blacklist.
+    Remove { t_names: Vec<String> },
+
+    /// List all test variants in the blacklist.
+    List,
+
+    /// Add all test variants from another list file, or all test variants
+    /// that are in a given state in the persistent test pass state (eg.
+    /// `timed-out` to blacklist every test that timed out in its last run).
+    Import {
+        /// A file with one test variant name per line.
+        #[arg(long, conflicts_with = "from_state")]
+        from_file: Option<PathBuf>,
+
+        /// A state of the persistent test pass state.
+        #[arg(long, value_enum)]
+        from_state: Option<PassState>,
+    },
 }
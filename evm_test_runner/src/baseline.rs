@@ -0,0 +1,190 @@
+//! Comparison of the summary report against a baseline one.
+//!
+//! The baseline can either be the `summary.json` written next to a previous
+//! `summary.md`, or (for reports generated before `summary.json` existed) the
+//! previous `summary.md` itself, whose sub-group tables are parsed.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Machine readable version of the summary report.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct SummaryCounts {
+    pub(crate) groups: Vec<GroupCounts>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct GroupCounts {
+    pub(crate) name: String,
+    pub(crate) sub_groups: Vec<SubGroupCounts>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct SubGroupCounts {
+    pub(crate) name: String,
+    pub(crate) num_passed: usize,
+    pub(crate) tot_tests: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Baseline {
+    /// `(num_passed, tot_tests)` keyed by `(group, sub-group)`.
+    sub_groups: HashMap<(String, String), (usize, usize)>,
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Reading baseline report {:?}", path))?;
+
+        let counts = match path.extension().is_some_and(|ext| ext == "json") {
+            true => serde_json::from_str(&content)
+                .with_context(|| format!("Deserializing baseline report {:?}", path))?,
+            false => parse_summary_markdown(&content),
+        };
+
+        let sub_groups = counts
+            .groups
+            .into_iter()
+            .flat_map(|g| {
+                g.sub_groups.into_iter().map(move |sub_g| {
+                    (
+                        (g.name.clone(), sub_g.name),
+                        (sub_g.num_passed, sub_g.tot_tests),
+                    )
+                })
+            })
+            .collect();
+
+        Ok(Self { sub_groups })
+    }
+
+    /// Returns the passed/failed deltas of a sub-group relative to the
+    /// baseline (eg. `+3 passed / -1 failed`).
+    pub(crate) fn sub_group_delta(
+        &self,
+        group: &str,
+        sub_group: &str,
+        num_passed: usize,
+        tot_tests: usize,
+    ) -> String {
+        match self
+            .sub_groups
+            .get(&(group.to_string(), sub_group.to_string()))
+        {
+            Some(&(base_passed, base_tot)) => {
+                format_delta(num_passed, tot_tests, base_passed, base_tot)
+            }
+            None => "new".to_string(),
+        }
+    }
+
+    /// Same as `sub_group_delta`, but for a whole group.
+    pub(crate) fn group_delta(&self, group: &str, num_passed: usize, tot_tests: usize) -> String {
+        let (base_passed, base_tot) = self
+            .sub_groups
+            .iter()
+            .filter(|((g, _), _)| g == group)
+            .fold((0, 0), |(p, t), (_, (base_p, base_t))| {
+                (p + base_p, t + base_t)
+            });
+
+        match base_tot {
+            0 => "new".to_string(),
+            _ => format_delta(num_passed, tot_tests, base_passed, base_tot),
+        }
+    }
+}
+
+fn format_delta(
+    num_passed: usize,
+    tot_tests: usize,
+    base_passed: usize,
+    base_tot: usize,
+) -> String {
+    // A hand-edited JSON baseline may have more passed tests than tests.
+    let failed = |passed: usize, tot: usize| tot as i64 - passed as i64;
+    let passed_delta = num_passed as i64 - base_passed as i64;
+    let failed_delta = failed(num_passed, tot_tests) - failed(base_passed, base_tot);
+
+    match (passed_delta, failed_delta) {
+        (0, 0) => "=".to_string(),
+        _ => format!("{:+} passed / {:+} failed", passed_delta, failed_delta),
+    }
+}
+
+/// Extracts the sub-group counts from the tables of a `summary.md`.
+fn parse_summary_markdown(content: &str) -> SummaryCounts {
+    let mut counts = SummaryCounts::default();
+    let mut in_group = false;
+
+    for line in content.lines() {
+        if let Some(group) = line.strip_prefix("### ") {
+            counts.groups.push(GroupCounts {
+                name: group.trim().to_string(),
+                sub_groups: Vec::new(),
+            });
+            in_group = true;
+            continue;
+        }
+        if line.starts_with("## ") {
+            in_group = false;
+            continue;
+        }
+
+        let cells: Vec<_> = line.split('|').map(str::trim).collect();
+        if let (true, Some(group), [_, name, passed, ..]) =
+            (in_group, counts.groups.last_mut(), cells.as_slice())
+            && let Some((num_passed, tot_tests)) = parse_passed_cell(passed)
+        {
+            group.sub_groups.push(SubGroupCounts {
+                name: name.to_string(),
+                num_passed,
+                tot_tests,
+            });
+        }
+    }
+
+    counts
+}
+
+/// Parses a `passed / total` cell, rejecting it if more tests passed than
+/// there are.
+fn parse_passed_cell(cell: &str) -> Option<(usize, usize)> {
+    let (passed, tot) = cell.split_once('/')?;
+    let (passed, tot) = (passed.trim().parse().ok()?, tot.trim().parse().ok()?);
+    (passed <= tot).then_some((passed, tot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_delta, parse_passed_cell, parse_summary_markdown};
+
+    #[test]
+    fn passed_cells_are_parsed() {
+        assert_eq!(parse_passed_cell("3 / 5"), Some((3, 5)));
+        assert_eq!(parse_passed_cell("0/0"), Some((0, 0)));
+        assert_eq!(parse_passed_cell("6 / 5"), None);
+        assert_eq!(parse_passed_cell("50.00%"), None);
+    }
+
+    #[test]
+    fn malformed_rows_are_skipped() {
+        let counts = parse_summary_markdown(
+            "## Group Results\n\n### GeneralStateTests\n| sub-group | passed | % |\n|-|-|-|\n| stA | 6 / 5 | 100% |\n| stB | 1 / 2 | 50% |\n",
+        );
+
+        let sub_groups = &counts.groups[0].sub_groups;
+        assert_eq!(sub_groups.len(), 1);
+        assert_eq!(sub_groups[0].name, "stB");
+    }
+
+    #[test]
+    fn deltas_of_malformed_baselines_are_signed() {
+        assert_eq!(format_delta(3, 5, 3, 5), "=");
+        assert_eq!(format_delta(4, 5, 3, 5), "+1 passed / -1 failed");
+        assert_eq!(format_delta(3, 5, 6, 5), "-3 passed / +3 failed");
+    }
+}
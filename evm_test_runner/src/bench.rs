@@ -0,0 +1,205 @@
+//! Benchmarking of the zkEVM over a fixed corpus of representative tests.
+//!
+//! Each test of the corpus is run several times, measuring its witness
+//! generation time, its proving time and the peak memory used while running
+//! it. The results are written as JSON, to be compared across zkEVM versions.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::{anyhow, Context};
+use evm_arithmetization::{prover::prove, AllStark, StarkConfig};
+use log::{info, warn};
+use plonky2::{
+    field::goldilocks_field::GoldilocksField, plonk::config::KeccakGoldilocksConfig,
+    util::timing::TimingTree,
+};
+use serde::Serialize;
+
+use crate::{
+    blacklist::load_test_names,
+    plonky2_runner::DEFAULT_MAX_CPU_LOG_LEN,
+    provenance::{parsed_tests_commit, Provenance},
+    report_generation::REPORT_OUTPUT,
+    resource_estimation::PeakRssSampler,
+    test_dir_reading::{
+        get_default_parsed_tests_path, read_in_all_parsed_tests, ParsedTestFilters, Test,
+    },
+    witness_cache::generate_witness,
+};
+
+#[derive(Debug, Serialize)]
+struct BenchReport {
+    provenance: Provenance,
+    iterations: usize,
+    max_cpu_log_len: usize,
+    tests: Vec<BenchTestResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct BenchTestResult {
+    name: String,
+    /// Witness generation time, in seconds.
+    witness_time: Option<Stats>,
+    /// Proving time, from the segment data of the witness generation, in
+    /// seconds.
+    proving_time: Option<Stats>,
+    /// Peak growth of the resident memory of the process while running the
+    /// test, in bytes.
+    peak_memory: Option<Stats>,
+    /// The error of the first failed iteration, if any.
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    mean: f64,
+    stddev: f64,
+}
+
+impl Stats {
+    fn new(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = match samples.len() {
+            1 => 0.0,
+            _ => samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.0),
+        };
+
+        Some(Self {
+            mean,
+            stddev: variance.sqrt(),
+        })
+    }
+}
+
+/// Runs every test of the corpus `iterations` times and writes the results to
+/// `out_path` (`reports/bench.json` by default), which is returned.
+pub(crate) async fn run_bench(
+    corpus_path: &Path,
+    iterations: usize,
+    max_cpu_log_len: Option<usize>,
+    out_path: Option<PathBuf>,
+) -> anyhow::Result<PathBuf> {
+    let corpus = load_test_names(corpus_path)
+        .with_context(|| format!("Reading the bench corpus {:?}", corpus_path))?;
+    let parsed_tests_path = get_default_parsed_tests_path()?;
+    let filters = ParsedTestFilters {
+        test_list: Some(Arc::new(corpus.clone())),
+        ..Default::default()
+    };
+
+    let mut tests: Vec<Test> = read_in_all_parsed_tests(&parsed_tests_path, filters)
+        .await?
+        .into_iter()
+        .flat_map(|g| g.sub_groups)
+        .flat_map(|sub_g| sub_g.tests)
+        .collect();
+    tests.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+    let found: HashSet<_> = tests.iter().map(|t| t.name.as_str()).collect();
+    for missing in corpus.iter().filter(|t| !found.contains(t.as_str())) {
+        warn!("{} is in the bench corpus but was not found", missing);
+    }
+    if tests.is_empty() {
+        return Err(anyhow!("None of the bench corpus tests were found"));
+    }
+
+    let max_cpu_log_len = max_cpu_log_len.unwrap_or(DEFAULT_MAX_CPU_LOG_LEN);
+    let all_stark = AllStark::default();
+    let config = StarkConfig::standard_fast_config();
+
+    let results = tests
+        .iter()
+        .map(|test| {
+            println!("Benchmarking {}...", test.name);
+            bench_test(test, iterations, &all_stark, &config, max_cpu_log_len)
+        })
+        .collect();
+
+    let report = BenchReport {
+        provenance: Provenance::new(parsed_tests_commit(&parsed_tests_path)),
+        iterations,
+        max_cpu_log_len,
+        tests: results,
+    };
+
+    let out_path = out_path.unwrap_or_else(|| Path::new(REPORT_OUTPUT).join("bench.json"));
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Creating report subdirectory {:?}", parent))?;
+    }
+    fs::write(&out_path, serde_json::to_vec_pretty(&report)?)
+        .with_context(|| format!("Writing bench results to {:?}", out_path))?;
+
+    Ok(out_path)
+}
+
+fn bench_test(
+    test: &Test,
+    iterations: usize,
+    all_stark: &AllStark<GoldilocksField, 2>,
+    config: &StarkConfig,
+    max_cpu_log_len: usize,
+) -> BenchTestResult {
+    let mut witness_times = Vec::with_capacity(iterations);
+    let mut proving_times = Vec::with_capacity(iterations);
+    let mut peak_memories = Vec::with_capacity(iterations);
+    let mut error = None;
+
+    for i in 0..iterations {
+        info!("{}: iteration {}/{}", test.name, i + 1, iterations);
+        let sampler = PeakRssSampler::start();
+
+        let start = Instant::now();
+        let witness_res = generate_witness(&test.info.gen_inputs, max_cpu_log_len);
+        let witness_time = start.elapsed();
+
+        // Only the traces of the segments are generated from here.
+        let start = Instant::now();
+        let proving_res = witness_res.and_then(|(trimmed_inputs, segments)| {
+            segments
+                .into_iter()
+                .map(|mut segment_data| {
+                    prove::<GoldilocksField, KeccakGoldilocksConfig, 2>(
+                        all_stark,
+                        config,
+                        trimmed_inputs.clone(),
+                        &mut segment_data,
+                        &mut TimingTree::default(),
+                        None,
+                    )
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        });
+        let proving_time = start.elapsed();
+        let peak_memory = sampler.stop();
+
+        if let Err(err) = proving_res {
+            warn!("{} failed: {:#}", test.name, err);
+            error = Some(err.to_string());
+            break;
+        }
+
+        witness_times.push(witness_time.as_secs_f64());
+        proving_times.push(proving_time.as_secs_f64());
+        peak_memories.extend(peak_memory.map(|bytes| bytes as f64));
+    }
+
+    BenchTestResult {
+        name: test.name.clone(),
+        witness_time: Stats::new(&witness_times),
+        proving_time: Stats::new(&proving_times),
+        peak_memory: Stats::new(&peak_memories),
+        error,
+    }
+}
@@ -0,0 +1,150 @@
+//! Loading and management of blacklist files.
+//!
+//! A blacklist file contains one test variant name per line. Empty lines and
+//! lines starting with `#` are ignored. The same format is used for test lists
+//! passed with `--test-list`.
+
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Result as IoResult, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+
+use crate::{arg_parsing::BlacklistAction, persistent_run_state::TestRunEntries};
+
+pub(crate) fn load_test_names(blacklist_file: &Path) -> IoResult<HashSet<String>> {
+    Ok(read_entries(blacklist_file)?.into_iter().collect())
+}
+
+/// Reads the test names of a blacklist file, preserving their order.
+fn read_entries(blacklist_file: &Path) -> IoResult<Vec<String>> {
+    let file = File::open(blacklist_file)?;
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter(|l| is_entry(l))
+        .map(|l| l.trim().to_string())
+        .collect())
+}
+
+fn is_entry(line: &str) -> bool {
+    let line = line.trim();
+    !line.is_empty() && !line.starts_with('#')
+}
+
+pub(crate) fn run_blacklist_action(
+    blacklist_path: &Path,
+    action: BlacklistAction,
+    persistent_test_state: &TestRunEntries,
+) -> anyhow::Result<()> {
+    match action {
+        BlacklistAction::Add { t_names } => {
+            let added = add_to_blacklist(blacklist_path, t_names, None)?;
+            println!("Added {} test(s) to {:?}.", added, blacklist_path);
+        }
+        BlacklistAction::Remove { t_names } => {
+            let removed = remove_from_blacklist(blacklist_path, &t_names)?;
+            println!("Removed {} test(s) from {:?}.", removed, blacklist_path);
+        }
+        BlacklistAction::List => {
+            for t_name in read_entries(blacklist_path)
+                .with_context(|| format!("Reading blacklist {:?}", blacklist_path))?
+            {
+                println!("{}", t_name);
+            }
+        }
+        BlacklistAction::Import {
+            from_file,
+            from_state,
+        } => {
+            let t_names: Vec<String> = match (from_file, from_state) {
+                (Some(path), _) => read_entries(&path)
+                    .with_context(|| format!("Reading test names from {:?}", path))?,
+                (None, Some(state)) => persistent_test_state
+                    .get_tests_in_state(state)
+                    .map(|t| t.to_string())
+                    .collect(),
+                (None, None) => anyhow::bail!("Either `--from-file` or `--from-state` is needed"),
+            };
+
+            let added = add_to_blacklist(blacklist_path, t_names, None)?;
+            println!("Imported {} test(s) into {:?}.", added, blacklist_path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Appends the tests that are not already present to the blacklist, preceded
+/// by an optional comment. Returns the number of added tests.
+pub(crate) fn add_to_blacklist(
+    blacklist_path: &Path,
+    t_names: Vec<String>,
+    comment: Option<&str>,
+) -> anyhow::Result<usize> {
+    let existing: HashSet<_> = match blacklist_path.exists() {
+        true => load_test_names(blacklist_path)?,
+        false => HashSet::new(),
+    };
+
+    let mut new_names: Vec<_> = t_names
+        .into_iter()
+        .filter(|t| !existing.contains(t))
+        .collect();
+    new_names.sort_unstable();
+    new_names.dedup();
+
+    if new_names.is_empty() {
+        return Ok(0);
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(blacklist_path)
+        .with_context(|| format!("Opening blacklist {:?}", blacklist_path))?;
+
+    // Make sure we don't append to an unterminated last line.
+    if fs::read_to_string(blacklist_path)?
+        .chars()
+        .last()
+        .is_some_and(|c| c != '\n')
+    {
+        writeln!(file)?;
+    }
+
+    if let Some(comment) = comment {
+        writeln!(file, "# {}", comment)?;
+    }
+    for t_name in new_names.iter() {
+        writeln!(file, "{}", t_name)?;
+    }
+
+    Ok(new_names.len())
+}
+
+/// Removes the given tests from the blacklist, keeping all other lines
+/// (including comments) untouched. Returns the number of removed tests.
+fn remove_from_blacklist(blacklist_path: &Path, t_names: &[String]) -> anyhow::Result<usize> {
+    let content = fs::read_to_string(blacklist_path)
+        .with_context(|| format!("Reading blacklist {:?}", blacklist_path))?;
+    let to_remove: HashSet<_> = t_names.iter().map(|t| t.as_str()).collect();
+
+    let mut removed = 0;
+    let mut kept = String::with_capacity(content.len());
+    for line in content.lines() {
+        if is_entry(line) && to_remove.contains(line.trim()) {
+            removed += 1;
+            continue;
+        }
+        kept.push_str(line);
+        kept.push('\n');
+    }
+
+    fs::write(blacklist_path, kept)
+        .with_context(|| format!("Writing blacklist {:?}", blacklist_path))?;
+    Ok(removed)
+}
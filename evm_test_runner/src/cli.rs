@@ -0,0 +1,932 @@
+//! The command line interface of the runner, wiring its arguments (and config
+//! file) to the rest of the crate.
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fmt,
+    net::TcpListener,
+    path::Path,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::anyhow;
+use clap::Parser;
+use common::{
+    eip_tags::{is_known_tag, known_tags},
+    profiles::Profile,
+    utils::{init_logger, subcommand_args},
+};
+use eth_test_parser::eth_tests_fetching::get_test_files_changed_since;
+use futures::executor::block_on;
+use log::{error, info};
+use tokio::{
+    runtime::{self},
+    sync::mpsc,
+};
+
+use crate::{
+    api::{api_router, ApiRunDefaults},
+    arg_parsing::{Command, FailOn, ProgArgs, ReportType, StateAction},
+    baseline::Baseline,
+    bench::run_bench,
+    blacklist::{load_test_names, run_blacklist_action},
+    compare_configs::run_config_comparison,
+    config_file::{args_with_config_file, test_timeouts},
+    dashboard::serve_dashboard,
+    distributed::run_worker,
+    fuzz::run_fuzz,
+    isolation::{run_isolated_test, IsolationSettings},
+    minimize::run_minimize,
+    opcode_coverage::write_opcode_coverage_report,
+    perf_regression::{find_perf_regressions, write_timings, TimingBaseline},
+    persistent_run_state::{
+        load_existing_pass_state_from_disk_if_exists_or_create, TestRunEntries,
+    },
+    plonky2_runner::{
+        run_plonky2_tests, run_plonky2_tests_streamed, TestGroupRunResults, TestRunConfig,
+        TestRunOutput,
+    },
+    pr_comment::write_pr_comment_report,
+    proof_archive::ProofArchive,
+    provenance::{json_tests_commit, parsed_tests_commit, Provenance},
+    report_generation::{
+        output_test_report_for_terminal, write_overall_status_report_summary_to_file,
+    },
+    resource_estimation::{check_memory_estimates, total_system_memory_bytes},
+    reverify::run_reverify,
+    run_history::RunHistoryDb,
+    test_dir_reading::{
+        get_default_eth_tests_path, get_default_parsed_tests_path, read_in_all_parsed_tests,
+        read_in_gen_inputs, read_in_json_tests, read_in_single_parsed_test, sample_tests,
+        stream_parsed_tests, ParsedTestFilters, ParsedTestGroup,
+    },
+    witness_cache::WitnessCache,
+    ProcessAbortedRecv,
+};
+
+/// The exit code of the runs that fail according to `--fail-on`, so that
+/// automation can tell them from the runs that couldn't complete (exiting
+/// with 1).
+const RUN_FAILURE_EXIT_CODE: i32 = 2;
+
+/// A run that completed, but fails according to `--fail-on`.
+#[derive(Debug)]
+struct RunFailure(String);
+
+impl fmt::Display for RunFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for RunFailure {}
+
+/// Runs the runner as invoked from the command line (see `--help`), which is
+/// all the `evm_test_runner` binary does.
+pub fn run_cli() -> anyhow::Result<()> {
+    run_with_args(std::env::args_os().collect(), Vec::new())
+}
+
+/// Runs the runner as the `subcommand` of another binary (eg. `evm-tests run`),
+/// given the command line arguments following it.
+pub fn run_cli_as_subcommand(subcommand: &str, args: Vec<OsString>) -> anyhow::Result<()> {
+    run_with_args(
+        subcommand_args(subcommand, args),
+        vec![subcommand.to_string()],
+    )
+}
+
+/// Runs the runner with the command line arguments `args`. The children of
+/// `--isolate` are invoked with the `exe_args` first, which select the runner
+/// in its executable.
+fn run_with_args(args: Vec<OsString>, exe_args: Vec<String>) -> anyhow::Result<()> {
+    let rt = runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Creating Tokio runtime");
+    let res = rt.block_on(run(args, exe_args));
+
+    match res {
+        // True if we exited without an error but need to stop any Plonky2 threads.
+        Ok(true) | Err(_) => {
+            // Don't wait for any plonky2 threads to finish.
+            rt.shutdown_background();
+        }
+        _ => (),
+    };
+
+    if let Err(err) = &res
+        && let Some(failure) = err.downcast_ref::<RunFailure>()
+    {
+        eprintln!("Error: {}", failure);
+        std::process::exit(RUN_FAILURE_EXIT_CODE);
+    }
+
+    res.map(|_| ())
+}
+
+async fn run(args: Vec<OsString>, exe_args: Vec<String>) -> anyhow::Result<bool> {
+    let abort_recv = init_ctrl_c_handler();
+
+    let ProgArgs {
+        command,
+        config: _,
+        profile,
+        test_filter,
+        report_type,
+        template_dir,
+        baseline,
+        save_timings,
+        perf_baseline,
+        perf_threshold,
+        fail_on_perf_regression,
+        fail_on,
+        variant_filter,
+        skip_passed,
+        only_failed,
+        include_timed_out,
+        carry_state_forward,
+        witness_only,
+        check_post_state,
+        gas_limit_policy,
+        max_cpu_log_len,
+        max_segment_log_len,
+        circuits_cache,
+        witness_cache,
+        proof_archive,
+        memory_limit_gb,
+        skip_memory_heavy,
+        jobs,
+        isolate,
+        coordinator,
+        prover_threads,
+        test_timeout,
+        timeout_per_mgas,
+        verbose,
+        log_file,
+        blacklist_path,
+        test_list,
+        since,
+        eth_tests_dir,
+        tag,
+        file,
+        json,
+        gen_inputs,
+        stream,
+        sample,
+        seed,
+        order,
+        opcode_coverage,
+        simple_progress_indicator,
+        update_persistent_state_from_upstream,
+        t8n_bin,
+        repro_dir,
+        history_db,
+        auto_blacklist_after,
+        fail_fast,
+    } = ProgArgs::parse_from(args_with_config_file(args.clone())?);
+    let _logger = init_logger(verbose, log_file.as_deref())?;
+    init_prover_thread_pool(prover_threads)?;
+    let profile = profile.map(Profile::defaults);
+    let mut test_timeouts = test_timeouts(
+        &args,
+        test_timeout.map(|t| t.into()),
+        timeout_per_mgas.map(|t| t.into()),
+    )?;
+    // The profile comes after the config file, which may set a default
+    // timeout.
+    test_timeouts.default = test_timeouts
+        .default
+        .or(profile.and_then(|p| p.test_timeout));
+    if test_timeouts.per_mgas.is_some() && test_timeouts.default.is_none() {
+        return Err(anyhow!(
+            "`--timeout-per-mgas` requires a `--test-timeout`, a default timeout in the config file or a `--profile` with one"
+        ));
+    }
+    let max_cpu_log_len = max_cpu_log_len.or(profile.and_then(|p| p.max_cpu_log_len));
+    let jobs = jobs.or(profile.map(|p| p.jobs)).unwrap_or(1);
+    let memory_limit_gb = memory_limit_gb.or(profile.and_then(|p| p.memory_limit_gb));
+
+    if matches!(command, Some(Command::RunIsolated)) {
+        run_isolated_test(
+            witness_only,
+            check_post_state,
+            gas_limit_policy,
+            max_cpu_log_len,
+            witness_cache,
+            proof_archive,
+        )?;
+        return Ok(false);
+    }
+
+    let mut persistent_test_state = load_existing_pass_state_from_disk_if_exists_or_create();
+
+    if let Some(command) = command {
+        match command {
+            Command::Blacklist {
+                blacklist_path,
+                action,
+            } => run_blacklist_action(&blacklist_path, action, &persistent_test_state)?,
+            Command::Bench {
+                corpus,
+                iterations,
+                out,
+            } => {
+                let out_path = run_bench(&corpus, iterations, max_cpu_log_len, out).await?;
+                println!("Bench results written to {:?}.", out_path);
+            }
+            Command::CompareConfigs { configs, out } => {
+                let filters = ParsedTestFilters {
+                    filter_str: test_filter.clone(),
+                    variant_filter: variant_filter.clone(),
+                    ..Default::default()
+                };
+                let tests = read_in_parsed_tests_and_update_state(
+                    file.as_deref(),
+                    json.as_deref(),
+                    gen_inputs.as_deref(),
+                    filters,
+                    false,
+                    true,
+                    &mut persistent_test_state,
+                )
+                .await?
+                .into_iter()
+                .flat_map(|g| g.sub_groups)
+                .flat_map(|sub_g| sub_g.tests)
+                .collect();
+
+                let provenance = Provenance::new(eth_tests_commit(
+                    file.as_deref(),
+                    json.as_deref(),
+                    gen_inputs.as_deref(),
+                ));
+                let num_disagreements = run_config_comparison(
+                    tests,
+                    &configs,
+                    check_post_state,
+                    gas_limit_policy,
+                    max_cpu_log_len,
+                    provenance,
+                    out,
+                )?;
+                if num_disagreements > 0 {
+                    return Err(anyhow!(
+                        "{} test(s) failed under some configurations only",
+                        num_disagreements
+                    ));
+                }
+            }
+            Command::Fuzz {
+                mutate,
+                cases,
+                seed,
+            } => {
+                let corpus = match mutate {
+                    true => {
+                        let filters = ParsedTestFilters {
+                            filter_str: test_filter.clone(),
+                            variant_filter: variant_filter.clone(),
+                            ..Default::default()
+                        };
+                        let parsed_tests = read_in_parsed_tests_and_update_state(
+                            file.as_deref(),
+                            json.as_deref(),
+                            gen_inputs.as_deref(),
+                            filters,
+                            false,
+                            true,
+                            &mut persistent_test_state,
+                        )
+                        .await?;
+
+                        Some(
+                            parsed_tests
+                                .into_iter()
+                                .flat_map(|g| g.sub_groups)
+                                .flat_map(|sub_g| sub_g.tests)
+                                .map(|t| t.info)
+                                .collect(),
+                        )
+                    }
+                    false => None,
+                };
+                let num_failures = run_fuzz(
+                    cases,
+                    seed,
+                    corpus,
+                    witness_only,
+                    max_cpu_log_len,
+                    repro_dir.as_deref(),
+                )?;
+                if num_failures > 0 {
+                    return Err(anyhow!("{} fuzz case(s) failed", num_failures));
+                }
+            }
+            Command::Reverify {
+                archive,
+                threads,
+                out,
+            } => {
+                let num_failures = run_reverify(archive, threads, out)?;
+                if num_failures > 0 {
+                    return Err(anyhow!("{} proof(s) failed re-verification", num_failures));
+                }
+            }
+            Command::Minimize { test, out } => {
+                let filters = ParsedTestFilters {
+                    test_list: Some(Arc::new(HashSet::from([test.clone()]))),
+                    ..Default::default()
+                };
+                let variant = read_in_parsed_tests_and_update_state(
+                    file.as_deref(),
+                    json.as_deref(),
+                    gen_inputs.as_deref(),
+                    filters,
+                    false,
+                    true,
+                    &mut persistent_test_state,
+                )
+                .await?
+                .into_iter()
+                .flat_map(|g| g.sub_groups)
+                .flat_map(|sub_g| sub_g.tests)
+                .next()
+                .ok_or_else(|| anyhow!("{} was not found", test))?;
+
+                run_minimize(variant.info, max_cpu_log_len, &out)?;
+            }
+            Command::Serve { addr } => {
+                let history_db = history_db
+                    .ok_or_else(|| anyhow!("`serve` requires the `--history-db` to browse"))?;
+                let blacklist =
+                    match &blacklist_path {
+                        Some(path) => Some(Arc::new(load_test_names(path).map_err(|_| {
+                            anyhow!("Could not retrieve blacklisted test variants")
+                        })?)),
+                        None => None,
+                    };
+                let api = api_router(
+                    history_db.clone(),
+                    ApiRunDefaults {
+                        witness_only,
+                        max_cpu_log_len,
+                        test_timeouts,
+                        blacklist,
+                        repro_dir,
+                    },
+                );
+                serve_dashboard(history_db, addr, api, abort_recv).await?;
+
+                // Don't wait for the test of a run started through the API.
+                return Ok(true);
+            }
+            Command::RunIsolated => unreachable!("Handled before loading the persistent state"),
+            Command::Worker { coordinator } => {
+                let num_tests = run_worker(&coordinator, circuits_cache.as_deref())?;
+                println!("Ran {} test(s) for the coordinator.", num_tests);
+            }
+            Command::State {
+                action: StateAction::Export { path },
+            } => persistent_test_state.export_to_json(&path)?,
+            Command::State {
+                action: StateAction::Import { path },
+            } => TestRunEntries::import_from_json(&path)?.write_to_disk(),
+        }
+
+        return Ok(false);
+    }
+
+    if carry_state_forward {
+        persistent_test_state.carry_forward_to_current_version();
+    }
+
+    // In segment mode, the segment length bounds the CPU length like
+    // `--max-cpu-log-len` does.
+    let max_cpu_log_len = max_segment_log_len.or(max_cpu_log_len);
+
+    let baseline = baseline.as_deref().map(Baseline::load).transpose()?;
+    let perf_baseline = perf_baseline
+        .as_deref()
+        .map(TimingBaseline::load)
+        .transpose()?;
+
+    let filters_used = test_filter.is_some()
+        || variant_filter.is_some()
+        || test_list.is_some()
+        || only_failed
+        || since.is_some()
+        || !tag.is_empty();
+
+    // Load blacklisted tests if any
+    let blacklisted_t_names = if let Some(path) = &blacklist_path {
+        load_test_names(path)
+            .map_err(|_| anyhow!("Could not retrieve blacklisted test variants"))?
+    } else {
+        HashSet::new()
+    };
+
+    // `ignored_t_names` contains both previously "passed" tests and "blacklisted"
+    // tests, if the corresponding flags are on.
+    let ignored_t_names: Option<Arc<HashSet<String>>> = match skip_passed {
+        true => {
+            let mut passed_t_names: HashSet<String> = persistent_test_state
+                .get_tests_that_have_passed(witness_only)
+                .map(|t| t.to_string())
+                .collect();
+            passed_t_names.extend(blacklisted_t_names);
+
+            Some(Arc::new(passed_t_names))
+        }
+        false => {
+            if blacklisted_t_names.is_empty() {
+                None
+            } else {
+                Some(Arc::new(blacklisted_t_names))
+            }
+        }
+    };
+
+    let test_list = match test_list {
+        Some(path) => Some(Arc::new(
+            load_test_names(&path).map_err(|_| anyhow!("Could not retrieve the test list"))?,
+        )),
+        None => None,
+    };
+    // The previous failures are run among the tests of the list, if any.
+    let test_list = match only_failed {
+        true => {
+            let failed_t_names = persistent_test_state
+                .get_tests_that_failed_last_run(include_timed_out)
+                .filter(|t| test_list.as_ref().is_none_or(|list| list.contains(*t)))
+                .map(|t| t.to_string())
+                .collect();
+
+            Some(Arc::new(failed_t_names))
+        }
+        false => test_list,
+    };
+
+    if let Some(unknown) = tag.iter().find(|t| !is_known_tag(t)) {
+        return Err(anyhow!(
+            "Unknown tag {}, expected one of: {}",
+            unknown,
+            known_tags().collect::<Vec<_>>().join(", ")
+        ));
+    }
+    let tags = (!tag.is_empty()).then(|| Arc::new(tag.into_iter().collect()));
+    let changed_files = match since {
+        Some(commit) => {
+            let eth_tests_dir = eth_tests_dir
+                .map(Ok)
+                .unwrap_or_else(get_default_eth_tests_path)?;
+            let changed_files = get_test_files_changed_since(&eth_tests_dir, &commit)?;
+            println!(
+                "{} test files changed upstream since {}",
+                changed_files.len(),
+                commit
+            );
+
+            Some(Arc::new(changed_files))
+        }
+        None => None,
+    };
+
+    let filters = ParsedTestFilters {
+        filter_str: test_filter.clone(),
+        variant_filter,
+        blacklist: ignored_t_names,
+        test_list,
+        tags,
+        changed_files,
+    };
+    let memory_limit = memory_limit_gb
+        .map(|gb| (gb * (1u64 << 30) as f64) as u64)
+        .or_else(total_system_memory_bytes);
+    let run_history = history_db
+        .map(|path| RunHistoryDb::open(&path, witness_only))
+        .transpose()?;
+
+    // Snapshot taken before the run updates the persistent state.
+    let previously_passed: HashSet<String> = match (&report_type, fail_on) {
+        (ReportType::PrComment, _) | (_, FailOn::Regressions) => persistent_test_state
+            .get_tests_that_passed_last_run(witness_only)
+            .map(|t| t.to_string())
+            .collect(),
+        _ => HashSet::new(),
+    };
+
+    let isolation = isolate.then(|| IsolationSettings {
+        exe_args,
+        witness_only,
+        check_post_state,
+        gas_limit_policy,
+        max_cpu_log_len,
+        witness_cache: witness_cache.clone(),
+        proof_archive: proof_archive.clone(),
+        prover_threads,
+        verbose,
+    });
+    let run_config = TestRunConfig {
+        simple_progress_indicator,
+        witness_only,
+        check_post_state,
+        gas_limit_policy,
+        order,
+        max_cpu_log_len,
+        test_timeouts,
+        t8n_bin,
+        repro_dir,
+        run_history,
+        auto_blacklist: auto_blacklist_after.zip(blacklist_path),
+        aggregate_segments: max_segment_log_len.is_some(),
+        circuits_cache,
+        fail_fast,
+        witness_cache: witness_cache.map(WitnessCache::new).transpose()?,
+        proof_archive: proof_archive.map(ProofArchive::new).transpose()?,
+        jobs,
+        memory_budget: memory_limit,
+        progress: None,
+        isolation,
+        coordinator: coordinator
+            .map(TcpListener::bind)
+            .transpose()
+            .map_err(|err| anyhow!("Unable to listen for workers: {}", err))?,
+    };
+
+    let provenance = Provenance::new(eth_tests_commit(
+        file.as_deref(),
+        json.as_deref(),
+        gen_inputs.as_deref(),
+    ));
+
+    let TestRunOutput {
+        results: test_res,
+        stopped_early,
+    } = match stream {
+        true => {
+            let parsed_tests = stream_parsed_tests(get_default_parsed_tests_path()?, filters)
+                .filter_map(|res| {
+                    res.map_err(|err| error!("Unable to read parsed test: {:#}", err))
+                        .ok()
+                });
+            run_plonky2_tests_streamed(
+                parsed_tests,
+                &mut persistent_test_state,
+                abort_recv,
+                run_config,
+            )
+        }
+        false => {
+            let mut parsed_tests = read_in_parsed_tests_and_update_state(
+                file.as_deref(),
+                json.as_deref(),
+                gen_inputs.as_deref(),
+                filters,
+                update_persistent_state_from_upstream,
+                filters_used,
+                &mut persistent_test_state,
+            )
+            .await?;
+
+            if let Some(num_tests) = sample {
+                let seed = seed.unwrap_or_else(rand::random);
+                println!("Sampling {} test variants with seed {}.", num_tests, seed);
+                sample_tests(&mut parsed_tests, num_tests, seed);
+            }
+
+            if opcode_coverage {
+                let report_path = write_opcode_coverage_report(
+                    &parsed_tests,
+                    &provenance,
+                    template_dir.as_deref(),
+                )?;
+                println!("Opcode coverage report written to {:?}.", report_path);
+                persistent_test_state.write_to_disk();
+                return Ok(false);
+            }
+
+            if let Some(memory_limit) = memory_limit {
+                let num_heavy = check_memory_estimates(
+                    &mut parsed_tests,
+                    &persistent_test_state,
+                    witness_only,
+                    max_cpu_log_len,
+                    memory_limit,
+                    skip_memory_heavy,
+                );
+                if num_heavy > 0 {
+                    println!(
+                        "{} test(s) may run out of memory{}.",
+                        num_heavy,
+                        if skip_memory_heavy {
+                            " and were skipped"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+
+            run_plonky2_tests(
+                parsed_tests,
+                &mut persistent_test_state,
+                abort_recv,
+                run_config,
+            )
+        }
+    };
+
+    if stopped_early {
+        println!("Run stopped early, the report only covers the tests that completed.");
+    }
+
+    if let Some(path) = &save_timings {
+        write_timings(&test_res, path)?;
+    }
+    let perf_regressions = match &perf_baseline {
+        Some(perf_baseline) => find_perf_regressions(&test_res, perf_baseline, perf_threshold),
+        None => Vec::new(),
+    };
+    if !perf_regressions.is_empty() {
+        println!(
+            "{} test(s) got slower by more than {}%.",
+            perf_regressions.len(),
+            perf_threshold
+        );
+    }
+    // Decided before the summary report consumes the results.
+    let run_failure = run_failure(&test_res, fail_on, &previously_passed);
+
+    match report_type {
+        ReportType::Test => {
+            info!("Outputting test results to stdout...");
+            output_test_report_for_terminal(
+                &test_res,
+                test_filter.clone(),
+                &perf_regressions,
+                &provenance,
+                template_dir.as_deref(),
+            )?;
+        }
+        ReportType::Summary => {
+            info!("Generating test results markdown...");
+            write_overall_status_report_summary_to_file(
+                test_res,
+                baseline.as_ref(),
+                &perf_regressions,
+                &provenance,
+                template_dir.as_deref(),
+            )?;
+        }
+        ReportType::PrComment => {
+            info!("Generating PR comment markdown...");
+            let report_path = write_pr_comment_report(
+                &test_res,
+                &previously_passed,
+                baseline.as_ref(),
+                &perf_regressions,
+                &provenance,
+            )?;
+            println!("PR comment written to {:?}.", report_path);
+        }
+    }
+
+    persistent_test_state.write_to_disk();
+
+    if fail_on_perf_regression && !perf_regressions.is_empty() {
+        return Err(anyhow!(
+            "{} performance regression(s) found",
+            perf_regressions.len()
+        ));
+    }
+    if let Some(run_failure) = run_failure {
+        return Err(RunFailure(run_failure).into());
+    }
+
+    Ok(stopped_early)
+}
+
+/// Why the run fails according to `fail_on`, if it does.
+fn run_failure(
+    test_res: &[TestGroupRunResults],
+    fail_on: FailOn,
+    previously_passed: &HashSet<String>,
+) -> Option<String> {
+    let num_failures = test_res
+        .iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter(|t| t.status.failed())
+        .filter(|t| match fail_on {
+            FailOn::Regressions => previously_passed.contains(&t.name),
+            FailOn::AnyFailure => true,
+            FailOn::Never => false,
+        })
+        .count();
+
+    match fail_on {
+        _ if num_failures == 0 => None,
+        FailOn::Regressions => Some(format!(
+            "{} test(s) that passed in their previous run failed",
+            num_failures
+        )),
+        FailOn::AnyFailure => Some(format!("{} test(s) failed", num_failures)),
+        FailOn::Never => None,
+    }
+}
+
+/// The commit of the Ethereum tests the tests to run were read from, whichever
+/// of `file`, `json` and `gen_inputs` (or the parsed tests directory) they were
+/// read from.
+fn eth_tests_commit(
+    file: Option<&Path>,
+    json: Option<&Path>,
+    gen_inputs: Option<&Path>,
+) -> Option<String> {
+    match (file, json, gen_inputs) {
+        (Some(file), _, _) => parsed_tests_commit(file),
+        (None, Some(json), _) => json_tests_commit(json),
+        // The inputs don't come from the Ethereum tests.
+        (None, None, Some(_)) => None,
+        (None, None, None) => get_default_parsed_tests_path()
+            .ok()
+            .and_then(|path| parsed_tests_commit(&path)),
+    }
+}
+
+/// Reads in the parsed tests up front (either a single file, raw JSON tests or
+/// the whole parsed tests directory), and updates the persistent state from
+/// them if requested.
+async fn read_in_parsed_tests_and_update_state(
+    file: Option<&Path>,
+    json: Option<&Path>,
+    gen_inputs: Option<&Path>,
+    filters: ParsedTestFilters,
+    update_persistent_state_from_upstream: bool,
+    filters_used: bool,
+    persistent_test_state: &mut TestRunEntries,
+) -> anyhow::Result<Vec<ParsedTestGroup>> {
+    let parsed_tests = Rc::new(match (file, json, gen_inputs) {
+        (Some(path), _, _) => read_in_single_parsed_test(path, filters).await?,
+        (None, Some(path), _) => read_in_json_tests(path, filters).await?,
+        (None, None, Some(path)) => read_in_gen_inputs(path, filters).await?,
+        (None, None, None) => {
+            read_in_all_parsed_tests(&get_default_parsed_tests_path()?, filters).await?
+        }
+    });
+
+    if update_persistent_state_from_upstream {
+        let parsed_tests_path = get_default_parsed_tests_path()?;
+
+        println!("Updating persisted test pass state from locally downloaded tests...");
+
+        let parsed_tests = match filters_used {
+            false => parsed_tests.clone(),
+
+            // I too like lifetime issues...
+            // If filters are used, then we need to reparse the tests.
+            // `add_remove_entries_from_upstream_tests` requires all the tests in the test directory
+            // in order to function correctly.
+            true => Rc::new(
+                read_in_all_parsed_tests(&parsed_tests_path, ParsedTestFilters::default()).await?,
+            ),
+        };
+
+        let t_names = parsed_tests
+            .iter()
+            .flat_map(|g| {
+                g.sub_groups
+                    .iter()
+                    .map(|sub_g| sub_g.tests.iter().flat_map(|t| t.all_names()))
+            })
+            .flatten();
+
+        persistent_test_state.add_remove_entries_from_upstream_tests(t_names);
+    }
+
+    // Remove the Rc since we no longer need it.
+    Ok(Rc::try_unwrap(parsed_tests).unwrap())
+}
+
+#[cfg(feature = "parallel")]
+fn init_prover_thread_pool(num_threads: Option<usize>) -> anyhow::Result<()> {
+    if let Some(num_threads) = num_threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build_global()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "parallel"))]
+fn init_prover_thread_pool(num_threads: Option<usize>) -> anyhow::Result<()> {
+    if num_threads.is_some() {
+        anyhow::bail!("`--prover-threads` requires the `parallel` feature");
+    }
+
+    Ok(())
+}
+
+fn init_ctrl_c_handler() -> ProcessAbortedRecv {
+    let (send, recv) = mpsc::channel(2);
+    let already_aborted = AtomicBool::new(false);
+
+    ctrlc::set_handler(move || {
+        // The in-flight proof can't be interrupted, so a second signal exits
+        // right away. The state of every completed test is already journaled.
+        if already_aborted.swap(true, Ordering::SeqCst) {
+            println!("Second abort signal received! Exiting immediately...");
+            std::process::exit(130);
+        }
+
+        println!("Abort signal received! Stopping currently running test...");
+        println!("(Press Ctrl-C again to exit without waiting for it)");
+        block_on(send.send(())).unwrap();
+    })
+    .unwrap();
+
+    recv
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::PathBuf, time::Duration};
+
+    use super::run_failure;
+    use crate::{
+        arg_parsing::FailOn,
+        plonky2_runner::{TestGroupRunResults, TestRunResult, TestStatus, TestSubGroupRunResults},
+    };
+
+    fn results(statuses: Vec<(&str, TestStatus)>) -> Vec<TestGroupRunResults> {
+        let test_res = statuses
+            .into_iter()
+            .map(|(name, status)| TestRunResult {
+                name: name.to_string(),
+                status,
+                duration: Duration::ZERO,
+                t8n_status: None,
+                input_path: PathBuf::new(),
+                proof_stats: None,
+                peak_memory: None,
+                altered_inputs: Vec::new(),
+            })
+            .collect();
+
+        vec![TestGroupRunResults {
+            name: "GeneralStateTests".to_string(),
+            sub_group_res: vec![TestSubGroupRunResults {
+                name: "stExample".to_string(),
+                test_res,
+            }],
+        }]
+    }
+
+    #[test]
+    fn run_failure_depends_on_fail_on() {
+        let test_res = results(vec![
+            ("passed", TestStatus::PassedWitness),
+            ("regressed", TestStatus::TimedOut),
+            ("new_failure", TestStatus::EvmErr("KernelPanic".to_string())),
+        ]);
+        let previously_passed = HashSet::from(["passed".to_string(), "regressed".to_string()]);
+
+        assert_eq!(
+            run_failure(&test_res, FailOn::Regressions, &previously_passed).as_deref(),
+            Some("1 test(s) that passed in their previous run failed")
+        );
+        assert_eq!(
+            run_failure(&test_res, FailOn::AnyFailure, &previously_passed).as_deref(),
+            Some("2 test(s) failed")
+        );
+        assert_eq!(
+            run_failure(&test_res, FailOn::Never, &previously_passed),
+            None
+        );
+    }
+
+    #[test]
+    fn run_failure_ignores_passing_runs() {
+        let test_res = results(vec![
+            ("passed", TestStatus::PassedWitness),
+            ("new_failure", TestStatus::EvmErr("KernelPanic".to_string())),
+        ]);
+        let previously_passed = HashSet::from(["passed".to_string()]);
+
+        assert_eq!(
+            run_failure(&test_res, FailOn::Regressions, &previously_passed),
+            None
+        );
+        assert_eq!(
+            run_failure(&results(Vec::new()), FailOn::AnyFailure, &previously_passed),
+            None
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Comparison of the proving configurations (see [`ProvingConfig`]) on the
+//! same tests.
+//!
+//! Each test is proven and verified under every configuration in turn, and
+//! the status, time and proof stats of each run are reported side by side. A
+//! test failing under some configurations but not under others points to a
+//! bug in the prover rather than in the zkEVM.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use log::info;
+use serde::Serialize;
+
+use crate::{
+    plonky2_runner::{
+        run_test_and_get_test_result, GasLimitPolicy, ProofStats, ProverSetup, ProvingConfig,
+        TestStatus,
+    },
+    provenance::Provenance,
+    report_generation::{serialize_display, serialize_duration_secs, REPORT_OUTPUT},
+    test_dir_reading::Test,
+};
+
+#[derive(Debug, Serialize)]
+struct ConfigComparisonReport {
+    provenance: Provenance,
+    configs: Vec<String>,
+    /// The number of tests that failed under some configurations only.
+    num_disagreements: usize,
+    tests: Vec<ComparedTest>,
+}
+
+#[derive(Debug, Serialize)]
+struct ComparedTest {
+    name: String,
+    /// The runs of the test, in the order of the configurations.
+    runs: Vec<ConfigRun>,
+}
+
+#[derive(Debug, Serialize)]
+struct ConfigRun {
+    #[serde(serialize_with = "serialize_display")]
+    config: ProvingConfig,
+    #[serde(serialize_with = "serialize_display")]
+    status: TestStatus,
+    #[serde(serialize_with = "serialize_duration_secs")]
+    duration: Duration,
+    proof_stats: Option<ProofStats>,
+}
+
+impl ComparedTest {
+    fn disagrees(&self) -> bool {
+        let mut failed = self.runs.iter().map(|run| run.status.failed());
+        let first = failed.next().unwrap_or_default();
+        failed.any(|failed| failed != first)
+    }
+}
+
+/// Runs every test under each of `configs`, writes the results to `out_path`
+/// (`reports/config_comparison.json` by default) and returns the number of
+/// tests that failed under some configurations only.
+pub(crate) fn run_config_comparison(
+    tests: Vec<Test>,
+    configs: &[ProvingConfig],
+    check_post_state: bool,
+    gas_limit_policy: GasLimitPolicy,
+    max_cpu_log_len: Option<usize>,
+    provenance: Provenance,
+    out_path: Option<PathBuf>,
+) -> anyhow::Result<usize> {
+    if tests.is_empty() {
+        return Err(anyhow!("No tests to compare the configurations on"));
+    }
+    println!(
+        "Running {} test(s) under {} configuration(s).",
+        tests.len(),
+        configs.len()
+    );
+
+    let prover_setups: Vec<_> = configs
+        .iter()
+        .map(|config| {
+            ProverSetup::new(false, max_cpu_log_len, None)
+                .with_post_state_check(check_post_state)
+                .with_gas_limit_policy(gas_limit_policy)
+                .with_proving_config(*config)
+        })
+        .collect();
+
+    let compared_tests: Vec<_> = tests
+        .into_iter()
+        .map(|test| {
+            println!("Comparing {}...", test.name);
+            let runs = configs
+                .iter()
+                .zip(prover_setups.iter())
+                .map(|(config, prover_setup)| {
+                    let start = Instant::now();
+                    let outcome = run_test_and_get_test_result(
+                        test.info.clone(),
+                        prover_setup,
+                        None,
+                        false,
+                        max_cpu_log_len,
+                    );
+                    let duration = start.elapsed();
+                    info!("{} under {}: {}", test.name, config, outcome.status);
+
+                    ConfigRun {
+                        config: *config,
+                        status: outcome.status,
+                        duration,
+                        proof_stats: outcome.proof_stats,
+                    }
+                })
+                .collect();
+
+            ComparedTest {
+                name: test.name,
+                runs,
+            }
+        })
+        .collect();
+
+    termimad::print_text(&comparison_table(configs, &compared_tests));
+
+    let num_disagreements = compared_tests.iter().filter(|t| t.disagrees()).count();
+    let report = ConfigComparisonReport {
+        provenance,
+        configs: configs.iter().map(|config| config.to_string()).collect(),
+        num_disagreements,
+        tests: compared_tests,
+    };
+
+    let out_path =
+        out_path.unwrap_or_else(|| Path::new(REPORT_OUTPUT).join("config_comparison.json"));
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Creating report subdirectory {:?}", parent))?;
+    }
+    fs::write(&out_path, serde_json::to_vec_pretty(&report)?)
+        .with_context(|| format!("Writing the configuration comparison to {:?}", out_path))?;
+    println!(
+        "{} test(s) failed under some configurations only. Results written to {:?}.",
+        num_disagreements, out_path
+    );
+
+    Ok(num_disagreements)
+}
+
+/// A markdown table with a row per test and a column per configuration, each
+/// cell holding the outcome and duration of a run.
+fn comparison_table(configs: &[ProvingConfig], tests: &[ComparedTest]) -> String {
+    let mut table = format!(
+        "|Test|{}|\n|-|{}\n",
+        configs
+            .iter()
+            .map(|config| config.to_string())
+            .collect::<Vec<_>>()
+            .join("|"),
+        "-|".repeat(configs.len())
+    );
+
+    for test in tests {
+        let cells: Vec<_> = test
+            .runs
+            .iter()
+            .map(|run| {
+                let outcome = match &run.status {
+                    status if status.passed() => "passed",
+                    status if status.clamped() => "passed (clamped)",
+                    status if status.failed() => "failed",
+                    _ => "ignored",
+                };
+                format!("{} ({:.1}s)", outcome, run.duration.as_secs_f64())
+            })
+            .collect();
+        let marker = match test.disagrees() {
+            true => " ⚠",
+            false => "",
+        };
+        table.push_str(&format!("|{}{}|{}|\n", test.name, marker, cells.join("|")));
+    }
+
+    table
+}
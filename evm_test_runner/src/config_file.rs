@@ -0,0 +1,243 @@
+//! Support for a TOML configuration file carrying default values for the
+//! program arguments.
+//!
+//! Each top-level key is the long name of an argument (eg. `test_timeout` or
+//! `test-timeout`). The values are injected before the CLI arguments, so any
+//! flag passed explicitly on the command line takes precedence. As boolean
+//! flags have no value to override, the ones set in the config file can be
+//! turned off on the command line with `--no-<flag>` (eg. `--no-witness-only`).
+//!
+//! ```toml
+//! blacklist_path = "../cancun_heavy_tests_blacklist.txt"
+//! report_type = "summary"
+//! test_timeout = "10m"
+//! witness_only = true
+//! ```
+//!
+//! The `[timeouts]` section overrides the test timeout of whole sub-groups,
+//! with a `default` for the others (used if `test_timeout` isn't set):
+//!
+//! ```toml
+//! [timeouts]
+//! default = "10m"
+//! stTimeConsuming = "6h"
+//! stExample = "30s"
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use clap::{ArgAction, CommandFactory};
+use toml::{Table, Value};
+
+use crate::{arg_parsing::ProgArgs, plonky2_runner::TestTimeouts};
+
+/// The config file loaded by default if present in the working directory.
+pub(crate) const DEFAULT_CONFIG_PATH: &str = "runner.toml";
+
+const CONFIG_ARG: &str = "--config";
+
+/// The prefix of the arguments turning off a boolean flag of the config file.
+const NEGATION_PREFIX: &str = "--no-";
+
+/// The section holding the per sub-group test timeouts.
+const TIMEOUTS_SECTION: &str = "timeouts";
+
+/// The key of the timeouts section applying to the other sub-groups.
+const DEFAULT_TIMEOUT_KEY: &str = "default";
+
+/// Returns the program arguments `args`, with the ones defined in the config
+/// file (if any) inserted before the ones passed on the command line, and
+/// without the `--no-<flag>` ones.
+pub(crate) fn args_with_config_file(mut args: Vec<OsString>) -> anyhow::Result<Vec<OsString>> {
+    let negated_flags = take_negated_flags(&mut args);
+    let Some(config_path) = config_path(&args) else {
+        return Ok(args);
+    };
+    let config_args = config_file_args(&load_config_table(&config_path)?, &negated_flags)
+        .with_context(|| format!("Reading config file {:?}", config_path))?;
+
+    args.splice(1..1, config_args.into_iter().map(OsString::from));
+    Ok(args)
+}
+
+/// Returns the test timeouts, with the ones of the `[timeouts]` section of the
+/// config file (if any). `test_timeout` takes precedence over its default.
+pub(crate) fn test_timeouts(
+    args: &[OsString],
+    test_timeout: Option<Duration>,
+    timeout_per_mgas: Option<Duration>,
+) -> anyhow::Result<TestTimeouts> {
+    let mut timeouts = TestTimeouts {
+        default: test_timeout,
+        per_mgas: timeout_per_mgas,
+        ..Default::default()
+    };
+
+    let Some(config_path) = config_path(args) else {
+        return Ok(timeouts);
+    };
+    let by_sub_group = sub_group_timeouts(&load_config_table(&config_path)?)
+        .with_context(|| format!("Reading config file {:?}", config_path))?;
+
+    for (sub_group, timeout) in by_sub_group {
+        match sub_group == DEFAULT_TIMEOUT_KEY {
+            true => timeouts.default = timeouts.default.or(Some(timeout)),
+            false => {
+                timeouts.by_sub_group.insert(sub_group, timeout);
+            }
+        }
+    }
+
+    Ok(timeouts)
+}
+
+fn sub_group_timeouts(table: &Table) -> anyhow::Result<HashMap<String, Duration>> {
+    let Some(section) = table.get(TIMEOUTS_SECTION) else {
+        return Ok(HashMap::new());
+    };
+    let section = section
+        .as_table()
+        .ok_or_else(|| anyhow!("`{}` should be a section", TIMEOUTS_SECTION))?;
+
+    section
+        .iter()
+        .map(|(sub_group, value)| {
+            let timeout = value
+                .as_str()
+                .ok_or_else(|| anyhow!("Unsupported timeout for `{}`: {}", sub_group, value))?
+                .parse::<humantime::Duration>()
+                .with_context(|| format!("Parsing the timeout of `{}`", sub_group))?;
+            Ok((sub_group.clone(), timeout.into()))
+        })
+        .collect()
+}
+
+/// Loads the raw config file table.
+pub(crate) fn load_config_table(path: &Path) -> anyhow::Result<Table> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Reading config file {:?}", path))?;
+    content
+        .parse()
+        .with_context(|| format!("Parsing config file {:?}", path))
+}
+
+/// Returns the config file path passed with `--config`, or the default one if
+/// it exists.
+pub(crate) fn config_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().filter_map(|a| a.to_str());
+    while let Some(arg) = args.next() {
+        if arg == CONFIG_ARG {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path.into());
+        }
+    }
+
+    Path::new(DEFAULT_CONFIG_PATH)
+        .exists()
+        .then(|| DEFAULT_CONFIG_PATH.into())
+}
+
+/// Removes the `--no-<flag>` arguments from `args`, for the boolean flags of
+/// the program, returning the flags (as `--<flag>`) they turn off.
+fn take_negated_flags(args: &mut Vec<OsString>) -> HashSet<String> {
+    let command = ProgArgs::command();
+    let boolean_flags: HashSet<_> = command
+        .get_arguments()
+        .filter(|arg| matches!(arg.get_action(), ArgAction::SetTrue))
+        .filter_map(|arg| arg.get_long())
+        .collect();
+
+    let mut negated_flags = HashSet::new();
+    args.retain(|arg| {
+        let flag = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix(NEGATION_PREFIX))
+            .filter(|flag| boolean_flags.contains(flag));
+        if let Some(flag) = flag {
+            negated_flags.insert(format!("--{}", flag));
+        }
+        flag.is_none()
+    });
+
+    negated_flags
+}
+
+fn config_file_args(table: &Table, negated_flags: &HashSet<String>) -> anyhow::Result<Vec<String>> {
+    let mut args = Vec::new();
+
+    for (key, value) in table {
+        // Tables are sections read by other parts of the runner.
+        if value.is_table() || key == "config" {
+            continue;
+        }
+
+        let flag = format!("--{}", key.replace('_', "-"));
+        match value {
+            Value::Boolean(true) if !negated_flags.contains(&flag) => args.push(flag),
+            Value::Boolean(_) => (),
+            Value::Array(vals) => {
+                for v in vals {
+                    args.push(flag.clone());
+                    args.push(scalar_to_string(key, v)?);
+                }
+            }
+            v => {
+                args.push(flag);
+                args.push(scalar_to_string(key, v)?);
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+fn scalar_to_string(key: &str, value: &Value) -> anyhow::Result<String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Integer(i) => Ok(i.to_string()),
+        Value::Float(f) => Ok(f.to_string()),
+        _ => Err(anyhow!("Unsupported value for `{}`: {}", key, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use toml::Table;
+
+    use super::{config_file_args, take_negated_flags};
+
+    #[test]
+    fn boolean_flags_of_the_config_file_can_be_negated() {
+        let table: Table = r#"
+            test_timeout = "10m"
+            witness_only = true
+            skip_passed = true
+            check_post_state = false
+        "#
+        .parse()
+        .unwrap();
+        let mut args: Vec<OsString> = ["evm_test_runner", "--no-witness-only", "--no-unknown"]
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+
+        let negated_flags = take_negated_flags(&mut args);
+
+        assert_eq!(args, ["evm_test_runner", "--no-unknown"]);
+        assert_eq!(
+            config_file_args(&table, &negated_flags).unwrap(),
+            ["--skip-passed", "--test-timeout", "10m"]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! A local web dashboard for browsing the runs recorded in the history
+//! database (see `--history-db`), served along with the [`crate::api`].
+//!
+//! Pages are rendered on each request straight from the database, so a run
+//! that is still going shows up with the tests it has completed so far.
+
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use log::warn;
+use serde::Deserialize;
+use tokio::net::TcpListener;
+
+use crate::{
+    run_history::{RunHistoryReader, RunSummary, TestRunRecord},
+    ProcessAbortedRecv,
+};
+
+/// The number of tests shown in the timing chart of a run.
+const NUM_SLOWEST_TESTS: usize = 30;
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 200.0;
+
+#[derive(Template)]
+#[template(path = "dashboard/runs.html")]
+struct RunsTemplate {
+    runs: Vec<RunSummary>,
+}
+
+#[derive(Template)]
+#[template(path = "dashboard/run.html")]
+struct RunTemplate {
+    run: RunSummary,
+    filter: TestFilter,
+    tests: Vec<TemplateTestRun>,
+    chart: TimingChart,
+}
+
+#[derive(Template)]
+#[template(path = "dashboard/test.html")]
+struct TestTemplate {
+    name: String,
+    runs: Vec<TemplateTestRun>,
+    chart: TimingChart,
+}
+
+struct TemplateTestRun {
+    run_id: i64,
+    name: String,
+    status: String,
+    error: String,
+    duration: String,
+    finished_at: String,
+}
+
+impl From<&TestRunRecord> for TemplateTestRun {
+    fn from(record: &TestRunRecord) -> Self {
+        Self {
+            run_id: record.run_id,
+            name: record.test_name.clone(),
+            status: record.status.clone(),
+            error: record.error.clone().unwrap_or_default(),
+            duration: format_duration(record.duration_ms),
+            finished_at: record.finished_at.clone(),
+        }
+    }
+}
+
+/// A bar chart of test durations, rendered as SVG.
+struct TimingChart {
+    width: f64,
+    height: f64,
+    bars: Vec<ChartBar>,
+    max_duration: String,
+}
+
+struct ChartBar {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    failed: bool,
+    label: String,
+}
+
+impl TimingChart {
+    fn new<'a>(records: impl ExactSizeIterator<Item = (String, &'a TestRunRecord)>) -> Self {
+        let slot_width = CHART_WIDTH / records.len().max(1) as f64;
+        let records: Vec<_> = records.collect();
+        let max_duration = records
+            .iter()
+            .map(|(_, r)| r.duration_ms)
+            .max()
+            .unwrap_or(0);
+
+        let bars = records
+            .into_iter()
+            .enumerate()
+            .map(|(i, (label, record))| {
+                let height = match max_duration {
+                    0 => 0.0,
+                    max => CHART_HEIGHT * record.duration_ms as f64 / max as f64,
+                };
+                ChartBar {
+                    x: i as f64 * slot_width,
+                    y: CHART_HEIGHT - height,
+                    width: (slot_width - 2.0).max(1.0),
+                    height,
+                    failed: !record.status.starts_with("Passed"),
+                    label: format!("{}: {}", label, format_duration(record.duration_ms)),
+                }
+            })
+            .collect();
+
+        Self {
+            width: CHART_WIDTH,
+            height: CHART_HEIGHT,
+            bars,
+            max_duration: format_duration(max_duration),
+        }
+    }
+}
+
+/// The filters of the test table of a run.
+#[derive(Debug, Default, Deserialize)]
+struct TestFilter {
+    /// One of `passed`, `failed`, `timed-out` or `ignored`.
+    #[serde(default)]
+    status: String,
+    /// A substring of the test names.
+    #[serde(default)]
+    q: String,
+}
+
+impl TestFilter {
+    fn matches(&self, record: &TestRunRecord) -> bool {
+        let status_matches = match self.status.as_str() {
+            "passed" => matches!(record.status.as_str(), "PassedWitness" | "PassedProof"),
+            "failed" => matches!(record.status.as_str(), "EvmErr" | "Crashed"),
+            "timed-out" => record.status == "TimedOut",
+            // Tests proven with a clamped gas limit didn't pass as such.
+            "ignored" => matches!(record.status.as_str(), "Ignored" | "PassedClampedProof"),
+            _ => true,
+        };
+
+        status_matches && record.test_name.contains(&self.q)
+    }
+}
+
+struct DashboardError(anyhow::Error);
+
+impl IntoResponse for DashboardError {
+    fn into_response(self) -> Response {
+        warn!("Dashboard error: {:#}", self.0);
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", self.0)).into_response()
+    }
+}
+
+type PageResult = Result<Response, DashboardError>;
+
+/// Serves the dashboard and the `api` routes until the process is aborted.
+pub(crate) async fn serve_dashboard(
+    history_db: PathBuf,
+    addr: SocketAddr,
+    api: Router,
+    mut process_aborted: ProcessAbortedRecv,
+) -> anyhow::Result<()> {
+    // Fail early if the database can't be read.
+    RunHistoryReader::open(&history_db)?;
+
+    let app = Router::new()
+        .route("/", get(runs_page))
+        .route("/runs/:id", get(run_page))
+        .route("/tests/*name", get(test_page))
+        .with_state(Arc::new(history_db))
+        .merge(api);
+
+    let listener = TcpListener::bind(addr).await?;
+    println!("Serving the dashboard on http://{}", listener.local_addr()?);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            process_aborted.recv().await;
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Renders a page from the database on a blocking thread. `None` is rendered
+/// as a 404.
+async fn render_page<T: Template + Send + 'static>(
+    history_db: Arc<PathBuf>,
+    page: impl FnOnce(&RunHistoryReader) -> anyhow::Result<Option<T>> + Send + 'static,
+) -> PageResult {
+    let rendered = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        let reader = RunHistoryReader::open(&history_db)?;
+        Ok(page(&reader)?.map(|page| page.render()).transpose()?)
+    })
+    .await
+    .map_err(|err| DashboardError(err.into()))?
+    .map_err(DashboardError)?;
+
+    Ok(match rendered {
+        Some(html) => Html(html).into_response(),
+        None => (StatusCode::NOT_FOUND, "Not found").into_response(),
+    })
+}
+
+async fn runs_page(State(history_db): State<Arc<PathBuf>>) -> PageResult {
+    render_page(history_db, |reader| {
+        Ok(Some(RunsTemplate {
+            runs: reader.runs()?,
+        }))
+    })
+    .await
+}
+
+async fn run_page(
+    State(history_db): State<Arc<PathBuf>>,
+    Path(run_id): Path<i64>,
+    Query(filter): Query<TestFilter>,
+) -> PageResult {
+    render_page(history_db, move |reader| {
+        let Some(run) = reader.run(run_id)? else {
+            return Ok(None);
+        };
+        let records: Vec<_> = reader
+            .run_tests(run_id)?
+            .into_iter()
+            .filter(|record| filter.matches(record))
+            .collect();
+
+        let mut slowest: Vec<_> = records.iter().collect();
+        slowest.sort_unstable_by(|a, b| b.duration_ms.cmp(&a.duration_ms));
+        slowest.truncate(NUM_SLOWEST_TESTS);
+        let chart = TimingChart::new(slowest.into_iter().map(|r| (r.test_name.clone(), r)));
+
+        Ok(Some(RunTemplate {
+            run,
+            filter,
+            tests: records.iter().map(TemplateTestRun::from).collect(),
+            chart,
+        }))
+    })
+    .await
+}
+
+async fn test_page(State(history_db): State<Arc<PathBuf>>, Path(name): Path<String>) -> PageResult {
+    render_page(history_db, move |reader| {
+        let records = reader.test_history(&name)?;
+        if records.is_empty() {
+            return Ok(None);
+        }
+
+        let chart = TimingChart::new(records.iter().map(|r| (format!("run {}", r.run_id), r)));
+        Ok(Some(TestTemplate {
+            name,
+            runs: records.iter().map(TemplateTestRun::from).collect(),
+            chart,
+        }))
+    })
+    .await
+}
+
+fn format_duration(duration_ms: u64) -> String {
+    format!("{:.2}s", duration_ms as f64 / 1000.0)
+}
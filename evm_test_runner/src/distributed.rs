@@ -0,0 +1,255 @@
+//! Distribution of a run over several machines.
+//!
+//! A coordinator (a regular run given `--coordinator <ADDR>`) listens on a TCP
+//! address, and worker processes (the `worker` subcommand) connect to it. Each
+//! worker first sends the version of `evm_arithmetization` it was built with,
+//! and is turned away if it differs from the one of the coordinator, since its
+//! outcomes would be recorded under the version of the latter. It is then sent
+//! the settings of the run once, and then one test variant at a time, whose
+//! outcome it sends back. All the bookkeeping (persistent state,
+//! run history, reports) happens on the coordinator, so a distributed run
+//! produces the same outputs as a local one. A test whose worker disconnects
+//! is sent to another worker.
+//!
+//! Messages are CBOR, each prefixed with its length as a big-endian `u32`, and
+//! can't be larger than [`MAX_MSG_LEN`].
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use common::{config::EVM_ARITHMETIZATION_VERSION, types::TestVariantRunInfo};
+use log::info;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::plonky2_runner::{
+    run_test_and_get_test_result, GasLimitPolicy, InputAlteration, ProofStats, ProverSetup,
+    TestOutcome, TestStatus,
+};
+
+/// The largest message accepted, which leaves room for the pre-state of the
+/// heaviest tests while not letting any peer make us allocate up to 4 GiB.
+const MAX_MSG_LEN: usize = 256 << 20;
+
+/// Sent by a worker when it connects.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct WorkerHello {
+    pub(crate) evm_arithmetization_version: String,
+}
+
+/// The settings of the run that the workers need to run tests the same way
+/// the coordinator would.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct WorkerSettings {
+    pub(crate) witness_only: bool,
+    pub(crate) check_post_state: bool,
+    pub(crate) gas_limit_policy: GasLimitPolicy,
+    pub(crate) max_cpu_log_len: Option<usize>,
+    pub(crate) aggregate_segments: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) enum CoordinatorMsg {
+    /// Sent once, when the worker connects.
+    Settings(WorkerSettings),
+    /// Sent instead of the settings to a worker that can't take part in the
+    /// run, with the reason why.
+    Rejected(String),
+    Test(Box<TestVariantRunInfo>),
+    /// There are no more tests to run.
+    Done,
+}
+
+/// The outcome of a test, as sent back by a worker (or by the child process
+/// running it with `--isolate`).
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct WorkerOutcome {
+    status: TestStatus,
+    /// The proof size and verification time, if the test was fully proven.
+    proof_stats: Option<(usize, Duration)>,
+    #[serde(default)]
+    peak_memory: Option<u64>,
+    #[serde(default)]
+    altered_inputs: Vec<InputAlteration>,
+}
+
+impl From<TestOutcome> for WorkerOutcome {
+    fn from(outcome: TestOutcome) -> Self {
+        Self {
+            status: outcome.status,
+            proof_stats: outcome.proof_stats.map(|s| (s.size, s.verify_time)),
+            peak_memory: outcome.peak_memory,
+            altered_inputs: outcome.altered_inputs,
+        }
+    }
+}
+
+impl From<WorkerOutcome> for TestOutcome {
+    fn from(outcome: WorkerOutcome) -> Self {
+        Self {
+            status: outcome.status,
+            proof_stats: outcome
+                .proof_stats
+                .map(|(size, verify_time)| ProofStats { size, verify_time }),
+            peak_memory: outcome.peak_memory,
+            altered_inputs: outcome.altered_inputs,
+        }
+    }
+}
+
+pub(crate) fn write_msg(stream: &mut TcpStream, msg: &impl Serialize) -> anyhow::Result<()> {
+    let bytes = serde_cbor::to_vec(msg)?;
+    stream.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    stream.write_all(&bytes)?;
+
+    Ok(())
+}
+
+pub(crate) fn read_msg<T: DeserializeOwned>(stream: &mut TcpStream) -> anyhow::Result<T> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MSG_LEN {
+        return Err(anyhow!(
+            "Message of {} bytes exceeds the limit of {} bytes",
+            len,
+            MAX_MSG_LEN
+        ));
+    }
+    let mut bytes = vec![0; len];
+    stream.read_exact(&mut bytes)?;
+
+    Ok(serde_cbor::from_slice(&bytes)?)
+}
+
+/// Runs the tests sent by the coordinator at `coordinator` until it has none
+/// left, returning the number of tests run. `circuits_cache` is where the
+/// recursive circuits are cached, if the coordinator aggregates segments.
+pub(crate) fn run_worker(
+    coordinator: &str,
+    circuits_cache: Option<&Path>,
+) -> anyhow::Result<usize> {
+    let mut stream = TcpStream::connect(coordinator)
+        .with_context(|| format!("Connecting to the coordinator at {}", coordinator))?;
+    println!("Connected to the coordinator at {}.", coordinator);
+
+    write_msg(
+        &mut stream,
+        &WorkerHello {
+            evm_arithmetization_version: EVM_ARITHMETIZATION_VERSION.to_string(),
+        },
+    )?;
+    let settings = match read_msg(&mut stream)? {
+        CoordinatorMsg::Settings(settings) => settings,
+        CoordinatorMsg::Rejected(reason) => {
+            anyhow::bail!("The coordinator turned this worker away: {}", reason)
+        }
+        msg => anyhow::bail!("Expected the run settings, but got {:?}", msg),
+    };
+    let prover_setup = ProverSetup::new(
+        settings.aggregate_segments,
+        settings.max_cpu_log_len,
+        circuits_cache,
+    )
+    .with_post_state_check(settings.check_post_state)
+    .with_gas_limit_policy(settings.gas_limit_policy);
+
+    let mut num_tests = 0;
+    loop {
+        let test = match read_msg(&mut stream) {
+            Ok(CoordinatorMsg::Test(test)) => test,
+            Ok(CoordinatorMsg::Done) => break,
+            Ok(msg) => anyhow::bail!("Expected a test, but got {:?}", msg),
+            // The coordinator exits without waiting for the workers that are
+            // still busy with tests that timed out.
+            Err(err) if is_connection_closed(&err) => {
+                println!("The coordinator closed the connection.");
+                break;
+            }
+            Err(err) => return Err(err),
+        };
+        let name = test.variant_name.clone();
+        info!("Running {}", name);
+
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+            run_test_and_get_test_result(
+                *test,
+                &prover_setup,
+                None,
+                settings.witness_only,
+                settings.max_cpu_log_len,
+            )
+        }))
+        .unwrap_or_else(|_| TestStatus::EvmErr("The prover panicked".to_string()).into());
+        info!("{}: {}", name, outcome.status);
+
+        write_msg(&mut stream, &WorkerOutcome::from(outcome))?;
+        num_tests += 1;
+    }
+
+    Ok(num_tests)
+}
+
+/// Checks the hello message of a worker, returning why it can't take part in
+/// the run, if it can't.
+pub(crate) fn check_worker_hello(hello: &WorkerHello) -> Result<(), String> {
+    match hello.evm_arithmetization_version == EVM_ARITHMETIZATION_VERSION {
+        true => Ok(()),
+        false => Err(format!(
+            "The worker was built with evm_arithmetization {}, but the coordinator with {}",
+            hello.evm_arithmetization_version, EVM_ARITHMETIZATION_VERSION
+        )),
+    }
+}
+
+fn is_connection_closed(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<io::Error>()
+        .is_some_and(|err| err.kind() == io::ErrorKind::UnexpectedEof)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        net::{TcpListener, TcpStream},
+    };
+
+    use common::config::EVM_ARITHMETIZATION_VERSION;
+
+    use super::{check_worker_hello, read_msg, write_msg, WorkerHello, MAX_MSG_LEN};
+
+    fn connected_streams() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn workers_of_other_versions_are_rejected() {
+        let hello = |version: &str| WorkerHello {
+            evm_arithmetization_version: version.to_string(),
+        };
+
+        assert!(check_worker_hello(&hello(EVM_ARITHMETIZATION_VERSION)).is_ok());
+        assert!(check_worker_hello(&hello("0.0.0-other")).is_err());
+    }
+
+    #[test]
+    fn messages_round_trip_up_to_the_size_limit() {
+        let (mut client, mut server) = connected_streams();
+
+        write_msg(&mut client, &"hello".to_string()).unwrap();
+        assert_eq!(read_msg::<String>(&mut server).unwrap(), "hello");
+
+        client
+            .write_all(&((MAX_MSG_LEN + 1) as u32).to_be_bytes())
+            .unwrap();
+        assert!(read_msg::<String>(&mut server).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! Estimation of the remaining run time.
+//!
+//! Test costs vary by orders of magnitude, so a uniform per-test ETA is
+//! meaningless. Instead, each test is weighted by its last recorded duration
+//! in the persistent state, falling back to an estimate based on the gas it
+//! uses. The remaining estimate is then rescaled by how the tests completed so
+//! far compared to their estimates, which accounts for running on a different
+//! machine or in a different mode (witness only vs proving).
+
+use std::{collections::HashMap, time::Duration};
+
+use crate::{persistent_run_state::TestRunEntries, test_dir_reading::ParsedTestGroup};
+
+/// Proving time per unit of gas used when no historical timing is available
+/// at all to derive it from.
+const FALLBACK_NANOS_PER_GAS: f64 = 10_000.0;
+
+#[derive(Debug, Default)]
+pub(crate) struct EtaEstimator {
+    estimates: HashMap<String, Duration>,
+    remaining_est: Duration,
+    completed_est: Duration,
+    completed_actual: Duration,
+    /// Total duration and gas used of the tests with a recorded duration.
+    timed_nanos: f64,
+    timed_gas: f64,
+}
+
+impl EtaEstimator {
+    pub(crate) fn new(groups: &[ParsedTestGroup], persistent_test_state: &TestRunEntries) -> Self {
+        let mut eta = Self::default();
+        eta.add_tests(groups, persistent_test_state);
+        eta
+    }
+
+    /// Adds tests to the ones remaining to be run.
+    pub(crate) fn add_tests(
+        &mut self,
+        groups: &[ParsedTestGroup],
+        persistent_test_state: &TestRunEntries,
+    ) {
+        let tests: Vec<_> = groups
+            .iter()
+            .flat_map(|g| g.sub_groups.iter())
+            .flat_map(|sub_g| sub_g.tests.iter())
+            .map(|t| {
+                let duration = persistent_test_state.get_last_duration(&t.name);
+                (t.name.as_str(), t.info.gas_used(), duration)
+            })
+            .collect();
+
+        // Derive the cost of a unit of gas from the tests we have timings for.
+        for (_, gas, duration) in tests.iter() {
+            if let Some(d) = duration {
+                self.timed_nanos += d.as_nanos() as f64;
+                self.timed_gas += *gas as f64;
+            }
+        }
+        let nanos_per_gas = match self.timed_gas > 0.0 {
+            true => self.timed_nanos / self.timed_gas,
+            false => FALLBACK_NANOS_PER_GAS,
+        };
+
+        for (name, gas, duration) in tests {
+            let est = duration
+                .unwrap_or_else(|| Duration::from_nanos((gas as f64 * nanos_per_gas) as u64));
+            self.remaining_est += est;
+            self.estimates.insert(name.to_string(), est);
+        }
+    }
+
+    pub(crate) fn test_completed(&mut self, t_name: &str, duration: Duration) {
+        let est = self.estimates.remove(t_name).unwrap_or_default();
+        self.remaining_est = self.remaining_est.saturating_sub(est);
+        self.completed_est += est;
+        self.completed_actual += duration;
+    }
+
+    /// The estimated time needed to run all remaining tests.
+    pub(crate) fn remaining(&self) -> Duration {
+        if self.completed_est.is_zero() || self.completed_actual.is_zero() {
+            return self.remaining_est;
+        }
+
+        let ratio = self.completed_actual.as_secs_f64() / self.completed_est.as_secs_f64();
+        self.remaining_est.mul_f64(ratio)
+    }
+}
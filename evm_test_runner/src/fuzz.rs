@@ -0,0 +1,137 @@
+//! Differential fuzzing of the zkEVM against `revm`.
+//!
+//! Each case is a random pre-state and a random transaction (see
+//! [`generator`]), bounded so that it stays within what the zkEVM can prove: a
+//! single legacy transaction in a Cancun block with a `u32` gas limit. The case
+//! is executed with `revm` to derive the expected post-state, transactions and
+//! receipts roots (see [`oracle`]), which are then checked by running the
+//! resulting `GenerationInputs` through witness generation or proving.
+//!
+//! Every case is generated from its own seed (the base seed plus the index of
+//! the case), so a failing case can be reproduced on its own with
+//! `fuzz --seed <seed> -n 1`.
+//!
+//! Cases can also be mutations of the variants of the parsed tests (see
+//! [`mutator`]), each case mutating the variant at the index of its seed
+//! (modulo the number of variants, sorted by name).
+
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use common::types::TestVariantRunInfo;
+use log::info;
+
+use crate::{
+    plonky2_runner::{run_test_and_get_test_result, ProverSetup},
+    repro_bundle::write_repro_bundle,
+};
+
+mod generator;
+mod mutator;
+mod oracle;
+
+use generator::generate_case;
+use mutator::BaseCase;
+use oracle::{execute_case, OracleOutcome};
+
+/// Runs `num_cases` random cases, or mutations of the variants of `corpus` if
+/// given, and returns the number of them for which the zkEVM disagreed with
+/// `revm`.
+pub(crate) fn run_fuzz(
+    num_cases: usize,
+    seed: Option<u64>,
+    corpus: Option<Vec<TestVariantRunInfo>>,
+    witness_only: bool,
+    max_cpu_log_len: Option<usize>,
+    repro_dir: Option<&Path>,
+) -> anyhow::Result<usize> {
+    let base_seed = seed.unwrap_or_else(rand::random);
+    let base_cases = corpus.map(base_cases).transpose()?;
+    println!("Fuzzing {} case(s) with seed {}.", num_cases, base_seed);
+
+    let prover_setup = ProverSetup::new(false, max_cpu_log_len, None);
+    let mut num_invalid = 0;
+    let mut num_failures = 0;
+
+    for i in 0..num_cases {
+        let case_seed = base_seed.wrapping_add(i as u64);
+        let case = match &base_cases {
+            Some(base_cases) => {
+                base_cases[(case_seed % base_cases.len() as u64) as usize].mutate(case_seed)
+            }
+            None => generate_case(case_seed),
+        };
+        let name = case.name.clone();
+
+        let test = match execute_case(case)? {
+            OracleOutcome::Valid(test) => test,
+            OracleOutcome::InvalidTxn(reason) => {
+                info!(
+                    "Skipping {}, as its transaction is invalid: {}",
+                    name, reason
+                );
+                num_invalid += 1;
+                continue;
+            }
+        };
+
+        let status = run_test_and_get_test_result(
+            test.clone(),
+            &prover_setup,
+            None,
+            witness_only,
+            max_cpu_log_len,
+        )
+        .status;
+
+        match status.failed() {
+            true => {
+                num_failures += 1;
+                println!("{} (seed {}) failed: {}", name, case_seed, status);
+                if let Some(repro_dir) = repro_dir {
+                    write_repro_bundle(repro_dir, &test, &status)
+                        .with_context(|| format!("Writing the repro bundle of {}", name))?;
+                }
+            }
+            false => info!("{}: {}", name, status),
+        }
+    }
+
+    println!(
+        "{} case(s) run, {} failed, {} skipped as invalid.",
+        num_cases - num_invalid,
+        num_failures,
+        num_invalid
+    );
+
+    Ok(num_failures)
+}
+
+/// The variants of `corpus` that can be mutated, sorted by name.
+fn base_cases(mut corpus: Vec<TestVariantRunInfo>) -> anyhow::Result<Vec<BaseCase>> {
+    corpus.sort_by(|a, b| a.variant_name.cmp(&b.variant_name));
+    let num_variants = corpus.len();
+
+    let base_cases: Vec<_> = corpus
+        .into_iter()
+        .filter_map(|variant| {
+            let name = variant.variant_name.clone();
+            BaseCase::new(variant)
+                .inspect_err(|reason| info!("{} can't be mutated, as {}", name, reason))
+                .ok()
+        })
+        .collect();
+    if base_cases.is_empty() {
+        return Err(anyhow!(
+            "None of the {} variant(s) can be mutated",
+            num_variants
+        ));
+    }
+    println!(
+        "Mutating {} of {} variant(s).",
+        base_cases.len(),
+        num_variants
+    );
+
+    Ok(base_cases)
+}
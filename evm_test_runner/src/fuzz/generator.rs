@@ -0,0 +1,482 @@
+//! Generation of random fuzz cases.
+//!
+//! Contract code is a random sequence of opcodes, each preceded by pushes of
+//! its inputs. Inputs are biased towards values that keep the execution going
+//! (small memory offsets, known addresses, storage keys that collide with the
+//! pre-state) with a few edge values thrown in, so that most cases exercise
+//! more than an immediate out-of-gas.
+
+use std::collections::HashMap;
+
+use common::{config::ETHEREUM_CHAIN_ID, types::AccountState};
+use eth_test_parser::txn_signing::{key_address, sign_payload, test_sender_key};
+use ethereum_types::{H160, H256, U256};
+use evm_arithmetization::proof::BlockHashes;
+use k256::ecdsa::SigningKey;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rlp::RlpStream;
+
+/// The EIP-4788 contract, whose storage the zkEVM updates at the start of
+/// every block.
+pub(super) const BEACON_ROOTS_ADDRESS: H160 = H160([
+    0x00, 0x0f, 0x3d, 0xf6, 0xd7, 0x32, 0x80, 0x7e, 0xf1, 0x31, 0x9f, 0xb7, 0xb8, 0xbb, 0x85, 0x22,
+    0xd0, 0xbe, 0xac, 0x02,
+]);
+pub(super) const HISTORY_BUFFER_LENGTH: u64 = 8191;
+
+const MAX_CONTRACTS: usize = 4;
+const MAX_OPS_PER_CONTRACT: usize = 48;
+const MAX_STORAGE_SLOTS: usize = 4;
+/// Storage keys are drawn from a small range so that loads and stores hit the
+/// pre-state.
+const MAX_STORAGE_KEY: u64 = 8;
+const MAX_MEMORY_OFFSET: u64 = 256;
+const MAX_CALLDATA_LEN: usize = 96;
+const BLOCK_GAS_LIMIT: u64 = 30_000_000;
+const MAX_TXN_GAS_LIMIT: u64 = 3_000_000;
+/// Enough to cover the intrinsic gas of the largest transactions.
+const MIN_TXN_GAS_LIMIT: u64 = 100_000;
+
+const GWEI: u64 = 1_000_000_000;
+
+/// Precompiles that can be called. The point evaluation one is left out as
+/// the oracle is built without KZG support.
+const PRECOMPILES: std::ops::RangeInclusive<u64> = 1..=9;
+
+/// A random block with a single transaction.
+#[derive(Debug)]
+pub(super) struct FuzzCase {
+    pub(super) name: String,
+    pub(super) pre_state: HashMap<H160, AccountState>,
+    pub(super) block: FuzzBlock,
+    pub(super) txn: FuzzTxn,
+    pub(super) sender: H160,
+    /// The RLP of the signed transaction.
+    pub(super) signed_txn: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct FuzzBlock {
+    pub(super) number: u64,
+    pub(super) timestamp: u64,
+    pub(super) gas_limit: u64,
+    pub(super) base_fee: u64,
+    pub(super) coinbase: H160,
+    pub(super) prev_randao: H256,
+    pub(super) parent_beacon_block_root: H256,
+    /// Empty for random blocks, which don't use `BLOCKHASH`.
+    pub(super) block_hashes: BlockHashes,
+}
+
+/// A legacy (EIP-155) transaction.
+#[derive(Clone, Debug)]
+pub(super) struct FuzzTxn {
+    pub(super) nonce: u64,
+    pub(super) gas_price: u64,
+    pub(super) gas_limit: u64,
+    /// `None` for contract creations.
+    pub(super) to: Option<H160>,
+    pub(super) value: U256,
+    pub(super) data: Vec<u8>,
+}
+
+pub(super) fn generate_case(seed: u64) -> FuzzCase {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let signing_key = test_sender_key();
+    let sender = key_address(&signing_key);
+
+    let contracts: Vec<H160> = (0..rng.gen_range(1..=MAX_CONTRACTS))
+        .map(|_| H160(rng.gen()))
+        .collect();
+    let coinbase = H160(rng.gen());
+    let mut known_addresses = contracts.clone();
+    known_addresses.extend([sender, coinbase]);
+
+    let mut pre_state = HashMap::new();
+    let sender_nonce = rng.gen_range(0..=5);
+    pre_state.insert(
+        sender,
+        AccountState {
+            // 1000 ETH.
+            balance: U256::exp10(21),
+            nonce: sender_nonce,
+            code: Vec::new(),
+            storage: HashMap::new(),
+        },
+    );
+    pre_state.insert(
+        coinbase,
+        AccountState {
+            balance: U256::one(),
+            nonce: 0,
+            code: Vec::new(),
+            storage: HashMap::new(),
+        },
+    );
+    pre_state.insert(BEACON_ROOTS_ADDRESS, beacon_roots_account());
+    for address in contracts.iter() {
+        let code = CodeGenerator::new(&mut rng, &known_addresses).generate(true);
+        let storage = (0..rng.gen_range(0..=MAX_STORAGE_SLOTS))
+            .map(|_| {
+                (
+                    U256::from(rng.gen_range(0..MAX_STORAGE_KEY)),
+                    U256::from(rng.gen_range(1..=u64::MAX)),
+                )
+            })
+            .collect();
+        pre_state.insert(
+            *address,
+            AccountState {
+                balance: U256::from(rng.gen_range(0..=GWEI)),
+                nonce: 1,
+                code,
+                storage,
+            },
+        );
+    }
+
+    let block = FuzzBlock {
+        number: rng.gen_range(1..=1_000_000),
+        timestamp: rng.gen_range(1..=u32::MAX as u64),
+        gas_limit: BLOCK_GAS_LIMIT,
+        base_fee: rng.gen_range(7..=100 * GWEI),
+        coinbase,
+        prev_randao: H256(rng.gen()),
+        parent_beacon_block_root: H256(rng.gen()),
+        block_hashes: BlockHashes::default(),
+    };
+
+    let (to, data) = match rng.gen_bool(0.2) {
+        true => (None, init_code(&mut rng, &known_addresses)),
+        false => {
+            let len = rng.gen_range(0..=MAX_CALLDATA_LEN);
+            (
+                Some(*contracts.choose(&mut rng).unwrap()),
+                (0..len).map(|_| rng.gen()).collect(),
+            )
+        }
+    };
+    let txn = FuzzTxn {
+        nonce: sender_nonce,
+        gas_price: block.base_fee + rng.gen_range(0..=2 * GWEI),
+        gas_limit: rng.gen_range(MIN_TXN_GAS_LIMIT..=MAX_TXN_GAS_LIMIT),
+        to,
+        value: match rng.gen_bool(0.5) {
+            true => U256::zero(),
+            false => U256::from(rng.gen_range(1..=GWEI)),
+        },
+        data,
+    };
+    let signed_txn = sign_txn(&txn, &signing_key);
+
+    FuzzCase {
+        name: format!("fuzz_{}", seed),
+        pre_state,
+        block,
+        txn,
+        sender,
+        signed_txn,
+    }
+}
+
+/// The account of the EIP-4788 contract in the pre-state, whose storage the
+/// zkEVM writes the beacon root to.
+pub(super) fn beacon_roots_account() -> AccountState {
+    AccountState {
+        balance: U256::zero(),
+        nonce: 1,
+        code: Vec::new(),
+        storage: HashMap::new(),
+    }
+}
+
+/// Signs the transaction as per EIP-155 and returns its RLP.
+pub(super) fn sign_txn(txn: &FuzzTxn, key: &SigningKey) -> Vec<u8> {
+    let append_fields = |stream: &mut RlpStream| {
+        stream.append(&txn.nonce);
+        stream.append(&txn.gas_price);
+        stream.append(&txn.gas_limit);
+        match &txn.to {
+            Some(to) => stream.append(to),
+            None => stream.append_empty_data(),
+        };
+        stream.append(&txn.value);
+        stream.append(&txn.data);
+    };
+
+    let mut unsigned = RlpStream::new_list(9);
+    append_fields(&mut unsigned);
+    unsigned.append(&ETHEREUM_CHAIN_ID);
+    unsigned.append(&0u8);
+    unsigned.append(&0u8);
+
+    let (recovery_id, r, s) =
+        sign_payload(&unsigned.out(), key).expect("Signing a transaction hash");
+    let v = recovery_id + 35 + 2 * ETHEREUM_CHAIN_ID;
+
+    let mut signed = RlpStream::new_list(9);
+    append_fields(&mut signed);
+    signed.append(&v);
+    signed.append(&r);
+    signed.append(&s);
+
+    signed.out().to_vec()
+}
+
+/// Init code of a contract creation: a few random operations, followed by
+/// returning a random runtime code.
+fn init_code(rng: &mut ChaCha8Rng, known_addresses: &[H160]) -> Vec<u8> {
+    let mut code = CodeGenerator::new(rng, known_addresses).generate(false);
+    let runtime = CodeGenerator::new(rng, known_addresses).generate(true);
+
+    // The runtime code is appended right after the constructor, which is 13
+    // bytes longer than the random operations.
+    let runtime_len = (runtime.len() as u16).to_be_bytes();
+    let runtime_offset = (code.len() as u16 + 13).to_be_bytes();
+    code.push(0x61); // PUSH2
+    code.extend_from_slice(&runtime_len);
+    code.push(0x61); // PUSH2
+    code.extend_from_slice(&runtime_offset);
+    code.push(0x5f); // PUSH0
+    code.push(0x39); // CODECOPY
+    code.push(0x61); // PUSH2
+    code.extend_from_slice(&runtime_len);
+    code.push(0x5f); // PUSH0
+    code.push(0xf3); // RETURN
+    code.extend(runtime);
+    code
+}
+
+/// How an opcode's inputs are generated.
+#[derive(Clone, Copy)]
+enum Input {
+    Any,
+    MemOffset,
+    MemSize,
+    StorageKey,
+    Address,
+    Gas,
+}
+
+use Input::*;
+
+/// Opcodes with their inputs (pushed right before them, the first one being
+/// on top of the stack) and number of outputs.
+const OPS: &[(u8, &[Input], usize)] = &[
+    (0x01, &[Any, Any], 1),                               // ADD
+    (0x02, &[Any, Any], 1),                               // MUL
+    (0x03, &[Any, Any], 1),                               // SUB
+    (0x04, &[Any, Any], 1),                               // DIV
+    (0x05, &[Any, Any], 1),                               // SDIV
+    (0x06, &[Any, Any], 1),                               // MOD
+    (0x07, &[Any, Any], 1),                               // SMOD
+    (0x08, &[Any, Any, Any], 1),                          // ADDMOD
+    (0x09, &[Any, Any, Any], 1),                          // MULMOD
+    (0x0a, &[Any, Any], 1),                               // EXP
+    (0x0b, &[Any, Any], 1),                               // SIGNEXTEND
+    (0x10, &[Any, Any], 1),                               // LT
+    (0x11, &[Any, Any], 1),                               // GT
+    (0x12, &[Any, Any], 1),                               // SLT
+    (0x13, &[Any, Any], 1),                               // SGT
+    (0x14, &[Any, Any], 1),                               // EQ
+    (0x15, &[Any], 1),                                    // ISZERO
+    (0x16, &[Any, Any], 1),                               // AND
+    (0x17, &[Any, Any], 1),                               // OR
+    (0x18, &[Any, Any], 1),                               // XOR
+    (0x19, &[Any], 1),                                    // NOT
+    (0x1a, &[Any, Any], 1),                               // BYTE
+    (0x1b, &[Any, Any], 1),                               // SHL
+    (0x1c, &[Any, Any], 1),                               // SHR
+    (0x1d, &[Any, Any], 1),                               // SAR
+    (0x20, &[MemOffset, MemSize], 1),                     // KECCAK256
+    (0x30, &[], 1),                                       // ADDRESS
+    (0x31, &[Address], 1),                                // BALANCE
+    (0x32, &[], 1),                                       // ORIGIN
+    (0x33, &[], 1),                                       // CALLER
+    (0x34, &[], 1),                                       // CALLVALUE
+    (0x35, &[MemOffset], 1),                              // CALLDATALOAD
+    (0x36, &[], 1),                                       // CALLDATASIZE
+    (0x37, &[MemOffset, MemOffset, MemSize], 0),          // CALLDATACOPY
+    (0x38, &[], 1),                                       // CODESIZE
+    (0x39, &[MemOffset, MemOffset, MemSize], 0),          // CODECOPY
+    (0x3a, &[], 1),                                       // GASPRICE
+    (0x3b, &[Address], 1),                                // EXTCODESIZE
+    (0x3c, &[Address, MemOffset, MemOffset, MemSize], 0), // EXTCODECOPY
+    (0x3d, &[], 1),                                       // RETURNDATASIZE
+    (0x3e, &[MemOffset, MemOffset, MemSize], 0),          // RETURNDATACOPY
+    (0x3f, &[Address], 1),                                // EXTCODEHASH
+    (0x41, &[], 1),                                       // COINBASE
+    (0x42, &[], 1),                                       // TIMESTAMP
+    (0x43, &[], 1),                                       // NUMBER
+    (0x44, &[], 1),                                       // PREVRANDAO
+    (0x45, &[], 1),                                       // GASLIMIT
+    (0x46, &[], 1),                                       // CHAINID
+    (0x47, &[], 1),                                       // SELFBALANCE
+    (0x48, &[], 1),                                       // BASEFEE
+    (0x4a, &[], 1),                                       // BLOBBASEFEE
+    (0x51, &[MemOffset], 1),                              // MLOAD
+    (0x52, &[MemOffset, Any], 0),                         // MSTORE
+    (0x53, &[MemOffset, Any], 0),                         // MSTORE8
+    (0x54, &[StorageKey], 1),                             // SLOAD
+    (0x55, &[StorageKey, Any], 0),                        // SSTORE
+    (0x58, &[], 1),                                       // PC
+    (0x59, &[], 1),                                       // MSIZE
+    (0x5a, &[], 1),                                       // GAS
+    (0x5c, &[StorageKey], 1),                             // TLOAD
+    (0x5d, &[StorageKey, Any], 0),                        // TSTORE
+    (0x5e, &[MemOffset, MemOffset, MemSize], 0),          // MCOPY
+    (0xa0, &[MemOffset, MemSize], 0),                     // LOG0
+    (0xa1, &[MemOffset, MemSize, Any], 0),                // LOG1
+    (0xa2, &[MemOffset, MemSize, Any, Any], 0),           // LOG2
+    (0xa3, &[MemOffset, MemSize, Any, Any, Any], 0),      // LOG3
+    (0xa4, &[MemOffset, MemSize, Any, Any, Any, Any], 0), // LOG4
+    (0xf0, &[Any, MemOffset, MemSize], 1),                // CREATE
+    (
+        0xf1,
+        &[Gas, Address, Any, MemOffset, MemSize, MemOffset, MemSize],
+        1,
+    ), // CALL
+    (
+        0xf2,
+        &[Gas, Address, Any, MemOffset, MemSize, MemOffset, MemSize],
+        1,
+    ), // CALLCODE
+    (
+        0xf4,
+        &[Gas, Address, MemOffset, MemSize, MemOffset, MemSize],
+        1,
+    ), // DELEGATECALL
+    (0xf5, &[Any, MemOffset, MemSize, Any], 1),           // CREATE2
+    (
+        0xfa,
+        &[Gas, Address, MemOffset, MemSize, MemOffset, MemSize],
+        1,
+    ), // STATICCALL
+];
+
+/// Opcodes ending the execution of a contract.
+const TERMINATORS: &[(u8, &[Input])] = &[
+    (0x00, &[]),                   // STOP
+    (0xf3, &[MemOffset, MemSize]), // RETURN
+    (0xfd, &[MemOffset, MemSize]), // REVERT
+    (0xfe, &[]),                   // INVALID
+    (0xff, &[Address]),            // SELFDESTRUCT
+];
+
+struct CodeGenerator<'a> {
+    rng: &'a mut ChaCha8Rng,
+    known_addresses: &'a [H160],
+    code: Vec<u8>,
+    stack_len: usize,
+}
+
+impl<'a> CodeGenerator<'a> {
+    fn new(rng: &'a mut ChaCha8Rng, known_addresses: &'a [H160]) -> Self {
+        Self {
+            rng,
+            known_addresses,
+            code: Vec::new(),
+            stack_len: 0,
+        }
+    }
+
+    /// Generates random code, ending with a random terminator if requested.
+    fn generate(mut self, terminate: bool) -> Vec<u8> {
+        for _ in 0..self.rng.gen_range(1..=MAX_OPS_PER_CONTRACT) {
+            match self.rng.gen_range(0..10) {
+                // Stack manipulation of the previous results.
+                0 if self.stack_len > 0 => {
+                    let depth = self.rng.gen_range(1..=self.stack_len.min(16));
+                    match self.rng.gen_range(0..3) {
+                        0 => {
+                            self.code.push(0x50); // POP
+                            self.stack_len -= 1;
+                        }
+                        1 => {
+                            self.code.push(0x80 + depth as u8 - 1); // DUPn
+                            self.stack_len += 1;
+                        }
+                        _ if self.stack_len > depth => {
+                            self.code.push(0x90 + depth as u8 - 1); // SWAPn
+                        }
+                        _ => (),
+                    }
+                }
+                _ => {
+                    let (opcode, inputs, num_outputs) = *OPS.choose(self.rng).unwrap();
+                    self.push_inputs(inputs);
+                    self.code.push(opcode);
+                    self.stack_len += num_outputs;
+                }
+            }
+        }
+
+        if terminate {
+            let (opcode, inputs) = *TERMINATORS.choose(self.rng).unwrap();
+            self.push_inputs(inputs);
+            self.code.push(opcode);
+        }
+
+        self.code
+    }
+
+    fn push_inputs(&mut self, inputs: &[Input]) {
+        // The first input needs to be on top of the stack, so it's pushed last.
+        for input in inputs.iter().rev() {
+            let value = match input {
+                Any => interesting_value(self.rng),
+                MemOffset => self.bounded(MAX_MEMORY_OFFSET),
+                MemSize => self.bounded(MAX_MEMORY_OFFSET / 2),
+                StorageKey => self.bounded(MAX_STORAGE_KEY),
+                Address => {
+                    let address = match self.rng.gen_range(0..4) {
+                        0 => H160::from_low_u64_be(self.rng.gen_range(PRECOMPILES)),
+                        1 => H160(self.rng.gen()),
+                        _ => *self.known_addresses.choose(self.rng).unwrap(),
+                    };
+                    U256::from_big_endian(address.as_bytes())
+                }
+                Gas if self.rng.gen_bool(0.5) => {
+                    self.code.push(0x5a); // GAS
+                    continue;
+                }
+                Gas => U256::from(self.rng.gen_range(0..=100_000)),
+            };
+            push_value(&mut self.code, value);
+        }
+    }
+
+    /// Returns a value below `max`, or rarely an edge value.
+    fn bounded(&mut self, max: u64) -> U256 {
+        match self.rng.gen_bool(0.95) {
+            true => U256::from(self.rng.gen_range(0..max)),
+            false => interesting_value(self.rng),
+        }
+    }
+}
+
+pub(super) fn interesting_value(rng: &mut ChaCha8Rng) -> U256 {
+    match rng.gen_range(0..6) {
+        0 => U256::from(rng.gen_range(0..=64)),
+        1 => U256::one() << rng.gen_range(0..256),
+        2 => (U256::one() << rng.gen_range(1..256)) - 1,
+        3 => U256::MAX,
+        4 => U256::one() << 255,
+        _ => U256::from_big_endian(&rng.gen::<[u8; 32]>()),
+    }
+}
+
+/// Pushes a value with the smallest `PUSH` opcode.
+fn push_value(code: &mut Vec<u8>, value: U256) {
+    if value.is_zero() {
+        code.push(0x5f); // PUSH0
+        return;
+    }
+
+    let len = (value.bits() + 7) / 8;
+    let mut bytes = [0; 32];
+    value.to_big_endian(&mut bytes);
+    code.push(0x5f + len as u8);
+    code.extend_from_slice(&bytes[32 - len..]);
+}
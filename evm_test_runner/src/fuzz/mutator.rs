@@ -0,0 +1,220 @@
+//! Mutation of the variants of the parsed tests into fuzz cases (see
+//! `fuzz --mutate`).
+//!
+//! Known-good variants are altered in a few targeted ways (storage values of
+//! the pre-state, calldata and gas limit of the transaction), which explores
+//! the neighborhood of real-world cases much more cheaply than random cases.
+//! The transaction of the variant is re-signed as a legacy one with the key of
+//! the Ethereum tests, and the expected roots of the mutated case are derived
+//! by the oracle like for random cases.
+
+use std::{collections::HashMap, fmt::Display};
+
+use common::types::{AccountState, TestVariantRunInfo};
+use eth_test_parser::txn_signing::{key_address, split_txn, test_sender_key};
+use ethereum_types::{H160, U256};
+use log::info;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rlp::Rlp;
+
+use super::generator::{
+    beacon_roots_account, interesting_value, sign_txn, FuzzBlock, FuzzCase, FuzzTxn,
+    BEACON_ROOTS_ADDRESS,
+};
+
+const MAX_MUTATIONS: usize = 3;
+const MAX_CALLDATA_EXTENSION: usize = 64;
+
+/// A variant of a parsed test, as the fuzz case its mutations are applied to.
+pub(super) struct BaseCase {
+    name: String,
+    pre_state: HashMap<H160, AccountState>,
+    block: FuzzBlock,
+    txn: FuzzTxn,
+    sender: H160,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Mutation {
+    /// Flips a bit of a storage value of the pre-state, or replaces it with an
+    /// edge value.
+    StorageValue,
+    /// Flips a bit of the calldata, replaces a word of it with an edge value,
+    /// truncates it or extends it.
+    Calldata,
+    /// Scales the gas limit of the transaction, up to the one of the block.
+    GasLimit,
+}
+
+impl Display for Mutation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mutation::StorageValue => write!(f, "storage value"),
+            Mutation::Calldata => write!(f, "calldata"),
+            Mutation::GasLimit => write!(f, "gas limit"),
+        }
+    }
+}
+
+impl BaseCase {
+    /// Returns why `variant` can't be mutated, if it can't. The transaction of
+    /// the variant is sent by the sender of the Ethereum tests, with the nonce
+    /// of its account.
+    pub(super) fn new(variant: TestVariantRunInfo) -> Result<Self, String> {
+        if !variant.chained_blocks.is_empty() {
+            return Err("it has several blocks".to_string());
+        }
+        let inputs = &variant.gen_inputs;
+        if !inputs.withdrawals.is_empty() {
+            return Err("its block has withdrawals".to_string());
+        }
+        let [signed_txn] = inputs.signed_txns.as_slice() else {
+            return Err("its block doesn't have a single transaction".to_string());
+        };
+
+        let sender = key_address(&test_sender_key());
+        let mut pre_state = (*variant.pre_state).clone();
+        let Some(sender_account) = pre_state.get(&sender) else {
+            return Err("its pre-state lacks the sender of the Ethereum tests".to_string());
+        };
+        let metadata = &inputs.block_metadata;
+        let block = FuzzBlock {
+            number: metadata.block_number.low_u64(),
+            timestamp: metadata.block_timestamp.low_u64(),
+            // As with the `clamp` policy of `--gas-limit-policy`.
+            gas_limit: metadata.block_gaslimit.min(u32::MAX.into()).low_u64(),
+            base_fee: metadata.block_base_fee.low_u64(),
+            coinbase: metadata.block_beneficiary,
+            prev_randao: metadata.block_random,
+            parent_beacon_block_root: metadata.parent_beacon_block_root,
+            block_hashes: inputs.block_hashes.clone(),
+        };
+        let txn = decode_txn(signed_txn, sender_account.nonce, block.base_fee)
+            .ok_or_else(|| "its transaction can't be decoded".to_string())?;
+        pre_state
+            .entry(BEACON_ROOTS_ADDRESS)
+            .or_insert_with(beacon_roots_account);
+
+        Ok(Self {
+            name: variant.variant_name,
+            pre_state,
+            block,
+            txn,
+            sender,
+        })
+    }
+
+    /// Applies random mutations drawn from `seed` to the variant.
+    pub(super) fn mutate(&self, seed: u64) -> FuzzCase {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let name = format!("{}_mut_{}", self.name, seed);
+        let mut pre_state = self.pre_state.clone();
+        let mut txn = self.txn.clone();
+
+        let mut mutations = Vec::new();
+        for _ in 0..rng.gen_range(1..=MAX_MUTATIONS) {
+            let mut slots: Vec<_> = pre_state
+                .iter()
+                .flat_map(|(address, acc)| acc.storage.keys().map(|key| (*address, *key)))
+                .collect();
+            // For the case to only depend on its seed.
+            slots.sort_unstable();
+
+            let mutation = match slots.is_empty() {
+                true => *[Mutation::Calldata, Mutation::GasLimit]
+                    .choose(&mut rng)
+                    .unwrap(),
+                false => *[
+                    Mutation::StorageValue,
+                    Mutation::Calldata,
+                    Mutation::GasLimit,
+                ]
+                .choose(&mut rng)
+                .unwrap(),
+            };
+            match mutation {
+                Mutation::StorageValue => {
+                    let (address, key) = *slots.choose(&mut rng).unwrap();
+                    let storage = &mut pre_state.get_mut(&address).unwrap().storage;
+                    let value = match rng.gen_bool(0.5) {
+                        true => storage[&key] ^ (U256::one() << rng.gen_range(0..256)),
+                        false => interesting_value(&mut rng),
+                    };
+                    // Zero values aren't stored.
+                    match value.is_zero() {
+                        true => storage.remove(&key),
+                        false => storage.insert(key, value),
+                    };
+                }
+                Mutation::Calldata => mutate_calldata(&mut txn.data, &mut rng),
+                Mutation::GasLimit => {
+                    let gas_limit =
+                        rng.gen_range(txn.gas_limit / 2..=txn.gas_limit.saturating_mul(2));
+                    txn.gas_limit = gas_limit.min(self.block.gas_limit);
+                }
+            }
+            mutations.push(mutation.to_string());
+        }
+        info!("Mutated {}: {}", name, mutations.join(", "));
+
+        let signed_txn = sign_txn(&txn, &test_sender_key());
+        FuzzCase {
+            name,
+            pre_state,
+            block: self.block.clone(),
+            txn,
+            sender: self.sender,
+            signed_txn,
+        }
+    }
+}
+
+fn mutate_calldata(data: &mut Vec<u8>, rng: &mut ChaCha8Rng) {
+    match rng.gen_range(0..4) {
+        0 if !data.is_empty() => {
+            let idx = rng.gen_range(0..data.len());
+            data[idx] ^= 1 << rng.gen_range(0..8);
+        }
+        1 if data.len() >= 32 => {
+            let offset = rng.gen_range(0..=data.len() - 32);
+            interesting_value(rng).to_big_endian(&mut data[offset..offset + 32]);
+        }
+        2 if !data.is_empty() => data.truncate(rng.gen_range(0..data.len())),
+        _ => {
+            let len = rng.gen_range(1..=MAX_CALLDATA_EXTENSION);
+            data.extend((0..len).map(|_| rng.gen::<u8>()));
+        }
+    }
+}
+
+/// Decodes the fields of a signed transaction (of any type) that the legacy
+/// transaction replacing it keeps, with the effective gas price of EIP-1559
+/// transactions as its gas price.
+fn decode_txn(signed_txn: &[u8], nonce: u64, base_fee: u64) -> Option<FuzzTxn> {
+    let (txn_type, payload, data_idx) = split_txn(signed_txn)?;
+    let rlp = Rlp::new(payload);
+    let gas_price = match txn_type {
+        None => rlp.val_at(1).ok()?,
+        Some(1) => rlp.val_at(2).ok()?,
+        // EIP-1559 and blob transactions.
+        Some(_) => {
+            let max_priority_fee: u64 = rlp.val_at(2).ok()?;
+            let max_fee: u64 = rlp.val_at(3).ok()?;
+            max_fee.min(base_fee.saturating_add(max_priority_fee))
+        }
+    };
+    let to = rlp.at(data_idx - 2).ok()?;
+
+    Some(FuzzTxn {
+        nonce,
+        gas_price,
+        gas_limit: rlp.val_at(data_idx - 3).ok()?,
+        to: match to.is_empty() {
+            true => None,
+            false => Some(to.as_val().ok()?),
+        },
+        value: rlp.val_at(data_idx - 1).ok()?,
+        data: rlp.val_at(data_idx).ok()?,
+    })
+}
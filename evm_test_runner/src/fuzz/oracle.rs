@@ -0,0 +1,184 @@
+//! Derives the expected outcome of a fuzz case by executing it with `revm`.
+
+use anyhow::anyhow;
+use common::{
+    config::ETHEREUM_CHAIN_ID,
+    types::{ExpectedFinalRoots, Plonky2ParsedTest, TestMetadata, TestVariantRunInfo, TxnOutcome},
+};
+use eth_test_parser::{
+    block_execution::{
+        db_state, deleted_accounts, logs_bloom, receipt, state_db, to_revm_address, to_revm_u256,
+    },
+    bloom_words, build_state_tries,
+    config::Fork,
+};
+use ethereum_types::U256;
+use evm_arithmetization::{generation::TrieInputs, proof::BlockMetadata};
+use keccak_hash::keccak;
+use mpt_trie::{
+    nibbles::Nibbles,
+    partial_trie::{HashedPartialTrie, PartialTrie},
+};
+use revm::{
+    primitives::{Bytes, EVMError, ExecutionResult, SpecId, TxKind, B256, U256 as RU256},
+    Evm,
+};
+
+use super::generator::{FuzzCase, BEACON_ROOTS_ADDRESS, HISTORY_BUFFER_LENGTH};
+
+/// The outcome of a fuzz case according to the oracle.
+pub(super) enum OracleOutcome {
+    /// The case is valid and can be run against the zkEVM.
+    Valid(TestVariantRunInfo),
+    /// The transaction is invalid (eg. its intrinsic gas is above its gas
+    /// limit), so there is no block to prove.
+    InvalidTxn(String),
+}
+
+pub(super) fn execute_case(case: FuzzCase) -> anyhow::Result<OracleOutcome> {
+    let mut db = state_db(&case.pre_state, Fork::Cancun)?;
+
+    // The beacon root is stored by the zkEVM at the start of the block, which
+    // `revm` doesn't do.
+    let timestamp_idx = case.block.timestamp % HISTORY_BUFFER_LENGTH;
+    let beacon_roots_address = to_revm_address(&BEACON_ROOTS_ADDRESS);
+    db.insert_account_storage(
+        beacon_roots_address,
+        RU256::from(timestamp_idx),
+        RU256::from(case.block.timestamp),
+    )?;
+    db.insert_account_storage(
+        beacon_roots_address,
+        RU256::from(timestamp_idx + HISTORY_BUFFER_LENGTH),
+        RU256::from_be_bytes(case.block.parent_beacon_block_root.0),
+    )?;
+
+    // The hashes of the previous blocks, if known, as `revm` otherwise derives
+    // them from their number.
+    let prev_hashes = case.block.block_hashes.prev_hashes.iter().rev();
+    for (parent_idx, hash) in prev_hashes.enumerate() {
+        if let Some(number) = case.block.number.checked_sub(parent_idx as u64 + 1) {
+            db.block_hashes
+                .insert(RU256::from(number), B256::from(hash.0));
+        }
+    }
+
+    let mut evm = Evm::builder()
+        .with_db(db)
+        .with_spec_id(SpecId::CANCUN)
+        .modify_cfg_env(|cfg| cfg.chain_id = ETHEREUM_CHAIN_ID)
+        .modify_block_env(|block| {
+            block.number = RU256::from(case.block.number);
+            block.coinbase = to_revm_address(&case.block.coinbase);
+            block.timestamp = RU256::from(case.block.timestamp);
+            block.gas_limit = RU256::from(case.block.gas_limit);
+            block.basefee = RU256::from(case.block.base_fee);
+            block.difficulty = RU256::ZERO;
+            block.prevrandao = Some(B256::from(case.block.prev_randao.0));
+        })
+        .modify_tx_env(|tx| {
+            tx.caller = to_revm_address(&case.sender);
+            tx.gas_limit = case.txn.gas_limit;
+            tx.gas_price = RU256::from(case.txn.gas_price);
+            tx.transact_to = match &case.txn.to {
+                Some(to) => TxKind::Call(to_revm_address(to)),
+                None => TxKind::Create,
+            };
+            tx.value = to_revm_u256(case.txn.value);
+            tx.data = Bytes::from(case.txn.data.clone());
+            tx.nonce = Some(case.txn.nonce);
+            tx.chain_id = Some(ETHEREUM_CHAIN_ID);
+        })
+        .build();
+
+    let result = match evm.transact_commit() {
+        Ok(result) => result,
+        Err(EVMError::Transaction(err)) => return Ok(OracleOutcome::InvalidTxn(err.to_string())),
+        Err(err) => return Err(anyhow!("Executing {} with revm: {}", case.name, err)),
+    };
+    let db = evm.into_context().evm.inner.db;
+
+    let post_state = db_state(&db);
+    let deleted_accounts = deleted_accounts(&case.pre_state, &post_state);
+
+    let (state_trie, storage_tries) = build_state_tries(&case.pre_state);
+    let (final_state_trie, _) = build_state_tries(&post_state);
+
+    let (success, gas_used, logs) = match result {
+        ExecutionResult::Success { gas_used, logs, .. } => (true, gas_used, logs),
+        ExecutionResult::Revert { gas_used, .. } | ExecutionResult::Halt { gas_used, .. } => {
+            (false, gas_used, Vec::new())
+        }
+    };
+    let bloom = logs_bloom(&logs);
+    let receipt = receipt(&case.signed_txn, success, gas_used, &logs);
+
+    let txn_trie = single_entry_trie(case.signed_txn.clone());
+    let receipt_trie = single_entry_trie(receipt);
+
+    let block_metadata = BlockMetadata {
+        block_beneficiary: case.block.coinbase,
+        block_timestamp: case.block.timestamp.into(),
+        block_number: case.block.number.into(),
+        block_difficulty: U256::zero(),
+        block_random: case.block.prev_randao,
+        block_gaslimit: case.block.gas_limit.into(),
+        block_chain_id: ETHEREUM_CHAIN_ID.into(),
+        block_base_fee: case.block.base_fee.into(),
+        block_gas_used: gas_used.into(),
+        block_blob_gas_used: U256::zero(),
+        block_excess_blob_gas: U256::zero(),
+        parent_beacon_block_root: case.block.parent_beacon_block_root,
+        block_bloom: bloom_words(&bloom),
+    };
+
+    let genesis_state_root = state_trie.hash();
+    let contract_code = case
+        .pre_state
+        .values()
+        .map(|acc| (keccak(&acc.code), acc.code.clone()))
+        .collect();
+    let test = Plonky2ParsedTest {
+        test_name: case.name,
+        txn_bytes: case.signed_txn,
+        final_roots: ExpectedFinalRoots {
+            state_root_hash: final_state_trie.hash(),
+            txn_trie_root_hash: txn_trie.hash(),
+            receipts_trie_root_hash: receipt_trie.hash(),
+        },
+        plonky2_metadata: TestMetadata {
+            tries: TrieInputs {
+                state_trie,
+                transactions_trie: HashedPartialTrie::default(),
+                receipts_trie: HashedPartialTrie::default(),
+                storage_tries,
+            },
+            genesis_state_root,
+            contract_code,
+            // The bloom of the block is the one computed with `revm` in the
+            // first place.
+            logs_bloom: Some(block_metadata.block_bloom),
+            block_metadata,
+            withdrawals: Vec::new(),
+            pre_state: case.pre_state,
+            txn_sender: Some(case.sender),
+            deleted_accounts,
+            post_state,
+            txn_outcome: Some(TxnOutcome { success, gas_used }),
+        },
+        block_hashes: case.block.block_hashes,
+        chained_blocks: Vec::new(),
+        eof_containers: Vec::new(),
+        aliases: Vec::new(),
+    };
+
+    Ok(OracleOutcome::Valid(test.into_run_info(0)))
+}
+
+/// A transactions or receipts trie of a block with a single transaction.
+fn single_entry_trie(value: Vec<u8>) -> HashedPartialTrie {
+    let mut trie = HashedPartialTrie::default();
+    trie.insert(Nibbles::from_bytes_be(&rlp::encode(&0u8)).unwrap(), value)
+        .expect("Inserting in an empty trie");
+    trie
+}
@@ -0,0 +1,155 @@
+//! Running tests in child processes (`--isolate`), so that a crash of the
+//! prover (segfault, abort, OOM kill) only takes down the test that caused it,
+//! which is recorded as [`TestStatus::Crashed`].
+//!
+//! The child is the runner itself, invoked with the hidden `run-isolated`
+//! subcommand. It reads the test as CBOR from its stdin and writes its outcome
+//! as CBOR to its stdout, while its logs go to the inherited stderr.
+
+use std::{
+    io::{self, Read, Write},
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+use common::types::TestVariantRunInfo;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{
+    distributed::WorkerOutcome,
+    plonky2_runner::{
+        run_test_and_get_test_result, GasLimitPolicy, ProverSetup, TestOutcome, TestStatus,
+    },
+    proof_archive::ProofArchive,
+    witness_cache::WitnessCache,
+};
+
+/// The arguments the child processes need to run tests the same way the
+/// parent would.
+#[derive(Debug)]
+pub(crate) struct IsolationSettings {
+    /// The arguments selecting the runner in its executable, eg. `run` for the
+    /// `evm-tests` binary.
+    pub(crate) exe_args: Vec<String>,
+    pub(crate) witness_only: bool,
+    pub(crate) check_post_state: bool,
+    pub(crate) gas_limit_policy: GasLimitPolicy,
+    pub(crate) max_cpu_log_len: Option<usize>,
+    pub(crate) witness_cache: Option<PathBuf>,
+    pub(crate) proof_archive: Option<PathBuf>,
+    pub(crate) prover_threads: Option<usize>,
+    pub(crate) verbose: u8,
+}
+
+impl IsolationSettings {
+    fn args(&self) -> Vec<String> {
+        let mut args = self.exe_args.clone();
+        if self.witness_only {
+            args.push("--witness-only".to_string());
+        }
+        if self.check_post_state {
+            args.push("--check-post-state".to_string());
+        }
+        if let Some(policy) = self.gas_limit_policy.to_possible_value() {
+            args.extend([
+                "--gas-limit-policy".to_string(),
+                policy.get_name().to_string(),
+            ]);
+        }
+        if let Some(max_cpu_log_len) = self.max_cpu_log_len {
+            args.extend(["--max-cpu-log-len".to_string(), max_cpu_log_len.to_string()]);
+        }
+        if let Some(witness_cache) = &self.witness_cache {
+            args.extend([
+                "--witness-cache".to_string(),
+                witness_cache.to_string_lossy().to_string(),
+            ]);
+        }
+        if let Some(proof_archive) = &self.proof_archive {
+            args.extend([
+                "--proof-archive".to_string(),
+                proof_archive.to_string_lossy().to_string(),
+            ]);
+        }
+        if let Some(prover_threads) = self.prover_threads {
+            args.extend(["--prover-threads".to_string(), prover_threads.to_string()]);
+        }
+        args.extend((0..self.verbose).map(|_| "--verbose".to_string()));
+        args.push("run-isolated".to_string());
+
+        args
+    }
+}
+
+/// Runs a test in a child process. The child is killed if the returned future
+/// is dropped, eg. when the test times out.
+pub(crate) async fn run_test_in_subprocess(
+    test: TestVariantRunInfo,
+    settings: &IsolationSettings,
+) -> TestOutcome {
+    match spawn_and_wait(test, settings).await {
+        Ok(outcome) => outcome,
+        Err(err) => TestStatus::Crashed(format!("{:#}", err)).into(),
+    }
+}
+
+async fn spawn_and_wait(
+    test: TestVariantRunInfo,
+    settings: &IsolationSettings,
+) -> anyhow::Result<TestOutcome> {
+    let input = serde_cbor::to_vec(&test)?;
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(settings.args())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    // A child that crashes right away closes its stdin early, which is then
+    // reported through its exit status.
+    let _ = stdin.write_all(&input).await;
+    drop(stdin);
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        anyhow::bail!("The test process exited with {}", output.status);
+    }
+
+    let outcome: WorkerOutcome = serde_cbor::from_slice(&output.stdout)
+        .map_err(|err| anyhow::anyhow!("Invalid output from the test process: {}", err))?;
+    Ok(outcome.into())
+}
+
+/// The entry point of the child processes.
+pub(crate) fn run_isolated_test(
+    witness_only: bool,
+    check_post_state: bool,
+    gas_limit_policy: GasLimitPolicy,
+    max_cpu_log_len: Option<usize>,
+    witness_cache: Option<PathBuf>,
+    proof_archive: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut input = Vec::new();
+    io::stdin().read_to_end(&mut input)?;
+    let test: TestVariantRunInfo = serde_cbor::from_slice(&input)?;
+
+    let witness_cache = witness_cache.map(WitnessCache::new).transpose()?;
+    let proof_archive = proof_archive.map(ProofArchive::new).transpose()?;
+    let outcome = run_test_and_get_test_result(
+        test,
+        &ProverSetup::new(false, max_cpu_log_len, None)
+            .with_post_state_check(check_post_state)
+            .with_gas_limit_policy(gas_limit_policy)
+            .with_proof_archive(proof_archive),
+        witness_cache.as_ref(),
+        witness_only,
+        max_cpu_log_len,
+    );
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&serde_cbor::to_vec(&WorkerOutcome::from(outcome))?)?;
+    stdout.flush()?;
+
+    Ok(())
+}
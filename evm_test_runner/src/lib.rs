@@ -0,0 +1,78 @@
+//! Running the Ethereum tests parsed by `eth_test_parser` against Polygon
+//! Zero's EVM.
+//!
+//! This is used by the runner binary, which is a thin wrapper around
+//! [`run_cli`], but also lets other tools run a set of tests against their
+//! build of the prover in-process, and report on the results:
+//!
+//! - [`read_in_all_parsed_tests`] (or [`read_in_json_tests`]) reads in the
+//!   tests, restricted by [`ParsedTestFilters`].
+//! - [`run_plonky2_tests`] runs them as configured by [`TestRunConfig`],
+//!   recording their state in [`TestRunEntries`], and returns their results.
+//! - [`output_test_report_for_terminal`] and
+//!   [`write_overall_status_report_summary_to_file`] report on the results.
+//!
+//! Tests are run on threads spawned from a Tokio runtime, which the functions
+//! above must be called from.
+
+#![feature(let_chains)]
+
+use tokio::sync::mpsc;
+
+pub use crate::{
+    baseline::Baseline,
+    cli::{run_cli, run_cli_as_subcommand},
+    perf_regression::PerfRegression,
+    persistent_run_state::{
+        load_existing_pass_state_from_disk_if_exists_or_create, TestRunEntries,
+    },
+    plonky2_runner::{
+        run_plonky2_tests, GasLimitPolicy, InputAlteration, ProofStats, TestGroupRunResults,
+        TestOrder, TestRunConfig, TestRunOutput, TestRunResult, TestStatus, TestSubGroupRunResults,
+        TestTimeouts,
+    },
+    provenance::Provenance,
+    report_generation::{
+        output_test_report_for_terminal, write_overall_status_report_summary_to_file,
+    },
+    t8n_runner::T8nStatus,
+    test_dir_reading::{
+        get_default_parsed_tests_path, read_in_all_parsed_tests, read_in_json_tests,
+        ParsedTestFilters, ParsedTestGroup, ParsedTestSubGroup, Test,
+    },
+};
+
+mod api;
+mod arg_parsing;
+mod baseline;
+mod bench;
+mod blacklist;
+mod cli;
+mod compare_configs;
+mod config_file;
+mod dashboard;
+mod distributed;
+mod eta;
+mod fuzz;
+mod isolation;
+mod minimize;
+mod opcode_coverage;
+mod perf_regression;
+mod persistent_run_state;
+mod plonky2_runner;
+mod pr_comment;
+mod proof_archive;
+mod provenance;
+mod report_generation;
+mod repro_bundle;
+mod resource_estimation;
+mod reverify;
+mod run_history;
+mod t8n_runner;
+mod test_dir_reading;
+mod witness_cache;
+
+/// Receives a message once the run is aborted (eg. with Ctrl-C), after which
+/// no more tests are started.
+// Oneshot is ideal here, but I can't get it to the abort handler.
+pub type ProcessAbortedRecv = mpsc::Receiver<()>;
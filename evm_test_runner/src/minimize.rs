@@ -0,0 +1,301 @@
+//! Shrinking of failing test variants into minimal reproducers.
+//!
+//! The pre-state and transaction of the variant are reduced step by step
+//! (dropping accounts, zeroing storage slots, truncating contract code and
+//! calldata), keeping each reduction only if witness generation still fails
+//! with the same error. This is repeated until no reduction applies anymore.
+//!
+//! The expected final roots are not recomputed for the reduced variants, so a
+//! variant whose original failure is a root mismatch may be shrunk past the
+//! cause of the mismatch. Only the first block of multi-block variants is
+//! kept, as the pre-states of the following ones depend on it.
+
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use anyhow::anyhow;
+use common::types::{AccountState, TestVariantRunInfo};
+use eth_test_parser::{
+    build_state_tries,
+    txn_signing::{test_sender_key, txn_data, with_txn_data},
+};
+use ethereum_types::Address;
+use k256::ecdsa::SigningKey;
+use keccak_hash::keccak;
+use log::info;
+use mpt_trie::partial_trie::PartialTrie;
+
+use crate::{
+    plonky2_runner::{run_test_and_get_test_result, ProverSetup, TestStatus},
+    repro_bundle::write_repro_bundle,
+};
+
+/// The parts of a variant that are reduced.
+#[derive(Clone)]
+struct Reducible {
+    pre_state: HashMap<Address, AccountState>,
+    signed_txn: Vec<u8>,
+}
+
+impl Reducible {
+    fn num_storage_slots(&self) -> usize {
+        self.pre_state.values().map(|acc| acc.storage.len()).sum()
+    }
+
+    fn code_len(&self) -> usize {
+        self.pre_state.values().map(|acc| acc.code.len()).sum()
+    }
+}
+
+struct Minimizer {
+    original: TestVariantRunInfo,
+    prover_setup: ProverSetup,
+    max_cpu_log_len: Option<usize>,
+    signing_key: SigningKey,
+    /// The status the reduced variants need to fail with.
+    failure: String,
+    num_runs: usize,
+}
+
+/// Shrinks a failing variant and writes the minimal reproducer as a repro
+/// bundle in `out_dir`.
+pub(crate) fn run_minimize(
+    test: TestVariantRunInfo,
+    max_cpu_log_len: Option<usize>,
+    out_dir: &Path,
+) -> anyhow::Result<()> {
+    let mut minimizer = Minimizer {
+        original: test.clone(),
+        prover_setup: ProverSetup::new(false, max_cpu_log_len, None),
+        max_cpu_log_len,
+        signing_key: test_sender_key(),
+        failure: String::new(),
+        num_runs: 0,
+    };
+
+    let status = minimizer.run(test.clone());
+    if !status.failed() {
+        return Err(anyhow!(
+            "{} does not fail witness generation ({})",
+            test.variant_name,
+            status
+        ));
+    }
+    println!(
+        "Minimizing {}, which fails with: {}",
+        test.variant_name, status
+    );
+    minimizer.failure = status.to_string();
+
+    let mut current = Reducible {
+        pre_state: (*test.pre_state).clone(),
+        signed_txn: test
+            .gen_inputs
+            .signed_txns
+            .first()
+            .cloned()
+            .unwrap_or_default(),
+    };
+    let before = current.clone();
+
+    loop {
+        let mut reduced = minimizer.drop_accounts(&mut current);
+        reduced |= minimizer.zero_storage_slots(&mut current);
+        reduced |= minimizer.truncate_code(&mut current);
+        reduced |= minimizer.truncate_calldata(&mut current);
+
+        if !reduced {
+            break;
+        }
+    }
+
+    println!(
+        "Minimized in {} run(s): {} -> {} account(s), {} -> {} storage slot(s), {} -> {} code byte(s), {} -> {} txn byte(s).",
+        minimizer.num_runs,
+        before.pre_state.len(),
+        current.pre_state.len(),
+        before.num_storage_slots(),
+        current.num_storage_slots(),
+        before.code_len(),
+        current.code_len(),
+        before.signed_txn.len(),
+        current.signed_txn.len(),
+    );
+
+    let minimized = minimizer.variant(&current);
+    let status = TestStatus::EvmErr(minimizer.failure.clone());
+    write_repro_bundle(out_dir, &minimized, &status)?;
+    println!(
+        "Reproducer written to {:?}.",
+        out_dir.join(&minimized.variant_name)
+    );
+
+    Ok(())
+}
+
+impl Minimizer {
+    fn run(&mut self, test: TestVariantRunInfo) -> TestStatus {
+        self.num_runs += 1;
+        run_test_and_get_test_result(test, &self.prover_setup, None, true, self.max_cpu_log_len)
+            .status
+    }
+
+    /// Returns whether the reduced variant still fails the same way.
+    fn still_fails(&mut self, candidate: &Reducible) -> bool {
+        let status = self.run(self.variant(candidate));
+        status.failed() && status.to_string() == self.failure
+    }
+
+    /// Builds a variant from the original one with the given reduced parts.
+    fn variant(&self, reduced: &Reducible) -> TestVariantRunInfo {
+        let mut gen_inputs = (*self.original.gen_inputs).clone();
+        let original_state_root = gen_inputs.tries.state_trie.hash();
+
+        let (state_trie, storage_tries) = build_state_tries(&reduced.pre_state);
+        if gen_inputs.checkpoint_state_trie_root == original_state_root {
+            gen_inputs.checkpoint_state_trie_root = state_trie.hash();
+        }
+        gen_inputs.tries.state_trie = state_trie;
+        gen_inputs.tries.storage_tries = storage_tries;
+        gen_inputs.contract_code = reduced
+            .pre_state
+            .values()
+            .map(|acc| (keccak(&acc.code), acc.code.clone()))
+            .collect();
+        // Blocks without a transaction have none to reduce.
+        if !reduced.signed_txn.is_empty() {
+            gen_inputs.signed_txns = vec![reduced.signed_txn.clone()];
+        }
+
+        TestVariantRunInfo {
+            variant_name: format!("{}_minimized", self.original.variant_name),
+            aliases: Vec::new(),
+            gen_inputs: Arc::new(gen_inputs),
+            final_roots: self.original.final_roots.clone(),
+            variant_idx: self.original.variant_idx,
+            pre_state: Arc::new(reduced.pre_state.clone()),
+            post_state: self.original.post_state.clone(),
+            txn_outcome: self.original.txn_outcome,
+            logs_bloom: self.original.logs_bloom,
+            chained_blocks: Vec::new(),
+        }
+    }
+
+    /// Keeps `candidate` as the current state if it still fails.
+    fn try_reduction(&mut self, current: &mut Reducible, candidate: Reducible) -> bool {
+        let reduced = self.still_fails(&candidate);
+        if reduced {
+            *current = candidate;
+        }
+        reduced
+    }
+
+    fn drop_accounts(&mut self, current: &mut Reducible) -> bool {
+        let mut reduced = false;
+        let mut addresses: Vec<_> = current.pre_state.keys().copied().collect();
+        addresses.sort_unstable();
+
+        for address in addresses {
+            let mut candidate = current.clone();
+            candidate.pre_state.remove(&address);
+            if self.try_reduction(current, candidate) {
+                info!("Dropped account {:?}", address);
+                reduced = true;
+            }
+        }
+
+        reduced
+    }
+
+    fn zero_storage_slots(&mut self, current: &mut Reducible) -> bool {
+        let mut reduced = false;
+        let mut slots: Vec<_> = current
+            .pre_state
+            .iter()
+            .flat_map(|(address, acc)| acc.storage.keys().map(|key| (*address, *key)))
+            .collect();
+        slots.sort_unstable();
+
+        for (address, key) in slots {
+            let mut candidate = current.clone();
+            candidate
+                .pre_state
+                .get_mut(&address)
+                .unwrap()
+                .storage
+                .remove(&key);
+            if self.try_reduction(current, candidate) {
+                info!("Zeroed storage slot {} of {:?}", key, address);
+                reduced = true;
+            }
+        }
+
+        reduced
+    }
+
+    fn truncate_code(&mut self, current: &mut Reducible) -> bool {
+        let mut reduced = false;
+        let mut addresses: Vec<_> = current
+            .pre_state
+            .iter()
+            .filter(|(_, acc)| !acc.code.is_empty())
+            .map(|(address, _)| *address)
+            .collect();
+        addresses.sort_unstable();
+
+        for address in addresses {
+            let code_len = current.pre_state[&address].code.len();
+            for len in truncated_lens(code_len) {
+                let mut candidate = current.clone();
+                candidate
+                    .pre_state
+                    .get_mut(&address)
+                    .unwrap()
+                    .code
+                    .truncate(len);
+                if self.try_reduction(current, candidate) {
+                    info!("Truncated the code of {:?} to {} bytes", address, len);
+                    reduced = true;
+                    break;
+                }
+            }
+        }
+
+        reduced
+    }
+
+    fn truncate_calldata(&mut self, current: &mut Reducible) -> bool {
+        let Some(data) = txn_data(&current.signed_txn) else {
+            return false;
+        };
+
+        for len in truncated_lens(data.len()) {
+            let Ok(signed_txn) =
+                with_txn_data(&current.signed_txn, &data[..len], &self.signing_key)
+            else {
+                return false;
+            };
+            let candidate = Reducible {
+                pre_state: current.pre_state.clone(),
+                signed_txn,
+            };
+            if self.try_reduction(current, candidate) {
+                info!("Truncated the calldata to {} bytes", len);
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// The lengths a byte string of length `len` is truncated to, from the
+/// shortest to the longest.
+fn truncated_lens(len: usize) -> Vec<usize> {
+    let mut lens = Vec::new();
+    let mut removed = len;
+    while removed > 0 {
+        lens.push(len - removed);
+        removed /= 2;
+    }
+    lens
+}
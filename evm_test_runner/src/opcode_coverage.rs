@@ -0,0 +1,119 @@
+//! Static opcode coverage of the selected tests.
+//!
+//! The contract code of the pre-state of each test variant (and the init code
+//! of contract creation transactions) is scanned for opcodes, giving an idea of
+//! which kernel paths the selected tests exercise without running them.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use askama::Template;
+use common::opcodes::{code_opcodes, create_txn_init_code, opcode_name};
+use serde::Serialize;
+
+use crate::{
+    provenance::Provenance,
+    report_generation::{render_report, REPORT_OUTPUT},
+    test_dir_reading::ParsedTestGroup,
+};
+
+const OPCODE_COVERAGE_TEMPLATE: &str = "opcode_coverage.md";
+
+#[derive(Debug, Serialize, Template)]
+#[template(path = "opcode_coverage.md")]
+struct OpcodeCoverageTemplate {
+    provenance: Provenance,
+    num_tests: usize,
+    num_covered: usize,
+    opcodes: Vec<OpcodeCoverage>,
+    uncovered: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpcodeCoverage {
+    name: String,
+    opcode: u8,
+    /// The number of test variants containing the opcode.
+    num_tests: usize,
+    /// The number of times the opcode appears across all test variants.
+    num_occurrences: u64,
+}
+
+/// Writes the opcode coverage report of the given tests and returns its path.
+pub(crate) fn write_opcode_coverage_report(
+    groups: &[ParsedTestGroup],
+    provenance: &Provenance,
+    template_dir: Option<&Path>,
+) -> anyhow::Result<PathBuf> {
+    let report = render_report(
+        &compute_coverage(groups, provenance),
+        OPCODE_COVERAGE_TEMPLATE,
+        template_dir,
+    )?;
+
+    let report_path = Path::new(REPORT_OUTPUT).join(OPCODE_COVERAGE_TEMPLATE);
+    fs::create_dir_all(REPORT_OUTPUT)
+        .with_context(|| format!("Creating report subdirectory {}", REPORT_OUTPUT))?;
+    fs::write(&report_path, report)
+        .with_context(|| format!("Writing report to {:?}", report_path))?;
+
+    Ok(report_path)
+}
+
+fn compute_coverage(groups: &[ParsedTestGroup], provenance: &Provenance) -> OpcodeCoverageTemplate {
+    let mut num_tests_per_opcode = [0; 256];
+    let mut num_occurrences = [0; 256];
+    let mut num_tests = 0;
+
+    let tests = groups
+        .iter()
+        .flat_map(|g| g.sub_groups.iter().flat_map(|sub_g| sub_g.tests.iter()));
+    for test in tests {
+        num_tests += 1;
+
+        let inputs = &test.info.gen_inputs;
+        let init_codes = inputs
+            .signed_txns
+            .iter()
+            .filter_map(|txn| create_txn_init_code(txn));
+
+        let mut seen = HashSet::new();
+        for code in inputs.contract_code.values().cloned().chain(init_codes) {
+            for opcode in code_opcodes(&code) {
+                num_occurrences[opcode as usize] += 1;
+                seen.insert(opcode);
+            }
+        }
+        for opcode in seen {
+            num_tests_per_opcode[opcode as usize] += 1;
+        }
+    }
+
+    let opcodes: Vec<_> = (0..=u8::MAX)
+        .filter_map(|opcode| {
+            opcode_name(opcode).map(|name| OpcodeCoverage {
+                name,
+                opcode,
+                num_tests: num_tests_per_opcode[opcode as usize],
+                num_occurrences: num_occurrences[opcode as usize],
+            })
+        })
+        .collect();
+    let uncovered: Vec<_> = opcodes
+        .iter()
+        .filter(|o| o.num_tests == 0)
+        .map(|o| o.name.clone())
+        .collect();
+
+    OpcodeCoverageTemplate {
+        provenance: provenance.clone(),
+        num_tests,
+        num_covered: opcodes.len() - uncovered.len(),
+        opcodes,
+        uncovered,
+    }
+}
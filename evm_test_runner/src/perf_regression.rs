@@ -0,0 +1,85 @@
+//! Detection of performance regressions against the timings of a previous run.
+//!
+//! Timing files map each test variant that passed to its duration in seconds.
+//! They are written with `--save-timings` and compared against with
+//! `--perf-baseline`.
+
+use std::{collections::BTreeMap, fs, path::Path, time::Duration};
+
+use anyhow::Context;
+
+use crate::plonky2_runner::{TestGroupRunResults, TestRunResult};
+
+/// Tests faster than this in the baseline are not compared, as their duration
+/// is dominated by noise.
+const MIN_COMPARED_DURATION: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub(crate) struct TimingBaseline {
+    durations: BTreeMap<String, f64>,
+}
+
+impl TimingBaseline {
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("Reading timings {:?}", path))?;
+        let durations = serde_json::from_slice(&bytes)
+            .with_context(|| format!("Parsing timings {:?}", path))?;
+
+        Ok(Self { durations })
+    }
+}
+
+/// A test that got slower than the baseline by more than the threshold.
+#[derive(Debug)]
+pub struct PerfRegression {
+    pub name: String,
+    pub baseline: Duration,
+    pub current: Duration,
+}
+
+impl PerfRegression {
+    /// The slowdown, in percent.
+    pub fn slowdown(&self) -> f64 {
+        100.0 * (self.current.as_secs_f64() / self.baseline.as_secs_f64() - 1.0)
+    }
+}
+
+fn passed_tests(res: &[TestGroupRunResults]) -> impl Iterator<Item = &TestRunResult> {
+    res.iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter(|t| t.status.passed())
+}
+
+/// Writes the duration of every test that passed as a timing file.
+pub(crate) fn write_timings(res: &[TestGroupRunResults], path: &Path) -> anyhow::Result<()> {
+    let durations: BTreeMap<_, _> = passed_tests(res)
+        .map(|t| (t.name.as_str(), t.duration.as_secs_f64()))
+        .collect();
+
+    fs::write(path, serde_json::to_vec_pretty(&durations)?)
+        .with_context(|| format!("Writing timings to {:?}", path))
+}
+
+/// Returns the tests that passed and got slower than in the baseline by more
+/// than `threshold` percent, from the largest slowdown to the smallest.
+pub(crate) fn find_perf_regressions(
+    res: &[TestGroupRunResults],
+    baseline: &TimingBaseline,
+    threshold: f64,
+) -> Vec<PerfRegression> {
+    let mut regressions: Vec<_> = passed_tests(res)
+        .filter_map(|t| {
+            let baseline = Duration::try_from_secs_f64(*baseline.durations.get(&t.name)?).ok()?;
+            Some(PerfRegression {
+                name: t.name.clone(),
+                baseline,
+                current: t.duration,
+            })
+        })
+        .filter(|r| r.baseline >= MIN_COMPARED_DURATION && r.slowdown() > threshold)
+        .collect();
+
+    regressions.sort_unstable_by(|a, b| b.slowdown().total_cmp(&a.slowdown()));
+    regressions
+}
@@ -1,53 +1,166 @@
 use std::{
     collections::{HashMap, HashSet},
-    fs::File,
-    io::{BufRead, BufReader, Result as IoResult},
-    path::PathBuf,
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    time::Duration,
 };
 
+use anyhow::Context;
 use chrono::{DateTime, Utc};
-use log::info;
+use clap::ValueEnum;
+use common::config::EVM_ARITHMETIZATION_VERSION;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
-use crate::plonky2_runner::TestStatus;
+use crate::plonky2_runner::{display_altered_inputs, InputAlteration, TestStatus};
 
 const PASS_STATE_PATH_STR: &str = "test_pass_state.csv";
+const PASS_STATE_TMP_PATH_STR: &str = "test_pass_state.csv.tmp";
+/// Entries are appended here after every test, so that a hard kill of the
+/// runner loses at most the test currently running. It is merged into the
+/// pass state file on the next successful write.
+const PASS_STATE_JOURNAL_PATH_STR: &str = "test_pass_state.journal";
 
 #[derive(Debug, Default)]
-pub(crate) struct TestRunEntries(HashMap<String, RunEntry>);
+pub struct TestRunEntries(HashMap<String, RunEntry>);
 
 impl TestRunEntries {
-    pub(crate) fn write_to_disk(self) {
+    pub fn write_to_disk(self) {
         println!("Persisting test pass state to disk...");
 
+        // Write to a temporary file first and atomically swap it with the
+        // previous state, so that we never end up with a truncated state file.
         let data = self.into_serializable();
-        let mut writer = csv::Writer::from_path(PASS_STATE_PATH_STR).unwrap();
+        let mut writer = csv::Writer::from_path(PASS_STATE_TMP_PATH_STR).unwrap();
 
         for entry in data {
             writer.serialize(entry).unwrap();
         }
+
+        let file = writer.into_inner().unwrap();
+        file.sync_all().unwrap();
+        fs::rename(PASS_STATE_TMP_PATH_STR, PASS_STATE_PATH_STR).unwrap();
+
+        // The journaled entries are now all part of the state file.
+        let _ = fs::remove_file(PASS_STATE_JOURNAL_PATH_STR);
+    }
+
+    fn append_to_journal(t_key: &str, entry: &RunEntry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(&entry.to_serializable(t_key.to_string()))?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(PASS_STATE_JOURNAL_PATH_STR)?;
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+
+        Ok(())
+    }
+
+    /// Applies the entries of a journal left over by a previous run that did
+    /// not terminate properly.
+    fn replay_journal(&mut self) {
+        let Ok(file) = fs::File::open(PASS_STATE_JOURNAL_PATH_STR) else {
+            return;
+        };
+
+        info!("Found a test run state journal, replaying it.");
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
+            // The last line may be partially written if the runner was killed.
+            match serde_json::from_str::<SerializableRunEntry>(&line) {
+                Ok(e) => {
+                    let (name, entry) = e.into_entry();
+                    self.0.insert(name, entry);
+                }
+                Err(_) => warn!("Skipping malformed journal entry {}", line),
+            }
+        }
+    }
+
+    /// Exports the state as a JSON array of entries.
+    pub(crate) fn export_to_json(self, path: &Path) -> anyhow::Result<()> {
+        let data = serde_json::to_vec_pretty(&self.into_serializable())?;
+        fs::write(path, data).with_context(|| format!("Writing run state to {:?}", path))
+    }
+
+    /// Imports a state previously exported with `export_to_json`.
+    pub(crate) fn import_from_json(path: &Path) -> anyhow::Result<Self> {
+        let data = fs::read(path).with_context(|| format!("Reading run state from {:?}", path))?;
+        let entries: Vec<SerializableRunEntry> =
+            serde_json::from_slice(&data).with_context(|| "Deserializing JSON run state")?;
+
+        Ok(entries.into())
     }
 
     fn into_serializable(self) -> Vec<SerializableRunEntry> {
         let mut data: Vec<_> = self
             .0
             .into_iter()
-            .map(|(test_name, data)| SerializableRunEntry {
-                test_name,
-                pass_state: data.pass_state,
-                last_run: data.last_run,
-            })
+            .map(|(test_name, data)| data.to_serializable(test_name))
             .collect();
 
         data.sort_unstable_by(|e1, e2| e1.test_name.cmp(&e2.test_name));
         data
     }
 
-    pub(crate) fn update_test_state(&mut self, t_key: &str, state: PassState) {
+    pub(crate) fn update_test_state(
+        &mut self,
+        t_key: &str,
+        status: &TestStatus,
+        duration: Duration,
+        peak_memory: Option<u64>,
+        altered_inputs: &[InputAlteration],
+    ) {
+        let mut entry = RunEntry::new(status, duration, peak_memory, altered_inputs);
+        if matches!(status, TestStatus::TimedOut) {
+            entry.consecutive_timeouts = self
+                .0
+                .get(t_key)
+                .map_or(0, |prev| prev.consecutive_timeouts)
+                + 1;
+        }
+
+        if let Err(err) = Self::append_to_journal(t_key, &entry) {
+            warn!("Unable to journal the state of {}: {:#}", t_key, err);
+        }
+        self.0.insert(t_key.to_string(), entry);
+    }
+
+    /// Returns when the given test was last run, if it ever was.
+    pub(crate) fn get_last_run(&self, t_key: &str) -> Option<DateTime<Utc>> {
+        self.0.get(t_key).and_then(|entry| entry.last_run)
+    }
+
+    /// Returns how many times in a row the given test has timed out.
+    pub(crate) fn get_consecutive_timeouts(&self, t_key: &str) -> u32 {
+        self.0
+            .get(t_key)
+            .map_or(0, |entry| entry.consecutive_timeouts)
+    }
+
+    /// Returns the error of the last run of the given test if it failed, timed
+    /// out or crashed.
+    pub(crate) fn get_last_error(&self, t_key: &str) -> Option<&str> {
+        self.0
+            .get(t_key)
+            .filter(|entry| {
+                matches!(
+                    entry.pass_state,
+                    PassState::Failed | PassState::TimedOut | PassState::Crashed
+                )
+            })
+            .and_then(|entry| entry.last_error.as_deref())
+    }
+
+    /// Returns how long the last run of the given test took, if known.
+    pub(crate) fn get_last_duration(&self, t_key: &str) -> Option<Duration> {
         self.0
-            .entry(t_key.to_string())
-            .and_modify(|entry| *entry = RunEntry::new(state))
-            .or_insert_with(|| RunEntry::new(state));
+            .get(t_key)
+            .and_then(|entry| entry.last_duration_ms)
+            .map(Duration::from_millis)
     }
 
     pub(crate) fn add_remove_entries_from_upstream_tests<'a>(
@@ -76,44 +189,91 @@ impl TestRunEntries {
     /// The filtering will always ignore tests for which proof verification was
     /// successful, but may not skip tests for which only witness generation
     /// was tested, if we haven't passed the `witness_only` argument.
+    ///
+    /// Only runs made against the current zkEVM version are considered.
     pub(crate) fn get_tests_that_have_passed(
         &self,
         witness_only: bool,
     ) -> impl Iterator<Item = &str> {
         self.0.iter().filter_map(move |(name, info)| {
-            info.pass_state
-                .get_passed_status(witness_only)
-                .then_some(name.as_str())
+            (info.zkevm_version.as_deref() == Some(EVM_ARITHMETIZATION_VERSION)
+                && info.pass_state.get_passed_status(witness_only))
+            .then_some(name.as_str())
         })
     }
+
+    /// Returns the tests that passed in their last run, whatever the zkEVM
+    /// version they were run against. Unlike with `skip_passed`, ignored tests
+    /// don't count as passed, as they can't regress.
+    pub(crate) fn get_tests_that_passed_last_run(
+        &self,
+        witness_only: bool,
+    ) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(move |(name, info)| {
+            (info.pass_state != PassState::Ignored
+                && info.pass_state.get_passed_status(witness_only))
+            .then_some(name.as_str())
+        })
+    }
+
+    /// Returns the tests that failed (or crashed) in their last run (see
+    /// `--only-failed`), along with those that timed out if
+    /// `include_timed_out` is set.
+    pub(crate) fn get_tests_that_failed_last_run(
+        &self,
+        include_timed_out: bool,
+    ) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(move |(name, info)| {
+            let failed = match info.pass_state {
+                PassState::Failed | PassState::Crashed => true,
+                PassState::TimedOut => include_timed_out,
+                _ => false,
+            };
+            failed.then_some(name.as_str())
+        })
+    }
+
+    /// Marks all entries as having been run against the current zkEVM
+    /// version, so that previous results are considered by `skip_passed`.
+    pub(crate) fn carry_forward_to_current_version(&mut self) {
+        for entry in self.0.values_mut() {
+            entry.zkevm_version = Some(EVM_ARITHMETIZATION_VERSION.to_string());
+        }
+    }
+
+    pub(crate) fn get_tests_in_state(&self, state: PassState) -> impl Iterator<Item = &str> {
+        self.0
+            .iter()
+            .filter_map(move |(name, info)| (info.pass_state == state).then_some(name.as_str()))
+    }
 }
 
 impl From<Vec<SerializableRunEntry>> for TestRunEntries {
     fn from(v: Vec<SerializableRunEntry>) -> Self {
-        TestRunEntries(HashMap::from_iter(v.into_iter().map(|e| {
-            (
-                e.test_name,
-                RunEntry {
-                    pass_state: e.pass_state,
-                    last_run: e.last_run,
-                },
-            )
-        })))
+        TestRunEntries(HashMap::from_iter(
+            v.into_iter().map(SerializableRunEntry::into_entry),
+        ))
     }
 }
 
-#[derive(Copy, Clone, Debug, Deserialize, Default, Serialize)]
+#[derive(Copy, Clone, Debug, Deserialize, Default, Eq, PartialEq, Serialize, ValueEnum)]
 pub(crate) enum PassState {
     PassedWitness,
     PassedProof,
+    /// Proven with a clamped gas limit (see `--gas-limit-policy`).
+    PassedClampedProof,
     Ignored,
     Failed,
+    TimedOut,
+    Crashed,
     #[default]
     NotRun,
 }
 
 impl PassState {
-    // Utility method to filter out passed tests from previous runs.
+    // Utility method to filter out passed tests from previous runs. Proofs
+    // made with a clamped gas limit don't count, as the test wasn't run as
+    // written.
     const fn get_passed_status(&self, witness_only: bool) -> bool {
         if witness_only {
             matches!(
@@ -131,8 +291,11 @@ impl From<TestStatus> for PassState {
         match v {
             TestStatus::PassedWitness => PassState::PassedWitness,
             TestStatus::PassedProof => PassState::PassedProof,
+            TestStatus::PassedClampedProof => PassState::PassedClampedProof,
             TestStatus::Ignored => PassState::Ignored,
-            TestStatus::EvmErr(_) | TestStatus::TimedOut => PassState::Failed,
+            TestStatus::EvmErr(_) => PassState::Failed,
+            TestStatus::TimedOut => PassState::TimedOut,
+            TestStatus::Crashed(_) => PassState::Crashed,
         }
     }
 }
@@ -142,24 +305,103 @@ pub(crate) struct SerializableRunEntry {
     test_name: String,
     pass_state: PassState,
     last_run: Option<DateTime<Utc>>,
+    #[serde(default)]
+    last_error: Option<String>,
+    #[serde(default)]
+    last_duration_ms: Option<u64>,
+    #[serde(default)]
+    consecutive_timeouts: u32,
+    #[serde(default)]
+    zkevm_version: Option<String>,
+    #[serde(default)]
+    last_peak_memory_bytes: Option<u64>,
+    #[serde(default)]
+    altered_inputs: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Default, Serialize)]
+impl SerializableRunEntry {
+    fn into_entry(self) -> (String, RunEntry) {
+        (
+            self.test_name,
+            RunEntry {
+                pass_state: self.pass_state,
+                last_run: self.last_run,
+                last_error: self.last_error,
+                last_duration_ms: self.last_duration_ms,
+                consecutive_timeouts: self.consecutive_timeouts,
+                zkevm_version: self.zkevm_version,
+                last_peak_memory_bytes: self.last_peak_memory_bytes,
+                altered_inputs: self.altered_inputs,
+            },
+        )
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Default, Serialize)]
 struct RunEntry {
     pass_state: PassState,
     last_run: Option<DateTime<Utc>>,
+    /// The error message of the last run, if it failed.
+    last_error: Option<String>,
+    last_duration_ms: Option<u64>,
+    consecutive_timeouts: u32,
+    /// The version of the zkEVM the test was last run against.
+    zkevm_version: Option<String>,
+    /// The peak resident memory of the last run, if it could be measured.
+    last_peak_memory_bytes: Option<u64>,
+    /// How the runner altered the inputs of the last run, if it did.
+    altered_inputs: Option<String>,
 }
 
 impl RunEntry {
-    fn new(pass_state: PassState) -> Self {
+    fn new(
+        status: &TestStatus,
+        duration: Duration,
+        peak_memory: Option<u64>,
+        altered_inputs: &[InputAlteration],
+    ) -> Self {
+        let last_error = match status {
+            TestStatus::EvmErr(err) => Some(err.clone()),
+            TestStatus::TimedOut | TestStatus::Crashed(_) => Some(status.to_string()),
+            _ => None,
+        };
+
         Self {
-            pass_state,
+            pass_state: status.clone().into(),
             last_run: Some(chrono::Utc::now()),
+            last_error,
+            last_duration_ms: Some(duration.as_millis() as u64),
+            consecutive_timeouts: 0,
+            zkevm_version: Some(EVM_ARITHMETIZATION_VERSION.to_string()),
+            last_peak_memory_bytes: peak_memory,
+            altered_inputs: (!altered_inputs.is_empty())
+                .then(|| display_altered_inputs(altered_inputs)),
         }
     }
+
+    fn to_serializable(&self, test_name: String) -> SerializableRunEntry {
+        SerializableRunEntry {
+            test_name,
+            pass_state: self.pass_state,
+            last_run: self.last_run,
+            last_error: self.last_error.clone(),
+            last_duration_ms: self.last_duration_ms,
+            consecutive_timeouts: self.consecutive_timeouts,
+            zkevm_version: self.zkevm_version.clone(),
+            last_peak_memory_bytes: self.last_peak_memory_bytes,
+            altered_inputs: self.altered_inputs.clone(),
+        }
+    }
+}
+
+pub fn load_existing_pass_state_from_disk_if_exists_or_create() -> TestRunEntries {
+    let mut entries = load_pass_state_file();
+    entries.replay_journal();
+
+    entries
 }
 
-pub(crate) fn load_existing_pass_state_from_disk_if_exists_or_create() -> TestRunEntries {
+fn load_pass_state_file() -> TestRunEntries {
     csv::Reader::from_path(PASS_STATE_PATH_STR)
         .map(|mut reader| {
             info!("Found existing test run state on disk.");
@@ -175,8 +417,3 @@ pub(crate) fn load_existing_pass_state_from_disk_if_exists_or_create() -> TestRu
             TestRunEntries::default()
         })
 }
-
-pub(crate) fn load_blacklist(blacklist_file: &PathBuf) -> IoResult<HashSet<String>> {
-    let file = File::open(blacklist_file)?;
-    Ok(BufReader::new(file).lines().map_while(Result::ok).collect())
-}
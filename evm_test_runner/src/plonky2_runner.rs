@@ -2,37 +2,91 @@
 //! Essentially converts parsed tests into test results.
 
 use std::{
+    collections::HashMap,
     fmt::{Debug, Display},
-    time::Duration,
+    iter,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use common::types::TestVariantRunInfo;
-use ethereum_types::U256;
+use chrono::Utc;
+use clap::ValueEnum;
+use common::types::{AccountState, ChainedBlockRunInfo, TestVariantRunInfo, TxnOutcome};
+use ethereum_types::{Address, H256, U256};
 use evm_arithmetization::{
-    prover::testing::{prove_all_segments, simulate_execution_all_segments},
+    generation::{DebugOutputTries, GenerationInputs},
+    proof::{AllProof, PublicValues, TrieRoots},
+    prover::{
+        prove,
+        testing::{prove_all_segments, simulate_execution_all_segments},
+    },
     verifier::testing::verify_all_proofs,
     AllStark, StarkConfig,
 };
 use futures::executor::block_on;
 use indicatif::{ProgressBar, ProgressStyle};
-use log::warn;
+use log::{error, info, warn};
+use mpt_trie::partial_trie::PartialTrie;
 use plonky2::{
-    field::goldilocks_field::GoldilocksField, plonk::config::KeccakGoldilocksConfig,
+    field::goldilocks_field::GoldilocksField,
+    plonk::config::{GenericConfig, KeccakGoldilocksConfig, PoseidonGoldilocksConfig},
     util::timing::TimingTree,
 };
+use serde::{Deserialize, Serialize};
 use tokio::{select, time::timeout};
 
+use self::{
+    continuations::Continuations,
+    post_state_check::{account_mismatches, check_post_state_root},
+    proving_config::ConfigHasher,
+    receipt_check::receipt_mismatch,
+};
 use crate::{
+    blacklist::add_to_blacklist,
+    distributed::WorkerSettings,
+    eta::EtaEstimator,
+    isolation::{run_test_in_subprocess, IsolationSettings},
     persistent_run_state::TestRunEntries,
+    proof_archive::{encode_segments, ProofArchive},
+    report_generation::{serialize_display, serialize_display_opt, serialize_duration_secs},
+    repro_bundle::write_repro_bundle,
+    resource_estimation::PeakRssSampler,
+    run_history::RunHistoryDb,
+    t8n_runner::{run_t8n, T8nStatus},
     test_dir_reading::{ParsedTestGroup, ParsedTestSubGroup, Test},
+    witness_cache::{generate_witness, Witness, WitnessCache},
     ProcessAbortedRecv,
 };
 
+/// The max CPU log length used when none is provided.
+pub(crate) const DEFAULT_MAX_CPU_LOG_LEN: usize = 32;
+
+/// The number of accounts differing from the post-state that are listed in the
+/// status of a failed test, the others being logged.
+const MAX_REPORTED_ACCOUNT_MISMATCHES: usize = 3;
+
+mod continuations;
+mod coordinator;
+mod post_state_check;
+mod proving_config;
+mod receipt_check;
+mod scheduler;
+
+pub(crate) use proving_config::ProvingConfig;
+
 pub(crate) type RunnerResult<T> = Result<T, ()>;
 
 trait TestProgressIndicator: Debug {
     fn set_current_test_name(&self, t_name: String);
     fn notify_test_completed(&mut self);
+    /// Adds tests to the total number of tests to run.
+    fn add_tests(&mut self, num_tests: u64);
+    /// Updates the estimated time needed to run the remaining tests.
+    fn set_eta(&mut self, remaining: Duration);
+    /// Prints a message without interfering with the indicator.
+    fn println(&self, msg: &str);
 }
 
 /// Simple test progress indicator that uses `println!`s.
@@ -40,13 +94,17 @@ trait TestProgressIndicator: Debug {
 struct SimpleProgressIndicator {
     num_tests: u64,
     curr_test: usize,
+    eta: Duration,
 }
 
 impl TestProgressIndicator for SimpleProgressIndicator {
     fn set_current_test_name(&self, t_name: String) {
         println!(
-            "({}/{}) Running {}...",
-            self.curr_test, self.num_tests, t_name
+            "({}/{}) Running {}... (ETA: {})",
+            self.curr_test,
+            self.num_tests,
+            t_name,
+            format_eta(self.eta)
         );
     }
 
@@ -54,6 +112,18 @@ impl TestProgressIndicator for SimpleProgressIndicator {
     fn notify_test_completed(&mut self) {
         self.curr_test += 1;
     }
+
+    fn add_tests(&mut self, num_tests: u64) {
+        self.num_tests += num_tests;
+    }
+
+    fn set_eta(&mut self, remaining: Duration) {
+        self.eta = remaining;
+    }
+
+    fn println(&self, msg: &str) {
+        println!("{}", msg);
+    }
 }
 
 /// More elegant test progress indicator that uses a progress bar library.
@@ -70,15 +140,82 @@ impl TestProgressIndicator for FancyProgressIndicator {
     fn notify_test_completed(&mut self) {
         self.prog_bar.inc(1);
     }
+
+    fn add_tests(&mut self, num_tests: u64) {
+        self.prog_bar.inc_length(num_tests);
+    }
+
+    fn set_eta(&mut self, remaining: Duration) {
+        self.prog_bar.set_prefix(format_eta(remaining));
+    }
+
+    fn println(&self, msg: &str) {
+        self.prog_bar.println(msg);
+    }
+}
+
+/// The progress of a run, shared with the HTTP API while the run goes on.
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct RunProgress {
+    pub(crate) num_tests: u64,
+    pub(crate) num_completed: u64,
+    pub(crate) current_test: Option<String>,
+    #[serde(serialize_with = "serialize_duration_secs")]
+    pub(crate) eta: Duration,
+}
+
+/// Test progress indicator that only updates a [`RunProgress`], for runs
+/// driven through the HTTP API.
+#[derive(Debug)]
+struct SharedProgressIndicator {
+    progress: Arc<Mutex<RunProgress>>,
 }
 
-#[derive(Clone, Debug)]
-pub(crate) enum TestStatus {
+impl TestProgressIndicator for SharedProgressIndicator {
+    fn set_current_test_name(&self, t_name: String) {
+        self.progress.lock().unwrap().current_test = Some(t_name);
+    }
+
+    fn notify_test_completed(&mut self) {
+        self.progress.lock().unwrap().num_completed += 1;
+    }
+
+    fn add_tests(&mut self, num_tests: u64) {
+        self.progress.lock().unwrap().num_tests += num_tests;
+    }
+
+    fn set_eta(&mut self, remaining: Duration) {
+        self.progress.lock().unwrap().eta = remaining;
+    }
+
+    fn println(&self, msg: &str) {
+        info!("{}", msg);
+    }
+}
+
+/// Formats an ETA with a second precision, as `indicatif`'s `eta_precise`.
+fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum TestStatus {
     PassedWitness,
     PassedProof,
+    /// The test was proven with a block gas limit clamped to `u32::MAX` (see
+    /// `--gas-limit-policy`), so its proof isn't one of the test itself.
+    PassedClampedProof,
     Ignored,
     EvmErr(String),
     TimedOut,
+    /// The process running the test died (see `--isolate`).
+    Crashed(String),
 }
 
 impl Display for TestStatus {
@@ -86,23 +223,46 @@ impl Display for TestStatus {
         match self {
             TestStatus::PassedWitness => write!(f, "Passed witness generation"),
             TestStatus::PassedProof => write!(f, "Passed proof verification"),
+            TestStatus::PassedClampedProof => {
+                write!(f, "Passed proof verification with a clamped gas limit")
+            }
             TestStatus::Ignored => write!(f, "Ignored"),
             TestStatus::EvmErr(err) => write!(f, "Evm error: {}", err),
             TestStatus::TimedOut => write!(f, "Test timed out"),
+            TestStatus::Crashed(err) => write!(f, "Test crashed: {}", err),
         }
     }
 }
 
 impl TestStatus {
-    pub(crate) const fn passed(&self) -> bool {
+    pub const fn passed(&self) -> bool {
         matches!(self, Self::PassedProof | Self::PassedWitness)
     }
+
+    pub const fn failed(&self) -> bool {
+        matches!(self, Self::EvmErr(_) | Self::TimedOut | Self::Crashed(_))
+    }
+
+    /// The status of a test whose proof was verified, with its gas limit
+    /// clamped or not.
+    const fn proven(is_gaslimit_changed: bool) -> Self {
+        match is_gaslimit_changed {
+            true => Self::PassedClampedProof,
+            false => Self::PassedProof,
+        }
+    }
+
+    /// Whether the test was only proven with a clamped gas limit, which is
+    /// neither a pass nor a failure.
+    pub const fn clamped(&self) -> bool {
+        matches!(self, Self::PassedClampedProof)
+    }
 }
 
 #[derive(Debug)]
-pub(crate) struct TestGroupRunResults {
-    pub(crate) name: String,
-    pub(crate) sub_group_res: Vec<TestSubGroupRunResults>,
+pub struct TestGroupRunResults {
+    pub name: String,
+    pub sub_group_res: Vec<TestSubGroupRunResults>,
 }
 
 fn num_tests_in_groups<'a>(groups: impl Iterator<Item = &'a ParsedTestGroup> + 'a) -> u64 {
@@ -117,15 +277,341 @@ fn num_tests_in_groups<'a>(groups: impl Iterator<Item = &'a ParsedTestGroup> + '
 }
 
 #[derive(Debug)]
-pub(crate) struct TestSubGroupRunResults {
-    pub(crate) name: String,
-    pub(crate) test_res: Vec<TestRunResult>,
+pub struct TestSubGroupRunResults {
+    pub name: String,
+    pub test_res: Vec<TestRunResult>,
 }
 
+/// The results of all the tests that were run.
 #[derive(Debug)]
-pub(crate) struct TestRunResult {
-    pub(crate) name: String,
+pub struct TestRunOutput {
+    pub results: Vec<TestGroupRunResults>,
+    /// Whether the run was aborted or stopped by `--fail-fast`, in which case
+    /// `results` only contains the tests that completed before that.
+    pub stopped_early: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TestRunResult {
+    pub name: String,
+    #[serde(serialize_with = "serialize_display")]
+    pub status: TestStatus,
+    #[serde(serialize_with = "serialize_duration_secs")]
+    pub duration: Duration,
+    /// The result of the differential `evm t8n` run, if enabled.
+    #[serde(serialize_with = "serialize_display_opt")]
+    pub t8n_status: Option<T8nStatus>,
+    /// The test file the variant was read from.
+    pub input_path: PathBuf,
+    /// Stats on the proof, if the test was fully proven.
+    pub proof_stats: Option<ProofStats>,
+    /// How much the resident memory of the process running the test grew
+    /// while it ran, in bytes. Not recorded for tests run concurrently in the
+    /// same process (see `--jobs`).
+    pub peak_memory: Option<u64>,
+    /// How the runner altered the inputs of the test, if it did.
+    pub altered_inputs: Vec<InputAlteration>,
+}
+
+impl TestRunResult {
+    /// The status of the test, marked if its inputs were altered.
+    pub fn marked_status(&self) -> String {
+        match self.altered_inputs.is_empty() {
+            true => self.status.to_string(),
+            false => format!(
+                "{} (inputs altered: {})",
+                self.status,
+                display_altered_inputs(&self.altered_inputs)
+            ),
+        }
+    }
+}
+
+/// Stats on the proof of a test that matter to downstream users of the prover.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ProofStats {
+    /// The size of the serialized proof (of all segments), in bytes.
+    pub size: usize,
+    /// The time taken to verify the proof.
+    #[serde(serialize_with = "serialize_duration_secs")]
+    pub verify_time: Duration,
+}
+
+/// What running a test against `plonky2` resulted in.
+#[derive(Debug)]
+pub(crate) struct TestOutcome {
     pub(crate) status: TestStatus,
+    pub(crate) proof_stats: Option<ProofStats>,
+    /// The peak resident memory of the process while the test ran, in bytes.
+    pub(crate) peak_memory: Option<u64>,
+    /// How the runner altered the inputs of the test to get it through the
+    /// prover, if it did.
+    pub(crate) altered_inputs: Vec<InputAlteration>,
+}
+
+/// An alteration of the inputs of a test made by the runner, which makes its
+/// result one of slightly different inputs.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum InputAlteration {
+    /// The block gas limit was clamped to `u32::MAX` (see
+    /// `--gas-limit-policy`).
+    ClampedGasLimit,
+}
+
+impl Display for InputAlteration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputAlteration::ClampedGasLimit => write!(f, "clamped gas limit"),
+        }
+    }
+}
+
+/// Lists the alterations of the inputs of a test, as shown in reports.
+pub(crate) fn display_altered_inputs(altered_inputs: &[InputAlteration]) -> String {
+    altered_inputs
+        .iter()
+        .map(|alteration| alteration.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl TestOutcome {
+    /// Adds the number of the block that failed to the error, for multi-block
+    /// tests.
+    fn in_block(self, block_num: usize) -> Self {
+        let status = match self.status {
+            TestStatus::EvmErr(err) => TestStatus::EvmErr(format!("Block {}: {}", block_num, err)),
+            TestStatus::Crashed(err) => {
+                TestStatus::Crashed(format!("Block {}: {}", block_num, err))
+            }
+            status => status,
+        };
+
+        Self { status, ..self }
+    }
+
+    /// Records alterations of the inputs of the test, on top of those already
+    /// recorded.
+    fn with_altered_inputs(mut self, altered_inputs: Vec<InputAlteration>) -> Self {
+        for alteration in altered_inputs {
+            if !self.altered_inputs.contains(&alteration) {
+                self.altered_inputs.push(alteration);
+            }
+        }
+        self
+    }
+
+    /// Combines the outcomes of two consecutive blocks that both passed, the
+    /// test being clamped if any of them was.
+    fn followed_by(self, next: Self) -> Self {
+        Self {
+            status: match self.status.clamped() {
+                true => self.status,
+                false => next.status,
+            },
+            proof_stats: self
+                .proof_stats
+                .zip(next.proof_stats)
+                .map(|(a, b)| ProofStats {
+                    size: a.size + b.size,
+                    verify_time: a.verify_time + b.verify_time,
+                }),
+            peak_memory: self.peak_memory.max(next.peak_memory),
+            altered_inputs: self.altered_inputs,
+        }
+        .with_altered_inputs(next.altered_inputs)
+    }
+}
+
+impl From<TestStatus> for TestOutcome {
+    fn from(status: TestStatus) -> Self {
+        Self {
+            status,
+            proof_stats: None,
+            peak_memory: None,
+            altered_inputs: Vec::new(),
+        }
+    }
+}
+
+/// Tracks the proofs of the blocks of a test, to check that they form a chain
+/// starting from its checkpoint, as the block circuit would when aggregating
+/// them.
+#[derive(Debug, Default)]
+struct ChainCheckpoint {
+    checkpoint_state_root: Option<H256>,
+    prev_state_root: Option<H256>,
+}
+
+impl ChainCheckpoint {
+    /// Checks the public values of the first and last segments of the proof
+    /// of the next block.
+    fn link(
+        &mut self,
+        first: &PublicValues<GoldilocksField>,
+        last: &PublicValues<GoldilocksField>,
+    ) -> anyhow::Result<()> {
+        let checkpoint = first.extra_block_data.checkpoint_state_trie_root;
+        let state_root_before = first.trie_roots_before.state_root;
+        match (self.checkpoint_state_root, self.prev_state_root) {
+            (Some(chain_checkpoint), Some(prev_state_root)) => {
+                anyhow::ensure!(
+                    checkpoint == chain_checkpoint,
+                    "The checkpoint state root is {:?}, but the chain started from {:?}",
+                    checkpoint,
+                    chain_checkpoint
+                );
+                anyhow::ensure!(
+                    state_root_before == prev_state_root,
+                    "The state root before the block is {:?}, but the previous block ended with {:?}",
+                    state_root_before,
+                    prev_state_root
+                );
+            }
+            _ => {
+                anyhow::ensure!(
+                    state_root_before == checkpoint,
+                    "The state root before the first block is {:?}, but the checkpoint is {:?}",
+                    state_root_before,
+                    checkpoint
+                );
+                self.checkpoint_state_root = Some(checkpoint);
+            }
+        }
+        self.prev_state_root = Some(last.trie_roots_after.state_root);
+
+        Ok(())
+    }
+}
+
+/// The time after which a test is marked as timed out, which can be set per
+/// sub-group (see [`crate::config_file`]) or scaled with the gas used by the
+/// test.
+#[derive(Clone, Debug, Default)]
+pub struct TestTimeouts {
+    /// The timeout of the sub-groups that don't have their own, if any.
+    pub default: Option<Duration>,
+    pub by_sub_group: HashMap<String, Duration>,
+    /// If set along with the default, the tests of the sub-groups that don't
+    /// have their own timeout get this much time per million gas they use on
+    /// top of it. Without a default, tests using little or no gas would time
+    /// out right away.
+    pub per_mgas: Option<Duration>,
+}
+
+impl TestTimeouts {
+    fn for_test(&self, sub_group: &str, info: &TestVariantRunInfo) -> Duration {
+        self.for_gas_used(sub_group, info.gas_used())
+    }
+
+    fn for_gas_used(&self, sub_group: &str, gas_used: u64) -> Duration {
+        if let Some(timeout) = self.by_sub_group.get(sub_group) {
+            return *timeout;
+        }
+
+        match (self.default, self.per_mgas) {
+            (Some(default), Some(per_mgas)) => {
+                let mgas = gas_used as f64 / 1_000_000.0;
+                let scaled = Duration::try_from_secs_f64(per_mgas.as_secs_f64() * mgas)
+                    .unwrap_or(Duration::MAX);
+                default.saturating_add(scaled)
+            }
+            (default, _) => default.unwrap_or(Duration::MAX),
+        }
+    }
+}
+
+/// Options controlling how the tests are run. The default runs the tests one
+/// at a time, proving each of them.
+#[derive(Debug, Default)]
+pub struct TestRunConfig {
+    pub(crate) simple_progress_indicator: bool,
+    pub(crate) witness_only: bool,
+    /// Check the accounts each block ends with against its post-state.
+    pub(crate) check_post_state: bool,
+    pub(crate) gas_limit_policy: GasLimitPolicy,
+    pub(crate) order: TestOrder,
+    pub(crate) max_cpu_log_len: Option<usize>,
+    pub(crate) test_timeouts: TestTimeouts,
+    /// Path to a geth `evm` binary used for differential testing.
+    pub(crate) t8n_bin: Option<PathBuf>,
+    /// Directory in which to write reproduction bundles for failing tests.
+    pub(crate) repro_dir: Option<PathBuf>,
+    /// Store in which every test run is recorded.
+    pub(crate) run_history: Option<RunHistoryDb>,
+    /// Number of consecutive timeouts after which a test is appended to the
+    /// given blacklist file.
+    pub(crate) auto_blacklist: Option<(u32, PathBuf)>,
+    /// Prove the segments of each test recursively and aggregate them.
+    pub(crate) aggregate_segments: bool,
+    /// Directory in which the recursive circuits are cached.
+    pub(crate) circuits_cache: Option<PathBuf>,
+    /// Number of failures after which the run is stopped.
+    pub(crate) fail_fast: Option<usize>,
+    /// Cache in which witnesses are stored in witness-only mode, and from
+    /// which they are reused when proving.
+    pub(crate) witness_cache: Option<WitnessCache>,
+    /// Archive in which the proofs of the tests are stored.
+    pub(crate) proof_archive: Option<ProofArchive>,
+    /// Number of tests to run in parallel.
+    pub(crate) jobs: usize,
+    /// Memory available to tests running in parallel, in bytes.
+    pub(crate) memory_budget: Option<u64>,
+    /// Progress shared with the HTTP API, reported instead of printing a
+    /// progress indicator.
+    pub(crate) progress: Option<Arc<Mutex<RunProgress>>>,
+    /// Listener on which workers connect, if the tests are run on workers
+    /// rather than locally.
+    pub(crate) coordinator: Option<TcpListener>,
+    /// Run each test in its own child process.
+    pub(crate) isolation: Option<IsolationSettings>,
+}
+
+impl TestRunConfig {
+    /// Only generate the witnesses of the tests, without proving them.
+    pub fn with_witness_only(mut self, witness_only: bool) -> Self {
+        self.witness_only = witness_only;
+        self
+    }
+
+    /// Check the accounts each block ends with against its post-state.
+    pub fn with_post_state_check(mut self, check_post_state: bool) -> Self {
+        self.check_post_state = check_post_state;
+        self
+    }
+
+    pub fn with_gas_limit_policy(mut self, gas_limit_policy: GasLimitPolicy) -> Self {
+        self.gas_limit_policy = gas_limit_policy;
+        self
+    }
+
+    pub fn with_order(mut self, order: TestOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// The maximum length of the CPU trace of a segment, as a power of two.
+    pub fn with_max_cpu_log_len(mut self, max_cpu_log_len: Option<usize>) -> Self {
+        self.max_cpu_log_len = max_cpu_log_len;
+        self
+    }
+
+    pub fn with_test_timeouts(mut self, test_timeouts: TestTimeouts) -> Self {
+        self.test_timeouts = test_timeouts;
+        self
+    }
+
+    /// Stop the run after this many failures.
+    pub fn with_fail_fast(mut self, fail_fast: Option<usize>) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Run this many tests in parallel.
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -134,52 +620,340 @@ struct TestRunState<'a> {
     persistent_test_state: &'a mut TestRunEntries,
     process_aborted_recv: ProcessAbortedRecv,
     witness_only: bool,
+    order: TestOrder,
     max_cpu_log_len: Option<usize>,
-    test_timeout: Duration,
+    test_timeouts: TestTimeouts,
+    t8n_bin: Option<PathBuf>,
+    repro_dir: Option<PathBuf>,
+    run_history: Option<RunHistoryDb>,
+    auto_blacklist: Option<(u32, PathBuf)>,
+    eta: EtaEstimator,
+    prover_setup: Arc<ProverSetup>,
+    witness_cache: Option<WitnessCache>,
+    isolation: Option<Arc<IsolationSettings>>,
+    fail_fast: Option<usize>,
+    num_failures: usize,
+    stopped_early: bool,
+}
+
+/// The STARKs and their config, which are the same for every test and hence
+/// only built once per run.
+pub(crate) struct ProverSetup {
+    all_stark: AllStark<GoldilocksField, 2>,
+    config: StarkConfig,
+    /// The hasher and FRI preset `config` was built from.
+    proving_config: ProvingConfig,
+    /// The recursive circuits, if segments are proven and aggregated.
+    continuations: Option<Continuations>,
+    /// Check the accounts each block ends with against its post-state.
+    check_post_state: bool,
+    gas_limit_policy: GasLimitPolicy,
+    /// Archive in which the proofs of the blocks are stored.
+    proof_archive: Option<ProofArchive>,
 }
 
-pub(crate) fn run_plonky2_tests(
+/// What to do with the blocks whose gas limit doesn't fit in 32 bits, which the
+/// plonky2 zkEVM verifier doesn't support.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, ValueEnum)]
+pub enum GasLimitPolicy {
+    /// Prove them with their gas limit clamped to `u32::MAX`, ignoring them if
+    /// that fails.
+    #[default]
+    Clamp,
+    /// Ignore them without proving them.
+    Skip,
+    /// Fail them.
+    Fail,
+}
+
+/// The order in which the tests are run. Their results are reported in the
+/// order of the corpus either way.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum TestOrder {
+    /// In the order of the corpus.
+    #[default]
+    Corpus,
+    /// The tests run the longest ago first, starting with those never run, so
+    /// that time-boxed runs that never get through the whole corpus still
+    /// re-validate every test eventually.
+    StalestFirst,
+}
+
+impl Debug for ProverSetup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProverSetup")
+            .field("config", &self.config)
+            .field("proving_config", &self.proving_config)
+            .field("continuations", &self.continuations.is_some())
+            .field("check_post_state", &self.check_post_state)
+            .field("gas_limit_policy", &self.gas_limit_policy)
+            .field("proof_archive", &self.proof_archive)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ProverSetup {
+    /// `circuits_cache` is where the recursive circuits are cached, if segments
+    /// are aggregated.
+    pub(crate) fn new(
+        aggregate_segments: bool,
+        max_cpu_log_len: Option<usize>,
+        circuits_cache: Option<&Path>,
+    ) -> Self {
+        let all_stark = AllStark::default();
+        let continuations = aggregate_segments.then(|| {
+            Continuations::new(
+                &all_stark,
+                max_cpu_log_len.unwrap_or(DEFAULT_MAX_CPU_LOG_LEN),
+                circuits_cache,
+            )
+        });
+
+        Self {
+            all_stark,
+            config: StarkConfig::standard_fast_config(),
+            proving_config: ProvingConfig::default(),
+            continuations,
+            check_post_state: false,
+            gas_limit_policy: GasLimitPolicy::default(),
+            proof_archive: None,
+        }
+    }
+
+    /// Checks the accounts each block ends with against the post-state of the
+    /// manifest (see [`post_state_check`]).
+    pub(crate) fn with_post_state_check(mut self, check_post_state: bool) -> Self {
+        self.check_post_state = check_post_state;
+        self
+    }
+
+    pub(crate) fn with_gas_limit_policy(mut self, gas_limit_policy: GasLimitPolicy) -> Self {
+        self.gas_limit_policy = gas_limit_policy;
+        self
+    }
+
+    /// Proves the segments with `proving_config` rather than the default
+    /// Keccak config. The recursive circuits are only built for the latter, so
+    /// this can't be combined with aggregating segments.
+    pub(crate) fn with_proving_config(mut self, proving_config: ProvingConfig) -> Self {
+        self.config = proving_config.stark_config();
+        self.proving_config = proving_config;
+        self
+    }
+
+    /// Stores the proofs of the blocks in `proof_archive`, unless segments are
+    /// aggregated.
+    pub(crate) fn with_proof_archive(mut self, proof_archive: Option<ProofArchive>) -> Self {
+        self.proof_archive = proof_archive;
+        self
+    }
+}
+
+impl TestRunState<'_> {
+    /// Records a test result and returns whether the `--fail-fast` failure
+    /// limit has been reached.
+    fn fail_fast_limit_reached(&mut self, status: &TestStatus) -> bool {
+        if !status.failed() {
+            return false;
+        }
+        self.num_failures += 1;
+
+        let reached = self.fail_fast.is_some_and(|max| self.num_failures >= max);
+        if reached {
+            self.p_indicator.println(&format!(
+                "Stopping the run after {} failure(s).",
+                self.num_failures
+            ));
+        }
+        reached
+    }
+}
+
+/// Runs `parsed_tests`, recording their results in `persistent_test_state`,
+/// until they have all run or `process_aborted` receives a message. Must be
+/// called from within a Tokio runtime.
+pub fn run_plonky2_tests(
     parsed_tests: Vec<ParsedTestGroup>,
-    simple_progress_indicator: bool,
     persistent_test_state: &mut TestRunEntries,
     process_aborted: ProcessAbortedRecv,
-    witness_only: bool,
-    max_cpu_log_len: Option<usize>,
-    test_timeout: Option<Duration>,
-) -> RunnerResult<Vec<TestGroupRunResults>> {
+    mut config: TestRunConfig,
+) -> TestRunOutput {
     let num_tests = num_tests_in_groups(parsed_tests.iter());
-    let p_indicator = create_progress_indicator(num_tests, simple_progress_indicator);
+    let eta = EtaEstimator::new(&parsed_tests, persistent_test_state);
+    let (jobs, memory_budget) = (config.jobs, config.memory_budget);
+    let worker_settings = WorkerSettings {
+        witness_only: config.witness_only,
+        check_post_state: config.check_post_state,
+        gas_limit_policy: config.gas_limit_policy,
+        max_cpu_log_len: config.max_cpu_log_len,
+        aggregate_segments: config.aggregate_segments,
+    };
+    let coordinator = config.coordinator.take();
+    // Workers build their own recursive circuits.
+    config.aggregate_segments &= coordinator.is_none();
+    let mut t_state = init_test_run_state(
+        num_tests,
+        eta,
+        persistent_test_state,
+        process_aborted,
+        config,
+    );
 
-    let test_timeout = match test_timeout {
-        Some(t) => t,
-        None => Duration::MAX,
+    if let Some(listener) = coordinator {
+        return TestRunOutput {
+            results: coordinator::run_tests_on_workers(
+                parsed_tests,
+                &mut t_state,
+                listener,
+                worker_settings,
+            ),
+            stopped_early: t_state.stopped_early,
+        };
+    }
+
+    // Tests run out of the order of the corpus are scheduled like parallel
+    // ones, which are reported in that order.
+    let results = match (jobs, t_state.order) {
+        (0 | 1, TestOrder::Corpus) => {
+            let mut results = Vec::new();
+            for group in parsed_tests {
+                results.push(run_test_group(group, &mut t_state));
+                if t_state.stopped_early {
+                    break;
+                }
+            }
+            results
+        }
+        (jobs, _) => scheduler::run_tests_in_parallel(
+            parsed_tests,
+            &mut t_state,
+            jobs.max(1),
+            memory_budget.unwrap_or(u64::MAX),
+        ),
     };
 
-    let mut t_state = TestRunState {
-        p_indicator,
+    TestRunOutput {
+        results,
+        stopped_early: t_state.stopped_early,
+    }
+}
+
+/// Runs the tests as they are read in, rather than after the whole corpus has
+/// been loaded. The total number of tests and the ETA grow as tests arrive.
+pub(crate) fn run_plonky2_tests_streamed(
+    parsed_tests: impl Iterator<Item = ParsedTestGroup>,
+    persistent_test_state: &mut TestRunEntries,
+    process_aborted: ProcessAbortedRecv,
+    config: TestRunConfig,
+) -> TestRunOutput {
+    let mut t_state = init_test_run_state(
+        0,
+        EtaEstimator::default(),
         persistent_test_state,
-        process_aborted_recv: process_aborted,
-        witness_only,
-        max_cpu_log_len,
-        test_timeout,
+        process_aborted,
+        config,
+    );
+
+    let mut results: Vec<TestGroupRunResults> = Vec::new();
+    for group in parsed_tests {
+        t_state
+            .p_indicator
+            .add_tests(num_tests_in_groups(std::iter::once(&group)));
+        t_state
+            .eta
+            .add_tests(std::slice::from_ref(&group), t_state.persistent_test_state);
+        t_state.p_indicator.set_eta(t_state.eta.remaining());
+
+        merge_group_results(&mut results, run_test_group(group, &mut t_state));
+        if t_state.stopped_early {
+            break;
+        }
+    }
+
+    TestRunOutput {
+        results,
+        stopped_early: t_state.stopped_early,
+    }
+}
+
+/// Merges the results of a (partial) group into the results of the group and
+/// sub-groups with the same name, if they were already run.
+fn merge_group_results(results: &mut Vec<TestGroupRunResults>, group_res: TestGroupRunResults) {
+    let Some(existing) = results.iter_mut().find(|g| g.name == group_res.name) else {
+        results.push(group_res);
+        return;
     };
 
-    parsed_tests
-        .into_iter()
-        .map(|g| run_test_group(g, &mut t_state))
-        .collect::<RunnerResult<_>>()
+    for sub_group_res in group_res.sub_group_res {
+        match existing
+            .sub_group_res
+            .iter_mut()
+            .find(|sub_g| sub_g.name == sub_group_res.name)
+        {
+            Some(existing_sub_g) => existing_sub_g.test_res.extend(sub_group_res.test_res),
+            None => existing.sub_group_res.push(sub_group_res),
+        }
+    }
+}
+
+fn init_test_run_state(
+    num_tests: u64,
+    eta: EtaEstimator,
+    persistent_test_state: &mut TestRunEntries,
+    process_aborted: ProcessAbortedRecv,
+    config: TestRunConfig,
+) -> TestRunState {
+    let mut p_indicator =
+        create_progress_indicator(num_tests, config.simple_progress_indicator, config.progress);
+    p_indicator.set_eta(eta.remaining());
+
+    TestRunState {
+        p_indicator,
+        persistent_test_state,
+        process_aborted_recv: process_aborted,
+        witness_only: config.witness_only,
+        order: config.order,
+        max_cpu_log_len: config.max_cpu_log_len,
+        test_timeouts: config.test_timeouts,
+        t8n_bin: config.t8n_bin,
+        repro_dir: config.repro_dir,
+        run_history: config.run_history,
+        auto_blacklist: config.auto_blacklist,
+        eta,
+        prover_setup: Arc::new(
+            ProverSetup::new(
+                config.aggregate_segments,
+                config.max_cpu_log_len,
+                config.circuits_cache.as_deref(),
+            )
+            .with_post_state_check(config.check_post_state)
+            .with_gas_limit_policy(config.gas_limit_policy)
+            .with_proof_archive(config.proof_archive),
+        ),
+        witness_cache: config.witness_cache,
+        isolation: config.isolation.map(Arc::new),
+        fail_fast: config.fail_fast,
+        num_failures: 0,
+        stopped_early: false,
+    }
 }
 
 fn create_progress_indicator(
     num_tests: u64,
     simple_progress_indicator: bool,
+    progress: Option<Arc<Mutex<RunProgress>>>,
 ) -> Box<dyn TestProgressIndicator> {
+    if let Some(progress) = progress {
+        progress.lock().unwrap().num_tests = num_tests;
+        return Box::new(SharedProgressIndicator { progress });
+    }
+
     match simple_progress_indicator {
         false => Box::new({
             FancyProgressIndicator {
                 prog_bar: ProgressBar::new(num_tests).with_style(
                     ProgressStyle::with_template(
-                        "{bar:60.magenta} {pos}/{len} ETA: [{eta_precise}] | Test: {msg}",
+                        "{bar:60.magenta} {pos}/{len} ETA: [{prefix}] | Test: {msg}",
                     )
                     .unwrap(),
                 ),
@@ -188,71 +962,241 @@ fn create_progress_indicator(
         true => Box::new(SimpleProgressIndicator {
             curr_test: 0,
             num_tests,
+            eta: Duration::ZERO,
         }),
     }
 }
 
-fn run_test_group(
-    group: ParsedTestGroup,
-    t_state: &mut TestRunState,
-) -> RunnerResult<TestGroupRunResults> {
-    Ok(TestGroupRunResults {
+fn run_test_group(group: ParsedTestGroup, t_state: &mut TestRunState) -> TestGroupRunResults {
+    let mut sub_group_res = Vec::new();
+    for sub_g in group.sub_groups {
+        sub_group_res.push(run_test_sub_group(sub_g, t_state));
+        if t_state.stopped_early {
+            break;
+        }
+    }
+
+    TestGroupRunResults {
         name: group.name,
-        sub_group_res: group
-            .sub_groups
-            .into_iter()
-            .map(|sub_g| run_test_sub_group(sub_g, t_state))
-            .collect::<RunnerResult<_>>()?,
-    })
+        sub_group_res,
+    }
 }
 
+/// Runs all tests of the sub-group, stopping early if the process is aborted
+/// or if the `--fail-fast` failure limit is reached.
 fn run_test_sub_group(
     sub_group: ParsedTestSubGroup,
     t_state: &mut TestRunState,
-) -> RunnerResult<TestSubGroupRunResults> {
-    Ok(TestSubGroupRunResults {
+) -> TestSubGroupRunResults {
+    let mut test_res = Vec::new();
+    for test in sub_group.tests {
+        let timeout = t_state.test_timeouts.for_test(&sub_group.name, &test.info);
+        match run_test(test, timeout, t_state) {
+            Ok(res) => {
+                let stop = t_state.fail_fast_limit_reached(&res[0].status);
+                test_res.extend(res);
+                if stop {
+                    t_state.stopped_early = true;
+                    break;
+                }
+            }
+            Err(_) => {
+                t_state.stopped_early = true;
+                break;
+            }
+        }
+    }
+
+    TestSubGroupRunResults {
         name: sub_group.name,
-        test_res: sub_group
-            .tests
-            .into_iter()
-            .map(|sub_g| run_test(sub_g, t_state))
-            .collect::<RunnerResult<_>>()?,
-    })
+        test_res,
+    }
 }
 
-fn run_test(test: Test, t_state: &mut TestRunState) -> RunnerResult<TestRunResult> {
+fn run_test(
+    test: Test,
+    timeout: Duration,
+    t_state: &mut TestRunState,
+) -> RunnerResult<Vec<TestRunResult>> {
     t_state
         .p_indicator
         .set_current_test_name(test.name.to_string());
-    let res = run_test_or_fail_on_timeout(test.info, t_state)?;
 
-    t_state
-        .persistent_test_state
-        .update_test_state(&test.name, res.clone().into());
+    let prepared = prepare_test(&test, t_state);
+    let start = Instant::now();
+    let res = run_test_or_fail_on_timeout(test.info, timeout, t_state)?;
+    let duration = start.elapsed();
+
+    Ok(complete_test(prepared, res, duration, t_state))
+}
+
+/// Everything about a test that needs to be kept around while it runs, as
+/// running it consumes its inputs.
+#[derive(Debug)]
+struct PreparedTest {
+    name: String,
+    aliases: Vec<String>,
+    input_path: PathBuf,
+    t8n_status: Option<T8nStatus>,
+    /// A copy of the inputs, in case a repro bundle needs to be written.
+    repro_info: Option<TestVariantRunInfo>,
+}
+
+fn prepare_test(test: &Test, t_state: &TestRunState) -> PreparedTest {
+    if let Some(prev_err) = t_state.persistent_test_state.get_last_error(&test.name) {
+        t_state.p_indicator.println(&format!(
+            "{} previously failed with: {}",
+            test.name, prev_err
+        ));
+    }
+
+    // The t8n run needs to happen first as plonky2 consumes the test inputs.
+    let t8n_status = t_state
+        .t8n_bin
+        .as_ref()
+        .map(|t8n_bin| run_t8n(t8n_bin, &test.info));
+
+    PreparedTest {
+        name: test.name.clone(),
+        aliases: test.aliases.clone(),
+        input_path: test.input_path.clone(),
+        t8n_status,
+        repro_info: t_state.repro_dir.is_some().then(|| test.info.clone()),
+    }
+}
+
+/// Records the result of a test in all the places that track it. The result is
+/// attributed to the aliases of the test as well, whose results follow its own.
+fn complete_test(
+    prepared: PreparedTest,
+    outcome: TestOutcome,
+    duration: Duration,
+    t_state: &mut TestRunState,
+) -> Vec<TestRunResult> {
+    let TestOutcome {
+        status: res,
+        proof_stats,
+        peak_memory,
+        altered_inputs,
+    } = outcome;
+    let PreparedTest {
+        name,
+        aliases,
+        input_path,
+        t8n_status,
+        repro_info,
+    } = prepared;
+
+    if let (Some(repro_dir), Some(info)) = (&t_state.repro_dir, repro_info)
+        && !res.passed()
+    {
+        if let Err(err) = write_repro_bundle(repro_dir, &info, &res) {
+            error!("Unable to write repro bundle for {}: {:#}", name, err);
+        }
+    }
+
+    if let Some(verdict) = t8n_status.as_ref().and_then(|s| s.verdict(&res)) {
+        warn!("{}: {}", name, verdict);
+    }
+
+    for t_name in iter::once(&name).chain(aliases.iter()) {
+        t_state.persistent_test_state.update_test_state(
+            t_name,
+            &res,
+            duration,
+            peak_memory,
+            &altered_inputs,
+        );
+        if let Some(run_history) = &t_state.run_history {
+            if let Err(err) = run_history.record_test_run(t_name, &res, duration) {
+                error!("Unable to record {} in the run history: {:#}", t_name, err);
+            }
+        }
+        if let Some((threshold, blacklist_path)) = &t_state.auto_blacklist {
+            auto_blacklist_if_needed(
+                t_name,
+                *threshold,
+                blacklist_path,
+                t_state.persistent_test_state,
+            );
+        }
+    }
+    t_state.eta.test_completed(&name, duration);
+    t_state.p_indicator.set_eta(t_state.eta.remaining());
     t_state.p_indicator.notify_test_completed();
 
-    Ok(TestRunResult {
-        name: test.name,
+    let res = TestRunResult {
+        name,
         status: res,
-    })
+        duration,
+        t8n_status,
+        input_path,
+        proof_stats,
+        peak_memory,
+        altered_inputs,
+    };
+    let alias_res: Vec<_> = aliases
+        .into_iter()
+        .map(|name| TestRunResult {
+            name,
+            ..res.clone()
+        })
+        .collect();
+
+    iter::once(res).chain(alias_res).collect()
+}
+
+fn auto_blacklist_if_needed(
+    t_name: &str,
+    threshold: u32,
+    blacklist_path: &Path,
+    persistent_test_state: &TestRunEntries,
+) {
+    let num_timeouts = persistent_test_state.get_consecutive_timeouts(t_name);
+    if num_timeouts < threshold {
+        return;
+    }
+
+    let comment = format!(
+        "Auto-blacklisted on {} after {} consecutive timeouts",
+        Utc::now().format("%Y-%m-%d"),
+        num_timeouts
+    );
+    match add_to_blacklist(blacklist_path, vec![t_name.to_string()], Some(&comment)) {
+        Ok(_) => warn!(
+            "{} timed out {} times in a row, blacklisting it",
+            t_name, num_timeouts
+        ),
+        Err(err) => error!("Unable to blacklist {}: {:#}", t_name, err),
+    }
 }
 
 fn run_test_or_fail_on_timeout(
     test: TestVariantRunInfo,
+    test_timeout: Duration,
     t_state: &mut TestRunState,
-) -> RunnerResult<TestStatus> {
+) -> RunnerResult<TestOutcome> {
     block_on(async {
         let proof_gen_fut = async {
-            run_test_and_get_test_result(test, t_state.witness_only, t_state.max_cpu_log_len)
+            match &t_state.isolation {
+                Some(isolation) => run_test_in_subprocess(test, isolation).await,
+                None => run_test_and_get_test_result(
+                    test,
+                    &t_state.prover_setup,
+                    t_state.witness_cache.as_ref(),
+                    t_state.witness_only,
+                    t_state.max_cpu_log_len,
+                ),
+            }
         };
-        let proof_gen_with_timeout_fut = timeout(t_state.test_timeout, proof_gen_fut);
+        let proof_gen_with_timeout_fut = timeout(test_timeout, proof_gen_fut);
         let process_aborted_fut = t_state.process_aborted_recv.recv();
 
         select! {
             res = proof_gen_with_timeout_fut => {
                 match res {
                     Ok(t_res) => Ok(t_res),
-                    Err(_) => Ok(TestStatus::TimedOut),
+                    Err(_) => Ok(TestStatus::TimedOut.into()),
                 }
             },
             // Process was aborted.
@@ -261,75 +1205,445 @@ fn run_test_or_fail_on_timeout(
     })
 }
 
-/// Run a test against `plonky2` and output a result based on what happens.
-fn run_test_and_get_test_result(
+/// Run a test against `plonky2` and output a result based on what happens,
+/// along with how much the resident memory of the process grew while it ran.
+pub(crate) fn run_test_and_get_test_result(
     test: TestVariantRunInfo,
+    prover_setup: &ProverSetup,
+    witness_cache: Option<&WitnessCache>,
     witness_only: bool,
     max_cpu_log_len: Option<usize>,
-) -> TestStatus {
-    let timing = TimingTree::new("prove", log::Level::Debug);
-    let max_cpu_log_len = max_cpu_log_len.unwrap_or(32); // 32 being the default maximum
+) -> TestOutcome {
+    let sampler = PeakRssSampler::start();
+    let outcome = run_blocks(
+        test,
+        prover_setup,
+        witness_cache,
+        witness_only,
+        max_cpu_log_len,
+    );
+    TestOutcome {
+        peak_memory: sampler.stop(),
+        ..outcome
+    }
+}
+
+/// Runs all the blocks of a test.
+///
+/// The blocks of multi-block tests are run in order, each one only once the
+/// previous one passed, since its final roots are checked by the prover.
+fn run_blocks(
+    test: TestVariantRunInfo,
+    prover_setup: &ProverSetup,
+    witness_cache: Option<&WitnessCache>,
+    witness_only: bool,
+    max_cpu_log_len: Option<usize>,
+) -> TestOutcome {
+    let TestVariantRunInfo {
+        variant_name,
+        gen_inputs,
+        final_roots,
+        post_state,
+        txn_outcome,
+        logs_bloom,
+        chained_blocks,
+        ..
+    } = test;
+    let num_blocks = chained_blocks.len() + 1;
+    let first_block = ChainedBlockRunInfo {
+        gen_inputs,
+        final_roots,
+        post_state,
+        txn_outcome,
+        logs_bloom,
+    };
+
+    let mut outcome: Option<TestOutcome> = None;
+    let mut chain = ChainCheckpoint::default();
+    for (block_idx, block) in iter::once(first_block).chain(chained_blocks).enumerate() {
+        let name = match block_idx {
+            0 => variant_name.clone(),
+            _ => format!("{}_block{}", variant_name, block_idx + 1),
+        };
+        let state_root = block.final_roots.state_root_hash;
+        let post_state = prover_setup
+            .check_post_state
+            .then(|| block.post_state.clone());
+        let mut block_outcome = run_block(
+            &name,
+            block,
+            &mut chain,
+            prover_setup,
+            witness_cache,
+            witness_only,
+            max_cpu_log_len,
+        );
+        let proven = block_outcome.status.passed() || block_outcome.status.clamped();
+        if proven
+            && let Some(post_state) = post_state
+            && let Err(err) = check_post_state_root(&post_state, state_root)
+        {
+            block_outcome = TestStatus::EvmErr(format!("{:#}", err)).into();
+        }
+        if !(block_outcome.status.passed() || block_outcome.status.clamped()) {
+            // The inputs of the previous blocks may have been altered too.
+            let altered_inputs = outcome.map(|outcome| outcome.altered_inputs);
+            let block_outcome =
+                block_outcome.with_altered_inputs(altered_inputs.unwrap_or_default());
+            return match num_blocks {
+                1 => block_outcome,
+                _ => block_outcome.in_block(block_idx + 1),
+            };
+        }
+
+        outcome = Some(match outcome {
+            Some(outcome) => outcome.followed_by(block_outcome),
+            None => block_outcome,
+        });
+    }
+
+    outcome.expect("Tests have at least one block")
+}
+
+/// What a block is expected to end with, against which the tries output by the
+/// zkEVM when it fails are compared.
+struct ExpectedBlockEnd<'a> {
+    roots: TrieRoots,
+    /// Only given with `--check-post-state`.
+    post_state: Option<&'a HashMap<Address, AccountState>>,
+    txn_outcome: Option<TxnOutcome>,
+    /// The logs bloom computed with `revm`, which the bloom of the proven
+    /// block is checked against.
+    logs_bloom: Option<[U256; 8]>,
+}
+
+impl ExpectedBlockEnd<'_> {
+    /// Checks the logs bloom of the block proven with `public_values` against
+    /// the one computed with `revm`.
+    fn check_logs_bloom(
+        &self,
+        public_values: &PublicValues<GoldilocksField>,
+    ) -> anyhow::Result<()> {
+        let proven_bloom = &public_values.block_metadata.block_bloom;
+        match self.logs_bloom {
+            Some(bloom) if &bloom != proven_bloom => {
+                warn!(
+                    "The logs bloom of the proof is {:x?}, but revm computes {:x?}",
+                    proven_bloom, bloom
+                );
+                Err(anyhow::anyhow!(
+                    "The logs bloom of the proof differs from the one computed with revm"
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Runs a single block. `name` identifies its witness in the cache, and its
+/// proof is checked to follow the ones of the previous blocks in `chain`. With
+/// `--check-post-state`, the accounts of a block that fails with another state
+/// root are compared with its post-state.
+fn run_block(
+    name: &str,
+    block: ChainedBlockRunInfo,
+    chain: &mut ChainCheckpoint,
+    prover_setup: &ProverSetup,
+    witness_cache: Option<&WitnessCache>,
+    witness_only: bool,
+    max_cpu_log_len: Option<usize>,
+) -> TestOutcome {
+    let ChainedBlockRunInfo {
+        gen_inputs,
+        post_state,
+        txn_outcome,
+        logs_bloom,
+        ..
+    } = block;
+    let max_cpu_log_len = max_cpu_log_len.unwrap_or(DEFAULT_MAX_CPU_LOG_LEN);
+    let expected = ExpectedBlockEnd {
+        roots: gen_inputs.trie_roots_after.clone(),
+        post_state: prover_setup.check_post_state.then_some(post_state.as_ref()),
+        txn_outcome,
+        logs_bloom,
+    };
 
     match witness_only {
         true => {
-            let res = simulate_execution_all_segments::<GoldilocksField>(
-                test.gen_inputs,
-                max_cpu_log_len,
-            );
+            let res = match witness_cache {
+                Some(cache) => generate_witness(&gen_inputs, max_cpu_log_len).map(|witness| {
+                    if let Err(err) = cache.store(name, &gen_inputs, max_cpu_log_len, witness) {
+                        warn!("Unable to cache the witness: {:#}", err);
+                    }
+                }),
+                None => simulate_execution_all_segments::<GoldilocksField>(
+                    Arc::unwrap_or_clone(gen_inputs),
+                    max_cpu_log_len,
+                )
+                .map_err(anyhow::Error::from),
+            };
 
             if let Err(evm_err) = res {
-                return handle_evm_err(evm_err.into(), false, "witness generation");
+                return handle_evm_err(evm_err, &expected, false, "witness generation").into();
             }
 
-            return TestStatus::PassedWitness;
+            TestStatus::PassedWitness.into()
         }
         false => {
             // plonky2 zkEVM verifier does not support a block gaslimit that does not fit
             // in a u32.
-            // If a test has such issue, we "try" proving it with an altered gaslimit, and
-            // will ignore it if proving the altered inputs failed so as to not
+            // By default, we "try" proving such blocks with an altered gaslimit, and
+            // ignore them if proving the altered inputs failed so as to not
             // have false positives.
-            let mut inputs = test.gen_inputs;
-            let is_gaslimit_changed =
-                TryInto::<u32>::try_into(inputs.block_metadata.block_gaslimit).is_err();
+            let mut inputs = Arc::unwrap_or_clone(gen_inputs);
+            let gas_limit = inputs.block_metadata.block_gaslimit;
+            let is_gaslimit_changed = TryInto::<u32>::try_into(gas_limit).is_err();
 
             if is_gaslimit_changed {
-                inputs.block_metadata.block_gaslimit = U256::from(u32::MAX);
+                match prover_setup.gas_limit_policy {
+                    GasLimitPolicy::Clamp => {
+                        inputs.block_metadata.block_gaslimit = U256::from(u32::MAX)
+                    }
+                    GasLimitPolicy::Skip => return TestStatus::Ignored.into(),
+                    GasLimitPolicy::Fail => {
+                        return TestStatus::EvmErr(format!(
+                            "The block gas limit {} doesn't fit in 32 bits",
+                            gas_limit
+                        ))
+                        .into()
+                    }
+                }
             }
 
-            let proof_run_res = prove_all_segments::<GoldilocksField, KeccakGoldilocksConfig, 2>(
-                &AllStark::default(),
-                &StarkConfig::standard_fast_config(),
-                inputs,
-                max_cpu_log_len,
-                &mut TimingTree::default(),
-                None,
-            );
-
-            timing.filter(Duration::from_millis(100)).print();
-
-            let proof_run_output = match proof_run_res {
-                Ok(v) => v,
-                Err(evm_err) => return handle_evm_err(evm_err, is_gaslimit_changed, "Proving"),
+            let outcome = match &prover_setup.continuations {
+                Some(continuations) => prove_and_verify_continuation(
+                    continuations,
+                    chain,
+                    prover_setup,
+                    inputs,
+                    &expected,
+                    max_cpu_log_len,
+                    is_gaslimit_changed,
+                ),
+                None => {
+                    // A cached witness was generated from the unaltered inputs.
+                    let cached_witness = witness_cache
+                        .filter(|_| !is_gaslimit_changed)
+                        .and_then(|cache| cache.load(name, &inputs, max_cpu_log_len));
+                    prove_and_verify(
+                        name,
+                        chain,
+                        prover_setup,
+                        inputs,
+                        cached_witness,
+                        &expected,
+                        max_cpu_log_len,
+                        is_gaslimit_changed,
+                    )
+                }
             };
 
-            let verif_output = verify_all_proofs(
-                &AllStark::default(),
-                &proof_run_output,
-                &StarkConfig::standard_fast_config(),
-            );
-            if verif_output.is_err() {
-                warn!("Verification failed with error: {:?}", verif_output);
-                return TestStatus::EvmErr("Proof verification failed.".to_string());
+            match is_gaslimit_changed {
+                true => outcome.with_altered_inputs(vec![InputAlteration::ClampedGasLimit]),
+                false => outcome,
             }
         }
     }
+}
+
+/// Proves a block, from its cached witness if any, with the hasher of the
+/// config of `prover_setup` and verifies its proof. Keccak proofs are then
+/// archived as the one of `name` if there is a proof archive.
+#[allow(clippy::too_many_arguments)]
+fn prove_and_verify(
+    name: &str,
+    chain: &mut ChainCheckpoint,
+    prover_setup: &ProverSetup,
+    inputs: GenerationInputs<GoldilocksField>,
+    cached_witness: Option<Witness>,
+    expected: &ExpectedBlockEnd,
+    max_cpu_log_len: usize,
+    is_gaslimit_changed: bool,
+) -> TestOutcome {
+    let res = match prover_setup.proving_config.hasher {
+        ConfigHasher::Keccak => prove_and_verify_with::<KeccakGoldilocksConfig>(
+            chain,
+            prover_setup,
+            inputs,
+            cached_witness,
+            expected,
+            max_cpu_log_len,
+            is_gaslimit_changed,
+        )
+        .map(|(proofs, proof_stats)| {
+            if let Some(archive) = &prover_setup.proof_archive
+                && let Err(err) = archive.store(name, &proofs)
+            {
+                warn!("Unable to archive the proof: {:#}", err);
+            }
+            proof_stats
+        }),
+        ConfigHasher::Poseidon => prove_and_verify_with::<PoseidonGoldilocksConfig>(
+            chain,
+            prover_setup,
+            inputs,
+            cached_witness,
+            expected,
+            max_cpu_log_len,
+            is_gaslimit_changed,
+        )
+        .map(|(_, proof_stats)| proof_stats),
+    };
+
+    match res {
+        Ok(proof_stats) => TestOutcome {
+            status: TestStatus::proven(is_gaslimit_changed),
+            proof_stats: Some(proof_stats),
+            peak_memory: None,
+            altered_inputs: Vec::new(),
+        },
+        Err(status) => status.into(),
+    }
+}
+
+/// Proves and verifies a block with the config `C`, returning its segment
+/// proofs, or the status of the test if that failed.
+fn prove_and_verify_with<C: GenericConfig<2, F = GoldilocksField>>(
+    chain: &mut ChainCheckpoint,
+    prover_setup: &ProverSetup,
+    inputs: GenerationInputs<GoldilocksField>,
+    cached_witness: Option<Witness>,
+    expected: &ExpectedBlockEnd,
+    max_cpu_log_len: usize,
+    is_gaslimit_changed: bool,
+) -> Result<(Vec<AllProof<GoldilocksField, C, 2>>, ProofStats), TestStatus> {
+    let timing = TimingTree::new("prove", log::Level::Debug);
+    let proof_run_res = match cached_witness {
+        Some((trimmed_inputs, segments)) => segments
+            .into_iter()
+            .map(|mut segment_data| {
+                prove::<GoldilocksField, C, 2>(
+                    &prover_setup.all_stark,
+                    &prover_setup.config,
+                    trimmed_inputs.clone(),
+                    &mut segment_data,
+                    &mut TimingTree::default(),
+                    None,
+                )
+            })
+            .collect(),
+        None => prove_all_segments::<GoldilocksField, C, 2>(
+            &prover_setup.all_stark,
+            &prover_setup.config,
+            inputs,
+            max_cpu_log_len,
+            &mut TimingTree::default(),
+            None,
+        ),
+    };
+
+    timing.filter(Duration::from_millis(100)).print();
+
+    let proof_run_output = match proof_run_res {
+        Ok(v) => v,
+        Err(evm_err) => {
+            return Err(handle_evm_err(
+                evm_err,
+                expected,
+                is_gaslimit_changed,
+                "Proving",
+            ))
+        }
+    };
+
+    let verify_start = Instant::now();
+    let verif_output = verify_all_proofs(
+        &prover_setup.all_stark,
+        &proof_run_output,
+        &prover_setup.config,
+    );
+    let verify_time = verify_start.elapsed();
+    if verif_output.is_err() {
+        warn!("Verification failed with error: {:?}", verif_output);
+        return Err(TestStatus::EvmErr("Proof verification failed.".to_string()));
+    }
+    if let (Some(first), Some(last)) = (proof_run_output.first(), proof_run_output.last())
+        && let Err(err) = chain
+            .link(&first.public_values, &last.public_values)
+            .and_then(|_| expected.check_logs_bloom(&last.public_values))
+    {
+        return Err(TestStatus::EvmErr(format!("{:#}", err)));
+    }
+
+    let proof_stats = ProofStats {
+        size: serialized_proof_size(&proof_run_output),
+        verify_time,
+    };
+    Ok((proof_run_output, proof_stats))
+}
+
+/// Returns the size of the STARK proofs of the segments, encoded like in the
+/// proof archive (the Keccak hashes of the proofs can't be serialized with
+/// serde), and of their serialized public values.
+fn serialized_proof_size<C: GenericConfig<2, F = GoldilocksField>>(
+    proofs: &[AllProof<GoldilocksField, C, 2>],
+) -> usize {
+    let public_values_size: usize = proofs
+        .iter()
+        .map(|p| serde_cbor::to_vec(&p.public_values).map_or(0, |bytes| bytes.len()))
+        .sum();
+
+    encode_segments(proofs).len() + public_values_size
+}
+
+fn prove_and_verify_continuation(
+    continuations: &Continuations,
+    chain: &mut ChainCheckpoint,
+    prover_setup: &ProverSetup,
+    inputs: GenerationInputs<GoldilocksField>,
+    expected: &ExpectedBlockEnd,
+    max_segment_log_len: usize,
+    is_gaslimit_changed: bool,
+) -> TestOutcome {
+    let aggregated = match continuations.prove(
+        &prover_setup.all_stark,
+        &prover_setup.config,
+        inputs,
+        max_segment_log_len,
+    ) {
+        Ok(v) => v,
+        Err(evm_err) => {
+            return handle_evm_err(evm_err, expected, is_gaslimit_changed, "Proving").into()
+        }
+    };
+
+    let verify_start = Instant::now();
+    if let Err(err) = continuations.verify(&aggregated) {
+        warn!("Verification failed with error: {:?}", err);
+        return TestStatus::EvmErr("Aggregated proof verification failed.".to_string()).into();
+    }
+    let public_values = &aggregated.proof_with_pvs.public_values;
+    if let Err(err) = chain
+        .link(public_values, public_values)
+        .and_then(|_| expected.check_logs_bloom(public_values))
+    {
+        return TestStatus::EvmErr(format!("{:#}", err)).into();
+    }
 
-    TestStatus::PassedProof
+    TestOutcome {
+        status: TestStatus::proven(is_gaslimit_changed),
+        proof_stats: Some(ProofStats {
+            size: aggregated.proof_with_pvs.intern.to_bytes().len(),
+            verify_time: verify_start.elapsed(),
+        }),
+        peak_memory: None,
+        altered_inputs: Vec::new(),
+    }
 }
 
 fn handle_evm_err(
     evm_err: anyhow::Error,
+    expected: &ExpectedBlockEnd,
     is_gaslimit_changed: bool,
     gen_type: &'static str,
 ) -> TestStatus {
@@ -340,5 +1654,144 @@ fn handle_evm_err(
 
     // The prover failed with unmodified inputs, so this is an actual error.
     warn!("{} failed with error: {:?}", gen_type, evm_err);
-    TestStatus::EvmErr(evm_err.to_string())
+    let Some(breakdown) = root_mismatch_breakdown(&evm_err, &expected.roots) else {
+        return TestStatus::EvmErr(evm_err.to_string());
+    };
+
+    let mut status = format!("{} [roots: {}]", evm_err, breakdown);
+    let Some(tries) = debug_tries(&evm_err) else {
+        return TestStatus::EvmErr(status);
+    };
+
+    if let Some(txn_outcome) = &expected.txn_outcome
+        && let Some(mismatch) = receipt_mismatch(&tries.receipt_trie, txn_outcome)
+    {
+        warn!("{}", mismatch);
+        status.push_str(&format!(" [receipt: {}]", mismatch));
+    }
+
+    let mismatches = match expected.post_state {
+        Some(post_state) if !post_state.is_empty() => {
+            account_mismatches(&tries.state_trie, post_state)
+        }
+        _ => Vec::new(),
+    };
+    for mismatch in mismatches.iter() {
+        warn!("{}", mismatch);
+    }
+    if !mismatches.is_empty() {
+        let num_reported = mismatches.len().min(MAX_REPORTED_ACCOUNT_MISMATCHES);
+        status.push_str(&format!(
+            " [accounts: {}",
+            mismatches[..num_reported].join("; ")
+        ));
+        if mismatches.len() > num_reported {
+            status.push_str(&format!("; and {} more", mismatches.len() - num_reported));
+        }
+        status.push(']');
+    }
+
+    TestStatus::EvmErr(status)
+}
+
+/// The tries the zkEVM ended up with, if it failed to generate a segment.
+fn debug_tries(evm_err: &anyhow::Error) -> Option<&DebugOutputTries> {
+    evm_err
+        .chain()
+        .find_map(|err| err.downcast_ref::<SegmentErrorWithTries>())?
+        .tries
+        .as_ref()
+}
+
+/// The error of the zkEVM when it fails to generate a segment, which holds the
+/// tries it ended up with. Its type isn't exported, but is the error type of
+/// [`evm_arithmetization::AllData`].
+type SegmentErrorWithTries = <evm_arithmetization::AllData as ResultError>::Error;
+
+trait ResultError {
+    type Error;
+}
+
+impl<T, E> ResultError for Result<T, E> {
+    type Error = E;
+}
+
+/// Compares the roots of the tries the zkEVM ended up with against the
+/// expected ones (eg. `state ✗, transactions ✓, receipts ✓`), if it failed
+/// because some of them differ.
+fn root_mismatch_breakdown(evm_err: &anyhow::Error, expected: &TrieRoots) -> Option<String> {
+    let tries = debug_tries(evm_err)?;
+
+    let roots = [
+        ("state", expected.state_root, tries.state_trie.hash()),
+        (
+            "transactions",
+            expected.transactions_root,
+            tries.transaction_trie.hash(),
+        ),
+        (
+            "receipts",
+            expected.receipts_root,
+            tries.receipt_trie.hash(),
+        ),
+    ];
+    if roots.iter().all(|(_, expected, actual)| expected == actual) {
+        return None;
+    }
+
+    for (name, expected, actual) in roots.iter().filter(|(_, e, a)| e != a) {
+        warn!(
+            "The {} root is {:?}, but {:?} was expected",
+            name, actual, expected
+        );
+    }
+    Some(
+        roots
+            .iter()
+            .map(|(name, expected, actual)| {
+                format!("{} {}", name, if expected == actual { "✓" } else { "✗" })
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use super::TestTimeouts;
+
+    #[test]
+    fn timeouts_scale_with_gas_used() {
+        let timeouts = TestTimeouts {
+            default: Some(Duration::from_secs(60)),
+            by_sub_group: HashMap::from([("stSlow".to_string(), Duration::from_secs(600))]),
+            per_mgas: Some(Duration::from_secs(10)),
+        };
+
+        assert_eq!(
+            timeouts.for_gas_used("stExample", 0),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            timeouts.for_gas_used("stExample", 3_000_000),
+            Duration::from_secs(90)
+        );
+        assert_eq!(
+            timeouts.for_gas_used("stSlow", 3_000_000),
+            Duration::from_secs(600)
+        );
+    }
+
+    #[test]
+    fn timeouts_are_not_scaled_without_a_default() {
+        let timeouts = TestTimeouts {
+            per_mgas: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+
+        assert_eq!(timeouts.for_gas_used("stExample", 0), Duration::MAX);
+        assert_eq!(timeouts.for_gas_used("stExample", 21_000), Duration::MAX);
+    }
 }
@@ -0,0 +1,166 @@
+//! Proving tests as continuations, the way blocks are proven in production.
+//!
+//! Instead of only checking the STARK proof of each segment, every segment is
+//! proven recursively and the segment proofs are then aggregated into a single
+//! proof, which is what gets verified. As in production, the recursive
+//! circuits use Poseidon, since Keccak isn't recursion friendly.
+//!
+//! Building the recursive circuits takes several minutes, so they can be kept
+//! in a cache directory, keyed by the zkEVM version and the degree ranges they
+//! support, and loaded by the following runs.
+
+use std::{
+    fs,
+    marker::PhantomData,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use common::config::EVM_ARITHMETIZATION_VERSION;
+use evm_arithmetization::{
+    fixed_recursive_verifier::{AllRecursiveCircuits, ProverOutputData, RecursionConfig},
+    generation::GenerationInputs,
+    AllStark, StarkConfig, NUM_TABLES,
+};
+use log::{info, warn};
+use plonky2::{
+    field::goldilocks_field::GoldilocksField,
+    plonk::config::PoseidonGoldilocksConfig,
+    util::{
+        serialization::{DefaultGateSerializer, DefaultGeneratorSerializer},
+        timing::TimingTree,
+    },
+};
+
+type SegmentProof = ProverOutputData<GoldilocksField, PoseidonGoldilocksConfig, 2>;
+type RecursiveCircuits = AllRecursiveCircuits<GoldilocksField, PoseidonGoldilocksConfig, 2>;
+
+/// The `degree_bits` ranges supported by the recursive circuits of each table
+/// (in `Table` order), except for the CPU table which is bounded by the max
+/// segment length. Segments with larger tables fail to prove.
+const DEGREE_BITS_RANGES: [Range<usize>; NUM_TABLES] = [
+    16..23, // Arithmetic
+    9..21,  // BytePacking
+    12..25, // Cpu
+    14..20, // Keccak
+    9..15,  // KeccakSponge
+    12..18, // Logic
+    17..28, // Memory
+    7..23,  // MemBefore
+    7..23,  // MemAfter
+];
+const CPU_TABLE_IDX: usize = 2;
+
+pub(super) struct Continuations {
+    circuits: RecursiveCircuits,
+}
+
+impl Continuations {
+    /// Builds the recursive circuits, which is slow (several minutes) and is
+    /// hence only done once per run, unless they are found in `cache_dir`.
+    pub(super) fn new(
+        all_stark: &AllStark<GoldilocksField, 2>,
+        max_segment_log_len: usize,
+        cache_dir: Option<&Path>,
+    ) -> Self {
+        let mut degree_bits_ranges = DEGREE_BITS_RANGES;
+        let cpu_range = &mut degree_bits_ranges[CPU_TABLE_IDX];
+        cpu_range.end = cpu_range.end.min(max_segment_log_len + 1);
+        cpu_range.start = cpu_range.start.min(max_segment_log_len);
+
+        let cache_path = cache_dir.map(|dir| cached_circuits_path(dir, &degree_bits_ranges));
+        if let Some(path) = cache_path.as_deref().filter(|path| path.exists()) {
+            info!("Loading the recursive circuits from {:?}...", path);
+            match load_circuits(path) {
+                Ok(circuits) => return Self { circuits },
+                Err(err) => warn!("Unable to load the cached recursive circuits: {:#}", err),
+            }
+        }
+
+        info!("Building the recursive circuits...");
+        let circuits =
+            AllRecursiveCircuits::new(all_stark, &degree_bits_ranges, RecursionConfig::default());
+        if let Some(path) = cache_path {
+            if let Err(err) = store_circuits(&circuits, &path) {
+                warn!("Unable to cache the recursive circuits: {:#}", err);
+            }
+        }
+
+        Self { circuits }
+    }
+
+    /// Proves every segment and aggregates them into a single proof.
+    pub(super) fn prove(
+        &self,
+        all_stark: &AllStark<GoldilocksField, 2>,
+        config: &StarkConfig,
+        inputs: GenerationInputs<GoldilocksField>,
+        max_segment_log_len: usize,
+    ) -> anyhow::Result<SegmentProof> {
+        let mut segment_proofs = self
+            .circuits
+            .prove_all_segments(
+                all_stark,
+                config,
+                inputs,
+                max_segment_log_len,
+                &mut TimingTree::default(),
+                None,
+            )?
+            .into_iter();
+
+        // There are always at least two segment proofs, as a dummy one is added if
+        // needed.
+        let first = segment_proofs.next().expect("At least one segment proof");
+        segment_proofs.try_fold(first, |agg, segment| {
+            self.circuits.prove_segment_aggregation(&agg, &segment)
+        })
+    }
+
+    pub(super) fn verify(&self, aggregated: &SegmentProof) -> anyhow::Result<()> {
+        self.circuits
+            .verify_segment_aggregation(&aggregated.proof_with_pvs.intern)
+    }
+}
+
+fn cached_circuits_path(cache_dir: &Path, degree_bits_ranges: &[Range<usize>]) -> PathBuf {
+    let ranges: Vec<_> = degree_bits_ranges
+        .iter()
+        .map(|range| format!("{}-{}", range.start, range.end))
+        .collect();
+    cache_dir.join(format!(
+        "recursive_circuits_{}_{}.bin",
+        EVM_ARITHMETIZATION_VERSION,
+        ranges.join("_")
+    ))
+}
+
+fn load_circuits(path: &Path) -> anyhow::Result<RecursiveCircuits> {
+    let bytes = fs::read(path).with_context(|| format!("Reading {:?}", path))?;
+    RecursiveCircuits::from_bytes(
+        &bytes,
+        false,
+        &DefaultGateSerializer,
+        &DefaultGeneratorSerializer::<PoseidonGoldilocksConfig, 2> {
+            _phantom: PhantomData,
+        },
+    )
+    .map_err(|err| anyhow!("Deserializing {:?}: {:?}", path, err))
+}
+
+fn store_circuits(circuits: &RecursiveCircuits, path: &Path) -> anyhow::Result<()> {
+    let bytes = circuits
+        .to_bytes(
+            false,
+            &DefaultGateSerializer,
+            &DefaultGeneratorSerializer::<PoseidonGoldilocksConfig, 2> {
+                _phantom: PhantomData,
+            },
+        )
+        .map_err(|err| anyhow!("Serializing the recursive circuits: {:?}", err))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Creating {:?}", dir))?;
+    }
+    fs::write(path, bytes).with_context(|| format!("Writing {:?}", path))
+}
@@ -0,0 +1,254 @@
+//! Runs tests on remote workers (see [`crate::distributed`]).
+//!
+//! Each connected worker is handled by its own thread, which forwards the
+//! tests it is given by the calling thread to the worker and reports back the
+//! outcomes. As in [`super::scheduler`], all the bookkeeping stays on the
+//! calling thread, including timeouts: a test that times out is recorded right
+//! away, while its worker only gets another test once it is done with it.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+
+use super::{
+    complete_test, prepare_test,
+    scheduler::{empty_results, into_group_results, ordered_tests, record_result, test_timeout},
+    PreparedTest, TestGroupRunResults, TestOutcome, TestRunState, TestStatus,
+};
+use crate::{
+    distributed::{
+        check_worker_hello, read_msg, write_msg, CoordinatorMsg, WorkerHello, WorkerOutcome,
+        WorkerSettings,
+    },
+    test_dir_reading::{ParsedTestGroup, Test},
+};
+
+/// How often timeouts and abort signals are checked while tests are running.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long a connecting worker has to say hello, so that a peer that doesn't
+/// can't hold up the other workers.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+type WorkerId = usize;
+
+#[derive(Debug)]
+enum WorkerEvent {
+    /// A worker connected, and can be sent tests through the given channel
+    /// (`None` meaning that there are none left).
+    Connected(WorkerId, SocketAddr, Sender<Option<Test>>),
+    Finished(WorkerId, TestOutcome),
+    /// A worker disconnected, giving back the test it was running, if any.
+    Lost(WorkerId, Option<Test>),
+}
+
+#[derive(Debug)]
+struct RunningTest {
+    pos: (usize, usize, usize),
+    name: String,
+    /// `None` once the test has timed out.
+    prepared: Option<PreparedTest>,
+    start: Instant,
+    timeout: Duration,
+}
+
+pub(super) fn run_tests_on_workers(
+    parsed_tests: Vec<ParsedTestGroup>,
+    t_state: &mut TestRunState,
+    listener: TcpListener,
+    settings: WorkerSettings,
+) -> Vec<TestGroupRunResults> {
+    let mut results = empty_results(&parsed_tests);
+    let mut queue: VecDeque<_> = ordered_tests(parsed_tests, t_state).into();
+
+    if let Ok(addr) = listener.local_addr() {
+        t_state
+            .p_indicator
+            .println(&format!("Waiting for workers on {}...", addr));
+    }
+    let (send, events) = mpsc::channel();
+    thread::spawn(move || accept_workers(listener, settings, send));
+
+    let mut workers: HashMap<WorkerId, Sender<Option<Test>>> = HashMap::new();
+    let mut idle: Vec<WorkerId> = Vec::new();
+    let mut running: HashMap<WorkerId, RunningTest> = HashMap::new();
+    let mut running_names = String::new();
+
+    loop {
+        while !t_state.stopped_early && !queue.is_empty() {
+            let Some(worker) = idle.pop() else {
+                break;
+            };
+            let (pos, test) = queue.pop_front().unwrap();
+            let prepared = prepare_test(&test, t_state);
+            running.insert(
+                worker,
+                RunningTest {
+                    pos,
+                    name: test.name.clone(),
+                    prepared: Some(prepared),
+                    start: Instant::now(),
+                    timeout: test_timeout(&results, pos, &test, t_state),
+                },
+            );
+            // A worker that is gone gives its test back through `Lost`.
+            let _ = workers[&worker].send(Some(test));
+        }
+
+        if t_state.stopped_early || (running.is_empty() && queue.is_empty()) {
+            break;
+        }
+
+        // Runs can wait on workers for a long time, so the running tests are
+        // only reported when they change.
+        let mut names: Vec<_> = running
+            .values()
+            .filter(|r| r.prepared.is_some())
+            .map(|r| r.name.as_str())
+            .collect();
+        names.sort_unstable();
+        let names = names.join(", ");
+        if names != running_names {
+            t_state.p_indicator.set_current_test_name(names.clone());
+            running_names = names;
+        }
+
+        match events.recv_timeout(POLL_INTERVAL) {
+            Ok(WorkerEvent::Connected(worker, worker_addr, send)) => {
+                t_state
+                    .p_indicator
+                    .println(&format!("Worker {} connected.", worker_addr));
+                workers.insert(worker, send);
+                idle.push(worker);
+            }
+            Ok(WorkerEvent::Finished(worker, outcome)) => {
+                let r = running.remove(&worker).unwrap();
+                idle.push(worker);
+
+                // Timed out tests have already been recorded.
+                if let Some(prepared) = r.prepared {
+                    let res = complete_test(prepared, outcome, r.start.elapsed(), t_state);
+                    record_result(&mut results, r.pos, res, t_state);
+                }
+            }
+            Ok(WorkerEvent::Lost(worker, test)) => {
+                workers.remove(&worker);
+                idle.retain(|w| *w != worker);
+                if let (Some(r), Some(test)) = (running.remove(&worker), test)
+                    && r.prepared.is_some()
+                {
+                    warn!("Worker lost while running {}, requeuing it", r.name);
+                    queue.push_front((r.pos, test));
+                }
+            }
+            Err(_) => (),
+        }
+
+        for r in running.values_mut() {
+            let duration = r.start.elapsed();
+            if duration > r.timeout
+                && let Some(prepared) = r.prepared.take()
+            {
+                let res = complete_test(prepared, TestStatus::TimedOut.into(), duration, t_state);
+                record_result(&mut results, r.pos, res, t_state);
+            }
+        }
+
+        if t_state.process_aborted_recv.try_recv().is_ok() {
+            t_state.stopped_early = true;
+        }
+    }
+
+    for send in workers.values() {
+        let _ = send.send(None);
+    }
+
+    into_group_results(results)
+}
+
+fn accept_workers(listener: TcpListener, settings: WorkerSettings, events: Sender<WorkerEvent>) {
+    for (worker, stream) in listener.incoming().enumerate() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("Unable to accept a worker: {}", err);
+                continue;
+            }
+        };
+
+        if let Err(err) = greet_worker(&mut stream, &settings) {
+            warn!("Unable to set up a worker: {:#}", err);
+            continue;
+        }
+        let (send, tests) = mpsc::channel();
+        let Ok(worker_addr) = stream.peer_addr() else {
+            continue;
+        };
+        if events
+            .send(WorkerEvent::Connected(worker, worker_addr, send))
+            .is_err()
+        {
+            // The run is over.
+            return;
+        }
+
+        let events = events.clone();
+        thread::spawn(move || {
+            let lost = serve_worker(worker, &mut stream, tests, &events);
+            let _ = events.send(WorkerEvent::Lost(worker, lost));
+            let _ = write_msg(&mut stream, &CoordinatorMsg::Done);
+        });
+    }
+}
+
+/// Checks the hello message of a worker before sending it the settings of the
+/// run, or the reason why it is turned away.
+fn greet_worker(stream: &mut TcpStream, settings: &WorkerSettings) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+    let hello = read_msg::<WorkerHello>(stream)?;
+    stream.set_read_timeout(None)?;
+
+    if let Err(reason) = check_worker_hello(&hello) {
+        let _ = write_msg(stream, &CoordinatorMsg::Rejected(reason.clone()));
+        return Err(anyhow::Error::msg(reason));
+    }
+    write_msg(stream, &CoordinatorMsg::Settings(settings.clone()))
+}
+
+/// Forwards tests to a worker until there are none left or the worker is
+/// lost, in which case the test it was running (if any) is returned.
+fn serve_worker(
+    worker: WorkerId,
+    stream: &mut TcpStream,
+    tests: Receiver<Option<Test>>,
+    events: &Sender<WorkerEvent>,
+) -> Option<Test> {
+    // The sender is dropped once the run is over.
+    while let Ok(Some(test)) = tests.recv() {
+        let msg = CoordinatorMsg::Test(Box::new(test.info.clone()));
+        let outcome = write_msg(stream, &msg).and_then(|_| read_msg::<WorkerOutcome>(stream));
+
+        match outcome {
+            Ok(outcome) => {
+                if events
+                    .send(WorkerEvent::Finished(worker, outcome.into()))
+                    .is_err()
+                {
+                    return None;
+                }
+            }
+            Err(err) => {
+                info!("Lost worker {}: {:#}", worker, err);
+                return Some(test);
+            }
+        }
+    }
+
+    None
+}
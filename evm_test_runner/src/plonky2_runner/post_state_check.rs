@@ -0,0 +1,133 @@
+//! Checks of the accounts the zkEVM ends a block with against the post-state
+//! recorded in the manifest (see `--check-post-state`).
+//!
+//! A block that passes ended with the expected state root, so its accounts are
+//! those of the post-state exactly when the post-state hashes to that root.
+//! When a block fails with another state root, the state trie the zkEVM ended
+//! up with is compared with the post-state account by account, to point at
+//! the fields it got wrong.
+
+use std::collections::HashMap;
+
+use anyhow::{ensure, Result};
+use common::types::AccountState;
+use eth_test_parser::build_state_tries;
+use ethereum_types::{Address, H256, U256};
+use keccak_hash::keccak;
+use mpt_trie::{
+    nibbles::Nibbles,
+    partial_trie::{HashedPartialTrie, PartialTrie},
+    trie_ops::ValOrHash,
+};
+use rlp::Rlp;
+
+/// Checks that `post_state` hashes to the state root a passing block ended
+/// with. Manifests that don't record the post-state aren't checked.
+pub(super) fn check_post_state_root(
+    post_state: &HashMap<Address, AccountState>,
+    state_root: H256,
+) -> Result<()> {
+    if post_state.is_empty() {
+        return Ok(());
+    }
+
+    let post_state_root = build_state_tries(post_state).0.hash();
+    ensure!(
+        post_state_root == state_root,
+        "The post-state of the manifest hashes to {:?}, but the block ended with the state root {:?}",
+        post_state_root,
+        state_root
+    );
+    Ok(())
+}
+
+/// Returns a description of each account of `state_trie`, as output by the
+/// zkEVM, that differs from `post_state`.
+pub(super) fn account_mismatches(
+    state_trie: &HashedPartialTrie,
+    post_state: &HashMap<Address, AccountState>,
+) -> Vec<String> {
+    let expected_trie = build_state_tries(post_state).0;
+
+    let mut addresses: Vec<_> = post_state.keys().collect();
+    addresses.sort();
+    let mut mismatches = Vec::new();
+    for address in addresses {
+        let key = Nibbles::from_h256_be(H256(keccak(address).0));
+        let expected = expected_trie.get(key).map(decode_account);
+        match (expected, state_trie.get(key).map(decode_account)) {
+            (Some(Ok(expected)), Some(Ok(actual))) => mismatches.extend(
+                actual
+                    .mismatches(&expected)
+                    .map(|(field, actual, expected)| {
+                        format!(
+                            "{:?}: the {} is {}, but {} was expected",
+                            address, field, actual, expected
+                        )
+                    }),
+            ),
+            (_, None) => mismatches.push(format!("{:?} is missing", address)),
+            (_, Some(Err(err))) | (Some(Err(err)), _) => {
+                mismatches.push(format!("{:?} couldn't be decoded: {}", address, err))
+            }
+            (None, _) => {}
+        }
+    }
+
+    // The zkEVM may also end up with accounts that should have been deleted.
+    for (key, value) in state_trie.items() {
+        if matches!(value, ValOrHash::Val(_)) && expected_trie.get(key).is_none() {
+            mismatches.push(format!(
+                "The account with hashed address {:x} shouldn't exist",
+                key
+            ));
+        }
+    }
+
+    mismatches
+}
+
+/// The fields of an account as stored in the state trie.
+#[derive(Debug)]
+struct AccountLeaf {
+    nonce: U256,
+    balance: U256,
+    storage_root: H256,
+    code_hash: H256,
+}
+
+impl AccountLeaf {
+    /// The fields that differ from `expected`, with both values.
+    fn mismatches(&self, expected: &Self) -> impl Iterator<Item = (&'static str, String, String)> {
+        [
+            ("nonce", self.nonce.to_string(), expected.nonce.to_string()),
+            (
+                "balance",
+                self.balance.to_string(),
+                expected.balance.to_string(),
+            ),
+            (
+                "storage root",
+                format!("{:?}", self.storage_root),
+                format!("{:?}", expected.storage_root),
+            ),
+            (
+                "code hash",
+                format!("{:?}", self.code_hash),
+                format!("{:?}", expected.code_hash),
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, actual, expected)| actual != expected)
+    }
+}
+
+fn decode_account(bytes: &[u8]) -> Result<AccountLeaf, rlp::DecoderError> {
+    let rlp = Rlp::new(bytes);
+    Ok(AccountLeaf {
+        nonce: rlp.val_at(0)?,
+        balance: rlp.val_at(1)?,
+        storage_root: rlp.val_at(2)?,
+        code_hash: rlp.val_at(3)?,
+    })
+}
@@ -0,0 +1,111 @@
+//! The configurations the STARKs can be proven with (see `compare-configs`):
+//! the hasher of the Merkle trees and of the challenger, and a preset of the
+//! FRI parameters.
+//!
+//! Every preset targets the same 100 bits of conjectured security, trading
+//! proving time for proof size: the higher the rate of the code, the more
+//! costly the low-degree extension but the fewer queries in the proof.
+
+use std::{fmt::Display, str::FromStr};
+
+use evm_arithmetization::StarkConfig;
+use plonky2::fri::{reduction_strategies::FriReductionStrategy, FriConfig};
+
+/// The hasher of the generic config the STARKs are proven with.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum ConfigHasher {
+    /// `KeccakGoldilocksConfig`, the one of the regular runs.
+    #[default]
+    Keccak,
+    /// `PoseidonGoldilocksConfig`, the one of recursive proofs.
+    Poseidon,
+}
+
+/// A preset of the FRI parameters.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum FriPreset {
+    /// `StarkConfig::standard_fast_config`, with a rate of 1/2.
+    #[default]
+    Fast,
+    /// A rate of 1/4, with half the queries.
+    Balanced,
+    /// A rate of 1/8, with a third of the queries. The low-degree extensions
+    /// of the traces take four times the memory they do with `fast`.
+    SmallProofs,
+}
+
+/// A proving configuration, written `<hasher>[:<fri preset>]` (eg. `keccak` or
+/// `poseidon:small-proofs`).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct ProvingConfig {
+    pub(crate) hasher: ConfigHasher,
+    pub(crate) fri: FriPreset,
+}
+
+impl ProvingConfig {
+    pub(crate) fn stark_config(&self) -> StarkConfig {
+        let (rate_bits, num_query_rounds) = match self.fri {
+            FriPreset::Fast => return StarkConfig::standard_fast_config(),
+            FriPreset::Balanced => (2, 42),
+            FriPreset::SmallProofs => (3, 28),
+        };
+
+        StarkConfig::new(
+            100,
+            2,
+            FriConfig {
+                rate_bits,
+                cap_height: 4,
+                proof_of_work_bits: 16,
+                reduction_strategy: FriReductionStrategy::ConstantArityBits(4, 5),
+                num_query_rounds,
+            },
+        )
+    }
+}
+
+impl Display for ProvingConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hasher = match self.hasher {
+            ConfigHasher::Keccak => "keccak",
+            ConfigHasher::Poseidon => "poseidon",
+        };
+        let fri = match self.fri {
+            FriPreset::Fast => "fast",
+            FriPreset::Balanced => "balanced",
+            FriPreset::SmallProofs => "small-proofs",
+        };
+        write!(f, "{}:{}", hasher, fri)
+    }
+}
+
+impl FromStr for ProvingConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hasher, fri) = s.split_once(':').unwrap_or((s, "fast"));
+        let hasher = match hasher {
+            "keccak" => ConfigHasher::Keccak,
+            "poseidon" => ConfigHasher::Poseidon,
+            _ => {
+                return Err(format!(
+                    "unknown hasher {:?} (expected `keccak` or `poseidon`)",
+                    hasher
+                ))
+            }
+        };
+        let fri = match fri {
+            "fast" => FriPreset::Fast,
+            "balanced" => FriPreset::Balanced,
+            "small-proofs" => FriPreset::SmallProofs,
+            _ => {
+                return Err(format!(
+                    "unknown FRI preset {:?} (expected `fast`, `balanced` or `small-proofs`)",
+                    fri
+                ))
+            }
+        };
+
+        Ok(Self { hasher, fri })
+    }
+}
@@ -0,0 +1,59 @@
+//! Checks of the receipt the zkEVM ends a block with against the outcome of
+//! its transaction recorded in the manifest.
+//!
+//! Many tests deliberately make their transaction revert, which only shows in
+//! the status of its receipt. A block that passes ended with the expected
+//! receipts root, so its receipt has the expected status and gas. When a block
+//! fails, the receipt the zkEVM output for debugging tells whether it got the
+//! outcome of the transaction wrong.
+
+use common::types::TxnOutcome;
+use mpt_trie::{
+    nibbles::Nibbles,
+    partial_trie::{HashedPartialTrie, PartialTrie},
+};
+use rlp::Rlp;
+
+/// Describes how the receipt of the transaction in `receipt_trie`, as output
+/// by the zkEVM, differs from `expected`, if it does.
+pub(super) fn receipt_mismatch(
+    receipt_trie: &HashedPartialTrie,
+    expected: &TxnOutcome,
+) -> Option<String> {
+    let key = Nibbles::from_bytes_be(&rlp::encode(&0u8)).unwrap();
+    let Some(receipt) = receipt_trie.get(key) else {
+        return Some("the transaction has no receipt".to_string());
+    };
+    // Typed receipts are prefixed with the type of their transaction.
+    let receipt = match receipt.first() {
+        Some(&txn_type) if txn_type < 0xc0 => &receipt[1..],
+        _ => receipt,
+    };
+
+    let rlp = Rlp::new(receipt);
+    let (status, gas_used) = match (rlp.val_at::<u8>(0), rlp.val_at::<u64>(1)) {
+        (Ok(status), Ok(gas_used)) => (status == 1, gas_used),
+        (Err(err), _) | (_, Err(err)) => {
+            return Some(format!("the receipt couldn't be decoded: {}", err))
+        }
+    };
+
+    let outcome = |success| match success {
+        true => "succeeds",
+        false => "reverts",
+    };
+    match (status != expected.success, gas_used != expected.gas_used) {
+        (false, false) => None,
+        (true, _) => Some(format!(
+            "the transaction {} using {} gas, but {} using {} gas with revm",
+            outcome(status),
+            gas_used,
+            outcome(expected.success),
+            expected.gas_used
+        )),
+        (false, true) => Some(format!(
+            "the transaction uses {} gas, but {} with revm",
+            gas_used, expected.gas_used
+        )),
+    }
+}
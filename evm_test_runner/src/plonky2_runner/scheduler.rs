@@ -0,0 +1,370 @@
+//! Runs tests in parallel, scheduling them against a global memory budget.
+//!
+//! Tests are started in order as long as fewer than `jobs` tests are running
+//! and their estimated memory usage fits in what remains of the budget. If the
+//! next test doesn't fit, a later (smaller) one that does is started instead,
+//! so that small tests pack tightly around heavy ones while two heavy tests
+//! never run together. A test that exceeds the budget on its own is only run
+//! once nothing else is.
+//!
+//! Proving happens on worker threads, while all the bookkeeping stays on the
+//! calling thread.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tokio::{runtime::Handle, time};
+
+use super::{
+    complete_test, prepare_test, run_test_and_get_test_result, PreparedTest, TestGroupRunResults,
+    TestOrder, TestOutcome, TestRunResult, TestRunState, TestStatus, TestSubGroupRunResults,
+};
+use crate::{
+    isolation::run_test_in_subprocess,
+    resource_estimation::estimate_memory_bytes,
+    test_dir_reading::{ParsedTestGroup, Test},
+};
+
+/// How often timeouts and abort signals are checked while tests are running.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug)]
+struct QueuedTest {
+    /// The position of the test in `(group, sub-group, test)` order.
+    pos: (usize, usize, usize),
+    test: Test,
+    estimated_memory: u64,
+}
+
+#[derive(Debug)]
+struct RunningTest {
+    pos: (usize, usize, usize),
+    prepared: Option<PreparedTest>,
+    start: Instant,
+    timeout: Duration,
+    estimated_memory: u64,
+}
+
+/// The index of the first of the queued tests (given by their estimated
+/// memory usage) that fits in what remains of the memory budget, if any. Any
+/// test fits when nothing else is running, so that tests exceeding the budget
+/// on their own are still run.
+fn next_fitting_test(
+    queued_memory: impl IntoIterator<Item = u64>,
+    num_running: usize,
+    used_memory: u64,
+    memory_budget: u64,
+) -> Option<usize> {
+    queued_memory.into_iter().position(|estimated_memory| {
+        num_running == 0 || used_memory.saturating_add(estimated_memory) <= memory_budget
+    })
+}
+
+pub(super) fn run_tests_in_parallel(
+    parsed_tests: Vec<ParsedTestGroup>,
+    t_state: &mut TestRunState,
+    jobs: usize,
+    memory_budget: u64,
+) -> Vec<TestGroupRunResults> {
+    let mut results = empty_results(&parsed_tests);
+    let mut queue: VecDeque<_> = ordered_tests(parsed_tests, t_state)
+        .into_iter()
+        .map(|(pos, test)| QueuedTest {
+            pos,
+            estimated_memory: estimate_memory_bytes(
+                &test.info,
+                t_state.witness_only,
+                t_state.max_cpu_log_len,
+            ),
+            test,
+        })
+        .collect();
+
+    let (send, recv) = mpsc::channel::<(String, TestOutcome)>();
+    let mut running: HashMap<String, RunningTest> = HashMap::new();
+    let mut used_memory = 0u64;
+
+    loop {
+        // Start as many tests as the job count and memory budget allow.
+        while !t_state.stopped_early && running.len() < jobs {
+            let Some(q_idx) = next_fitting_test(
+                queue.iter().map(|q| q.estimated_memory),
+                running.len(),
+                used_memory,
+                memory_budget,
+            ) else {
+                break;
+            };
+            let queued = queue.remove(q_idx).unwrap();
+            used_memory += queued.estimated_memory;
+
+            let prepared = prepare_test(&queued.test, t_state);
+            let timeout = test_timeout(&results, queued.pos, &queued.test, t_state);
+            spawn_test(queued.test, timeout, t_state, send.clone());
+            running.insert(
+                prepared.name.clone(),
+                RunningTest {
+                    pos: queued.pos,
+                    prepared: Some(prepared),
+                    start: Instant::now(),
+                    timeout,
+                    estimated_memory: queued.estimated_memory,
+                },
+            );
+        }
+
+        if t_state.stopped_early || (running.is_empty() && queue.is_empty()) {
+            break;
+        }
+
+        let mut running_names: Vec<_> = running
+            .iter()
+            .filter(|(_, r)| r.prepared.is_some())
+            .map(|(name, _)| name.as_str())
+            .collect();
+        running_names.sort_unstable();
+        t_state
+            .p_indicator
+            .set_current_test_name(running_names.join(", "));
+
+        if let Ok((name, outcome)) = recv.recv_timeout(POLL_INTERVAL) {
+            let r = running.remove(&name).unwrap();
+            used_memory -= r.estimated_memory;
+
+            // Timed out tests have already been recorded.
+            if let Some(prepared) = r.prepared {
+                record_result(
+                    &mut results,
+                    r.pos,
+                    complete_test(prepared, outcome, r.start.elapsed(), t_state),
+                    t_state,
+                );
+            }
+        }
+
+        // Timed out tests keep running in the background (unless isolated, in
+        // which case their process is killed) and hence keep their memory
+        // reserved, but their result is recorded right away.
+        for r in running.values_mut() {
+            let duration = r.start.elapsed();
+            if duration > r.timeout
+                && let Some(prepared) = r.prepared.take()
+            {
+                let res = complete_test(prepared, TestStatus::TimedOut.into(), duration, t_state);
+                record_result(&mut results, r.pos, res, t_state);
+            }
+        }
+
+        if t_state.process_aborted_recv.try_recv().is_ok() {
+            t_state.stopped_early = true;
+        }
+    }
+
+    into_group_results(results)
+}
+
+/// The results of the tests run so far, in which they are recorded in any
+/// order along with their position in their sub-group.
+pub(super) type PartialResults = Vec<(String, Vec<(String, Vec<(usize, TestRunResult)>)>)>;
+
+/// Flattens the tests, along with their position in `(group, sub-group, test)`
+/// order.
+pub(super) fn positioned_tests(
+    parsed_tests: Vec<ParsedTestGroup>,
+) -> impl Iterator<Item = ((usize, usize, usize), Test)> {
+    parsed_tests.into_iter().enumerate().flat_map(|(g_idx, g)| {
+        g.sub_groups
+            .into_iter()
+            .enumerate()
+            .flat_map(move |(sub_g_idx, sub_g)| {
+                sub_g
+                    .tests
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(t_idx, test)| ((g_idx, sub_g_idx, t_idx), test))
+            })
+    })
+}
+
+/// Flattens the tests along with their position (see [`positioned_tests`]), in
+/// the order they are run in (see `--order`).
+pub(super) fn ordered_tests(
+    parsed_tests: Vec<ParsedTestGroup>,
+    t_state: &TestRunState,
+) -> Vec<((usize, usize, usize), Test)> {
+    let mut tests: Vec<_> = positioned_tests(parsed_tests).collect();
+    match t_state.order {
+        TestOrder::Corpus => (),
+        // Tests never run come first, as they have no last run.
+        TestOrder::StalestFirst => tests
+            .sort_by_cached_key(|(_, test)| t_state.persistent_test_state.get_last_run(&test.name)),
+    }
+
+    tests
+}
+
+/// The timeout of `test`, whose sub-group at `pos` is named in `results`.
+pub(super) fn test_timeout(
+    results: &PartialResults,
+    (g_idx, sub_g_idx, _): (usize, usize, usize),
+    test: &Test,
+    t_state: &TestRunState,
+) -> Duration {
+    t_state
+        .test_timeouts
+        .for_test(&results[g_idx].1[sub_g_idx].0, &test.info)
+}
+
+pub(super) fn empty_results(parsed_tests: &[ParsedTestGroup]) -> PartialResults {
+    parsed_tests
+        .iter()
+        .map(|g| {
+            (
+                g.name.clone(),
+                g.sub_groups
+                    .iter()
+                    .map(|sub_g| (sub_g.name.clone(), Vec::new()))
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// Records the results of a test and of its aliases (see [`complete_test`]).
+pub(super) fn record_result(
+    results: &mut PartialResults,
+    (g_idx, sub_g_idx, t_idx): (usize, usize, usize),
+    res: Vec<TestRunResult>,
+    t_state: &mut TestRunState,
+) {
+    if t_state.fail_fast_limit_reached(&res[0].status) {
+        t_state.stopped_early = true;
+    }
+    results[g_idx].1[sub_g_idx]
+        .1
+        .extend(res.into_iter().map(|res| (t_idx, res)));
+}
+
+/// Orders the results of each sub-group as their tests were.
+pub(super) fn into_group_results(results: PartialResults) -> Vec<TestGroupRunResults> {
+    results
+        .into_iter()
+        .map(|(name, sub_groups)| TestGroupRunResults {
+            name,
+            sub_group_res: sub_groups
+                .into_iter()
+                .map(|(name, mut test_res)| {
+                    test_res.sort_unstable_by_key(|(t_idx, _)| *t_idx);
+                    TestSubGroupRunResults {
+                        name,
+                        test_res: test_res.into_iter().map(|(_, res)| res).collect(),
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+fn spawn_test(
+    test: Test,
+    timeout: Duration,
+    t_state: &TestRunState,
+    send: mpsc::Sender<(String, TestOutcome)>,
+) {
+    let prover_setup = Arc::clone(&t_state.prover_setup);
+    let witness_cache = t_state.witness_cache.clone();
+    let witness_only = t_state.witness_only;
+    let max_cpu_log_len = t_state.max_cpu_log_len;
+    let isolation = t_state.isolation.clone();
+    let runtime = Handle::current();
+
+    thread::spawn(move || {
+        let outcome = match isolation {
+            // Dropping the child process on timeout kills it.
+            Some(isolation) => runtime
+                .block_on(time::timeout(
+                    timeout,
+                    run_test_in_subprocess(test.info, &isolation),
+                ))
+                .unwrap_or_else(|_| TestStatus::TimedOut.into()),
+            None => panic::catch_unwind(AssertUnwindSafe(|| {
+                let outcome = run_test_and_get_test_result(
+                    test.info,
+                    &prover_setup,
+                    witness_cache.as_ref(),
+                    witness_only,
+                    max_cpu_log_len,
+                );
+                // The memory of the tests running alongside would be counted
+                // too.
+                TestOutcome {
+                    peak_memory: None,
+                    ..outcome
+                }
+            }))
+            .unwrap_or_else(|_| TestStatus::EvmErr("The prover panicked".to_string()).into()),
+        };
+
+        // The receiver is gone if the run was stopped early.
+        let _ = send.send((test.name, outcome));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::next_fitting_test;
+
+    /// Starts the queued tests (given by their estimated memory usage) as
+    /// the scheduler would, with up to `jobs` of them running and none
+    /// finishing, returning the ones started in order.
+    fn start_tests(queue: &mut Vec<u64>, jobs: usize, memory_budget: u64) -> Vec<u64> {
+        let mut running = Vec::new();
+        while running.len() < jobs {
+            let used_memory = running.iter().sum();
+            let Some(idx) = next_fitting_test(
+                queue.iter().copied(),
+                running.len(),
+                used_memory,
+                memory_budget,
+            ) else {
+                break;
+            };
+            running.push(queue.remove(idx));
+        }
+
+        running
+    }
+
+    #[test]
+    fn small_tests_go_ahead_of_heavy_ones() {
+        let mut queue = vec![6, 6, 3, 12, 1];
+
+        assert_eq!(start_tests(&mut queue, 4, 10), [6, 3, 1]);
+        assert_eq!(queue, [6, 12]);
+    }
+
+    #[test]
+    fn heavy_tests_never_run_together() {
+        let mut queue = vec![12, 11, 2];
+
+        // A test over budget runs on its own, and then the others one by one
+        // once it is done.
+        assert_eq!(start_tests(&mut queue, 4, 10), [12]);
+        assert_eq!(start_tests(&mut queue, 4, 10), [11]);
+        assert_eq!(start_tests(&mut queue, 4, 10), [2]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn the_job_count_limits_the_tests_started() {
+        let mut queue = vec![1, 1, 1];
+
+        assert_eq!(start_tests(&mut queue, 2, 10), [1, 1]);
+        assert_eq!(queue, [1]);
+    }
+}
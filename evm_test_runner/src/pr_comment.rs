@@ -0,0 +1,205 @@
+//! Generates a report meant to be pasted as a GitHub PR comment (eg. on a
+//! zkEVM bump PR).
+//!
+//! It starts with the overall pass rate, followed by collapsible sections for
+//! the regressions (tests that passed in their previous run but failed in this
+//! one), the other failures, the performance regressions and, if a baseline is
+//! given, the sub-groups whose results changed. Sections are truncated so that
+//! the comment stays under GitHub's size limit.
+
+use std::{collections::HashSet, fmt::Write, fs, path::PathBuf};
+
+use anyhow::Context;
+
+use crate::{
+    baseline::Baseline,
+    perf_regression::PerfRegression,
+    plonky2_runner::{TestGroupRunResults, TestRunResult, TestStatus},
+    provenance::Provenance,
+    report_generation::REPORT_OUTPUT,
+};
+
+/// The maximum length of a GitHub comment body.
+const MAX_COMMENT_LEN: usize = 65_536;
+
+/// Room kept for closing the sections and noting truncated rows.
+const SECTION_FOOTER_LEN: usize = 256;
+
+const MAX_ERROR_LEN: usize = 120;
+
+pub(crate) fn write_pr_comment_report(
+    res: &[TestGroupRunResults],
+    previously_passed: &HashSet<String>,
+    baseline: Option<&Baseline>,
+    perf_regressions: &[PerfRegression],
+    provenance: &Provenance,
+) -> anyhow::Result<PathBuf> {
+    let comment = generate_pr_comment(
+        res,
+        previously_passed,
+        baseline,
+        perf_regressions,
+        provenance,
+    );
+
+    let report_path = PathBuf::from(REPORT_OUTPUT).join("pr_comment.md");
+    fs::create_dir_all(REPORT_OUTPUT)
+        .with_context(|| format!("Creating report subdirectory {}", REPORT_OUTPUT))?;
+    fs::write(&report_path, comment)
+        .with_context(|| format!("Writing report to {:?}", report_path))?;
+
+    Ok(report_path)
+}
+
+fn generate_pr_comment(
+    res: &[TestGroupRunResults],
+    previously_passed: &HashSet<String>,
+    baseline: Option<&Baseline>,
+    perf_regressions: &[PerfRegression],
+    provenance: &Provenance,
+) -> String {
+    let tests: Vec<&TestRunResult> = res
+        .iter()
+        .flat_map(|g| {
+            g.sub_group_res
+                .iter()
+                .flat_map(|sub_g| sub_g.test_res.iter())
+        })
+        .collect();
+    let num_passed = tests.iter().filter(|t| t.status.passed()).count();
+    let (regressions, other_failures): (Vec<_>, Vec<_>) = tests
+        .iter()
+        .filter(|t| t.status.failed())
+        .partition(|t| previously_passed.contains(&t.name));
+
+    let mut comment = String::new();
+    let _ = writeln!(
+        comment,
+        "## {} zkEVM tests: {} / {} passed ({:.1}%)\n",
+        if regressions.is_empty() { "✅" } else { "❌" },
+        num_passed,
+        tests.len(),
+        100.0 * num_passed as f64 / tests.len().max(1) as f64,
+    );
+    let _ = writeln!(comment, "{}\n", provenance);
+
+    let failure_row =
+        |t: &&TestRunResult| format!("| {} | {} |", t.name, failure_excerpt(&t.status));
+    push_section(
+        &mut comment,
+        &format!("Regressions ({})", regressions.len()),
+        "| test | error |\n|------|-------|",
+        regressions.iter().map(failure_row),
+    );
+    push_section(
+        &mut comment,
+        &format!("Other failures ({})", other_failures.len()),
+        "| test | error |\n|------|-------|",
+        other_failures.iter().map(failure_row),
+    );
+
+    push_section(
+        &mut comment,
+        &format!("Performance regressions ({})", perf_regressions.len()),
+        "| test | baseline | current | slowdown |\n|------|----------|---------|----------|",
+        perf_regressions.iter().map(|r| {
+            format!(
+                "| {} | {:.2?} | {:.2?} | +{:.0}% |",
+                r.name,
+                r.baseline,
+                r.current,
+                r.slowdown()
+            )
+        }),
+    );
+
+    if let Some(baseline) = baseline {
+        let changed: Vec<_> = res
+            .iter()
+            .flat_map(|g| {
+                g.sub_group_res.iter().filter_map(move |sub_g| {
+                    let num_passed = sub_g.test_res.iter().filter(|t| t.status.passed()).count();
+                    let delta = baseline.sub_group_delta(
+                        &g.name,
+                        &sub_g.name,
+                        num_passed,
+                        sub_g.test_res.len(),
+                    );
+                    (delta != "=").then(|| {
+                        format!(
+                            "| {}/{} | {} / {} | {} |",
+                            g.name,
+                            sub_g.name,
+                            num_passed,
+                            sub_g.test_res.len(),
+                            delta
+                        )
+                    })
+                })
+            })
+            .collect();
+
+        push_section(
+            &mut comment,
+            &format!("Changed sub-groups vs baseline ({})", changed.len()),
+            "| sub-group | passed | vs baseline |\n|-----------|--------|-------------|",
+            changed.into_iter(),
+        );
+    }
+
+    comment
+}
+
+/// Appends a collapsible section, truncating its rows if the comment would
+/// otherwise exceed the maximum length.
+fn push_section(
+    comment: &mut String,
+    title: &str,
+    header: &str,
+    rows: impl ExactSizeIterator<Item = String>,
+) {
+    let num_rows = rows.len();
+    let header_len = title.len() + header.len() + 32;
+    if num_rows == 0 || comment.len() + header_len + SECTION_FOOTER_LEN > MAX_COMMENT_LEN {
+        return;
+    }
+
+    let _ = write!(
+        comment,
+        "<details><summary>{}</summary>\n\n{}\n",
+        title, header
+    );
+
+    let mut num_written = 0;
+    for row in rows {
+        if comment.len() + row.len() + 1 + SECTION_FOOTER_LEN > MAX_COMMENT_LEN {
+            break;
+        }
+        comment.push_str(&row);
+        comment.push('\n');
+        num_written += 1;
+    }
+    if num_written < num_rows {
+        let _ = writeln!(
+            comment,
+            "\n... and {} more (truncated)",
+            num_rows - num_written
+        );
+    }
+
+    comment.push_str("\n</details>\n\n");
+}
+
+fn failure_excerpt(status: &TestStatus) -> String {
+    let msg = match status {
+        TestStatus::EvmErr(err) => err.split_whitespace().collect::<Vec<_>>().join(" "),
+        status => status.to_string(),
+    };
+
+    let mut excerpt: String = msg.chars().take(MAX_ERROR_LEN).collect();
+    if excerpt.len() < msg.len() {
+        excerpt.push_str("...");
+    }
+
+    excerpt.replace('|', "\\|")
+}
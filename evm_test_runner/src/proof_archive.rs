@@ -0,0 +1,195 @@
+//! On-disk archive of the proofs of the blocks of the tests
+//! (`--proof-archive`).
+//!
+//! The proofs of a full run add up to hundreds of GB, so every archived proof
+//! is compressed with zstd, and the public values of its segments, which are
+//! often identical between variants and between runs, are stored apart and
+//! deduplicated:
+//!
+//! - `proofs/<block>.bin.zst`: the STARK proofs of each segment of the block
+//!   (see [`encoding`]).
+//! - `public_values/<hash>.cbor.zst`: the public values of a segment, named
+//!   after their hash.
+//! - `index.jsonl`: a line per archived block, listing its proof file and the
+//!   hashes of the public values of its segments. Lines are appended (so that
+//!   parallel or isolated runs can share an archive), and the last one of a
+//!   block is the one describing its current proof file.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{anyhow, Context};
+use common::config::EVM_ARITHMETIZATION_VERSION;
+use evm_arithmetization::proof::{AllProof, PublicValues};
+use keccak_hash::keccak;
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::KeccakGoldilocksConfig};
+use serde::{Deserialize, Serialize};
+
+mod encoding;
+
+use encoding::decode_segments;
+pub(crate) use encoding::encode_segments;
+
+type F = GoldilocksField;
+type C = KeccakGoldilocksConfig;
+
+pub(crate) const PROOF_ARCHIVE_INDEX_FILE_NAME: &str = "index.jsonl";
+
+/// The zstd level the proofs are compressed with.
+const COMPRESSION_LEVEL: i32 = 9;
+
+/// A line of the index.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct ArchivedBlock {
+    pub(crate) block: String,
+    pub(crate) zkevm_version: String,
+    /// The proof file, relative to the archive.
+    pub(crate) proof: PathBuf,
+    /// The hashes of the public values of the segments, in order.
+    pub(crate) public_values: Vec<String>,
+    /// The size of the compressed proof file, in bytes.
+    pub(crate) compressed_size: usize,
+    /// The size of the serialized proofs and public values before compression
+    /// and deduplication, in bytes.
+    pub(crate) uncompressed_size: usize,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ProofArchive {
+    dir: PathBuf,
+}
+
+impl ProofArchive {
+    pub(crate) fn new(dir: PathBuf) -> anyhow::Result<Self> {
+        for sub_dir in ["proofs", "public_values"] {
+            fs::create_dir_all(dir.join(sub_dir))
+                .with_context(|| format!("Creating proof archive directory {:?}", dir))?;
+        }
+        Ok(Self { dir })
+    }
+
+    /// Archives the segment proofs of `block`, replacing its previous ones.
+    pub(crate) fn store(&self, block: &str, proofs: &[AllProof<F, C, 2>]) -> anyhow::Result<()> {
+        let mut public_values = Vec::with_capacity(proofs.len());
+        let mut uncompressed_size = 0;
+        for proof in proofs {
+            let (hash, size) = self.store_public_values(&proof.public_values)?;
+            public_values.push(hash);
+            uncompressed_size += size;
+        }
+
+        let encoded = encode_segments(proofs);
+        uncompressed_size += encoded.len();
+        let compressed = zstd::encode_all(encoded.as_slice(), COMPRESSION_LEVEL)?;
+        let proof = Path::new("proofs").join(format!("{}.bin.zst", block));
+        write_atomically(&self.dir.join(&proof), &compressed)?;
+
+        self.append_to_index(&ArchivedBlock {
+            block: block.to_string(),
+            zkevm_version: EVM_ARITHMETIZATION_VERSION.to_string(),
+            proof,
+            public_values,
+            compressed_size: compressed.len(),
+            uncompressed_size,
+        })
+    }
+
+    /// Stores the public values unless identical ones already are, returning
+    /// their hash and serialized size.
+    fn store_public_values(
+        &self,
+        public_values: &PublicValues<F>,
+    ) -> anyhow::Result<(String, usize)> {
+        let serialized = serde_cbor::to_vec(public_values)?;
+        let hash = format!("{:x}", keccak(&serialized));
+        let path = self.public_values_path(&hash);
+        if !path.exists() {
+            let compressed = zstd::encode_all(serialized.as_slice(), COMPRESSION_LEVEL)?;
+            write_atomically(&path, &compressed)?;
+        }
+
+        Ok((hash, serialized.len()))
+    }
+
+    fn public_values_path(&self, hash: &str) -> PathBuf {
+        self.dir
+            .join("public_values")
+            .join(format!("{}.cbor.zst", hash))
+    }
+
+    /// Returns the current entry of each archived block, sorted by block.
+    pub(crate) fn entries(&self) -> anyhow::Result<Vec<ArchivedBlock>> {
+        let path = self.dir.join(PROOF_ARCHIVE_INDEX_FILE_NAME);
+        let file = File::open(&path)
+            .with_context(|| format!("Opening the proof archive index {:?}", path))?;
+
+        let mut entries = BTreeMap::new();
+        for (line_idx, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.with_context(|| format!("Reading {:?}", path))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: ArchivedBlock = serde_json::from_str(&line)
+                .with_context(|| format!("Parsing line {} of {:?}", line_idx + 1, path))?;
+            entries.insert(entry.block.clone(), entry);
+        }
+
+        Ok(entries.into_values().collect())
+    }
+
+    /// Loads the segment proofs of an archived block.
+    pub(crate) fn load(&self, entry: &ArchivedBlock) -> anyhow::Result<Vec<AllProof<F, C, 2>>> {
+        let public_values = entry
+            .public_values
+            .iter()
+            .map(|hash| {
+                let path = self.public_values_path(hash);
+                serde_cbor::from_slice(&read_compressed(&path)?)
+                    .with_context(|| format!("Deserializing {:?}", path))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let path = self.dir.join(&entry.proof);
+        decode_segments(&read_compressed(&path)?, public_values)
+            .map_err(|_| anyhow!("Decoding the proofs of {:?}", path))
+    }
+
+    fn append_to_index(&self, entry: &ArchivedBlock) -> anyhow::Result<()> {
+        let path = self.dir.join(PROOF_ARCHIVE_INDEX_FILE_NAME);
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        // A single write of the whole line, so that the lines of concurrent
+        // writers don't interleave.
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(&line))
+            .with_context(|| format!("Appending to the proof archive index {:?}", path))
+    }
+}
+
+fn read_compressed(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let compressed = fs::read(path).with_context(|| format!("Reading {:?}", path))?;
+    zstd::decode_all(compressed.as_slice()).with_context(|| format!("Decompressing {:?}", path))
+}
+
+/// Writes to a temporary file first, so that readers never see a partially
+/// written file.
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    static NUM_TMP_FILES: AtomicUsize = AtomicUsize::new(0);
+    let tmp_path = path.with_extension(format!(
+        "tmp{}-{}",
+        std::process::id(),
+        NUM_TMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&tmp_path, bytes)
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .with_context(|| format!("Writing {:?}", path))
+}
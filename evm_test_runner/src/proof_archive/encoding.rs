@@ -0,0 +1,447 @@
+//! Binary encoding of the segment proofs of an archived block, which also
+//! measures the size of the proofs of the tests.
+//!
+//! The Keccak hashes of the Merkle caps and proofs can't be serialized with
+//! serde, and the serialization of plonky2 expects the data of a circuit to
+//! know the length of each part of a proof, which STARK proofs don't have. So
+//! the proofs are encoded with the primitives of the latter, prefixing every
+//! part with its length.
+
+use evm_arithmetization::{
+    proof::{AllProof, MultiProof, PublicValues},
+    NUM_TABLES,
+};
+use plonky2::{
+    field::{extension::Extendable, polynomial::PolynomialCoeffs},
+    fri::proof::{FriInitialTreeProof, FriProof, FriQueryRound, FriQueryStep},
+    hash::{hashing::PlonkyPermutation, merkle_proofs::MerkleProof, merkle_tree::MerkleCap},
+    plonk::config::{GenericConfig, Hasher},
+    util::serialization::{Buffer, IoError, IoResult, Read, Write},
+};
+use starky::{
+    lookup::{GrandProductChallenge, GrandProductChallengeSet},
+    proof::{StarkOpeningSet, StarkProof, StarkProofWithMetadata},
+};
+
+use super::F;
+
+const D: usize = 2;
+
+/// Encodes the segment proofs of a block, without their public values.
+pub(crate) fn encode_segments<C: GenericConfig<D, F = F>>(proofs: &[AllProof<F, C, D>]) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    // Writing to a `Vec` can't fail.
+    write_segments(&mut buffer, proofs).expect("Encoding the segment proofs");
+    buffer
+}
+
+/// Decodes the segment proofs of a block, given the public values of each
+/// segment.
+pub(super) fn decode_segments<C: GenericConfig<D, F = F>>(
+    bytes: &[u8],
+    public_values: Vec<PublicValues<F>>,
+) -> IoResult<Vec<AllProof<F, C, D>>> {
+    let mut buffer = Buffer::new(bytes);
+    if buffer.read_usize()? != public_values.len() {
+        return Err(IoError);
+    }
+    let proofs = public_values
+        .into_iter()
+        .map(|public_values| read_segment(&mut buffer, public_values))
+        .collect::<IoResult<_>>()?;
+    match buffer.unread_bytes().is_empty() {
+        true => Ok(proofs),
+        false => Err(IoError),
+    }
+}
+
+fn write_segments<C: GenericConfig<D, F = F>>(
+    buffer: &mut Vec<u8>,
+    proofs: &[AllProof<F, C, D>],
+) -> IoResult<()> {
+    buffer.write_usize(proofs.len())?;
+    for proof in proofs {
+        buffer.write_usize(NUM_TABLES)?;
+        for stark_proof in proof.multi_proof.stark_proofs.iter() {
+            buffer.write_bool(stark_proof.is_some())?;
+            if let Some(stark_proof) = stark_proof {
+                let init_challenger_state = stark_proof.init_challenger_state.as_ref();
+                buffer.write_usize(init_challenger_state.len())?;
+                buffer.write_field_vec(init_challenger_state)?;
+                write_stark_proof(buffer, &stark_proof.proof)?;
+            }
+        }
+
+        let challenges = &proof.multi_proof.ctl_challenges.challenges;
+        buffer.write_usize(challenges.len())?;
+        for challenge in challenges {
+            buffer.write_field(challenge.beta)?;
+            buffer.write_field(challenge.gamma)?;
+        }
+        for &in_use in proof.table_in_use.iter() {
+            buffer.write_bool(in_use)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_segment<C: GenericConfig<D, F = F>>(
+    buffer: &mut Buffer,
+    public_values: PublicValues<F>,
+) -> IoResult<AllProof<F, C, D>> {
+    // The number of tables changes between zkEVM versions.
+    if buffer.read_usize()? != NUM_TABLES {
+        return Err(IoError);
+    }
+    let mut stark_proofs = core::array::from_fn(|_| None);
+    for stark_proof in stark_proofs.iter_mut() {
+        if buffer.read_bool()? {
+            let len = buffer.read_usize()?;
+            let init_challenger_state = buffer.read_field_vec(len)?;
+            *stark_proof = Some(StarkProofWithMetadata {
+                init_challenger_state: <C::Hasher as Hasher<F>>::Permutation::new(
+                    init_challenger_state,
+                ),
+                proof: read_stark_proof(buffer)?,
+            });
+        }
+    }
+
+    let num_challenges = buffer.read_usize()?;
+    let challenges = (0..num_challenges)
+        .map(|_| {
+            Ok(GrandProductChallenge {
+                beta: buffer.read_field()?,
+                gamma: buffer.read_field()?,
+            })
+        })
+        .collect::<IoResult<_>>()?;
+    let mut table_in_use = [false; NUM_TABLES];
+    for in_use in table_in_use.iter_mut() {
+        *in_use = buffer.read_bool()?;
+    }
+
+    Ok(AllProof {
+        multi_proof: MultiProof {
+            stark_proofs,
+            ctl_challenges: GrandProductChallengeSet { challenges },
+        },
+        public_values,
+        table_in_use,
+    })
+}
+
+fn write_stark_proof<C: GenericConfig<D, F = F>>(
+    buffer: &mut Vec<u8>,
+    proof: &StarkProof<F, C, D>,
+) -> IoResult<()> {
+    write_merkle_cap(buffer, &proof.trace_cap)?;
+    for cap in [&proof.auxiliary_polys_cap, &proof.quotient_polys_cap] {
+        buffer.write_bool(cap.is_some())?;
+        if let Some(cap) = cap {
+            write_merkle_cap(buffer, cap)?;
+        }
+    }
+
+    let openings = &proof.openings;
+    write_field_ext_vec(buffer, &openings.local_values)?;
+    write_field_ext_vec(buffer, &openings.next_values)?;
+    for values in [
+        &openings.auxiliary_polys,
+        &openings.auxiliary_polys_next,
+        &openings.quotient_polys,
+    ] {
+        buffer.write_bool(values.is_some())?;
+        if let Some(values) = values {
+            write_field_ext_vec(buffer, values)?;
+        }
+    }
+    buffer.write_bool(openings.ctl_zs_first.is_some())?;
+    if let Some(ctl_zs_first) = &openings.ctl_zs_first {
+        buffer.write_usize(ctl_zs_first.len())?;
+        buffer.write_field_vec(ctl_zs_first)?;
+    }
+
+    write_fri_proof(buffer, &proof.opening_proof)
+}
+
+fn read_stark_proof<C: GenericConfig<D, F = F>>(
+    buffer: &mut Buffer,
+) -> IoResult<StarkProof<F, C, D>> {
+    let trace_cap = read_merkle_cap(buffer)?;
+    let auxiliary_polys_cap = read_option(buffer, read_merkle_cap)?;
+    let quotient_polys_cap = read_option(buffer, read_merkle_cap)?;
+
+    let local_values = read_field_ext_vec(buffer)?;
+    let next_values = read_field_ext_vec(buffer)?;
+    let auxiliary_polys = read_option(buffer, read_field_ext_vec)?;
+    let auxiliary_polys_next = read_option(buffer, read_field_ext_vec)?;
+    let quotient_polys = read_option(buffer, read_field_ext_vec)?;
+    let ctl_zs_first = read_option(buffer, |buffer| {
+        let len = buffer.read_usize()?;
+        buffer.read_field_vec(len)
+    })?;
+
+    Ok(StarkProof {
+        trace_cap,
+        auxiliary_polys_cap,
+        quotient_polys_cap,
+        openings: StarkOpeningSet {
+            local_values,
+            next_values,
+            auxiliary_polys,
+            auxiliary_polys_next,
+            ctl_zs_first,
+            quotient_polys,
+        },
+        opening_proof: read_fri_proof(buffer)?,
+    })
+}
+
+fn write_fri_proof<H: Hasher<F>>(buffer: &mut Vec<u8>, proof: &FriProof<F, H, D>) -> IoResult<()> {
+    buffer.write_usize(proof.commit_phase_merkle_caps.len())?;
+    for cap in proof.commit_phase_merkle_caps.iter() {
+        write_merkle_cap(buffer, cap)?;
+    }
+
+    buffer.write_usize(proof.query_round_proofs.len())?;
+    for round in proof.query_round_proofs.iter() {
+        let evals_proofs = &round.initial_trees_proof.evals_proofs;
+        buffer.write_usize(evals_proofs.len())?;
+        for (evals, merkle_proof) in evals_proofs {
+            buffer.write_usize(evals.len())?;
+            buffer.write_field_vec(evals)?;
+            buffer.write_merkle_proof(merkle_proof)?;
+        }
+
+        buffer.write_usize(round.steps.len())?;
+        for step in round.steps.iter() {
+            write_field_ext_vec(buffer, &step.evals)?;
+            buffer.write_merkle_proof(&step.merkle_proof)?;
+        }
+    }
+
+    write_field_ext_vec(buffer, &proof.final_poly.coeffs)?;
+    buffer.write_field(proof.pow_witness)
+}
+
+fn read_fri_proof<H: Hasher<F>>(buffer: &mut Buffer) -> IoResult<FriProof<F, H, D>> {
+    let num_caps = buffer.read_usize()?;
+    let commit_phase_merkle_caps = (0..num_caps)
+        .map(|_| read_merkle_cap(buffer))
+        .collect::<IoResult<_>>()?;
+
+    let num_rounds = buffer.read_usize()?;
+    let query_round_proofs = (0..num_rounds)
+        .map(|_| {
+            let num_evals_proofs = buffer.read_usize()?;
+            let evals_proofs = (0..num_evals_proofs)
+                .map(|_| {
+                    let len = buffer.read_usize()?;
+                    let evals = buffer.read_field_vec(len)?;
+                    Ok((evals, read_merkle_proof(buffer)?))
+                })
+                .collect::<IoResult<_>>()?;
+
+            let num_steps = buffer.read_usize()?;
+            let steps = (0..num_steps)
+                .map(|_| {
+                    Ok(FriQueryStep {
+                        evals: read_field_ext_vec(buffer)?,
+                        merkle_proof: read_merkle_proof(buffer)?,
+                    })
+                })
+                .collect::<IoResult<_>>()?;
+
+            Ok(FriQueryRound {
+                initial_trees_proof: FriInitialTreeProof { evals_proofs },
+                steps,
+            })
+        })
+        .collect::<IoResult<_>>()?;
+
+    Ok(FriProof {
+        commit_phase_merkle_caps,
+        query_round_proofs,
+        final_poly: PolynomialCoeffs::new(read_field_ext_vec(buffer)?),
+        pow_witness: buffer.read_field()?,
+    })
+}
+
+/// Writes the cap with its height, as it depends on the config of the prover.
+fn write_merkle_cap<H: Hasher<F>>(buffer: &mut Vec<u8>, cap: &MerkleCap<F, H>) -> IoResult<()> {
+    buffer.write_usize(cap.height())?;
+    buffer.write_merkle_cap(cap)
+}
+
+fn read_merkle_cap<H: Hasher<F>>(buffer: &mut Buffer) -> IoResult<MerkleCap<F, H>> {
+    let height = buffer.read_usize()?;
+    buffer.read_merkle_cap(height)
+}
+
+fn read_merkle_proof<H: Hasher<F>>(buffer: &mut Buffer) -> IoResult<MerkleProof<F, H>> {
+    buffer.read_merkle_proof()
+}
+
+fn write_field_ext_vec(
+    buffer: &mut Vec<u8>,
+    values: &[<F as Extendable<D>>::Extension],
+) -> IoResult<()> {
+    buffer.write_usize(values.len())?;
+    buffer.write_field_ext_vec::<F, D>(values)
+}
+
+fn read_field_ext_vec(buffer: &mut Buffer) -> IoResult<Vec<<F as Extendable<D>>::Extension>> {
+    let len = buffer.read_usize()?;
+    buffer.read_field_ext_vec::<F, D>(len)
+}
+
+fn read_option<T>(
+    buffer: &mut Buffer,
+    read: impl FnOnce(&mut Buffer) -> IoResult<T>,
+) -> IoResult<Option<T>> {
+    match buffer.read_bool()? {
+        true => read(buffer).map(Some),
+        false => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use evm_arithmetization::{
+        proof::{AllProof, MultiProof, PublicValues},
+        NUM_TABLES,
+    };
+    use plonky2::{
+        field::{extension::Extendable, polynomial::PolynomialCoeffs, types::Sample},
+        fri::proof::{FriInitialTreeProof, FriProof, FriQueryRound, FriQueryStep},
+        hash::{
+            hash_types::BytesHash, hashing::PlonkyPermutation, merkle_proofs::MerkleProof,
+            merkle_tree::MerkleCap,
+        },
+        plonk::config::{GenericConfig, Hasher, KeccakGoldilocksConfig},
+    };
+    use starky::{
+        lookup::{GrandProductChallenge, GrandProductChallengeSet},
+        proof::{StarkOpeningSet, StarkProof, StarkProofWithMetadata},
+    };
+
+    use super::{decode_segments, encode_segments, D, F};
+
+    type C = KeccakGoldilocksConfig;
+    type H = <C as GenericConfig<D>>::Hasher;
+    type FE = <F as Extendable<D>>::Extension;
+
+    fn merkle_cap(height: usize) -> MerkleCap<F, H> {
+        MerkleCap((0..1 << height).map(|_| BytesHash::rand()).collect())
+    }
+
+    fn merkle_proof(len: usize) -> MerkleProof<F, H> {
+        MerkleProof {
+            siblings: (0..len).map(|_| BytesHash::rand()).collect(),
+        }
+    }
+
+    fn stark_proof(num_columns: usize) -> StarkProof<F, C, D> {
+        StarkProof {
+            trace_cap: merkle_cap(2),
+            auxiliary_polys_cap: Some(merkle_cap(1)),
+            quotient_polys_cap: None,
+            openings: StarkOpeningSet {
+                local_values: FE::rand_vec(num_columns),
+                next_values: FE::rand_vec(num_columns),
+                auxiliary_polys: Some(FE::rand_vec(3)),
+                auxiliary_polys_next: Some(FE::rand_vec(3)),
+                ctl_zs_first: Some(F::rand_vec(2)),
+                quotient_polys: None,
+            },
+            opening_proof: FriProof {
+                commit_phase_merkle_caps: vec![merkle_cap(1), merkle_cap(0)],
+                query_round_proofs: (0..2)
+                    .map(|_| FriQueryRound {
+                        initial_trees_proof: FriInitialTreeProof {
+                            evals_proofs: vec![
+                                (F::rand_vec(num_columns), merkle_proof(4)),
+                                (F::rand_vec(5), merkle_proof(4)),
+                            ],
+                        },
+                        steps: vec![FriQueryStep {
+                            evals: FE::rand_vec(2),
+                            merkle_proof: merkle_proof(3),
+                        }],
+                    })
+                    .collect(),
+                final_poly: PolynomialCoeffs::new(FE::rand_vec(4)),
+                pow_witness: F::rand(),
+            },
+        }
+    }
+
+    /// A segment proof with random values, in which only the even tables are
+    /// in use.
+    fn segment_proof(public_values: PublicValues<F>) -> AllProof<F, C, D> {
+        AllProof {
+            multi_proof: MultiProof {
+                stark_proofs: core::array::from_fn(|table| {
+                    (table % 2 == 0).then(|| StarkProofWithMetadata {
+                        init_challenger_state: <H as Hasher<F>>::Permutation::new(F::rand_vec(
+                            <H as Hasher<F>>::Permutation::WIDTH,
+                        )),
+                        proof: stark_proof(table + 1),
+                    })
+                }),
+                ctl_challenges: GrandProductChallengeSet {
+                    challenges: (0..3)
+                        .map(|_| GrandProductChallenge {
+                            beta: F::rand(),
+                            gamma: F::rand(),
+                        })
+                        .collect(),
+                },
+            },
+            public_values,
+            table_in_use: core::array::from_fn(|table| table % 2 == 0),
+        }
+    }
+
+    fn public_values(num_segments: usize) -> Vec<PublicValues<F>> {
+        (0..num_segments)
+            .map(|segment| {
+                let mut public_values = PublicValues::default();
+                public_values.extra_block_data.txn_number_before = segment.into();
+                public_values
+            })
+            .collect()
+    }
+
+    #[test]
+    fn segments_round_trip() {
+        let proofs: Vec<_> = public_values(2).into_iter().map(segment_proof).collect();
+        let bytes = encode_segments(&proofs);
+
+        let decoded = decode_segments::<C>(&bytes, public_values(2)).unwrap();
+
+        // The proofs don't implement `PartialEq`.
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", proofs));
+        assert_eq!(decoded[0].multi_proof.stark_proofs.len(), NUM_TABLES);
+    }
+
+    #[test]
+    fn decoding_rejects_trailing_bytes() {
+        let proofs: Vec<_> = public_values(1).into_iter().map(segment_proof).collect();
+        let mut bytes = encode_segments(&proofs);
+        bytes.push(0);
+
+        assert!(decode_segments::<C>(&bytes, public_values(1)).is_err());
+    }
+
+    #[test]
+    fn decoding_rejects_a_wrong_segment_count() {
+        let proofs: Vec<_> = public_values(2).into_iter().map(segment_proof).collect();
+        let bytes = encode_segments(&proofs);
+
+        assert!(decode_segments::<C>(&bytes, public_values(1)).is_err());
+        assert!(decode_segments::<C>(&bytes, public_values(3)).is_err());
+    }
+}
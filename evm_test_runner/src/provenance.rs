@@ -0,0 +1,82 @@
+//! What a run was obtained with (zkEVM versions, tests commit and runner
+//! invocation), embedded in the header of every report so that reports can be
+//! attributed once they are shared around.
+
+use std::{fmt::Display, fs, path::Path, process::Command};
+
+use common::config::{ETH_TESTS_COMMIT_FILE_NAME, EVM_ARITHMETIZATION_VERSION, PLONKY2_VERSION};
+use serde::Serialize;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Provenance {
+    evm_arithmetization_version: &'static str,
+    plonky2_version: &'static str,
+    /// The commit of `ethereum/tests` the tests come from, if known.
+    eth_tests_commit: Option<String>,
+    /// The command line the runner was started with.
+    invocation: String,
+}
+
+impl Provenance {
+    pub fn new(eth_tests_commit: Option<String>) -> Self {
+        let invocation = std::env::args()
+            .map(|arg| match arg.contains(char::is_whitespace) {
+                true => format!("'{}'", arg),
+                false => arg,
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Self {
+            evm_arithmetization_version: EVM_ARITHMETIZATION_VERSION,
+            plonky2_version: PLONKY2_VERSION,
+            eth_tests_commit,
+            invocation,
+        }
+    }
+}
+
+/// Renders the markdown header of the reports.
+impl Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "_evm_arithmetization {}, plonky2 {}, ethereum/tests {}, run with_ `{}`",
+            self.evm_arithmetization_version,
+            self.plonky2_version,
+            self.eth_tests_commit.as_deref().unwrap_or("unknown"),
+            self.invocation
+        )
+    }
+}
+
+/// Returns the tests commit recorded by the parser in its output directory
+/// (which is an ancestor of the parsed tests).
+pub(crate) fn parsed_tests_commit(parsed_tests_path: &Path) -> Option<String> {
+    parsed_tests_path
+        .ancestors()
+        .map(|ancestor| ancestor.join(ETH_TESTS_COMMIT_FILE_NAME))
+        .find(|path| path.exists())
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|commit| commit.trim().to_string())
+}
+
+/// Returns the commit of the git checkout containing the raw JSON tests.
+pub(crate) fn json_tests_commit(json_path: &Path) -> Option<String> {
+    let dir = match json_path.is_dir() {
+        true => json_path,
+        false => json_path.parent()?,
+    };
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|commit| commit.trim().to_string())
+}
@@ -1,29 +1,42 @@
 //! Performs two types of report generation:
 //! - Generates a summary markdown report which contains an entry for each
 //!   `sub-group` in each `group` showing the number of tests passed/failed (no
-//!   specific info per individual test).
+//!   specific info per individual test), followed by stats on the proofs (for
+//!   full-proving runs) and an appendix with the details of each failure.
 //! - Generates markdown for all tests that match a string filter output to
 //!   `stdout`. Tests are not displayed in groups and instead are shown in a
 //!   single table with information of failures if any.
 
-use std::{fs, path::Path};
+use std::{fmt::Display, fs, path::Path, time::Duration};
 
 use anyhow::Context;
 use askama::Template;
+use serde::{Serialize, Serializer};
 
-use crate::plonky2_runner::{
-    TestGroupRunResults, TestRunResult, TestStatus, TestSubGroupRunResults,
+use crate::{
+    baseline::{Baseline, GroupCounts, SubGroupCounts, SummaryCounts},
+    perf_regression::PerfRegression,
+    plonky2_runner::{
+        ProofStats, TestGroupRunResults, TestRunResult, TestStatus, TestSubGroupRunResults,
+    },
+    provenance::Provenance,
 };
 
-const REPORT_OUTPUT: &str = "reports";
+pub(crate) const REPORT_OUTPUT: &str = "reports";
+
+const FILTERED_TEST_RESULTS_TEMPLATE: &str = "filtered_test_results.md";
+const TEST_RESULTS_SUMMARY_TEMPLATE: &str = "test_results_summary.md";
 
 /// Template for writing a summary markdown report to file.
-#[derive(Debug, Template)]
+#[derive(Debug, Serialize, Template)]
 #[template(path = "filtered_test_results.md")]
 struct FilteredTestResultsTemplate {
+    provenance: Provenance,
     filter_str_template: String,
     passed_info: PassedInfo,
     tests: Vec<TestRunResult>,
+    perf_regressions: Vec<TemplatePerfRegression>,
+    show_t8n: bool,
 }
 
 impl TestGroupRunResults {
@@ -37,6 +50,12 @@ impl TestGroupRunResults {
                 TestRunResult {
                     name: full_path.to_str().unwrap().to_string(),
                     status: test.status.clone(),
+                    duration: test.duration,
+                    t8n_status: test.t8n_status.clone(),
+                    input_path: test.input_path.clone(),
+                    proof_stats: test.proof_stats,
+                    peak_memory: test.peak_memory,
+                    altered_inputs: test.altered_inputs.clone(),
                 }
             })
         })
@@ -45,9 +64,15 @@ impl TestGroupRunResults {
 
 impl FilteredTestResultsTemplate {
     // Note: Tests are already filtered from a previous step.
-    fn new(res: &[TestGroupRunResults], filter_str_template: &Option<String>) -> Self {
+    fn new(
+        res: &[TestGroupRunResults],
+        filter_str_template: &Option<String>,
+        perf_regressions: &[PerfRegression],
+        provenance: &Provenance,
+    ) -> Self {
         let tests: Vec<_> = res.iter().flat_map(|g| g.flatten_tests()).collect();
         let num_passed = tests.iter().filter(|t| t.status.passed()).count();
+        let show_t8n = tests.iter().any(|t| t.t8n_status.is_some());
 
         let filter_str_template = match filter_str_template {
             Some(filter_str) => format!("({})", filter_str),
@@ -55,33 +80,181 @@ impl FilteredTestResultsTemplate {
         };
 
         Self {
+            provenance: provenance.clone(),
             filter_str_template,
             passed_info: PassedInfo::new(tests.len(), num_passed),
             tests,
+            perf_regressions: perf_regressions.iter().map(|r| r.into()).collect(),
+            show_t8n,
         }
     }
 }
 
+/// Error messages longer than this are truncated in the failure appendix.
+const MAX_ERROR_EXCERPT_LEN: usize = 200;
+
 /// Template for displaying filtered tests to `stdout`.
-#[derive(Debug, Template)]
+#[derive(Debug, Serialize, Template)]
 #[template(path = "test_results_summary.md")]
 struct TestResultsSummaryTemplate {
+    provenance: Provenance,
     groups: Vec<TemplateGroupResultsData>,
+    failures: Vec<TemplateFailureData>,
+    proof_stats: Option<TemplateProofStats>,
+    perf_regressions: Vec<TemplatePerfRegression>,
+    show_baseline: bool,
+}
+
+impl TestResultsSummaryTemplate {
+    fn new(
+        v: Vec<TestGroupRunResults>,
+        baseline: Option<&Baseline>,
+        perf_regressions: &[PerfRegression],
+        provenance: &Provenance,
+    ) -> Self {
+        let failures = v
+            .iter()
+            .flat_map(|g| g.flatten_tests())
+            .filter(|t| t.status.failed())
+            .map(|t| t.into())
+            .collect();
+        let proof_stats = TemplateProofStats::new(
+            v.iter()
+                .flat_map(|g| g.sub_group_res.iter())
+                .flat_map(|sub_g| sub_g.test_res.iter())
+                .filter_map(|t| t.proof_stats)
+                .collect(),
+        );
+
+        let mut groups: Vec<TemplateGroupResultsData> = v.into_iter().map(|g| g.into()).collect();
+        if let Some(baseline) = baseline {
+            for group in groups.iter_mut() {
+                group.baseline_delta = Some(baseline.group_delta(
+                    &group.name,
+                    group.passed_info.num_passed,
+                    group.passed_info.tot_tests,
+                ));
+                for sub_group in group.sub_groups.iter_mut() {
+                    sub_group.baseline_delta = Some(baseline.sub_group_delta(
+                        &group.name,
+                        &sub_group.name,
+                        sub_group.passed_info.num_passed,
+                        sub_group.passed_info.tot_tests,
+                    ));
+                }
+            }
+        }
+
+        Self {
+            provenance: provenance.clone(),
+            groups,
+            failures,
+            proof_stats,
+            perf_regressions: perf_regressions.iter().map(|r| r.into()).collect(),
+            show_baseline: baseline.is_some(),
+        }
+    }
+
+    fn counts(&self) -> SummaryCounts {
+        SummaryCounts {
+            groups: self
+                .groups
+                .iter()
+                .map(|g| GroupCounts {
+                    name: g.name.clone(),
+                    sub_groups: g
+                        .sub_groups
+                        .iter()
+                        .map(|sub_g| SubGroupCounts {
+                            name: sub_g.name.clone(),
+                            num_passed: sub_g.passed_info.num_passed,
+                            tot_tests: sub_g.passed_info.tot_tests,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
 }
 
-impl From<Vec<TestGroupRunResults>> for TestResultsSummaryTemplate {
-    fn from(v: Vec<TestGroupRunResults>) -> Self {
+#[derive(Debug, Serialize)]
+struct TemplatePerfRegression {
+    name: String,
+    baseline: String,
+    current: String,
+    slowdown: String,
+}
+
+impl From<&PerfRegression> for TemplatePerfRegression {
+    fn from(v: &PerfRegression) -> Self {
         Self {
-            groups: v.into_iter().map(|g| g.into()).collect(),
+            name: v.name.clone(),
+            baseline: format!("{:.2?}", v.baseline),
+            current: format!("{:.2?}", v.current),
+            slowdown: format!("+{:.0}%", v.slowdown()),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+struct TemplateFailureData {
+    name: String,
+    error: String,
+    duration: String,
+    input_file_name: String,
+    input_link: String,
+}
+
+impl From<TestRunResult> for TemplateFailureData {
+    fn from(v: TestRunResult) -> Self {
+        let error = match &v.status {
+            TestStatus::EvmErr(err) => error_excerpt(err),
+            status => status.to_string(),
+        };
+
+        Self {
+            name: v.name,
+            error,
+            duration: format!("{:.2?}", v.duration),
+            input_file_name: v
+                .input_path
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            input_link: link_from_report_dir(&v.input_path),
+        }
+    }
+}
+
+/// Returns the error on a single line (so that it fits in a table cell),
+/// truncated if it's too long.
+fn error_excerpt(err: &str) -> String {
+    let single_line = err.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut excerpt: String = single_line.chars().take(MAX_ERROR_EXCERPT_LEN).collect();
+    if excerpt.len() < single_line.len() {
+        excerpt.push_str("...");
+    }
+
+    excerpt.replace('|', "\\|")
+}
+
+/// Returns a link to the given file that works from the report directory.
+fn link_from_report_dir(path: &Path) -> String {
+    let path = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok())
+        .map(|rel_path| Path::new("..").join(rel_path))
+        .unwrap_or_else(|| path.to_path_buf());
+
+    path.to_string_lossy().replace(' ', "%20")
+}
+
+#[derive(Debug, Serialize)]
 struct TemplateGroupResultsData {
     name: String,
     passed_info: PassedInfo,
     sub_groups: Vec<TemplateSubGroupResultsData>,
+    baseline_delta: Option<String>,
 }
 
 impl From<TestGroupRunResults> for TemplateGroupResultsData {
@@ -103,14 +276,17 @@ impl From<TestGroupRunResults> for TemplateGroupResultsData {
             name: v.name,
             passed_info: PassedInfo::new(tot_tests, num_passed),
             sub_groups,
+            baseline_delta: None,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 struct TemplateSubGroupResultsData {
     name: String,
     passed_info: PassedInfo,
+    timing: TimingStats,
+    baseline_delta: Option<String>,
 }
 
 impl From<TestSubGroupRunResults> for TemplateSubGroupResultsData {
@@ -128,13 +304,101 @@ impl From<TestSubGroupRunResults> for TemplateSubGroupResultsData {
 
         Self {
             name: v.name,
+            timing: TimingStats::new(tests.iter().map(|t| t.duration).collect()),
             passed_info: PassedInfo::new(tests.len(), num_passed),
+            baseline_delta: None,
         }
     }
 }
 
+/// Stats on the time taken by the tests of a sub-group.
+#[derive(Debug, Serialize)]
+struct TimingStats {
+    total: String,
+    mean: String,
+    median: String,
+    max: String,
+}
+
+impl TimingStats {
+    fn new(mut durations: Vec<Duration>) -> Self {
+        if durations.is_empty() {
+            return Self {
+                total: "-".to_string(),
+                mean: "-".to_string(),
+                median: "-".to_string(),
+                max: "-".to_string(),
+            };
+        }
+
+        durations.sort_unstable();
+        let total: Duration = durations.iter().sum();
+        let mid = durations.len() / 2;
+        let median = match durations.len() % 2 {
+            0 => (durations[mid - 1] + durations[mid]) / 2,
+            _ => durations[mid],
+        };
+
+        Self {
+            total: format!("{:.2?}", total),
+            mean: format!("{:.2?}", total / durations.len() as u32),
+            median: format!("{:.2?}", median),
+            max: format!("{:.2?}", durations[durations.len() - 1]),
+        }
+    }
+}
+
+/// Aggregate stats on the proofs of the tests that were fully proven.
+#[derive(Debug, Serialize)]
+struct TemplateProofStats {
+    num_proofs: usize,
+    size_mean: String,
+    size_median: String,
+    size_max: String,
+    verify_time_mean: String,
+    verify_time_median: String,
+    verify_time_max: String,
+}
+
+impl TemplateProofStats {
+    fn new(stats: Vec<ProofStats>) -> Option<Self> {
+        if stats.is_empty() {
+            return None;
+        }
+
+        let num_proofs = stats.len();
+        let mut sizes: Vec<_> = stats.iter().map(|s| s.size).collect();
+        sizes.sort_unstable();
+        let mut verify_times: Vec<_> = stats.iter().map(|s| s.verify_time).collect();
+        verify_times.sort_unstable();
+
+        let mid = num_proofs / 2;
+        let (size_median, verify_time_median) = match num_proofs % 2 {
+            0 => (
+                (sizes[mid - 1] + sizes[mid]) / 2,
+                (verify_times[mid - 1] + verify_times[mid]) / 2,
+            ),
+            _ => (sizes[mid], verify_times[mid]),
+        };
+        let format_size = |size: usize| format!("{:.1} KiB", size as f64 / 1024.0);
+
+        Some(Self {
+            num_proofs,
+            size_mean: format_size(sizes.iter().sum::<usize>() / num_proofs),
+            size_median: format_size(size_median),
+            size_max: format_size(sizes[num_proofs - 1]),
+            verify_time_mean: format!(
+                "{:.2?}",
+                verify_times.iter().sum::<Duration>() / num_proofs as u32
+            ),
+            verify_time_median: format!("{:.2?}", verify_time_median),
+            verify_time_max: format!("{:.2?}", verify_times[num_proofs - 1]),
+        })
+    }
+}
+
 /// Aggregate stats on tests that have passed/failed.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 struct PassedInfo {
     tot_tests: usize,
     num_passed: usize,
@@ -154,28 +418,43 @@ impl PassedInfo {
 }
 
 /// Print the test report to the terminal.
-pub(crate) fn output_test_report_for_terminal(
+pub fn output_test_report_for_terminal(
     res: &[TestGroupRunResults],
     test_filter_str: Option<String>,
-) {
-    let filtered_tests_output_template = FilteredTestResultsTemplate::new(res, &test_filter_str);
-    let report = filtered_tests_output_template
-        .render()
-        .expect("Error rendering filtered test output markdown");
+    perf_regressions: &[PerfRegression],
+    provenance: &Provenance,
+    template_dir: Option<&Path>,
+) -> anyhow::Result<()> {
+    let filtered_tests_output_template =
+        FilteredTestResultsTemplate::new(res, &test_filter_str, perf_regressions, provenance);
+    let report = render_report(
+        &filtered_tests_output_template,
+        FILTERED_TEST_RESULTS_TEMPLATE,
+        template_dir,
+    )?;
 
     termimad::print_text(&report);
+    Ok(())
 }
 
 /// Write a generalized markdown report to file showing the number of passing
-/// tests per each group's sub-groups. Does not include any information on
-/// specific test failures.
-pub(crate) fn write_overall_status_report_summary_to_file(
+/// tests per each group's sub-groups (compared to the baseline if any),
+/// followed by the details of each failure. The counts are also written as
+/// JSON, to be used as a baseline by later runs, along with a pass rate badge.
+pub fn write_overall_status_report_summary_to_file(
     res: Vec<TestGroupRunResults>,
+    baseline: Option<&Baseline>,
+    perf_regressions: &[PerfRegression],
+    provenance: &Provenance,
+    template_dir: Option<&Path>,
 ) -> anyhow::Result<()> {
-    let overall_summary_template: TestResultsSummaryTemplate = res.into();
-    let report = overall_summary_template
-        .render()
-        .expect("Error rendering summary report markdown");
+    let overall_summary_template =
+        TestResultsSummaryTemplate::new(res, baseline, perf_regressions, provenance);
+    let report = render_report(
+        &overall_summary_template,
+        TEST_RESULTS_SUMMARY_TEMPLATE,
+        template_dir,
+    )?;
 
     let summary_path = Path::new(&REPORT_OUTPUT).join("summary.md");
     fs::create_dir_all(summary_path.parent().unwrap())
@@ -183,5 +462,218 @@ pub(crate) fn write_overall_status_report_summary_to_file(
 
     fs::write(&summary_path, report)
         .with_context(|| format!("Writing report to {:?}", summary_path))?;
+
+    let counts_path = summary_path.with_extension("json");
+    fs::write(
+        &counts_path,
+        serde_json::to_vec_pretty(&overall_summary_template.counts())?,
+    )
+    .with_context(|| format!("Writing report to {:?}", counts_path))?;
+
+    let badge_path = summary_path.with_file_name("badge.json");
+    fs::write(
+        &badge_path,
+        serde_json::to_vec_pretty(&Badge::new(&overall_summary_template.groups))?,
+    )
+    .with_context(|| format!("Writing report to {:?}", badge_path))?;
     Ok(())
 }
+
+/// A shields.io endpoint badge showing the overall pass rate.
+///
+/// See <https://shields.io/badges/endpoint-badge>.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Badge {
+    schema_version: u8,
+    label: &'static str,
+    message: String,
+    color: &'static str,
+}
+
+impl Badge {
+    fn new(groups: &[TemplateGroupResultsData]) -> Self {
+        let (tot_tests, num_passed) = groups.iter().fold((0, 0), |(tot, passed), g| {
+            (
+                tot + g.passed_info.tot_tests,
+                passed + g.passed_info.num_passed,
+            )
+        });
+        let perc_passed = 100.0 * num_passed as f64 / tot_tests.max(1) as f64;
+
+        let color = match perc_passed {
+            p if p >= 95.0 => "brightgreen",
+            p if p >= 90.0 => "green",
+            p if p >= 75.0 => "yellow",
+            p if p >= 50.0 => "orange",
+            _ => "red",
+        };
+
+        Self {
+            schema_version: 1,
+            label: "zkEVM tests",
+            message: format!("{:.1}% ({}/{})", perc_passed, num_passed, tot_tests),
+            color,
+        }
+    }
+}
+
+/// Renders a report with the template of the same name in `template_dir` if
+/// there is one, and with the built-in template otherwise.
+///
+/// User templates are rendered at runtime with `minijinja`, with the fields of
+/// the built-in template's data as the context.
+pub(crate) fn render_report<T: Template + Serialize>(
+    template: &T,
+    template_name: &str,
+    template_dir: Option<&Path>,
+) -> anyhow::Result<String> {
+    let user_template_path = template_dir
+        .map(|dir| dir.join(template_name))
+        .filter(|path| path.exists());
+
+    let Some(path) = user_template_path else {
+        return template
+            .render()
+            .with_context(|| format!("Rendering the built-in {} template", template_name));
+    };
+
+    let source =
+        fs::read_to_string(&path).with_context(|| format!("Reading report template {:?}", path))?;
+    let mut env = minijinja::Environment::new();
+    env.add_template(template_name, &source)
+        .with_context(|| format!("Parsing report template {:?}", path))?;
+
+    env.get_template(template_name)?
+        .render(template)
+        .with_context(|| format!("Rendering report template {:?}", path))
+}
+
+/// Serializes a value (eg. a test status) as its displayed text for user
+/// templates.
+pub(crate) fn serialize_display<T: Display, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+pub(crate) fn serialize_display_opt<T: Display, S: Serializer>(
+    value: &Option<T>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => serializer.collect_str(value),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serializes a duration as a number of seconds for user templates.
+pub(crate) fn serialize_duration_secs<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, process, time::Duration};
+
+    use askama::Template;
+
+    use super::TestResultsSummaryTemplate;
+    use crate::{
+        baseline::Baseline,
+        plonky2_runner::{TestGroupRunResults, TestRunResult, TestStatus, TestSubGroupRunResults},
+        provenance::Provenance,
+    };
+
+    fn sub_group(name: &str, statuses: Vec<TestStatus>) -> TestSubGroupRunResults {
+        TestSubGroupRunResults {
+            name: name.to_string(),
+            test_res: statuses
+                .into_iter()
+                .enumerate()
+                .map(|(i, status)| TestRunResult {
+                    name: format!("{}_d{}g0v0", name, i),
+                    status,
+                    duration: Duration::from_millis(10),
+                    t8n_status: None,
+                    input_path: PathBuf::new(),
+                    proof_stats: None,
+                    peak_memory: None,
+                    altered_inputs: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    fn results() -> Vec<TestGroupRunResults> {
+        vec![
+            TestGroupRunResults {
+                name: "GeneralStateTests".to_string(),
+                sub_group_res: vec![
+                    sub_group(
+                        "stExample",
+                        vec![
+                            TestStatus::PassedProof,
+                            TestStatus::EvmErr("KernelPanic".to_string()),
+                        ],
+                    ),
+                    sub_group("stCreate2", vec![TestStatus::PassedProof; 3]),
+                ],
+            },
+            TestGroupRunResults {
+                name: "BlockchainTests".to_string(),
+                sub_group_res: vec![sub_group("bcValidBlockTest", vec![TestStatus::TimedOut])],
+            },
+        ]
+    }
+
+    /// Renders the `summary.md` of `results()` and loads it back as a
+    /// baseline.
+    fn rendered_baseline(baseline: Option<&Baseline>) -> Baseline {
+        let summary =
+            TestResultsSummaryTemplate::new(results(), baseline, &[], &Provenance::new(None))
+                .render()
+                .unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "evm_tests_summary_{}_{}.md",
+            process::id(),
+            baseline.is_some()
+        ));
+        fs::write(&path, summary).unwrap();
+
+        let baseline = Baseline::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        baseline
+    }
+
+    #[test]
+    fn rendered_summary_is_read_back_as_a_baseline() {
+        let baseline = rendered_baseline(None);
+        // With a baseline, the tables have an extra column.
+        let baseline = rendered_baseline(Some(&baseline));
+
+        for (group, sub_group, num_passed, tot_tests) in [
+            ("GeneralStateTests", "stExample", 1, 2),
+            ("GeneralStateTests", "stCreate2", 3, 3),
+            ("BlockchainTests", "bcValidBlockTest", 0, 1),
+        ] {
+            assert_eq!(
+                baseline.sub_group_delta(group, sub_group, num_passed, tot_tests),
+                "="
+            );
+        }
+        assert_eq!(
+            baseline.sub_group_delta("GeneralStateTests", "stExample", 2, 2),
+            "+1 passed / -1 failed"
+        );
+        assert_eq!(baseline.group_delta("GeneralStateTests", 4, 5), "=");
+        assert_eq!(
+            baseline.sub_group_delta("GeneralStateTests", "stNew", 1, 1),
+            "new"
+        );
+    }
+}
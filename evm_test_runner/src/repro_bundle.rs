@@ -0,0 +1,74 @@
+//! Exports self-contained reproduction bundles for failing test variants, so
+//! that a failure can be reported upstream without having to explain how to
+//! extract and decode the parsed CBOR manifests.
+//!
+//! Each bundle is a directory named after the variant containing:
+//! - `generation_inputs.json`: The `GenerationInputs` fed to the zkEVM.
+//! - `txn_{i}.rlp`: The raw RLP encoding of each transaction.
+//! - `pre_state.json`: The pre-state accounts (in the `t8n` alloc format).
+//! - `expected_roots.json`: The expected final trie roots.
+//! - `error.txt`: The status reported by the runner.
+//!
+//! The inputs, transactions and expected roots above are the ones of the first
+//! block. Those of the following blocks of multi-block variants are written the
+//! same way to a `block{i}` subdirectory each, starting from `block2`.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use common::{
+    t8n::t8n_alloc,
+    types::{ExpectedFinalRoots, TestVariantRunInfo},
+};
+use evm_arithmetization::GenerationInputs;
+
+use crate::plonky2_runner::TestStatus;
+
+pub(crate) fn write_repro_bundle(
+    repro_dir: &Path,
+    test: &TestVariantRunInfo,
+    status: &TestStatus,
+) -> anyhow::Result<()> {
+    let bundle_dir = repro_dir.join(&test.variant_name);
+    fs::create_dir_all(&bundle_dir)
+        .with_context(|| format!("Creating repro bundle directory {:?}", bundle_dir))?;
+
+    write_block_files(&bundle_dir, &test.gen_inputs, &test.final_roots)?;
+    for (i, block) in test.chained_blocks.iter().enumerate() {
+        let block_dir = bundle_dir.join(format!("block{}", i + 2));
+        fs::create_dir_all(&block_dir)
+            .with_context(|| format!("Creating repro bundle directory {:?}", block_dir))?;
+        write_block_files(&block_dir, &block.gen_inputs, &block.final_roots)?;
+    }
+
+    fs::write(
+        bundle_dir.join("pre_state.json"),
+        serde_json::to_vec_pretty(&t8n_alloc(&test.pre_state))?,
+    )?;
+    fs::write(bundle_dir.join("error.txt"), status.to_string())?;
+
+    Ok(())
+}
+
+/// Writes the inputs, transactions and expected roots of a block to `dir`.
+fn write_block_files(
+    dir: &Path,
+    gen_inputs: &GenerationInputs,
+    final_roots: &ExpectedFinalRoots,
+) -> anyhow::Result<()> {
+    fs::write(
+        dir.join("generation_inputs.json"),
+        serde_json::to_vec_pretty(gen_inputs)?,
+    )?;
+
+    for (i, txn) in gen_inputs.signed_txns.iter().enumerate() {
+        fs::write(dir.join(format!("txn_{}.rlp", i)), txn)?;
+    }
+
+    fs::write(
+        dir.join("expected_roots.json"),
+        serde_json::to_vec_pretty(final_roots)?,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,196 @@
+//! Rough pre-run estimation of the memory needed to run each test, so that
+//! tests likely to exhaust the machine's RAM are reported (or skipped) before
+//! the run starts instead of killing it halfway through.
+//!
+//! The estimate is based on the number of CPU rows a test is expected to
+//! generate, derived from the gas it uses and the size of its pre-state. The
+//! constants below are deliberately conservative approximations.
+//!
+//! The actual memory usage can be measured with a [`PeakRssSampler`]. As it
+//! samples the resident memory of the whole process, what it measures is only
+//! the memory usage of a test if nothing else runs in the process meanwhile,
+//! so the memory of tests run concurrently in the same process isn't
+//! recorded.
+
+use std::{
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use common::types::TestVariantRunInfo;
+use log::warn;
+
+use crate::{persistent_run_state::TestRunEntries, test_dir_reading::ParsedTestGroup};
+
+/// Fixed kernel overhead of processing a transaction.
+const BASE_CPU_ROWS: u64 = 1 << 16;
+const CPU_ROWS_PER_GAS: u64 = 1;
+/// Contract code is loaded and hashed byte by byte.
+const CPU_ROWS_PER_CODE_BYTE: u64 = 2;
+/// Storage slots are hashed into the storage tries.
+const CPU_ROWS_PER_STORAGE_SLOT: u64 = 256;
+
+/// Memory used per CPU row (all STARK tables and their LDEs included).
+const PROVING_BYTES_PER_CPU_ROW: u64 = 32 * 1024;
+const WITNESS_BYTES_PER_CPU_ROW: u64 = 2 * 1024;
+
+pub(crate) fn estimate_memory_bytes(
+    info: &TestVariantRunInfo,
+    witness_only: bool,
+    max_cpu_log_len: Option<usize>,
+) -> u64 {
+    let gas_used = info.gas_used();
+    let code_bytes: u64 = info.pre_state.values().map(|a| a.code.len() as u64).sum();
+    let storage_slots: u64 = info
+        .pre_state
+        .values()
+        .map(|a| a.storage.len() as u64)
+        .sum();
+
+    let mut cpu_rows = BASE_CPU_ROWS
+        + gas_used * CPU_ROWS_PER_GAS
+        + code_bytes * CPU_ROWS_PER_CODE_BYTE
+        + storage_slots * CPU_ROWS_PER_STORAGE_SLOT;
+
+    // Segments are proven one after the other, so only the largest one matters.
+    if let Some(max_log_len) = max_cpu_log_len {
+        cpu_rows = cpu_rows.min(1 << max_log_len);
+    }
+
+    let bytes_per_row = match witness_only {
+        true => WITNESS_BYTES_PER_CPU_ROW,
+        false => PROVING_BYTES_PER_CPU_ROW,
+    };
+    cpu_rows.saturating_mul(bytes_per_row)
+}
+
+/// Returns the total RAM of the machine, if it can be determined.
+pub(crate) fn total_system_memory_bytes() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let kib: u64 = meminfo
+        .lines()
+        .find_map(|l| l.strip_prefix("MemTotal:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+
+    Some(kib * 1024)
+}
+
+/// How often the resident memory is sampled.
+const RSS_SAMPLING_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Samples the resident memory of the process in the background, keeping the
+/// peak seen until it is stopped.
+pub(crate) struct PeakRssSampler {
+    /// The resident memory when sampling started.
+    baseline: Option<u64>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<u64>,
+}
+
+impl PeakRssSampler {
+    pub(crate) fn start() -> Self {
+        let baseline = current_rss_bytes();
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = Arc::clone(&stop);
+            move || {
+                let mut peak = 0;
+                loop {
+                    peak = peak.max(current_rss_bytes().unwrap_or_default());
+                    if stop.load(Ordering::Relaxed) {
+                        return peak;
+                    }
+                    thread::sleep(RSS_SAMPLING_INTERVAL);
+                }
+            }
+        });
+
+        Self {
+            baseline,
+            stop,
+            handle,
+        }
+    }
+
+    /// Stops sampling and returns by how many bytes the resident memory peaked
+    /// above what it was when sampling started, if it can be determined.
+    pub(crate) fn stop(self) -> Option<u64> {
+        self.stop.store(true, Ordering::Relaxed);
+        let peak = self.handle.join().ok()?;
+        Some(peak.saturating_sub(self.baseline?))
+    }
+}
+
+/// Returns the resident memory of the process, if it can be determined.
+fn current_rss_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let kib: u64 = status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+
+    Some(kib * 1024)
+}
+
+/// Warns about every test whose estimated memory usage exceeds `memory_limit`
+/// and removes them from the run if `skip_heavy` is set. Returns the number of
+/// such tests.
+pub(crate) fn check_memory_estimates(
+    groups: &mut [ParsedTestGroup],
+    persistent_test_state: &TestRunEntries,
+    witness_only: bool,
+    max_cpu_log_len: Option<usize>,
+    memory_limit: u64,
+    skip_heavy: bool,
+) -> usize {
+    let mut num_heavy = 0;
+
+    for sub_g in groups.iter_mut().flat_map(|g| g.sub_groups.iter_mut()) {
+        sub_g.tests.retain(|t| {
+            let estimate = estimate_memory_bytes(&t.info, witness_only, max_cpu_log_len);
+            if estimate <= memory_limit {
+                return true;
+            }
+            num_heavy += 1;
+
+            let last_duration = persistent_test_state
+                .get_last_duration(&t.name)
+                .map(|d| {
+                    format!(
+                        ", last run took {}",
+                        humantime::format_duration(Duration::from_secs(d.as_secs()))
+                    )
+                })
+                .unwrap_or_default();
+            warn!(
+                "{} may need ~{} GiB of memory (limit: {} GiB){}{}",
+                t.name,
+                to_gib(estimate),
+                to_gib(memory_limit),
+                last_duration,
+                if skip_heavy { ", skipping it" } else { "" }
+            );
+
+            !skip_heavy
+        });
+    }
+
+    num_heavy
+}
+
+fn to_gib(bytes: u64) -> String {
+    format!("{:.1}", bytes as f64 / (1u64 << 30) as f64)
+}
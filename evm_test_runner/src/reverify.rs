@@ -0,0 +1,149 @@
+//! Re-verification of the proofs of a proof archive (see `--proof-archive`).
+//!
+//! Verifying a proof takes a fraction of the time it took to generate it, and
+//! the blocks of the archive are independent, so they are verified in parallel
+//! on all cores. This cross-checks every archived proof against the current
+//! verifier after a change to it, without proving anything again.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use anyhow::{anyhow, Context};
+use evm_arithmetization::{verifier::testing::verify_all_proofs, AllStark, StarkConfig};
+use log::{info, warn};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use serde::Serialize;
+
+use crate::{
+    proof_archive::{ArchivedBlock, ProofArchive},
+    provenance::Provenance,
+    report_generation::REPORT_OUTPUT,
+};
+
+#[derive(Debug, Serialize)]
+struct ReverifyReport {
+    provenance: Provenance,
+    archive: PathBuf,
+    num_passed: usize,
+    num_failed: usize,
+    blocks: Vec<ReverifiedBlock>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReverifiedBlock {
+    block: String,
+    /// The zkEVM version the proof was generated with.
+    zkevm_version: String,
+    passed: bool,
+    /// Why the proof couldn't be loaded or didn't verify.
+    error: Option<String>,
+    /// Time spent loading and verifying the proof, in seconds.
+    verify_time: f64,
+}
+
+/// Verifies every block of the archive in `archive_dir` on `threads` threads
+/// (all cores by default), writes the report to `out_path`
+/// (`reports/reverify.json` by default) and returns the number of blocks that
+/// failed.
+pub(crate) fn run_reverify(
+    archive_dir: PathBuf,
+    threads: Option<usize>,
+    out_path: Option<PathBuf>,
+) -> anyhow::Result<usize> {
+    if !archive_dir.is_dir() {
+        return Err(anyhow!(
+            "The proof archive {:?} does not exist",
+            archive_dir
+        ));
+    }
+    let archive = ProofArchive::new(archive_dir.clone())?;
+    let entries = archive.entries()?;
+    if entries.is_empty() {
+        return Err(anyhow!("The proof archive {:?} is empty", archive_dir));
+    }
+
+    let threads = match threads {
+        Some(threads) => threads,
+        None => thread::available_parallelism()?.get(),
+    }
+    .clamp(1, entries.len());
+    println!(
+        "Re-verifying {} block(s) on {} thread(s).",
+        entries.len(),
+        threads
+    );
+
+    let all_stark = AllStark::default();
+    let config = StarkConfig::standard_fast_config();
+    let next_idx = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(entries.len()));
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                while let Some(entry) = entries.get(next_idx.fetch_add(1, Ordering::Relaxed)) {
+                    let result = reverify_block(&archive, entry, &all_stark, &config);
+                    results.lock().unwrap().push(result);
+                }
+            });
+        }
+    });
+
+    let mut blocks = results.into_inner().unwrap();
+    blocks.sort_unstable_by(|a, b| a.block.cmp(&b.block));
+    let num_failed = blocks.iter().filter(|b| !b.passed).count();
+    let report = ReverifyReport {
+        provenance: Provenance::new(None),
+        archive: archive_dir,
+        num_passed: blocks.len() - num_failed,
+        num_failed,
+        blocks,
+    };
+
+    let out_path = out_path.unwrap_or_else(|| Path::new(REPORT_OUTPUT).join("reverify.json"));
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Creating report subdirectory {:?}", parent))?;
+    }
+    fs::write(&out_path, serde_json::to_vec_pretty(&report)?)
+        .with_context(|| format!("Writing re-verification results to {:?}", out_path))?;
+    println!(
+        "{} block(s) passed, {} failed. Results written to {:?}.",
+        report.num_passed, report.num_failed, out_path
+    );
+
+    Ok(num_failed)
+}
+
+fn reverify_block(
+    archive: &ProofArchive,
+    entry: &ArchivedBlock,
+    all_stark: &AllStark<GoldilocksField, 2>,
+    config: &StarkConfig,
+) -> ReverifiedBlock {
+    let start = Instant::now();
+    let res = archive
+        .load(entry)
+        .and_then(|proofs| verify_all_proofs(all_stark, &proofs, config));
+    let verify_time = start.elapsed().as_secs_f64();
+
+    match &res {
+        Ok(_) => info!("{} passed re-verification", entry.block),
+        Err(err) => warn!("{} failed re-verification: {:?}", entry.block, err),
+    }
+
+    ReverifiedBlock {
+        block: entry.block.clone(),
+        zkevm_version: entry.zkevm_version.clone(),
+        passed: res.is_ok(),
+        error: res.err().map(|err| format!("{:#}", err)),
+        verify_time,
+    }
+}
@@ -0,0 +1,229 @@
+//! Optional SQLite store complementing the persistent pass state file. Instead
+//! of only keeping the latest state of each test, a row is appended per (test,
+//! run), which allows trend queries such as "when did this test start
+//! failing":
+//!
+//! ```sql
+//! SELECT r.started_at, r.zkevm_version, t.status, t.error
+//! FROM test_runs t JOIN runs r ON t.run_id = r.id
+//! WHERE t.test_name = 'CALLBlake2f_d1g0v0_Cancun'
+//! ORDER BY r.started_at;
+//! ```
+
+use std::{path::Path, time::Duration};
+
+use anyhow::Context;
+use chrono::Utc;
+use common::config::EVM_ARITHMETIZATION_VERSION;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use serde::Serialize;
+
+use crate::plonky2_runner::TestStatus;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    started_at TEXT NOT NULL,
+    zkevm_version TEXT NOT NULL,
+    witness_only INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS test_runs (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    test_name TEXT NOT NULL,
+    status TEXT NOT NULL,
+    error TEXT,
+    duration_ms INTEGER NOT NULL,
+    finished_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS test_runs_test_name ON test_runs(test_name);
+";
+
+#[derive(Debug)]
+pub(crate) struct RunHistoryDb {
+    conn: Connection,
+    run_id: i64,
+}
+
+impl RunHistoryDb {
+    /// Opens (or creates) the history database and registers a new run in it.
+    pub(crate) fn open(path: &Path, witness_only: bool) -> anyhow::Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Opening run history database {:?}", path))?;
+        conn.execute_batch(SCHEMA)
+            .with_context(|| "Creating run history tables")?;
+
+        conn.execute(
+            "INSERT INTO runs (started_at, zkevm_version, witness_only) VALUES (?1, ?2, ?3)",
+            params![
+                Utc::now().to_rfc3339(),
+                EVM_ARITHMETIZATION_VERSION,
+                witness_only
+            ],
+        )?;
+        let run_id = conn.last_insert_rowid();
+
+        Ok(Self { conn, run_id })
+    }
+
+    pub(crate) fn run_id(&self) -> i64 {
+        self.run_id
+    }
+
+    pub(crate) fn record_test_run(
+        &self,
+        t_name: &str,
+        status: &TestStatus,
+        duration: Duration,
+    ) -> anyhow::Result<()> {
+        let (status_str, error) = match status {
+            TestStatus::PassedWitness => ("PassedWitness", None),
+            TestStatus::PassedProof => ("PassedProof", None),
+            TestStatus::PassedClampedProof => ("PassedClampedProof", None),
+            TestStatus::Ignored => ("Ignored", None),
+            TestStatus::EvmErr(err) => ("EvmErr", Some(err.as_str())),
+            TestStatus::TimedOut => ("TimedOut", None),
+            TestStatus::Crashed(err) => ("Crashed", Some(err.as_str())),
+        };
+
+        self.conn.execute(
+            "INSERT INTO test_runs (run_id, test_name, status, error, duration_ms, finished_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                self.run_id,
+                t_name,
+                status_str,
+                error,
+                duration.as_millis() as i64,
+                Utc::now().to_rfc3339()
+            ],
+        )?;
+
+        Ok(())
+    }
+}
+
+/// A run, along with the number of its tests in each status.
+#[derive(Debug, Serialize)]
+pub(crate) struct RunSummary {
+    pub(crate) id: i64,
+    pub(crate) started_at: String,
+    pub(crate) zkevm_version: String,
+    pub(crate) witness_only: bool,
+    pub(crate) num_passed: u64,
+    pub(crate) num_failed: u64,
+    pub(crate) num_timed_out: u64,
+    pub(crate) num_ignored: u64,
+    /// When the latest test of the run finished, if any did.
+    pub(crate) last_finished_at: Option<String>,
+}
+
+/// A test run, as recorded in the database.
+#[derive(Debug, Serialize)]
+pub(crate) struct TestRunRecord {
+    pub(crate) run_id: i64,
+    pub(crate) test_name: String,
+    pub(crate) status: String,
+    pub(crate) error: Option<String>,
+    pub(crate) duration_ms: u64,
+    pub(crate) finished_at: String,
+}
+
+const RUN_SUMMARY_QUERY: &str = "
+SELECT r.id, r.started_at, r.zkevm_version, r.witness_only,
+    COALESCE(SUM(t.status IN ('PassedWitness', 'PassedProof')), 0),
+    COALESCE(SUM(t.status IN ('EvmErr', 'Crashed')), 0),
+    COALESCE(SUM(t.status = 'TimedOut'), 0),
+    COALESCE(SUM(t.status IN ('Ignored', 'PassedClampedProof')), 0),
+    MAX(t.finished_at)
+FROM runs r LEFT JOIN test_runs t ON t.run_id = r.id
+";
+
+/// Read-only access to the history database, for browsing past (and ongoing)
+/// runs.
+pub(crate) struct RunHistoryReader {
+    conn: Connection,
+}
+
+impl RunHistoryReader {
+    pub(crate) fn open(path: &Path) -> anyhow::Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Opening run history database {:?}", path))?;
+
+        Ok(Self { conn })
+    }
+
+    /// Returns all runs, from the most recent to the oldest.
+    pub(crate) fn runs(&self) -> anyhow::Result<Vec<RunSummary>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} GROUP BY r.id ORDER BY r.id DESC",
+            RUN_SUMMARY_QUERY
+        ))?;
+        let runs = stmt
+            .query_map([], run_summary_from_row)?
+            .collect::<Result<_, _>>()?;
+
+        Ok(runs)
+    }
+
+    pub(crate) fn run(&self, run_id: i64) -> anyhow::Result<Option<RunSummary>> {
+        let run = self
+            .conn
+            .query_row(
+                &format!("{} WHERE r.id = ?1 GROUP BY r.id", RUN_SUMMARY_QUERY),
+                [run_id],
+                run_summary_from_row,
+            )
+            .optional()?;
+
+        Ok(run)
+    }
+
+    /// Returns the tests of a run, in the order they finished.
+    pub(crate) fn run_tests(&self, run_id: i64) -> anyhow::Result<Vec<TestRunRecord>> {
+        self.test_runs("WHERE run_id = ?1 ORDER BY finished_at", run_id)
+    }
+
+    /// Returns every run of a test, from the oldest to the most recent.
+    pub(crate) fn test_history(&self, t_name: &str) -> anyhow::Result<Vec<TestRunRecord>> {
+        self.test_runs("WHERE test_name = ?1 ORDER BY run_id", t_name)
+    }
+
+    fn test_runs(
+        &self,
+        clauses: &str,
+        param: impl rusqlite::ToSql,
+    ) -> anyhow::Result<Vec<TestRunRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT run_id, test_name, status, error, duration_ms, finished_at FROM test_runs {}",
+            clauses
+        ))?;
+        let tests = stmt
+            .query_map([param], |row| {
+                Ok(TestRunRecord {
+                    run_id: row.get(0)?,
+                    test_name: row.get(1)?,
+                    status: row.get(2)?,
+                    error: row.get(3)?,
+                    duration_ms: row.get(4)?,
+                    finished_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(tests)
+    }
+}
+
+fn run_summary_from_row(row: &Row) -> rusqlite::Result<RunSummary> {
+    Ok(RunSummary {
+        id: row.get(0)?,
+        started_at: row.get(1)?,
+        zkevm_version: row.get(2)?,
+        witness_only: row.get(3)?,
+        num_passed: row.get(4)?,
+        num_failed: row.get(5)?,
+        num_timed_out: row.get(6)?,
+        num_ignored: row.get(7)?,
+        last_finished_at: row.get(8)?,
+    })
+}
@@ -0,0 +1,159 @@
+//! Differential testing backend that feeds the parsed tests into an external
+//! `evm t8n` (geth) binary and compares its results against the expected
+//! roots. Having a third independent implementation helps deciding who is
+//! wrong when plonky2 disagrees with the test fixture.
+
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Context};
+use common::{
+    t8n::{t8n_alloc, t8n_env, t8n_txs_rlp, T8nResult},
+    types::TestVariantRunInfo,
+};
+use log::warn;
+
+use crate::plonky2_runner::TestStatus;
+
+const T8N_FORK: &str = "Cancun";
+
+#[derive(Clone, Debug)]
+pub enum T8nStatus {
+    /// `evm t8n` produced the expected roots.
+    Agrees,
+    /// `evm t8n` produced roots or a gas used different from the expected
+    /// ones. Contains the names of the diverging values.
+    Mismatch(String),
+    /// `evm t8n` could not process the test.
+    Err(String),
+}
+
+impl Display for T8nStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            T8nStatus::Agrees => write!(f, "Agrees"),
+            T8nStatus::Mismatch(roots) => write!(f, "Mismatch ({})", roots),
+            T8nStatus::Err(err) => write!(f, "t8n error: {}", err),
+        }
+    }
+}
+
+impl T8nStatus {
+    /// Combines the `t8n` result with the plonky2 one to give a hint on which
+    /// implementation is likely at fault.
+    pub(crate) fn verdict(&self, plonky2_status: &TestStatus) -> Option<&'static str> {
+        match (self, plonky2_status.passed()) {
+            (T8nStatus::Agrees, false) => Some("t8n matches the fixture, likely a zkEVM bug"),
+            (T8nStatus::Mismatch(_), true) => {
+                Some("zkEVM matches the fixture but t8n does not, likely a t8n or fixture issue")
+            }
+            (T8nStatus::Mismatch(_), false) => {
+                Some("Neither t8n nor the zkEVM match the fixture, likely a bad fixture")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Runs the test through `evm t8n` and compares the output roots and gas used
+/// with the expected ones.
+pub(crate) fn run_t8n(t8n_bin: &Path, test: &TestVariantRunInfo) -> T8nStatus {
+    match run_t8n_intern(t8n_bin, test) {
+        Ok(res) => compare_results(test, &res),
+        Err(err) => T8nStatus::Err(format!("{:#}", err)),
+    }
+}
+
+fn run_t8n_intern(t8n_bin: &Path, test: &TestVariantRunInfo) -> anyhow::Result<T8nResult> {
+    let work_dir = t8n_work_dir(&test.variant_name);
+    fs::create_dir_all(&work_dir)
+        .with_context(|| format!("Creating t8n work directory {:?}", work_dir))?;
+
+    let inputs = &test.gen_inputs;
+    fs::write(
+        work_dir.join("alloc.json"),
+        serde_json::to_vec(&t8n_alloc(&test.pre_state))?,
+    )?;
+    fs::write(
+        work_dir.join("env.json"),
+        serde_json::to_vec(&t8n_env(
+            &inputs.block_metadata,
+            &inputs.withdrawals,
+            &inputs.block_hashes,
+        ))?,
+    )?;
+    fs::write(work_dir.join("txs.rlp"), t8n_txs_rlp(&inputs.signed_txns))?;
+
+    let output = Command::new(t8n_bin)
+        .current_dir(&work_dir)
+        .args([
+            "t8n",
+            "--input.alloc=alloc.json",
+            "--input.env=env.json",
+            "--input.txs=txs.rlp",
+            "--output.basedir=.",
+            "--output.result=result.json",
+            "--output.alloc=alloc_out.json",
+            "--state.reward=-1",
+        ])
+        .arg(format!("--state.fork={}", T8N_FORK))
+        .arg(format!(
+            "--state.chainid={}",
+            inputs.block_metadata.block_chain_id
+        ))
+        .output()
+        .with_context(|| format!("Executing {:?}", t8n_bin))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let res = serde_json::from_slice(&fs::read(work_dir.join("result.json"))?)
+        .with_context(|| "Deserializing t8n result.json")?;
+    let _ = fs::remove_dir_all(&work_dir);
+
+    Ok(res)
+}
+
+fn compare_results(test: &TestVariantRunInfo, res: &T8nResult) -> T8nStatus {
+    let expected = &test.final_roots;
+
+    // Gas accounting bugs can hide behind tests whose final state doesn't depend
+    // on the remaining gas, so we check the gas used independently of the roots.
+    let expected_gas_used = test.gen_inputs.gas_used_after;
+    if expected_gas_used != res.gas_used {
+        warn!(
+            "{}: t8n gas used {} differs from the block header gas used {}",
+            test.variant_name, res.gas_used, expected_gas_used
+        );
+    }
+
+    let diverging: Vec<_> = [
+        ("state", expected.state_root_hash == res.state_root),
+        ("txns", expected.txn_trie_root_hash == res.tx_root),
+        (
+            "receipts",
+            expected.receipts_trie_root_hash == res.receipts_root,
+        ),
+        ("gas used", expected_gas_used == res.gas_used),
+    ]
+    .into_iter()
+    .filter_map(|(name, matches)| (!matches).then_some(name))
+    .collect();
+
+    match diverging.is_empty() {
+        true => T8nStatus::Agrees,
+        false => T8nStatus::Mismatch(diverging.join(", ")),
+    }
+}
+
+fn t8n_work_dir(variant_name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("evm_tests_t8n_{}", variant_name))
+}
@@ -10,41 +10,102 @@
 
 use std::{
     collections::HashSet,
+    iter,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{mpsc::sync_channel, Arc},
+    thread,
 };
 
 use anyhow::{anyhow, Context};
 use common::{
     config::{GENERATION_INPUTS_DEFAULT_OUTPUT_DIR, MAIN_TEST_DIR},
-    types::{ParsedTestManifest, TestVariantRunInfo, VariantFilterType},
+    types::{
+        ExpectedFinalRoots, FilteredVariantsOutput, ParsedTestManifest, TestVariantRunInfo,
+        VariantFilterType,
+    },
 };
-use log::{info, trace};
+use eth_test_parser::{
+    config::{Fork, ETH_TESTS_REPO_LOCAL_PATH},
+    diagnostics::Severity,
+    eth_tests_fetching::test_file_key,
+    parse_test_file,
+    provability::UnprovableVariants,
+    ParseOptions,
+};
+use evm_arithmetization::GenerationInputs;
+use log::{debug, info, trace, warn};
+use memmap2::Mmap;
+use rand::{seq::index, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use tokio::{
-    fs::{self, read_dir},
-    task::JoinSet,
+    fs::read_dir,
+    task::{self, JoinSet},
 };
 use tokio_stream::{wrappers::ReadDirStream, StreamExt};
 
+/// Number of parsed test files deserialized ahead of the one being run when
+/// streaming tests.
+const STREAM_READ_AHEAD: usize = 4;
+
 #[derive(Debug)]
-pub(crate) struct ParsedTestGroup {
-    pub(crate) name: String,
-    pub(crate) sub_groups: Vec<ParsedTestSubGroup>,
+pub struct ParsedTestGroup {
+    pub name: String,
+    pub sub_groups: Vec<ParsedTestSubGroup>,
 }
 
 #[derive(Debug)]
-pub(crate) struct ParsedTestSubGroup {
-    pub(crate) name: String,
-    pub(crate) tests: Vec<Test>,
+pub struct ParsedTestSubGroup {
+    pub name: String,
+    pub tests: Vec<Test>,
+}
+
+/// Filters restricting which parsed tests are read in.
+#[derive(Clone, Debug, Default)]
+pub struct ParsedTestFilters {
+    /// Only read tests whose path contains this string.
+    pub filter_str: Option<String>,
+    pub(crate) variant_filter: Option<VariantFilterType>,
+    /// Test variants to skip.
+    pub blacklist: Option<Arc<HashSet<String>>>,
+    /// If set, only these test variants are read in.
+    pub test_list: Option<Arc<HashSet<String>>>,
+    /// If set, only the tests with any of these tags are read in.
+    pub tags: Option<Arc<HashSet<String>>>,
+    /// If set, only the test files with these keys (see
+    /// [`eth_test_parser::eth_tests_fetching::test_file_key`]) are read in.
+    pub changed_files: Option<Arc<HashSet<PathBuf>>>,
+}
+
+impl ParsedTestFilters {
+    /// Whether the test file at `path` is filtered out by the filter string or
+    /// by the changed files.
+    fn excludes_file(&self, file_path: &Path) -> bool {
+        test_is_not_in_filter_str(&self.filter_str, file_path)
+            || self.changed_files.as_ref().is_some_and(|changed_files| {
+                test_file_key(file_path).is_none_or(|key| !changed_files.contains(&key))
+            })
+    }
 }
 
 #[derive(Debug)]
-pub(crate) struct Test {
-    pub(crate) name: String,
-    pub(crate) info: TestVariantRunInfo,
+pub struct Test {
+    pub name: String,
+    /// The variants with identical inputs that the test is run for as well (see
+    /// [`common::types::Plonky2ParsedTest::aliases`]).
+    pub aliases: Vec<String>,
+    pub info: TestVariantRunInfo,
+    /// The (parsed or JSON) test file the variant was read from.
+    pub input_path: PathBuf,
 }
 
-pub(crate) fn get_default_parsed_tests_path() -> anyhow::Result<PathBuf> {
+impl Test {
+    /// The name of the test followed by those of its aliases.
+    pub(crate) fn all_names(&self) -> impl Iterator<Item = &str> {
+        iter::once(self.name.as_str()).chain(self.aliases.iter().map(String::as_str))
+    }
+}
+
+pub fn get_default_parsed_tests_path() -> anyhow::Result<PathBuf> {
     std::env::current_dir()?
         .ancestors()
         .map(|ancestor| {
@@ -62,12 +123,32 @@ pub(crate) fn get_default_parsed_tests_path() -> anyhow::Result<PathBuf> {
         })
 }
 
+/// Returns the checkout of the tests made by the parser, looking for it in the
+/// ancestry of the cwd.
+pub(crate) fn get_default_eth_tests_path() -> anyhow::Result<PathBuf> {
+    std::env::current_dir()?
+        .ancestors()
+        .flat_map(|ancestor| {
+            [
+                ancestor.join(ETH_TESTS_REPO_LOCAL_PATH),
+                ancestor
+                    .join("eth_test_parser")
+                    .join(ETH_TESTS_REPO_LOCAL_PATH),
+            ]
+        })
+        .find(|path| path.exists())
+        .ok_or_else(|| {
+            anyhow!(
+                "Unable to find {} in cwd ancestry. Have you run the parser binary?",
+                ETH_TESTS_REPO_LOCAL_PATH
+            )
+        })
+}
+
 /// Reads in all parsed tests from the given parsed test directory.
-pub(crate) async fn read_in_all_parsed_tests(
+pub async fn read_in_all_parsed_tests(
     parsed_tests_path: &Path,
-    filter_str: Option<String>,
-    variant_filter: Option<VariantFilterType>,
-    blacklist: Option<Arc<HashSet<String>>>,
+    filters: ParsedTestFilters,
 ) -> anyhow::Result<Vec<ParsedTestGroup>> {
     let (mut groups, mut join_set, mut read_dirs) =
         parse_dir_init(Path::new(parsed_tests_path)).await?;
@@ -79,12 +160,7 @@ pub(crate) async fn read_in_all_parsed_tests(
             continue;
         }
 
-        join_set.spawn(parse_test_group(
-            entry.path(),
-            filter_str.clone(),
-            variant_filter.clone(),
-            blacklist.clone(),
-        ));
+        join_set.spawn(parse_test_group(entry.path(), filters.clone()));
     }
 
     wait_for_task_to_finish_and_push_to_vec(&mut join_set, &mut groups).await?;
@@ -92,11 +168,314 @@ pub(crate) async fn read_in_all_parsed_tests(
     Ok(groups)
 }
 
+/// Reads in a single parsed test manifest, bypassing the directory scan.
+///
+/// The group and sub-group names are taken from the parent directories of the
+/// file, as it would have been laid out by the parser.
+pub(crate) async fn read_in_single_parsed_test(
+    path: &Path,
+    filters: ParsedTestFilters,
+) -> anyhow::Result<Vec<ParsedTestGroup>> {
+    let sub_group_path = path.parent();
+    let group_path = sub_group_path.and_then(|p| p.parent());
+    let dir_name = |p: Option<&Path>| {
+        p.and_then(|p| get_file_stem(p).ok())
+            .unwrap_or_else(|| "-".to_string())
+    };
+
+    let tests = parse_test(path.to_path_buf(), filters).await?;
+
+    Ok(vec![ParsedTestGroup {
+        name: dir_name(group_path),
+        sub_groups: vec![ParsedTestSubGroup {
+            name: dir_name(sub_group_path),
+            tests,
+        }],
+    }])
+}
+
+/// Parses raw Ethereum JSON tests in-process, bypassing the parser binary and
+/// its CBOR output entirely.
+///
+/// `path` is either a single JSON test file or a directory searched
+/// recursively for them. All tests are put in a single group named after
+/// `path`, with a sub-group per directory containing JSON tests. The variants
+/// listed as unprovable are left out, as by the parser.
+pub async fn read_in_json_tests(
+    path: &Path,
+    filters: ParsedTestFilters,
+) -> anyhow::Result<Vec<ParsedTestGroup>> {
+    let mut json_files = Vec::new();
+    find_files(path, &["json"], &mut json_files)?;
+    let options = ParseOptions {
+        unprovable_variants: UnprovableVariants::builtin(),
+        ..Default::default()
+    };
+
+    let mut join_set = JoinSet::new();
+    for json_path in json_files {
+        if filters.excludes_file(&json_path) {
+            continue;
+        }
+
+        let filters = filters.clone();
+        let options = options.clone();
+        join_set.spawn_blocking(move || {
+            trace!("Parsing {:?}...", json_path);
+            let parsed = parse_test_file(&json_path, Fork::default(), options)
+                .with_context(|| format!("Parsing JSON test {:?}", json_path))?;
+            for diagnostic in parsed.diagnostics {
+                match diagnostic.severity {
+                    Severity::Skip => debug!("{:?}: {}", json_path, diagnostic.message),
+                    _ => warn!("{:?}: {}", json_path, diagnostic.message),
+                }
+            }
+            let manifest = parsed.manifest;
+            let sub_group = json_path
+                .parent()
+                .map_or_else(|| Ok("-".to_string()), get_file_stem)?;
+
+            anyhow::Ok((sub_group, filter_variants(manifest, &filters, &json_path)))
+        });
+    }
+
+    let mut sub_groups: Vec<ParsedTestSubGroup> = Vec::new();
+    while let Some(h) = join_set.join_next().await {
+        let (sub_group, tests) = h.with_context(|| "Getting the result from a join vec")??;
+        match sub_groups.iter_mut().find(|sub_g| sub_g.name == sub_group) {
+            Some(sub_g) => sub_g.tests.extend(tests),
+            None => sub_groups.push(ParsedTestSubGroup {
+                name: sub_group,
+                tests,
+            }),
+        }
+    }
+
+    Ok(vec![ParsedTestGroup {
+        name: get_file_stem(path)?,
+        sub_groups,
+    }])
+}
+
+/// Reads in raw `GenerationInputs` produced by other tools (eg. the trace
+/// decoder), as JSON or CBOR files, each run as a single-block test named after
+/// its file.
+///
+/// As for [`read_in_json_tests`], `path` is either a single file or a directory
+/// searched recursively, and the tests are grouped by directory. The inputs
+/// have no pre-state beyond their tries, nor tags.
+pub(crate) async fn read_in_gen_inputs(
+    path: &Path,
+    filters: ParsedTestFilters,
+) -> anyhow::Result<Vec<ParsedTestGroup>> {
+    let mut files = Vec::new();
+    find_files(path, &["json", "cbor"], &mut files)?;
+
+    let mut join_set = JoinSet::new();
+    for inputs_path in files {
+        if filters.excludes_file(&inputs_path) {
+            continue;
+        }
+
+        let filters = filters.clone();
+        join_set.spawn_blocking(move || {
+            trace!("Reading in {:?}...", inputs_path);
+            let gen_inputs = read_gen_inputs(&inputs_path)?;
+            let final_roots = ExpectedFinalRoots {
+                state_root_hash: gen_inputs.trie_roots_after.state_root,
+                txn_trie_root_hash: gen_inputs.trie_roots_after.transactions_root,
+                receipts_trie_root_hash: gen_inputs.trie_roots_after.receipts_root,
+            };
+            let info = TestVariantRunInfo {
+                variant_name: get_file_stem(&inputs_path)?,
+                aliases: Vec::new(),
+                gen_inputs: Arc::new(gen_inputs),
+                final_roots,
+                variant_idx: 0,
+                pre_state: Arc::default(),
+                post_state: Arc::default(),
+                txn_outcome: None,
+                logs_bloom: None,
+                chained_blocks: Vec::new(),
+            };
+            let v_out = FilteredVariantsOutput {
+                variants: vec![info],
+                tot_variants_without_filter: 1,
+                tags: Vec::new(),
+            };
+            let sub_group = inputs_path
+                .parent()
+                .map_or_else(|| Ok("-".to_string()), get_file_stem)?;
+
+            anyhow::Ok((
+                sub_group,
+                filter_variants_by_name(v_out, &filters, &inputs_path),
+            ))
+        });
+    }
+
+    let mut sub_groups: Vec<ParsedTestSubGroup> = Vec::new();
+    while let Some(h) = join_set.join_next().await {
+        let (sub_group, tests) = h.with_context(|| "Getting the result from a join vec")??;
+        match sub_groups.iter_mut().find(|sub_g| sub_g.name == sub_group) {
+            Some(sub_g) => sub_g.tests.extend(tests),
+            None => sub_groups.push(ParsedTestSubGroup {
+                name: sub_group,
+                tests,
+            }),
+        }
+    }
+
+    Ok(vec![ParsedTestGroup {
+        name: get_file_stem(path)?,
+        sub_groups,
+    }])
+}
+
+fn read_gen_inputs(path: &Path) -> anyhow::Result<GenerationInputs> {
+    let bytes = std::fs::read(path).with_context(|| format!("Reading {:?}", path))?;
+    match path.extension().is_some_and(|ext| ext == "cbor") {
+        true => serde_cbor::from_slice(&bytes).map_err(anyhow::Error::from),
+        false => serde_json::from_slice(&bytes).map_err(anyhow::Error::from),
+    }
+    .with_context(|| format!("Deserializing the generation inputs {:?}", path))
+}
+
+/// Finds the files with any of the given extensions, either `path` itself or
+/// in the directory it points to.
+fn find_files(path: &Path, extensions: &[&str], out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    if !path.is_dir() {
+        out.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    for entry in sorted_dir_entries(path)? {
+        if entry.is_dir() {
+            find_files(&entry, extensions, out)?;
+        } else if entry
+            .extension()
+            .is_some_and(|ext| extensions.iter().any(|e| ext == *e))
+        {
+            out.push(entry);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads in the parsed tests on a background thread, one test file at a time,
+/// as they are consumed by the returned iterator.
+///
+/// Each item is a single test file, wrapped in its group and sub-group. Only a
+/// few files are deserialized ahead of the one being consumed, so that tests
+/// can start running right away without ever holding the whole corpus in
+/// memory.
+pub(crate) fn stream_parsed_tests(
+    parsed_tests_path: PathBuf,
+    filters: ParsedTestFilters,
+) -> impl Iterator<Item = anyhow::Result<ParsedTestGroup>> {
+    let (send, recv) = sync_channel(STREAM_READ_AHEAD);
+
+    thread::spawn(move || {
+        let files = match scan_parsed_test_files(&parsed_tests_path, &filters) {
+            Ok(files) => files,
+            Err(err) => {
+                let _ = send.send(Err(err));
+                return;
+            }
+        };
+
+        for (group, sub_group, path) in files {
+            let res = read_tests_from_file(&path, &filters).map(|tests| ParsedTestGroup {
+                name: group,
+                sub_groups: vec![ParsedTestSubGroup {
+                    name: sub_group,
+                    tests,
+                }],
+            });
+
+            // The receiver is gone if the run was stopped early.
+            if send.send(res).is_err() {
+                break;
+            }
+        }
+    });
+
+    recv.into_iter()
+}
+
+/// Returns the `(group, sub-group, path)` of every parsed test file matching
+/// the filter string, in a deterministic order.
+fn scan_parsed_test_files(
+    parsed_tests_path: &Path,
+    filters: &ParsedTestFilters,
+) -> anyhow::Result<Vec<(String, String, PathBuf)>> {
+    let mut files = Vec::new();
+
+    for group_path in sorted_dirs(parsed_tests_path)? {
+        let group = get_file_stem(&group_path)?;
+        for sub_group_path in sorted_dirs(&group_path)? {
+            let sub_group = get_file_stem(&sub_group_path)?;
+            for path in sorted_dir_entries(&sub_group_path)? {
+                if filters.excludes_file(&path) {
+                    continue;
+                }
+                files.push((group.clone(), sub_group.clone(), path));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+fn sorted_dirs(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    Ok(sorted_dir_entries(path)?
+        .into_iter()
+        .filter(|p| p.is_dir())
+        .collect())
+}
+
+fn sorted_dir_entries(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut entries = std::fs::read_dir(path)
+        .with_context(|| format!("Reading directory {:?}", path))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_unstable();
+
+    Ok(entries)
+}
+
+/// Only keeps a random subset of `num_tests` test variants across all groups.
+///
+/// Variants are sampled from their sorted names, so that the same seed always
+/// yields the same subset on the same corpus, regardless of the order in which
+/// the tests were read in.
+pub(crate) fn sample_tests(groups: &mut [ParsedTestGroup], num_tests: usize, seed: u64) {
+    let mut t_names: Vec<_> = groups
+        .iter()
+        .flat_map(|g| g.sub_groups.iter())
+        .flat_map(|sub_g| sub_g.tests.iter())
+        .map(|t| t.name.clone())
+        .collect();
+    if num_tests >= t_names.len() {
+        return;
+    }
+    t_names.sort_unstable();
+
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let sampled: HashSet<_> = index::sample(&mut rng, t_names.len(), num_tests)
+        .into_iter()
+        .map(|i| t_names[i].as_str())
+        .collect();
+
+    for sub_g in groups.iter_mut().flat_map(|g| g.sub_groups.iter_mut()) {
+        sub_g.tests.retain(|t| sampled.contains(t.name.as_str()));
+    }
+}
+
 async fn parse_test_group(
     path: PathBuf,
-    filter_str: Option<String>,
-    variant_filter: Option<VariantFilterType>,
-    blacklist: Option<Arc<HashSet<String>>>,
+    filters: ParsedTestFilters,
 ) -> anyhow::Result<ParsedTestGroup> {
     info!("Reading in test group {:?}...", path);
     let (mut sub_groups, mut join_set, mut read_dirs) = parse_dir_init(&path).await?;
@@ -108,12 +487,7 @@ async fn parse_test_group(
             continue;
         }
 
-        join_set.spawn(parse_test_sub_group(
-            entry.path(),
-            filter_str.clone(),
-            variant_filter.clone(),
-            blacklist.clone(),
-        ));
+        join_set.spawn(parse_test_sub_group(entry.path(), filters.clone()));
     }
 
     wait_for_task_to_finish_and_push_to_vec(&mut join_set, &mut sub_groups).await?;
@@ -126,9 +500,7 @@ async fn parse_test_group(
 
 async fn parse_test_sub_group(
     path: PathBuf,
-    filter_str: Option<String>,
-    variant_filter: Option<VariantFilterType>,
-    blacklist: Option<Arc<HashSet<String>>>,
+    filters: ParsedTestFilters,
 ) -> anyhow::Result<ParsedTestSubGroup> {
     trace!("Reading in test subgroup {:?}...", path);
     let (mut tests, mut join_set, mut read_dirs) = parse_dir_init(&path).await?;
@@ -137,15 +509,11 @@ async fn parse_test_sub_group(
         let entry = entry?;
         let file_path = entry.path();
 
-        if test_is_not_in_filter_str(&filter_str, &file_path) {
+        if filters.excludes_file(&file_path) {
             continue;
         }
 
-        join_set.spawn(parse_test(
-            file_path,
-            variant_filter.clone(),
-            blacklist.clone(),
-        ));
+        join_set.spawn(parse_test(file_path, filters.clone()));
     }
 
     wait_for_task_to_finish_and_extend_vec(&mut join_set, &mut tests).await?;
@@ -160,6 +528,10 @@ fn blacklisted(blacklist: Option<&HashSet<String>>, t_name: &str) -> bool {
     blacklist.is_some_and(|b_list| b_list.contains(t_name))
 }
 
+fn not_in_test_list(test_list: Option<&HashSet<String>>, t_name: &str) -> bool {
+    test_list.is_some_and(|t_list| !t_list.contains(t_name))
+}
+
 fn test_is_not_in_filter_str(filter_str: &Option<String>, file_path: &Path) -> bool {
     filter_str.as_ref().is_some_and(|f_str| {
         file_path
@@ -168,28 +540,92 @@ fn test_is_not_in_filter_str(filter_str: &Option<String>, file_path: &Path) -> b
     })
 }
 
-async fn parse_test(
-    path: PathBuf,
-    variant_filter: Option<VariantFilterType>,
-    blacklist: Option<Arc<HashSet<String>>>,
-) -> anyhow::Result<Vec<Test>> {
+async fn parse_test(path: PathBuf, filters: ParsedTestFilters) -> anyhow::Result<Vec<Test>> {
+    task::spawn_blocking(move || read_tests_from_file(&path, &filters))
+        .await
+        .with_context(|| "Joining the parsed test reading task")?
+}
+
+fn read_tests_from_file(path: &Path, filters: &ParsedTestFilters) -> anyhow::Result<Vec<Test>> {
     trace!("Reading in {:?}...", path);
 
-    let parsed_test_bytes = fs::read(&path).await?;
-    let parsed_test: ParsedTestManifest = serde_cbor::from_slice(&parsed_test_bytes)
-        .unwrap_or_else(|_| panic!("Unable to parse the test {:?} (bad format)", path));
+    let v_out = read_parsed_test_manifest(path, filters.variant_filter.as_ref())?;
 
-    let v_out = parsed_test.into_filtered_variants(variant_filter);
+    Ok(filter_variants_by_name(v_out, filters, path))
+}
+
+fn filter_variants(
+    parsed_test: ParsedTestManifest,
+    filters: &ParsedTestFilters,
+    path: &Path,
+) -> Vec<Test> {
+    let v_out = parsed_test.into_filtered_variants(filters.variant_filter.clone());
+
+    filter_variants_by_name(v_out, filters, path)
+}
 
-    let blacklist_ref = blacklist.as_deref();
-    Ok(v_out
+fn filter_variants_by_name(
+    v_out: FilteredVariantsOutput,
+    filters: &ParsedTestFilters,
+    path: &Path,
+) -> Vec<Test> {
+    if filters
+        .tags
+        .as_ref()
+        .is_some_and(|tags| !v_out.tags.iter().any(|tag| tags.contains(tag)))
+    {
+        return Vec::new();
+    }
+
+    let blacklist_ref = filters.blacklist.as_deref();
+    let test_list_ref = filters.test_list.as_deref();
+    v_out
         .variants
         .into_iter()
         .filter_map(|info| {
-            let name = info.variant_name.clone();
-            (!blacklisted(blacklist_ref, &name)).then_some(Test { name, info })
+            // The test is run as long as any of the variants it stands for
+            // passes the filters, but only recorded for these.
+            let mut names = iter::once(&info.variant_name)
+                .chain(info.aliases.iter())
+                .filter(|name| {
+                    !blacklisted(blacklist_ref, name) && !not_in_test_list(test_list_ref, name)
+                })
+                .cloned();
+            let name = names.next()?;
+            let aliases = names.collect();
+
+            Some(Test {
+                name,
+                aliases,
+                info,
+                input_path: path.to_path_buf(),
+            })
         })
-        .collect())
+        .collect()
+}
+
+/// Memory-maps the parsed test file rather than reading it into a buffer, so
+/// that the raw CBOR of all the tests being read in concurrently doesn't need
+/// to be held in memory at once. Only the variants matching the filter are
+/// deserialized.
+fn read_parsed_test_manifest(
+    path: &Path,
+    v_filter: Option<&VariantFilterType>,
+) -> anyhow::Result<FilteredVariantsOutput> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Reading parsed test {:?}", path))?;
+
+    // SAFETY: Parsed test files are only written by the parser, which is not
+    // expected to run concurrently with the runner.
+    let mmap = unsafe { Mmap::map(&file) }
+        .with_context(|| format!("Memory-mapping parsed test {:?}", path))?;
+
+    ParsedTestManifest::read_filtered_variants(&mmap, v_filter).with_context(|| {
+        format!(
+            "Unable to read the parsed test {:?}, re-run the parser to update it",
+            path
+        )
+    })
 }
 
 async fn wait_for_task_to_finish_and_push_to_vec<T: 'static>(
@@ -253,3 +689,50 @@ fn get_file_stem(path: &Path) -> anyhow::Result<String> {
         .to_string();
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::Path, sync::Arc};
+
+    use common::types::{FilteredVariantsOutput, Plonky2ParsedTest};
+
+    use super::{filter_variants_by_name, ParsedTestFilters};
+
+    #[test]
+    fn filter_variants_by_name_promotes_alias() {
+        let variant = |name: &str, aliases: &[&str]| {
+            Plonky2ParsedTest {
+                test_name: name.to_string(),
+                aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+                ..Default::default()
+            }
+            .into_run_info(0)
+        };
+        let v_out = FilteredVariantsOutput {
+            variants: vec![
+                variant(
+                    "test_d0g0v0_Cancun",
+                    &["test_d1g0v0_Cancun", "test_d2g0v0_Cancun"],
+                ),
+                variant("test_d3g0v0_Cancun", &[]),
+            ],
+            tot_variants_without_filter: 2,
+            tags: Vec::new(),
+        };
+        let blacklist = ["test_d0g0v0_Cancun", "test_d3g0v0_Cancun"]
+            .into_iter()
+            .map(String::from)
+            .collect::<HashSet<_>>();
+        let filters = ParsedTestFilters {
+            blacklist: Some(Arc::new(blacklist)),
+            ..Default::default()
+        };
+
+        let tests = filter_variants_by_name(v_out, &filters, Path::new("test.cbor"));
+
+        assert_eq!(tests.len(), 1);
+        assert_eq!(tests[0].name, "test_d1g0v0_Cancun");
+        assert_eq!(tests[0].aliases, ["test_d2g0v0_Cancun"]);
+        assert_eq!(tests[0].info.variant_name, "test_d0g0v0_Cancun");
+    }
+}
@@ -0,0 +1,143 @@
+//! On-disk cache of the witnesses generated in witness-only mode.
+//!
+//! Generating the witness splits the execution of a test into segments by
+//! running it through the zkEVM interpreter, which is a significant part of the
+//! runtime of large tests. When a cache directory is provided, the resulting
+//! segment data is kept after a witness-only run, so that a later proving run
+//! can go straight to generating the traces of each segment and proving them.
+//!
+//! Cached witnesses are only reused if they were generated from the same
+//! inputs, by the same zkEVM version and with the same max CPU log length.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use common::config::EVM_ARITHMETIZATION_VERSION;
+use ethereum_types::H256;
+use evm_arithmetization::{
+    generation::{GenerationInputs, TrimmedGenerationInputs},
+    GenerationSegmentData, SegmentDataIterator,
+};
+use keccak_hash::keccak;
+use log::warn;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use serde::{Deserialize, Serialize};
+
+use crate::proof_archive::write_atomically;
+
+pub(crate) type Witness = (
+    TrimmedGenerationInputs<GoldilocksField>,
+    Vec<GenerationSegmentData>,
+);
+
+#[derive(Deserialize, Serialize)]
+struct CachedWitness {
+    zkevm_version: String,
+    max_cpu_log_len: usize,
+    /// The hash of the serialized inputs the witness was generated from.
+    inputs_hash: H256,
+    inputs: TrimmedGenerationInputs<GoldilocksField>,
+    segments: Vec<GenerationSegmentData>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct WitnessCache {
+    dir: PathBuf,
+}
+
+impl WitnessCache {
+    pub(crate) fn new(dir: PathBuf) -> anyhow::Result<Self> {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Creating witness cache directory {:?}", dir))?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, t_name: &str) -> PathBuf {
+        self.dir.join(format!("{}.cbor", t_name))
+    }
+
+    /// Returns the cached witness of the given test, if there is one generated
+    /// from `inputs`.
+    pub(crate) fn load(
+        &self,
+        t_name: &str,
+        inputs: &GenerationInputs<GoldilocksField>,
+        max_cpu_log_len: usize,
+    ) -> Option<Witness> {
+        let path = self.path(t_name);
+        if !path.exists() {
+            return None;
+        }
+
+        let cached: CachedWitness = match read_cached_witness(&path) {
+            Ok(cached) => cached,
+            Err(err) => {
+                warn!("Ignoring cached witness of {}: {:#}", t_name, err);
+                return None;
+            }
+        };
+
+        let inputs_hash = match hash_inputs(inputs) {
+            Ok(hash) => hash,
+            Err(err) => {
+                warn!("Unable to hash the inputs of {}: {:#}", t_name, err);
+                return None;
+            }
+        };
+
+        (cached.zkevm_version == EVM_ARITHMETIZATION_VERSION
+            && cached.max_cpu_log_len == max_cpu_log_len
+            && cached.inputs_hash == inputs_hash)
+            .then_some((cached.inputs, cached.segments))
+    }
+
+    pub(crate) fn store(
+        &self,
+        t_name: &str,
+        inputs: &GenerationInputs<GoldilocksField>,
+        max_cpu_log_len: usize,
+        (trimmed_inputs, segments): Witness,
+    ) -> anyhow::Result<()> {
+        let cached = CachedWitness {
+            zkevm_version: EVM_ARITHMETIZATION_VERSION.to_string(),
+            max_cpu_log_len,
+            inputs_hash: hash_inputs(inputs)?,
+            inputs: trimmed_inputs,
+            segments,
+        };
+
+        let path = self.path(t_name);
+        write_atomically(&path, &serde_cbor::to_vec(&cached)?)
+            .with_context(|| format!("Caching the witness of {}", t_name))
+    }
+}
+
+fn hash_inputs(inputs: &GenerationInputs<GoldilocksField>) -> anyhow::Result<H256> {
+    Ok(keccak(serde_cbor::to_vec(inputs)?))
+}
+
+fn read_cached_witness(path: &Path) -> anyhow::Result<CachedWitness> {
+    let bytes = fs::read(path)?;
+    Ok(serde_cbor::from_slice(&bytes)?)
+}
+
+/// Runs the execution of all segments, keeping the data needed to later
+/// generate their traces.
+pub(crate) fn generate_witness(
+    inputs: &GenerationInputs<GoldilocksField>,
+    max_cpu_log_len: usize,
+) -> anyhow::Result<Witness> {
+    let mut trimmed_inputs = None;
+    let mut segments = Vec::new();
+
+    for segment in SegmentDataIterator::<GoldilocksField>::new(inputs, Some(max_cpu_log_len)) {
+        let (segment_inputs, segment_data) = segment?;
+        trimmed_inputs.get_or_insert(segment_inputs);
+        segments.push(segment_data);
+    }
+
+    Ok((trimmed_inputs.unwrap_or_default(), segments))
+}
@@ -0,0 +1,45 @@
+//! The `evm-tests` binary, parsing the Ethereum tests and running them against
+//! Polygon Zero's EVM from a single entry point.
+//!
+//! Each subcommand hands its arguments over to the command line interface of
+//! the parser or of the runner, which are otherwise run as their own binaries
+//! (`eth_test_parser` and `evm_test_runner`), so they take the same arguments.
+//! The subcommands of the runner (eg. `bench`, `fuzz` or `reverify`) come after
+//! its arguments, as in `evm-tests run -w fuzz -n 100`.
+
+use std::ffi::OsString;
+
+use clap::{Parser, Subcommand};
+
+#[derive(Debug, Parser)]
+#[command(name = "evm-tests", author, version, about)]
+struct ProgArgs {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Fetch the Ethereum tests and parse them into generation inputs for the
+    /// runner (see `evm-tests parse --help`).
+    #[command(disable_help_flag = true)]
+    Parse {
+        #[arg(allow_hyphen_values = true, trailing_var_arg = true)]
+        args: Vec<OsString>,
+    },
+
+    /// Run the parsed tests against the zkEVM, or one of the subcommands of
+    /// the runner (see `evm-tests run --help`).
+    #[command(disable_help_flag = true)]
+    Run {
+        #[arg(allow_hyphen_values = true, trailing_var_arg = true)]
+        args: Vec<OsString>,
+    },
+}
+
+fn main() -> anyhow::Result<()> {
+    match ProgArgs::parse().command {
+        Command::Parse { args } => eth_test_parser::run_cli_as_subcommand("parse", args),
+        Command::Run { args } => evm_test_runner::run_cli_as_subcommand("run", args),
+    }
+}